@@ -2,10 +2,7 @@
 //!
 //! This example shows how to use musk in a production application
 
-use musk::{
-    client::NodeClient, Arguments, Program, SpendBuilder, Value, WitnessName, WitnessValues,
-};
-use std::collections::HashMap;
+use musk::{Arguments, Program};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Musk Library Usage Example\n");
@@ -38,14 +35,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let address = compiled.address(&musk::elements::AddressParams::ELEMENTS);
     println!("   Address: {}", address);
 
-    // 5. Show transaction building (conceptual)
-    println!("\n5. Building transaction (conceptual)...");
-    println!("   To spend from this program:");
-    println!("   - Create a SpendBuilder with the compiled program and UTXO");
-    println!("   - Add outputs and set lock_time/sequence as needed");
-    println!("   - Compute sighash_all() for signature generation");
-    println!("   - Generate witness values");
-    println!("   - Finalize the transaction");
+    // 5. Run the spend flow end to end against a node
+    println!("\n5. Spending...");
+    println!("   See musk::scenarios for runnable end-to-end flows:");
+    println!("   - fund_spend_confirm: fund -> spend -> confirm");
+    println!("   - htlc_swap: lock funds in an HTLC and claim with the preimage");
 
     Ok(())
 }
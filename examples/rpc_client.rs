@@ -9,7 +9,7 @@
 //!   - Elements node running (regtest mode)
 //!   - RPC credentials configured
 
-use musk::{Arguments, NodeConfig, Program, RpcClient, SpendBuilder};
+use musk::{Arguments, NodeClient, NodeConfig, Program, RpcClient};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Musk RpcClient Example\n");
@@ -95,7 +95,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn demonstrate_program_workflow(client: &RpcClient) -> Result<(), Box<dyn std::error::Error>> {
-    use musk::client::NodeClient;
+    use musk::scenarios::{fund_spend_confirm, ScenarioConfig};
+    use musk::WitnessValues;
 
     println!("\n6. Program workflow demonstration...");
 
@@ -117,21 +118,23 @@ fn main() {
     let address = compiled.address(client.address_params());
     println!("   Address: {}", address);
 
-    // Fund the address (requires wallet with funds)
-    println!("\n   Funding program address...");
-    match client.send_to_address(&address, 100_000_000) {
-        // 1 BTC
-        Ok(txid) => {
-            println!("   ✓ Funded! txid: {}", txid);
-
-            // Generate a block to confirm
-            match client.generate_blocks(1) {
-                Ok(_) => println!("   ✓ Block generated"),
-                Err(e) => println!("   Note: Could not generate block: {}", e),
-            }
+    // Fund the address, spend it back to the wallet and confirm
+    // (requires a regtest wallet with funds)
+    println!("\n   Running fund -> spend -> confirm scenario...");
+    let Some(genesis_hash) = client.config().genesis_hash().ok() else {
+        println!("   Note: set chain.genesis_hash in the config to build spends");
+        return Ok(());
+    };
+    let mut config = ScenarioConfig::regtest(genesis_hash);
+    config.params = client.address_params();
+
+    match fund_spend_confirm(client, &compiled, &config, |_| WitnessValues::default()) {
+        Ok(report) => {
+            println!("   ✓ Funded! txid: {}", report.funding_txid);
+            println!("   ✓ Spent and confirmed! txid: {}", report.spend_txid);
         }
         Err(e) => {
-            println!("   Note: Could not fund ({})", e);
+            println!("   Note: Scenario failed ({})", e);
             println!("   This is expected if wallet has no funds");
         }
     }
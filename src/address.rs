@@ -2,9 +2,13 @@
 
 use crate::error::ProgramError;
 use crate::util::default_internal_key;
-use elements::taproot::{TaprootBuilder, TaprootSpendInfo};
-use secp256k1::Secp256k1;
+use elements::confidential;
+use elements::schnorr::TweakedPublicKey;
+use elements::taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use elements::{Address, AddressParams, AssetId, Script};
+use secp256k1::{PublicKey, Secp256k1, XOnlyPublicKey};
 use simplicityhl::CompiledProgram;
+use std::str::FromStr;
 
 /// Create taproot spend info for a compiled program
 ///
@@ -12,7 +16,21 @@ use simplicityhl::CompiledProgram;
 ///
 /// Returns an error if the taproot tree cannot be built or finalized.
 pub fn create_taproot_info(compiled: &CompiledProgram) -> Result<TaprootSpendInfo, ProgramError> {
-    let internal_key = default_internal_key();
+    create_taproot_info_with_key(compiled, default_internal_key())
+}
+
+/// Create taproot spend info for a compiled program with a custom internal key
+///
+/// Unlike the default NUMS internal key, the holder of `internal_key` can also
+/// spend the output via the key path.
+///
+/// # Errors
+///
+/// Returns an error if the taproot tree cannot be built or finalized.
+pub fn create_taproot_info_with_key(
+    compiled: &CompiledProgram,
+    internal_key: XOnlyPublicKey,
+) -> Result<TaprootSpendInfo, ProgramError> {
     let builder = TaprootBuilder::new();
 
     let script = elements::script::Script::from(compiled.commit().cmr().as_ref().to_vec());
@@ -26,3 +44,321 @@ pub fn create_taproot_info(compiled: &CompiledProgram) -> Result<TaprootSpendInf
         .finalize(&Secp256k1::new(), internal_key)
         .map_err(|e| ProgramError::TaprootError(e.to_string()))
 }
+
+/// Verify that a control block commits to a leaf under an output key
+///
+/// Returns `true` only if the control block carries `version` and proves
+/// that `script` is a leaf of the tree committed to by `output_key`.
+///
+/// # Examples
+///
+/// ```
+/// use musk::address::verify_control_block;
+/// use musk::{Arguments, Program};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let (script, version) = compiled.script_version();
+/// let info = compiled.taproot_info();
+/// let control_block = info.control_block(&(script.clone(), version)).unwrap();
+///
+/// let output_key = info.output_key().into_inner();
+/// assert!(verify_control_block(&script, version, &control_block, &output_key));
+/// assert!(!verify_control_block(&musk::elements::Script::new(), version, &control_block, &output_key));
+/// ```
+#[must_use]
+pub fn verify_control_block(
+    script: &Script,
+    version: LeafVersion,
+    control_block: &ControlBlock,
+    output_key: &XOnlyPublicKey,
+) -> bool {
+    control_block.leaf_version == version
+        && control_block.verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            &TweakedPublicKey::new(*output_key),
+            script,
+        )
+}
+
+/// Parse an address string and check that it belongs to the expected network
+///
+/// Both confidential (blech32) and unconfidential (bech32) forms are accepted.
+///
+/// # Examples
+///
+/// ```
+/// use musk::address::parse_address;
+/// use musk::{Arguments, Program, elements};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let address = compiled.address(&elements::AddressParams::ELEMENTS);
+///
+/// let parsed = parse_address(&address.to_string(), &elements::AddressParams::ELEMENTS).unwrap();
+/// assert_eq!(parsed, address);
+/// assert!(parse_address(&address.to_string(), &elements::AddressParams::LIQUID).is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the string is not a valid address or is for another network.
+pub fn parse_address(
+    address: &str,
+    params: &'static AddressParams,
+) -> Result<Address, ProgramError> {
+    let parsed = Address::from_str(address)
+        .map_err(|e| ProgramError::ParseError(format!("Invalid address `{address}`: {e}")))?;
+    if parsed.params != params {
+        return Err(ProgramError::ParseError(format!(
+            "Address `{address}` is not for the expected network"
+        )));
+    }
+    Ok(parsed)
+}
+
+/// Get the blinding public key embedded in a confidential address
+#[must_use]
+pub const fn blinding_pubkey(address: &Address) -> Option<PublicKey> {
+    address.blinding_pubkey
+}
+
+/// Get the output nonce for paying to an address before blinding
+///
+/// For confidential addresses this is the receiver's blinding public key,
+/// which the blinder uses for ECDH; unconfidential addresses get a null nonce.
+///
+/// # Examples
+///
+/// ```
+/// use musk::address::receiver_nonce;
+/// use musk::{Arguments, Program, elements};
+/// use secp256k1::{Secp256k1, SecretKey};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let blinding_pubkey = SecretKey::from_slice(&[7; 32]).unwrap().public_key(&Secp256k1::new());
+/// let address = compiled.confidential_address(&elements::AddressParams::ELEMENTS, blinding_pubkey);
+///
+/// assert_eq!(
+///     receiver_nonce(&address),
+///     elements::confidential::Nonce::Confidential(blinding_pubkey)
+/// );
+/// assert!(receiver_nonce(&address.to_unconfidential()).is_null());
+/// ```
+#[must_use]
+pub fn receiver_nonce(address: &Address) -> confidential::Nonce {
+    address
+        .blinding_pubkey
+        .map_or(confidential::Nonce::Null, confidential::Nonce::Confidential)
+}
+
+/// Check whether two addresses pay to the same scriptPubkey
+///
+/// The confidential and unconfidential forms of a program address, and the
+/// same address on different networks, all compare equal.
+#[must_use]
+pub fn same_destination(a: &Address, b: &Address) -> bool {
+    a.script_pubkey() == b.script_pubkey()
+}
+
+/// URI scheme for Liquid mainnet and regtest payment requests
+const URI_SCHEME: &str = "liquidnetwork";
+
+/// URI scheme for Liquid testnet payment requests
+const TESTNET_URI_SCHEME: &str = "liquidtestnet";
+
+/// A parsed BIP21-style payment request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentUri {
+    /// Destination address
+    pub address: Address,
+    /// Requested amount in satoshis (or the asset's base unit)
+    pub amount: Option<u64>,
+    /// Requested asset
+    pub asset: Option<AssetId>,
+    /// Label for the recipient
+    pub label: Option<String>,
+    /// Message describing the payment
+    pub message: Option<String>,
+}
+
+/// Build a BIP21-style payment URI for an address
+///
+/// The scheme is `liquidtestnet:` for Liquid testnet addresses and
+/// `liquidnetwork:` otherwise. The amount is rendered in whole units with up
+/// to eight decimals, without trailing zeros, and the asset is passed in the
+/// `assetid` parameter.
+///
+/// # Examples
+///
+/// ```
+/// use musk::address::{parse_uri, to_uri};
+/// use musk::{Arguments, Program, elements};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let address = compiled.address(&elements::AddressParams::LIQUID);
+///
+/// let uri = to_uri(&address, Some(150_000_000), None, Some("Order 42"));
+/// assert_eq!(uri, format!("liquidnetwork:{address}?amount=1.5&label=Order%2042"));
+///
+/// let parsed = parse_uri(&uri).unwrap();
+/// assert_eq!(parsed.amount, Some(150_000_000));
+/// assert_eq!(parsed.label.as_deref(), Some("Order 42"));
+/// ```
+#[must_use]
+pub fn to_uri(
+    address: &Address,
+    amount: Option<u64>,
+    asset: Option<AssetId>,
+    label: Option<&str>,
+) -> String {
+    let scheme = uri_scheme(address.params);
+
+    let mut query = Vec::new();
+    if let Some(amount) = amount {
+        query.push(format!("amount={}", format_amount(amount)));
+    }
+    if let Some(asset) = asset {
+        query.push(format!("assetid={asset}"));
+    }
+    if let Some(label) = label {
+        query.push(format!("label={}", percent_encode(label)));
+    }
+
+    if query.is_empty() {
+        format!("{scheme}:{address}")
+    } else {
+        format!("{scheme}:{address}?{}", query.join("&"))
+    }
+}
+
+/// Parse a `liquidnetwork:` or `liquidtestnet:` payment URI
+///
+/// The scheme must match the network of the address, so a testnet URI
+/// cannot request a payment to a mainnet address or the other way around.
+/// Unknown optional parameters are ignored; unknown parameters prefixed with
+/// `req-` are rejected, as required by BIP21.
+///
+/// # Errors
+///
+/// Returns an error if the scheme, address or any known parameter is
+/// invalid, or if the scheme does not match the network of the address.
+pub fn parse_uri(uri: &str) -> Result<PaymentUri, ProgramError> {
+    let invalid =
+        |reason: String| ProgramError::ParseError(format!("Invalid URI `{uri}`: {reason}"));
+
+    let (scheme, rest) = uri
+        .split_once(':')
+        .ok_or_else(|| invalid("missing scheme".into()))?;
+    if !scheme.eq_ignore_ascii_case(URI_SCHEME) && !scheme.eq_ignore_ascii_case(TESTNET_URI_SCHEME)
+    {
+        return Err(invalid(format!("unsupported scheme `{scheme}`")));
+    }
+
+    let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let address = Address::from_str(address).map_err(|e| invalid(e.to_string()))?;
+    if !scheme.eq_ignore_ascii_case(uri_scheme(address.params)) {
+        return Err(invalid(format!(
+            "scheme `{scheme}` does not match the network of the address"
+        )));
+    }
+    let mut request = PaymentUri {
+        address,
+        amount: None,
+        asset: None,
+        label: None,
+        message: None,
+    };
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value =
+            percent_decode(value).ok_or_else(|| invalid(format!("bad encoding in `{key}`")))?;
+        match key {
+            "amount" => {
+                request.amount = Some(
+                    parse_amount(&value).ok_or_else(|| invalid(format!("bad amount `{value}`")))?,
+                )
+            }
+            "assetid" => {
+                request.asset = Some(AssetId::from_str(&value).map_err(|e| invalid(e.to_string()))?)
+            }
+            "label" => request.label = Some(value),
+            "message" => request.message = Some(value),
+            key if key.starts_with("req-") => {
+                return Err(invalid(format!("unsupported required parameter `{key}`")))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(request)
+}
+
+/// URI scheme for payment requests to addresses of `params`
+fn uri_scheme(params: &AddressParams) -> &'static str {
+    if params == &AddressParams::LIQUID_TESTNET {
+        TESTNET_URI_SCHEME
+    } else {
+        URI_SCHEME
+    }
+}
+
+/// Render a satoshi amount as a decimal with up to eight fractional digits
+fn format_amount(amount: u64) -> String {
+    let whole = amount / 100_000_000;
+    let fraction = amount % 100_000_000;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{fraction:08}");
+    format!("{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+/// Parse a decimal amount with up to eight fractional digits into satoshis
+fn parse_amount(amount: &str) -> Option<u64> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > 8 || (whole.is_empty() && fraction.is_empty()) {
+        return None;
+    }
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if !digits(whole) || !digits(fraction) {
+        return None;
+    }
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction: u64 = format!("{fraction:0<8}").parse().ok()?;
+    whole.checked_mul(100_000_000)?.checked_add(fraction)
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Decode a percent-encoded string
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
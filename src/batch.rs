@@ -0,0 +1,335 @@
+//! Payout batching
+//!
+//! A [`BatchPlanner`] pays many [`Payment`]s from a pool of UTXOs of one
+//! program, e.g. a contract treasury, using as few transactions as the size
+//! limit allows. Payments are packed in order into transactions no heavier
+//! than the planner's maximum weight, each funded with the largest remaining
+//! UTXOs and paying a fee estimated at the planner's fee rate, with the rest
+//! returned as change. The spends still need the genesis hash to be signed.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::batch::{BatchPlanner, Payment};
+//! use musk::client::Utxo;
+//! use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+//! use musk::{Arguments, Program, WitnessValues};
+//!
+//! let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+//! let compiled = program.instantiate(Arguments::default()).unwrap();
+//! let script_pubkey = compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey();
+//! let asset = elements::AssetId::default();
+//! let utxos: Vec<Utxo> = (0..3)
+//!     .map(|vout| Utxo {
+//!         txid: Txid::all_zeros(),
+//!         vout,
+//!         amount: 100_000,
+//!         script_pubkey: script_pubkey.clone(),
+//!         asset: confidential::Asset::Explicit(asset),
+//!         value_commitment: None,
+//!         confirmations: 0,
+//!         block_height: None,
+//!     })
+//!     .collect();
+//! let payments: Vec<Payment> = (0..4)
+//!     .map(|i| Payment::new(Script::from(vec![0x51, i]), 50_000))
+//!     .collect();
+//!
+//! let planner = BatchPlanner::new(compiled, asset, script_pubkey, 0.1);
+//! let batches = planner.plan(&utxos, &payments, &WitnessValues::default()).unwrap();
+//! assert_eq!(batches.len(), 1);
+//! ```
+
+use crate::client::Utxo;
+use crate::error::SpendError;
+use crate::program::InstantiatedProgram;
+use crate::spend::{NeedsGenesis, SpendBuilder, DUST_THRESHOLD};
+use elements::{AssetId, Script};
+use simplicityhl::WitnessValues;
+
+/// Largest transaction weight relayed by default (`MAX_STANDARD_TX_WEIGHT`)
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+/// A payment of an amount to a scriptPubkey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payment {
+    /// Destination scriptPubkey
+    pub script_pubkey: Script,
+    /// Amount in satoshis
+    pub amount: u64,
+}
+
+impl Payment {
+    /// Create a payment of `amount` to `script_pubkey`
+    #[must_use]
+    pub const fn new(script_pubkey: Script, amount: u64) -> Self {
+        Self {
+            script_pubkey,
+            amount,
+        }
+    }
+}
+
+/// Planner splitting payments from a program's UTXOs into transactions
+#[derive(Clone)]
+pub struct BatchPlanner {
+    program: InstantiatedProgram,
+    asset: AssetId,
+    change: Script,
+    fee_rate: f64,
+    max_weight: usize,
+}
+
+impl BatchPlanner {
+    /// Create a planner paying `asset` from UTXOs of `program`
+    ///
+    /// Change is sent to `change` and fees are estimated at `fee_rate`
+    /// sat/vB. Transactions are limited to [`MAX_STANDARD_TX_WEIGHT`].
+    #[must_use]
+    pub fn new(
+        program: InstantiatedProgram,
+        asset: AssetId,
+        change: Script,
+        fee_rate: f64,
+    ) -> Self {
+        Self {
+            program,
+            asset,
+            change,
+            fee_rate,
+            max_weight: MAX_STANDARD_TX_WEIGHT,
+        }
+    }
+
+    /// Set the maximum weight of each transaction
+    #[must_use]
+    pub const fn max_weight(mut self, max_weight: usize) -> Self {
+        self.max_weight = max_weight;
+        self
+    }
+
+    /// Split `payments` into spends funded from `utxos`
+    ///
+    /// Payments keep their order across and within the returned spends,
+    /// which still have to be finalized. `witness_values` is only used to
+    /// estimate sizes, as in `SpendBuilder::estimate_fee`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `utxos` cannot fund all payments and fees, or a
+    /// single payment does not fit in a transaction.
+    pub fn plan(
+        &self,
+        utxos: &[Utxo],
+        payments: &[Payment],
+        witness_values: &WitnessValues,
+    ) -> Result<Vec<SpendBuilder<NeedsGenesis>>, SpendError> {
+        let mut pool: Vec<Utxo> = utxos.to_vec();
+        pool.sort_by_key(|utxo| std::cmp::Reverse(utxo.amount));
+        let mut pool = pool.into_iter();
+
+        let mut batches = Vec::new();
+        let mut inputs = Vec::new();
+        let mut batch: Vec<Payment> = Vec::new();
+        for payment in payments {
+            batch.push(payment.clone());
+            let builder = loop {
+                match self.build(&inputs, &batch, witness_values) {
+                    Err(SpendError::InsufficientFunds { .. }) if !pool.as_slice().is_empty() => {
+                        inputs.extend(pool.next());
+                    }
+                    result => break result?,
+                }
+            };
+
+            if builder.estimate_weight(witness_values.clone())? > self.max_weight {
+                batch.pop();
+                if batch.is_empty() {
+                    return Err(SpendError::BuildError(
+                        "Payment does not fit in a transaction".into(),
+                    ));
+                }
+                // Close the batch without this payment and start a new one
+                batches.push(self.close(&mut inputs, &mut batch, witness_values)?);
+                batch.push(payment.clone());
+            }
+        }
+        if !batch.is_empty() {
+            batches.push(self.close(&mut inputs, &mut batch, witness_values)?);
+        }
+        Ok(batches)
+    }
+
+    /// Build the spend of `batch`, drop its surplus inputs and start anew
+    fn close(
+        &self,
+        inputs: &mut Vec<Utxo>,
+        batch: &mut Vec<Payment>,
+        witness_values: &WitnessValues,
+    ) -> Result<SpendBuilder<NeedsGenesis>, SpendError> {
+        // Inputs pulled in for the payment that did not fit may be unneeded
+        let mut surplus = Vec::new();
+        while inputs.len() > 1 {
+            let last = inputs.pop().expect("more than one input");
+            if self.build(inputs, batch, witness_values).is_ok() {
+                surplus.push(last);
+            } else {
+                inputs.push(last);
+                break;
+            }
+        }
+
+        let builder = self.build(inputs, batch, witness_values)?;
+        *inputs = surplus;
+        batch.clear();
+        Ok(builder)
+    }
+
+    /// Build a spend of `inputs` paying `payments`, a fee and any change
+    fn build(
+        &self,
+        inputs: &[Utxo],
+        payments: &[Payment],
+        witness_values: &WitnessValues,
+    ) -> Result<SpendBuilder<NeedsGenesis>, SpendError> {
+        let total_in: u64 = inputs.iter().map(|utxo| utxo.amount).sum();
+        let total_out: u64 = payments.iter().map(|payment| payment.amount).sum();
+        let insufficient = |needed| SpendError::InsufficientFunds {
+            needed,
+            available: total_in,
+        };
+        if inputs.is_empty() {
+            return Err(insufficient(total_out));
+        }
+
+        let new_builder = || -> Result<SpendBuilder<NeedsGenesis>, SpendError> {
+            let mut builder = SpendBuilder::from_utxos(self.program.clone(), inputs.to_vec())?;
+            for payment in payments {
+                builder.add_output_simple(
+                    payment.script_pubkey.clone(),
+                    payment.amount,
+                    self.asset,
+                );
+            }
+            Ok(builder)
+        };
+
+        let mut sized = new_builder()?;
+        sized.add_output_simple(self.change.clone(), 0, self.asset);
+        let fee = sized.estimate_fee(self.fee_rate, witness_values.clone())?;
+
+        let change = total_in
+            .checked_sub(total_out + fee)
+            .ok_or_else(|| insufficient(total_out + fee))?;
+        let mut builder = new_builder()?;
+        if change >= DUST_THRESHOLD {
+            builder.add_fee(fee, self.asset);
+            builder.add_output_simple(self.change.clone(), change, self.asset);
+        } else {
+            builder.add_fee(fee + change, self.asset);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::test_fixtures::{test_genesis_hash, test_utxo, SIMPLE_PROGRAM};
+    use simplicityhl::Arguments;
+
+    fn planner() -> (BatchPlanner, Vec<Utxo>) {
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let script_pubkey = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .script_pubkey();
+        let utxos = (0..4)
+            .map(|vout| Utxo {
+                vout,
+                amount: 1_000_000,
+                script_pubkey: script_pubkey.clone(),
+                ..test_utxo()
+            })
+            .collect();
+        let planner = BatchPlanner::new(program, AssetId::default(), script_pubkey, 1.0);
+        (planner, utxos)
+    }
+
+    fn payments(count: u8, amount: u64) -> Vec<Payment> {
+        (0..count)
+            .map(|i| Payment::new(Script::from(vec![0x51, i]), amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_single_batch() {
+        let (planner, utxos) = planner();
+        let batches = planner
+            .plan(&utxos, &payments(3, 500_000), &WitnessValues::default())
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].inputs().len(), 2);
+    }
+
+    #[test]
+    fn test_plan_splits_by_weight() {
+        let (planner, utxos) = planner();
+        let weight = planner
+            .plan(&utxos, &payments(1, 100_000), &WitnessValues::default())
+            .unwrap()[0]
+            .estimate_weight(WitnessValues::default())
+            .unwrap();
+        let planner = planner.max_weight(weight + 200);
+
+        let payments = payments(4, 100_000);
+        let batches = planner
+            .plan(&utxos, &payments, &WitnessValues::default())
+            .unwrap();
+        assert!(batches.len() > 1);
+        for batch in &batches {
+            assert!(batch.estimate_weight(WitnessValues::default()).unwrap() <= weight + 200);
+        }
+        let inputs: usize = batches.iter().map(|batch| batch.inputs().len()).sum();
+        assert!(inputs <= utxos.len());
+
+        let txs: Vec<_> = batches
+            .into_iter()
+            .map(|batch| {
+                batch
+                    .genesis_hash(test_genesis_hash())
+                    .finalize(WitnessValues::default())
+                    .unwrap()
+            })
+            .collect();
+        let paid: Vec<_> = txs
+            .iter()
+            .flat_map(|tx| &tx.output)
+            .filter(|output| output.script_pubkey.len() == 2)
+            .map(|output| output.script_pubkey.clone())
+            .collect();
+        assert_eq!(
+            paid,
+            payments
+                .iter()
+                .map(|payment| payment.script_pubkey.clone())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let (planner, utxos) = planner();
+        assert!(matches!(
+            planner.plan(&utxos, &payments(5, 1_000_000), &WitnessValues::default()),
+            Err(SpendError::InsufficientFunds { .. })
+        ));
+        assert!(planner
+            .max_weight(100)
+            .plan(&utxos, &payments(1, 1_000), &WitnessValues::default())
+            .is_err());
+    }
+}
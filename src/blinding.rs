@@ -0,0 +1,717 @@
+//! Deterministic blinding keys (SLIP-77, ELIP-150/151)
+//!
+//! Confidential addresses embed a blinding public key. Instead of generating
+//! and storing a key per address, SLIP-77 derives every blinding key from a
+//! single master blinding key and the address's scriptPubkey, which is the
+//! scheme used by Elements Core and Blockstream Green.
+//!
+//! ELIP-150 confidential descriptors generalize this: the blinding key
+//! expression of `ct(<key>, <descriptor>)` may also be a bare public key
+//! (enough to derive addresses) or a private "view key" (enough to also
+//! unblind), and ELIP-151 derives such a view key from the descriptor's own
+//! public keys. [`CtBlindingKey`] implements these key expressions so a
+//! watch-only service given the same expression derives the same
+//! confidential program addresses as the signer.
+//!
+//! The same keys unblind what others send to those addresses:
+//! [`CtBlindingKey::unblind_outputs`] recovers the amounts and assets of a
+//! received transaction's outputs, [`unblind_utxo`] turns one into a
+//! spendable [`Utxo`] with its blinding secrets, and [`verify_amount_proofs`]
+//! checks its
+//! rangeproofs and surjection proofs, so confidential deposits can be
+//! validated without trusting a node's wallet.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::blinding::MasterBlindingKey;
+//! use musk::{Arguments, Program, elements};
+//!
+//! let master = MasterBlindingKey::from_seed(&[0u8; 64]);
+//! let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+//! let compiled = program.instantiate(Arguments::default()).unwrap();
+//!
+//! let address = master.confidential_address(&compiled, &elements::AddressParams::ELEMENTS);
+//! assert!(address.is_blinded());
+//! assert_eq!(
+//!     address,
+//!     master.blind_address(&compiled.address(&elements::AddressParams::ELEMENTS))
+//! );
+//! ```
+
+use crate::client::{NodeClient, Utxo};
+use crate::error::{ProgramError, SpendError};
+use crate::program::{ConfidentialAddress, InstantiatedProgram};
+use crate::util::tagged_hash;
+use elements::hashes::{hmac, sha256, sha512, Hash, HashEngine};
+use elements::hex::{FromHex, ToHex};
+use elements::{Address, AddressParams, Script, Transaction, TxOut, TxOutSecrets, Txid};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use std::str::FromStr;
+
+/// SLIP-21 domain label for SLIP-77 master blinding keys
+const SLIP77_LABEL: &[u8] = b"SLIP-0077";
+
+/// SLIP-21 root key derivation label
+const SLIP21_SEED_KEY: &[u8] = b"Symmetric key seed";
+
+/// ELIP-150 tag for tweaking a blinding key with a scriptPubkey
+const ELIP150_TAG: &str = "CT-Blinding-Key/1.0";
+
+/// ELIP-151 tag for deriving a view key from descriptor keys
+const ELIP151_TAG: &str = "Deterministic-View-Key/1.0";
+
+/// A SLIP-77 master blinding key
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MasterBlindingKey([u8; 32]);
+
+impl MasterBlindingKey {
+    /// Derive the master blinding key from a BIP32 seed
+    ///
+    /// This is the SLIP-21 node at path `m/"SLIP-0077"`.
+    #[must_use]
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let root = hmac_sha512(SLIP21_SEED_KEY, &[seed]);
+        let node = hmac_sha512(&root[..32], &[&[0u8], SLIP77_LABEL]);
+
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&node[32..]);
+        Self(key)
+    }
+
+    /// Create a master blinding key from its raw bytes
+    #[must_use]
+    pub const fn from_byte_array(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Get the raw bytes of the master blinding key
+    #[must_use]
+    pub const fn to_byte_array(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Derive the blinding secret key for a scriptPubkey
+    ///
+    /// # Panics
+    ///
+    /// Panics if the derived key is not a valid secret key, which happens
+    /// with negligible probability.
+    #[must_use]
+    pub fn blinding_secret_key(&self, script_pubkey: &Script) -> SecretKey {
+        let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&self.0);
+        engine.input(script_pubkey.as_bytes());
+        let hash = hmac::Hmac::<sha256::Hash>::from_engine(engine);
+
+        SecretKey::from_slice(hash.as_byte_array()).expect("HMAC output is a valid secret key")
+    }
+
+    /// Derive the blinding public key for a scriptPubkey
+    #[must_use]
+    pub fn blinding_public_key(&self, script_pubkey: &Script) -> PublicKey {
+        self.blinding_secret_key(script_pubkey)
+            .public_key(&Secp256k1::signing_only())
+    }
+
+    /// Generate the confidential address of a program
+    #[must_use]
+    pub fn confidential_address(
+        &self,
+        program: &InstantiatedProgram,
+        params: &'static AddressParams,
+    ) -> Address {
+        let script_pubkey = program.address(params).script_pubkey();
+        program.confidential_address(params, self.blinding_public_key(&script_pubkey))
+    }
+
+    /// Generate the confidential address of a program with its blinding secret
+    #[must_use]
+    pub fn confidential_program_address(
+        &self,
+        program: &InstantiatedProgram,
+        params: &'static AddressParams,
+    ) -> ConfidentialAddress {
+        let script_pubkey = program.address(params).script_pubkey();
+        ConfidentialAddress {
+            address: self.confidential_address(program, params),
+            blinding_key: self.blinding_secret_key(&script_pubkey),
+        }
+    }
+
+    /// Generate the confidential address of a program and import it with its
+    /// blinding key into a node's wallet
+    ///
+    /// Confidential deposits to the program then show up in
+    /// `NodeClient::get_utxos` with no further setup. The blinding key can
+    /// always be derived again, so it need not be stored.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use musk::blinding::MasterBlindingKey;
+    /// use musk::{Arguments, NodeClient, Program, RpcClient};
+    ///
+    /// let client = RpcClient::from_url("http://localhost:18884", "user", "password")?;
+    /// let program = Program::from_source("fn main() { assert!(true); }")?;
+    /// let compiled = program.instantiate(Arguments::default())?;
+    ///
+    /// let master = MasterBlindingKey::from_seed(&[0u8; 64]);
+    /// let confidential =
+    ///     master.import_program_address(&client, &compiled, client.address_params())?;
+    /// let utxos = client.get_utxos(&confidential.address)?;
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the import fails or the client does not support it.
+    pub fn import_program_address<C: NodeClient + ?Sized>(
+        &self,
+        client: &C,
+        program: &InstantiatedProgram,
+        params: &'static AddressParams,
+    ) -> Result<ConfidentialAddress, ProgramError> {
+        let confidential = self.confidential_program_address(program, params);
+        confidential.import_into(client)?;
+        Ok(confidential)
+    }
+
+    /// Turn an address into its confidential form using the derived blinding key
+    ///
+    /// Addresses that are already confidential are re-blinded with the
+    /// key derived from their scriptPubkey.
+    #[must_use]
+    pub fn blind_address(&self, address: &Address) -> Address {
+        let blinding_pubkey = self.blinding_public_key(&address.script_pubkey());
+        address.to_unconfidential().to_confidential(blinding_pubkey)
+    }
+}
+
+impl std::fmt::Debug for MasterBlindingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MasterBlindingKey").field(&"..").finish()
+    }
+}
+
+/// Blinding key expression of an ELIP-150 confidential descriptor
+///
+/// The string form is the first argument of `ct(...)`: `slip77(<hex>)`,
+/// a compressed public key in hex, or a private view key in hex.
+///
+/// # Examples
+///
+/// ```
+/// use musk::blinding::CtBlindingKey;
+/// use musk::{Arguments, Program, elements};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+///
+/// // The signer holds the view key, the watch-only service only the public key
+/// let view_key: CtBlindingKey = "0101010101010101010101010101010101010101010101010101010101010101"
+///     .parse()
+///     .unwrap();
+/// let watch_only: CtBlindingKey = view_key.to_public().to_string().parse().unwrap();
+///
+/// let params = &elements::AddressParams::ELEMENTS;
+/// assert_eq!(
+///     view_key.confidential_address(&compiled, params),
+///     watch_only.confidential_address(&compiled, params),
+/// );
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CtBlindingKey {
+    /// SLIP-77 master blinding key
+    Slip77(MasterBlindingKey),
+    /// Public blinding key, tweaked per scriptPubkey (ELIP-150)
+    Bare(PublicKey),
+    /// Private view key, tweaked per scriptPubkey (ELIP-150)
+    View(SecretKey),
+}
+
+impl CtBlindingKey {
+    /// Derive the ELIP-151 view key from the public keys of a descriptor
+    ///
+    /// Keys must be given in the order they appear in the descriptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `keys` is empty or the derived key is invalid.
+    pub fn elip151(keys: &[PublicKey]) -> Result<Self, ProgramError> {
+        if keys.is_empty() {
+            return Err(ProgramError::InstantiationError(
+                "ELIP-151 requires at least one descriptor key".into(),
+            ));
+        }
+        let serialized: Vec<[u8; 33]> = keys.iter().map(PublicKey::serialize).collect();
+        let chunks: Vec<&[u8]> = serialized.iter().map(<[u8; 33]>::as_slice).collect();
+
+        SecretKey::from_slice(&tagged_hash(ELIP151_TAG, &chunks))
+            .map(Self::View)
+            .map_err(|e| ProgramError::InstantiationError(format!("Invalid ELIP-151 key: {e}")))
+    }
+
+    /// Get the public form of this key, suitable for watch-only services
+    ///
+    /// SLIP-77 keys have no public form and are returned unchanged.
+    #[must_use]
+    pub fn to_public(&self) -> Self {
+        match self {
+            Self::View(secret_key) => Self::Bare(secret_key.public_key(&Secp256k1::signing_only())),
+            other => *other,
+        }
+    }
+
+    /// Derive the blinding public key for a scriptPubkey
+    #[must_use]
+    pub fn blinding_public_key(&self, script_pubkey: &Script) -> PublicKey {
+        let secp = Secp256k1::new();
+        match self {
+            Self::Slip77(master) => master.blinding_public_key(script_pubkey),
+            Self::Bare(pubkey) => pubkey
+                .add_exp_tweak(&secp, &elip150_tweak(pubkey, script_pubkey))
+                .expect("tweak is a valid scalar"),
+            Self::View(_) => self
+                .blinding_secret_key(script_pubkey)
+                .expect("view keys derive a secret key")
+                .public_key(&secp),
+        }
+    }
+
+    /// Derive the blinding secret key for a scriptPubkey
+    ///
+    /// Returns `None` for public-only keys.
+    #[must_use]
+    pub fn blinding_secret_key(&self, script_pubkey: &Script) -> Option<SecretKey> {
+        match self {
+            Self::Slip77(master) => Some(master.blinding_secret_key(script_pubkey)),
+            Self::Bare(_) => None,
+            Self::View(secret_key) => {
+                let pubkey = secret_key.public_key(&Secp256k1::signing_only());
+                Some(
+                    secret_key
+                        .add_tweak(&elip150_tweak(&pubkey, script_pubkey))
+                        .expect("tweaked key is valid"),
+                )
+            }
+        }
+    }
+
+    /// Generate the confidential address of a program
+    #[must_use]
+    pub fn confidential_address(
+        &self,
+        program: &InstantiatedProgram,
+        params: &'static AddressParams,
+    ) -> Address {
+        let script_pubkey = program.address(params).script_pubkey();
+        program.confidential_address(params, self.blinding_public_key(&script_pubkey))
+    }
+
+    /// Unblind the outputs of `tx` that were blinded to this key
+    ///
+    /// Returns the index and secrets of every confidential output whose
+    /// blinding key derives from this key for its scriptPubkey. Rewinding an
+    /// output's rangeproof proves its amount; use [`verify_amount_proofs`] to
+    /// also check that the transaction balances. Public-only keys unblind
+    /// nothing.
+    #[must_use]
+    pub fn unblind_outputs(&self, tx: &Transaction) -> Vec<(u32, TxOutSecrets)> {
+        let secp = Secp256k1::new();
+        tx.output
+            .iter()
+            .zip(0u32..)
+            .filter(|(output, _)| output.value.is_confidential())
+            .filter_map(|(output, vout)| {
+                let blinding_key = self.blinding_secret_key(&output.script_pubkey)?;
+                output
+                    .unblind(&secp, blinding_key)
+                    .ok()
+                    .map(|secrets| (vout, secrets))
+            })
+            .collect()
+    }
+
+    /// Unblind output `vout` of transaction `txid`, locked by an address of this key
+    ///
+    /// See [`unblind_utxo`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this key is public-only or the output was not
+    /// blinded to it.
+    pub fn unblind_utxo(
+        &self,
+        txid: Txid,
+        vout: u32,
+        txout: &TxOut,
+    ) -> Result<(Utxo, TxOutSecrets), SpendError> {
+        let blinding_key = self
+            .blinding_secret_key(&txout.script_pubkey)
+            .ok_or_else(|| {
+                SpendError::InvalidUtxo("Cannot unblind with a public blinding key".into())
+            })?;
+        unblind_utxo(txid, vout, txout, blinding_key)
+    }
+}
+
+/// Unblind output `vout` of transaction `txid` with its blinding secret key
+///
+/// The returned UTXO has the unblinded amount and keeps the output's asset
+/// and value commitments, which sighashes commit to. The secrets hold the
+/// unblinded asset and blinding factors, to be passed to
+/// `SpendBuilder::blind` when spending it. No node needs to know the blinding
+/// key. The UTXO has no confirmations, as the output alone does not tell.
+///
+/// # Errors
+///
+/// Returns an error if the output is explicit or was not blinded to
+/// `blinding_key`.
+pub fn unblind_utxo(
+    txid: Txid,
+    vout: u32,
+    txout: &TxOut,
+    blinding_key: SecretKey,
+) -> Result<(Utxo, TxOutSecrets), SpendError> {
+    if txout.value.is_explicit() {
+        return Err(SpendError::InvalidUtxo(format!(
+            "Output {txid}:{vout} is not confidential"
+        )));
+    }
+    let secrets = txout
+        .unblind(&Secp256k1::new(), blinding_key)
+        .map_err(|e| SpendError::InvalidUtxo(format!("Cannot unblind {txid}:{vout}: {e}")))?;
+    let utxo = Utxo {
+        txid,
+        vout,
+        amount: secrets.value,
+        script_pubkey: txout.script_pubkey.clone(),
+        asset: txout.asset,
+        value_commitment: txout.value.commitment(),
+        confirmations: 0,
+        block_height: None,
+    };
+    Ok((utxo, secrets))
+}
+
+/// Verify the rangeproofs, surjection proofs and balance of `tx`
+///
+/// `spent` are the outputs spent by `tx`, in input order, as fetched from the
+/// chain. Every confidential output must prove its amount is in range and its
+/// asset is one of the inputs' assets, and the value commitments must balance
+/// with the fee, as a node checks on acceptance.
+///
+/// # Errors
+///
+/// Returns an error if `spent` does not match the inputs or a proof fails.
+pub fn verify_amount_proofs(tx: &Transaction, spent: &[TxOut]) -> Result<(), SpendError> {
+    if spent.len() != tx.input.len() {
+        return Err(SpendError::VerificationError(format!(
+            "Expected {} spent outputs, got {}",
+            tx.input.len(),
+            spent.len()
+        )));
+    }
+    tx.verify_tx_amt_proofs(&Secp256k1::verification_only(), spent)
+        .map_err(|e| SpendError::VerificationError(format!("Invalid amount proofs: {e}")))
+}
+
+impl std::fmt::Display for CtBlindingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Slip77(master) => write!(f, "slip77({})", master.0.to_hex()),
+            Self::Bare(pubkey) => write!(f, "{pubkey}"),
+            Self::View(secret_key) => write!(f, "{}", secret_key.secret_bytes().to_hex()),
+        }
+    }
+}
+
+impl std::fmt::Debug for CtBlindingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Slip77(master) => f.debug_tuple("Slip77").field(master).finish(),
+            Self::Bare(pubkey) => f.debug_tuple("Bare").field(pubkey).finish(),
+            Self::View(_) => f.debug_tuple("View").field(&"..").finish(),
+        }
+    }
+}
+
+impl FromStr for CtBlindingKey {
+    type Err = ProgramError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            ProgramError::ParseError(format!("Invalid blinding key `{s}`: {e}"))
+        };
+
+        if let Some(inner) = s.strip_prefix("slip77(").and_then(|s| s.strip_suffix(')')) {
+            let bytes = <[u8; 32]>::from_hex(inner).map_err(|e| invalid(&e))?;
+            return Ok(Self::Slip77(MasterBlindingKey(bytes)));
+        }
+        match s.len() {
+            66 => PublicKey::from_str(s)
+                .map(Self::Bare)
+                .map_err(|e| invalid(&e)),
+            64 => SecretKey::from_str(s)
+                .map(Self::View)
+                .map_err(|e| invalid(&e)),
+            _ => Err(invalid(&"expected slip77(..), a public key or a view key")),
+        }
+    }
+}
+
+/// ELIP-150 tweak: `H_tag(pubkey || consensus_encode(script_pubkey))`
+fn elip150_tweak(pubkey: &PublicKey, script_pubkey: &Script) -> Scalar {
+    let hash = tagged_hash(
+        ELIP150_TAG,
+        &[
+            &pubkey.serialize(),
+            &elements::encode::serialize(script_pubkey),
+        ],
+    );
+    Scalar::from_be_bytes(hash).expect("hash is a valid scalar")
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(key);
+    for chunk in data {
+        engine.input(chunk);
+    }
+    hmac::Hmac::<sha512::Hash>::from_engine(engine).to_byte_array()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::spend::SpendBuilder;
+    use crate::test_fixtures::{test_address, test_genesis_hash, test_utxo, SIMPLE_PROGRAM};
+    use elements::confidential;
+    use simplicityhl::{Arguments, WitnessValues};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_from_seed_vector() {
+        // BIP39 seed of "abandon abandon ... about" with an empty passphrase
+        let seed = elements::hex::FromHex::from_hex(
+            "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc1\
+             9a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4",
+        );
+        let seed: Vec<u8> = seed.unwrap();
+        let expected: [u8; 32] = elements::hex::FromHex::from_hex(
+            "9c8e4f05c7711a98c838be228bcb84924d4570ca53f35fa1c793e58841d47023",
+        )
+        .unwrap();
+
+        assert_eq!(
+            MasterBlindingKey::from_seed(&seed).to_byte_array(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_from_seed_deterministic() {
+        let master1 = MasterBlindingKey::from_seed(&[1u8; 64]);
+        let master2 = MasterBlindingKey::from_seed(&[1u8; 64]);
+        let master3 = MasterBlindingKey::from_seed(&[2u8; 64]);
+        assert_eq!(master1, master2);
+        assert_ne!(master1, master3);
+    }
+
+    #[test]
+    fn test_blinding_key_per_script() {
+        let master = MasterBlindingKey::from_seed(&[1u8; 64]);
+        let script1 = test_address().script_pubkey();
+        let script2 = Script::new();
+
+        assert_eq!(
+            master.blinding_secret_key(&script1),
+            master.blinding_secret_key(&script1)
+        );
+        assert_ne!(
+            master.blinding_public_key(&script1),
+            master.blinding_public_key(&script2)
+        );
+    }
+
+    #[test]
+    fn test_blind_address() {
+        let master = MasterBlindingKey::from_seed(&[1u8; 64]);
+        let address = test_address();
+        let blinded = master.blind_address(&address);
+
+        assert_eq!(
+            blinded.blinding_pubkey,
+            Some(master.blinding_public_key(&address.script_pubkey()))
+        );
+        assert_eq!(blinded.to_unconfidential(), address);
+        assert_eq!(master.blind_address(&blinded), blinded);
+    }
+
+    #[test]
+    fn test_import_program_address() {
+        use crate::mock_client::MockClient;
+
+        let master = MasterBlindingKey::from_seed(&[1u8; 64]);
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let params = &elements::AddressParams::ELEMENTS;
+        let client = MockClient::new();
+
+        let confidential = master
+            .import_program_address(&client, &program, params)
+            .unwrap();
+        assert_eq!(
+            confidential.address,
+            master.confidential_address(&program, params)
+        );
+        assert_eq!(
+            confidential
+                .blinding_key
+                .public_key(&Secp256k1::signing_only()),
+            confidential.address.blinding_pubkey.unwrap()
+        );
+        assert!(client.is_imported(&confidential.address));
+        assert_eq!(
+            client.blinding_key(&confidential.address),
+            Some(confidential.blinding_key)
+        );
+    }
+
+    #[test]
+    fn test_ct_key_roundtrip() {
+        let keys = [
+            "slip77(9c8e4f05c7711a98c838be228bcb84924d4570ca53f35fa1c793e58841d47023)",
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "0101010101010101010101010101010101010101010101010101010101010101",
+        ];
+        for key in keys {
+            assert_eq!(key.parse::<CtBlindingKey>().unwrap().to_string(), key);
+        }
+        assert!("slip77(00)".parse::<CtBlindingKey>().is_err());
+        assert!("not a key".parse::<CtBlindingKey>().is_err());
+    }
+
+    #[test]
+    fn test_view_key_matches_bare_key() {
+        let view = CtBlindingKey::View(SecretKey::from_slice(&[3u8; 32]).unwrap());
+        let bare = view.to_public();
+        let script = test_address().script_pubkey();
+
+        assert_eq!(
+            view.blinding_public_key(&script),
+            bare.blinding_public_key(&script)
+        );
+        assert_eq!(
+            view.blinding_secret_key(&script)
+                .unwrap()
+                .public_key(&Secp256k1::new()),
+            bare.blinding_public_key(&script)
+        );
+        assert!(bare.blinding_secret_key(&script).is_none());
+    }
+
+    #[test]
+    fn test_elip151_deterministic() {
+        let pk1 = SecretKey::from_slice(&[1u8; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        let pk2 = SecretKey::from_slice(&[2u8; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+
+        assert_eq!(
+            CtBlindingKey::elip151(&[pk1, pk2]).unwrap(),
+            CtBlindingKey::elip151(&[pk1, pk2]).unwrap()
+        );
+        assert_ne!(
+            CtBlindingKey::elip151(&[pk1, pk2]).unwrap(),
+            CtBlindingKey::elip151(&[pk2, pk1]).unwrap()
+        );
+        assert!(CtBlindingKey::elip151(&[]).is_err());
+    }
+
+    #[test]
+    fn test_debug_redacts_key() {
+        let master = MasterBlindingKey::from_seed(&[1u8; 64]);
+        assert_eq!(format!("{master:?}"), "MasterBlindingKey(\"..\")");
+    }
+
+    /// Build a transaction paying 60,000,000 to a confidential address of `key`
+    fn received_tx(key: &CtBlindingKey) -> (Transaction, Vec<TxOut>) {
+        let asset = elements::AssetId::default();
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let address = key.confidential_address(&program, &AddressParams::ELEMENTS);
+        let utxo = crate::client::Utxo {
+            script_pubkey: address.script_pubkey(),
+            ..test_utxo()
+        };
+        let spent = vec![TxOut {
+            asset: utxo.asset,
+            value: utxo.value(),
+            nonce: confidential::Nonce::Null,
+            script_pubkey: utxo.script_pubkey.clone(),
+            witness: elements::TxOutWitness::empty(),
+        }];
+
+        let mut builder =
+            SpendBuilder::new_unchecked(program, utxo).genesis_hash(test_genesis_hash());
+        builder
+            .add_confidential_output(&address, 60_000_000, asset)
+            .unwrap();
+        builder.add_fee(1_000, asset);
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+        builder
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .unwrap();
+        (builder.finalize(WitnessValues::default()).unwrap(), spent)
+    }
+
+    #[test]
+    fn test_unblind_and_verify_received() {
+        let key = CtBlindingKey::Slip77(MasterBlindingKey::from_seed(&[1u8; 64]));
+        let (tx, spent) = received_tx(&key);
+
+        verify_amount_proofs(&tx, &spent).unwrap();
+        assert!(verify_amount_proofs(&tx, &[]).is_err());
+
+        let unblinded = key.unblind_outputs(&tx);
+        assert_eq!(unblinded.len(), 1);
+        assert_eq!(unblinded[0].0, 0);
+        assert_eq!(unblinded[0].1.value, 60_000_000);
+        assert_eq!(unblinded[0].1.asset, elements::AssetId::default());
+        let other = CtBlindingKey::Slip77(MasterBlindingKey::from_seed(&[2u8; 64]));
+        assert!(other.unblind_outputs(&tx).is_empty());
+
+        let mut tampered = tx;
+        tampered.output[0].witness.rangeproof = tampered.output[1].witness.rangeproof.clone();
+        assert!(verify_amount_proofs(&tampered, &spent).is_err());
+    }
+
+    #[test]
+    fn test_unblind_utxo() {
+        let master = MasterBlindingKey::from_seed(&[1u8; 64]);
+        let key = CtBlindingKey::Slip77(master);
+        let (tx, _) = received_tx(&key);
+        let txid = tx.txid();
+
+        let (utxo, secrets) = key.unblind_utxo(txid, 0, &tx.output[0]).unwrap();
+        assert_eq!(utxo.amount, 60_000_000);
+        assert_eq!((utxo.txid, utxo.vout), (txid, 0));
+        assert_eq!(utxo.asset, tx.output[0].asset);
+        assert_eq!(secrets.asset, elements::AssetId::default());
+
+        let blinding_key = master.blinding_secret_key(&tx.output[0].script_pubkey);
+        let (_, raw) = unblind_utxo(txid, 0, &tx.output[0], blinding_key).unwrap();
+        assert_eq!(raw, secrets);
+
+        assert!(key.unblind_utxo(txid, 1, &tx.output[1]).is_err());
+        let wrong = SecretKey::from_slice(&[9u8; 32]).unwrap();
+        assert!(unblind_utxo(txid, 0, &tx.output[0], wrong).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+//! Node client that caches the responses of another client
+//!
+//! [`CachedClient`] keeps data that cannot change, transactions by txid and
+//! the hashes of buried blocks, for as long as the client lives. UTXO sets
+//! and the chain tip change with every block, so they are kept for a short
+//! time to live instead. Indexers that resolve the same transactions and
+//! addresses over and over then only reach the node once.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use musk::{CachedClient, NodeClient, RpcClient};
+//! use std::time::Duration;
+//!
+//! let client = CachedClient::new(RpcClient::from_url(url, user, password)?)
+//!     .with_ttl(Duration::from_secs(30));
+//! let tx = client.get_transaction(&txid)?; // Reaches the node
+//! let tx = client.get_transaction(&txid)?; // Served from the cache
+//! ```
+
+use crate::client::{ClientResult, MempoolInfo, NodeClient, Utxo};
+use elements::{Address, BlockHash, Transaction, Txid};
+use secp256k1::SecretKey;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Default time to live of UTXO sets and the chain tip
+pub const DEFAULT_TTL: Duration = Duration::from_secs(10);
+
+/// Default number of confirmations after which a block hash is cached
+pub const DEFAULT_FINALITY_DEPTH: u64 = 6;
+
+/// Default number of cached transactions
+pub const DEFAULT_MAX_TRANSACTIONS: usize = 10_000;
+
+/// Node client that caches the responses of another client
+///
+/// Sending, broadcasting and generating blocks through the cache drop the
+/// cached UTXO sets and tip, since they change both. Changes made elsewhere
+/// show up once the time to live runs out, or after [`invalidate`].
+///
+/// [`invalidate`]: CachedClient::invalidate
+#[derive(Debug)]
+pub struct CachedClient<C> {
+    inner: C,
+    ttl: Duration,
+    finality_depth: u64,
+    max_transactions: usize,
+    cache: Mutex<Cache>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    transactions: HashMap<Txid, Transaction>,
+    block_hashes: HashMap<u64, BlockHash>,
+    block_count: Option<(Instant, u64)>,
+    /// UTXO sets by address and minimum confirmations, `None` for `get_utxos`
+    utxos: HashMap<(Address, Option<u32>), (Instant, Vec<Utxo>)>,
+}
+
+impl<C: NodeClient> CachedClient<C> {
+    /// Wrap a client with the default time to live and limits
+    #[must_use]
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            ttl: DEFAULT_TTL,
+            finality_depth: DEFAULT_FINALITY_DEPTH,
+            max_transactions: DEFAULT_MAX_TRANSACTIONS,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Set how long UTXO sets and the chain tip are cached
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set how many confirmations a block needs before its hash is cached
+    ///
+    /// Hashes of more recent blocks are not cached, because a reorganization
+    /// may replace them. The genesis hash is always cached.
+    #[must_use]
+    pub fn with_finality_depth(mut self, finality_depth: u64) -> Self {
+        self.finality_depth = finality_depth;
+        self
+    }
+
+    /// Set how many transactions are cached at most
+    #[must_use]
+    pub fn with_max_transactions(mut self, max_transactions: usize) -> Self {
+        self.max_transactions = max_transactions;
+        self
+    }
+
+    /// Get the wrapped client
+    #[must_use]
+    pub const fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the client
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Get the genesis block hash, which is cached after the first call
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails or is not supported.
+    pub fn genesis_hash(&self) -> ClientResult<BlockHash> {
+        self.get_block_hash(0)
+    }
+
+    /// Drop the cached UTXO sets and chain tip, e.g. after spending through
+    /// another client
+    pub fn invalidate(&self) {
+        let mut cache = self.cache();
+        cache.utxos.clear();
+        cache.block_count = None;
+    }
+
+    /// Drop everything that is cached
+    pub fn clear(&self) {
+        *self.cache() = Cache::default();
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().expect("cache lock poisoned")
+    }
+
+    fn is_fresh(&self, fetched: Instant) -> bool {
+        fetched.elapsed() < self.ttl
+    }
+
+    fn cache_transaction(&self, txid: Txid, tx: Transaction) {
+        if self.max_transactions == 0 {
+            return;
+        }
+        let mut cache = self.cache();
+        if cache.transactions.len() >= self.max_transactions
+            && !cache.transactions.contains_key(&txid)
+        {
+            // Transactions never change, so any of them may go
+            if let Some(evicted) = cache.transactions.keys().next().copied() {
+                cache.transactions.remove(&evicted);
+            }
+        }
+        cache.transactions.insert(txid, tx);
+    }
+
+    fn cached_utxos(
+        &self,
+        address: &Address,
+        min_conf: Option<u32>,
+        fetch: impl FnOnce() -> ClientResult<Vec<Utxo>>,
+    ) -> ClientResult<Vec<Utxo>> {
+        let key = (address.clone(), min_conf);
+        if let Some((fetched, utxos)) = self.cache().utxos.get(&key) {
+            if self.is_fresh(*fetched) {
+                return Ok(utxos.clone());
+            }
+        }
+        let utxos = fetch()?;
+        self.cache()
+            .utxos
+            .insert(key, (Instant::now(), utxos.clone()));
+        Ok(utxos)
+    }
+}
+
+impl<C: NodeClient> NodeClient for CachedClient<C> {
+    fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+        let result = self.inner.send_to_address(addr, amount);
+        self.invalidate();
+        result
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+        if let Some(tx) = self.cache().transactions.get(txid) {
+            return Ok(tx.clone());
+        }
+        let tx = self.inner.get_transaction(txid)?;
+        self.cache_transaction(*txid, tx.clone());
+        Ok(tx)
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+        let result = self.inner.broadcast(tx);
+        self.invalidate();
+        let txid = result?;
+        self.cache_transaction(txid, tx.clone());
+        Ok(txid)
+    }
+
+    fn test_accept(&self, tx: &Transaction) -> ClientResult<()> {
+        self.inner.test_accept(tx)
+    }
+
+    fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+        let result = self.inner.generate_blocks(count);
+        self.invalidate();
+        result
+    }
+
+    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+        self.cached_utxos(address, None, || self.inner.get_utxos(address))
+    }
+
+    fn get_utxos_with_min_conf(&self, address: &Address, min_conf: u32) -> ClientResult<Vec<Utxo>> {
+        self.cached_utxos(address, Some(min_conf), || {
+            self.inner.get_utxos_with_min_conf(address, min_conf)
+        })
+    }
+
+    fn get_new_address(&self) -> ClientResult<Address> {
+        self.inner.get_new_address()
+    }
+
+    fn import_address(&self, address: &Address) -> ClientResult<()> {
+        self.inner.import_address(address)?;
+        // Outputs of a newly watched address may show up now
+        self.cache()
+            .utxos
+            .retain(|(cached, _), _| cached != address);
+        Ok(())
+    }
+
+    fn import_blinding_key(&self, address: &Address, blinding_key: &SecretKey) -> ClientResult<()> {
+        self.inner.import_blinding_key(address, blinding_key)?;
+        // Outputs sent to the address can be unblinded now
+        self.cache()
+            .utxos
+            .retain(|(cached, _), _| cached != address);
+        Ok(())
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        if let Some((fetched, count)) = self.cache().block_count {
+            if self.is_fresh(fetched) {
+                return Ok(count);
+            }
+        }
+        let count = self.inner.get_block_count()?;
+        self.cache().block_count = Some((Instant::now(), count));
+        Ok(count)
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        if let Some(hash) = self.cache().block_hashes.get(&height) {
+            return Ok(*hash);
+        }
+        let hash = self.inner.get_block_hash(height)?;
+        let is_final = height == 0
+            || self
+                .get_block_count()
+                .is_ok_and(|tip| height.saturating_add(self.finality_depth) <= tip);
+        if is_final {
+            self.cache().block_hashes.insert(height, hash);
+        }
+        Ok(hash)
+    }
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        self.inner.estimate_fee_rate(target_blocks)
+    }
+
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        self.inner.get_mempool_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::test_address;
+    use elements::hashes::Hash;
+    use std::cell::RefCell;
+
+    /// Client that records which methods reach the mock
+    struct CountingClient {
+        inner: MockClient,
+        calls: RefCell<Vec<&'static str>>,
+    }
+
+    impl CountingClient {
+        fn new() -> Self {
+            Self {
+                inner: MockClient::new(),
+                calls: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn record(&self, method: &'static str) {
+            self.calls.borrow_mut().push(method);
+        }
+
+        fn count(&self, method: &str) -> usize {
+            self.calls.borrow().iter().filter(|m| **m == method).count()
+        }
+    }
+
+    impl NodeClient for CountingClient {
+        fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+            self.record("send_to_address");
+            self.inner.send_to_address(addr, amount)
+        }
+
+        fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+            self.record("get_transaction");
+            self.inner.get_transaction(txid)
+        }
+
+        fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+            self.record("broadcast");
+            self.inner.broadcast(tx)
+        }
+
+        fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+            self.record("generate_blocks");
+            self.inner.generate_blocks(count)
+        }
+
+        fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+            self.record("get_utxos");
+            self.inner.get_utxos(address)
+        }
+
+        fn get_new_address(&self) -> ClientResult<Address> {
+            self.record("get_new_address");
+            self.inner.get_new_address()
+        }
+
+        fn get_block_count(&self) -> ClientResult<u64> {
+            self.record("get_block_count");
+            self.inner.get_block_count()
+        }
+
+        fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+            self.record("get_block_hash");
+            self.inner.get_block_hash(height)
+        }
+    }
+
+    #[test]
+    fn test_transactions_are_cached() {
+        let client = CachedClient::new(CountingClient::new());
+        let txid = client.send_to_address(&test_address(), 1_000).unwrap();
+
+        let tx = client.get_transaction(&txid).unwrap();
+        assert_eq!(client.get_transaction(&txid).unwrap(), tx);
+        assert_eq!(client.inner().count("get_transaction"), 1);
+
+        // Errors are not cached
+        let missing = Txid::all_zeros();
+        assert!(client.get_transaction(&missing).is_err());
+        assert!(client.get_transaction(&missing).is_err());
+        assert_eq!(client.inner().count("get_transaction"), 3);
+    }
+
+    #[test]
+    fn test_transaction_limit() {
+        let client = CachedClient::new(CountingClient::new()).with_max_transactions(1);
+        let first = client.send_to_address(&test_address(), 1_000).unwrap();
+        let second = client.send_to_address(&test_address(), 2_000).unwrap();
+
+        client.get_transaction(&first).unwrap();
+        client.get_transaction(&second).unwrap();
+        client.get_transaction(&first).unwrap();
+        assert_eq!(client.inner().count("get_transaction"), 3);
+        assert_eq!(client.cache().transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_only_buried_block_hashes_are_cached() {
+        let client = CachedClient::new(CountingClient::new())
+            .with_finality_depth(2)
+            .with_ttl(Duration::ZERO);
+        client.generate_blocks(3).unwrap();
+
+        let genesis = client.genesis_hash().unwrap();
+        assert_eq!(client.genesis_hash().unwrap(), genesis);
+        assert_eq!(client.inner().count("get_block_hash"), 1);
+
+        // Height 1 is buried under 2 blocks, height 2 is not
+        client.get_block_hash(1).unwrap();
+        client.get_block_hash(1).unwrap();
+        assert_eq!(client.inner().count("get_block_hash"), 2);
+        client.get_block_hash(2).unwrap();
+        client.get_block_hash(2).unwrap();
+        assert_eq!(client.inner().count("get_block_hash"), 4);
+    }
+
+    #[test]
+    fn test_utxos_expire() {
+        let client = CachedClient::new(CountingClient::new()).with_ttl(Duration::from_secs(60));
+        client
+            .inner()
+            .inner
+            .send_to_address(&test_address(), 1_000)
+            .unwrap();
+
+        assert_eq!(client.get_utxos(&test_address()).unwrap().len(), 1);
+        assert_eq!(client.get_utxos(&test_address()).unwrap().len(), 1);
+        assert_eq!(client.inner().count("get_utxos"), 1);
+
+        // Paid elsewhere, so the cache is stale until invalidated
+        client
+            .inner()
+            .inner
+            .send_to_address(&test_address(), 2_000)
+            .unwrap();
+        assert_eq!(client.get_utxos(&test_address()).unwrap().len(), 1);
+        client.invalidate();
+        assert_eq!(client.get_utxos(&test_address()).unwrap().len(), 2);
+
+        // Paying through the cache invalidates it
+        client.send_to_address(&test_address(), 3_000).unwrap();
+        assert_eq!(client.get_utxos(&test_address()).unwrap().len(), 3);
+
+        let client = CachedClient::new(CountingClient::new()).with_ttl(Duration::ZERO);
+        client.get_utxos(&test_address()).unwrap();
+        client.get_utxos(&test_address()).unwrap();
+        assert_eq!(client.inner().count("get_utxos"), 2);
+    }
+
+    #[test]
+    fn test_block_count_is_invalidated_by_new_blocks() {
+        let client = CachedClient::new(CountingClient::new()).with_ttl(Duration::from_secs(60));
+        assert_eq!(client.get_block_count().unwrap(), 0);
+        assert_eq!(client.get_block_count().unwrap(), 0);
+        assert_eq!(client.inner().count("get_block_count"), 1);
+
+        client.generate_blocks(2).unwrap();
+        assert_eq!(client.get_block_count().unwrap(), 2);
+    }
+}
@@ -2,7 +2,9 @@
 
 use crate::error::ProgramError;
 use elements::hashes::Hash;
+use elements::secp256k1_zkp::PedersenCommitment;
 use elements::{Address, BlockHash, Transaction, Txid};
+use secp256k1::SecretKey;
 
 /// Result type for node client operations
 pub type ClientResult<T> = Result<T, ProgramError>;
@@ -15,6 +17,70 @@ pub struct Utxo {
     pub amount: u64,
     pub script_pubkey: elements::Script,
     pub asset: elements::confidential::Asset,
+    /// Value commitment of a confidential output, whose unblinded amount, if
+    /// known, is `amount`; `None` for an explicit output
+    pub value_commitment: Option<PedersenCommitment>,
+    /// Number of blocks confirming the output, 0 while in the mempool
+    pub confirmations: u32,
+    /// Height of the block confirming the output, if confirmed
+    pub block_height: Option<u32>,
+}
+
+impl Utxo {
+    /// Create an unconfirmed UTXO with an explicit amount
+    ///
+    /// Set `confirmations` and `block_height` once the output is confirmed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    ///
+    /// let asset = confidential::Asset::Explicit(elements::AssetId::default());
+    /// let utxo = Utxo::new(Txid::all_zeros(), 0, 10_000, Script::new(), asset);
+    /// assert_eq!(utxo.confirmations, 0);
+    /// ```
+    #[must_use]
+    pub fn new(
+        txid: Txid,
+        vout: u32,
+        amount: u64,
+        script_pubkey: elements::Script,
+        asset: elements::confidential::Asset,
+    ) -> Self {
+        Self {
+            txid,
+            vout,
+            amount,
+            script_pubkey,
+            asset,
+            value_commitment: None,
+            confirmations: 0,
+            block_height: None,
+        }
+    }
+
+    /// Get the value of the output as it is on chain
+    ///
+    /// Sighashes commit to the spent outputs, so a confidential output must
+    /// be described by its commitment rather than its unblinded amount.
+    #[must_use]
+    pub fn value(&self) -> elements::confidential::Value {
+        self.value_commitment.map_or(
+            elements::confidential::Value::Explicit(self.amount),
+            elements::confidential::Value::Confidential,
+        )
+    }
+
+    /// Count the confirmations of a UTXO with a `block_height`, given the
+    /// height of the chain tip
+    pub(crate) fn set_confirmations(&mut self, tip_height: u64) {
+        self.confirmations = self.block_height.map_or(0, |height| {
+            let confirmations = (tip_height + 1).saturating_sub(u64::from(height));
+            u32::try_from(confirmations).unwrap_or(u32::MAX)
+        });
+    }
 }
 
 impl From<elements::TxOut> for Utxo {
@@ -28,14 +94,99 @@ impl From<elements::TxOut> for Utxo {
             },
             script_pubkey: txout.script_pubkey,
             asset: txout.asset,
+            value_commitment: txout.value.commitment(),
+            confirmations: 0,
+            block_height: None,
         }
     }
 }
 
+/// Mempool statistics of a node
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MempoolInfo {
+    /// Number of transactions in the mempool
+    pub size: u64,
+    /// Total virtual size of the mempool's transactions in vbytes
+    pub vsize: u64,
+    /// Minimum fee rate in sat/vB for a transaction to be accepted, if the
+    /// backend reports it
+    pub min_fee_rate: Option<f64>,
+}
+
+/// A node's reason for rejecting a transaction from its mempool
+///
+/// Each variant holds the node's own reason, e.g. `min relay fee not met`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The fee is below the node's minimum
+    FeeTooLow(String),
+    /// The script or Simplicity program of an input failed
+    ScriptFailure(String),
+    /// An input is unknown or already spent
+    MissingInputs(String),
+    /// Any other reason, e.g. a conflict or a non-standard transaction
+    Other(String),
+}
+
+impl RejectReason {
+    /// Classify a reject reason reported by an Elements node
+    #[must_use]
+    pub fn from_node_reason(reason: &str) -> Self {
+        let reason_owned = reason.to_string();
+        if reason.contains("min relay fee not met")
+            || reason.contains("mempool min fee not met")
+            || reason.contains("insufficient fee")
+        {
+            Self::FeeTooLow(reason_owned)
+        } else if reason.contains("script-verify-flag") {
+            Self::ScriptFailure(reason_owned)
+        } else if reason.contains("missing-inputs") || reason.contains("missingorspent") {
+            Self::MissingInputs(reason_owned)
+        } else {
+            Self::Other(reason_owned)
+        }
+    }
+
+    /// Get the node's reason
+    #[must_use]
+    pub fn reason(&self) -> &str {
+        match self {
+            Self::FeeTooLow(reason)
+            | Self::ScriptFailure(reason)
+            | Self::MissingInputs(reason)
+            | Self::Other(reason) => reason,
+        }
+    }
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
 /// Abstract interface for interacting with Elements nodes
 ///
 /// This trait allows musk to work with different network backends
 /// (regtest, testnet, mainnet) through a unified interface.
+///
+/// The trait is object safe, so applications can pick a backend at runtime
+/// as a `Box<dyn NodeClient>` or `Arc<dyn NodeClient>`. References, boxes
+/// and reference-counted pointers to a client are clients too.
+///
+/// # Examples
+///
+/// ```ignore
+/// use musk::{NodeClient, RpcClient};
+/// use std::sync::Arc;
+///
+/// let client: Arc<dyn NodeClient + Send + Sync> = if light {
+///     Arc::new(musk::EsploraClient::liquid_testnet())
+/// } else {
+///     Arc::new(RpcClient::from_url(url, user, password)?)
+/// };
+/// let utxos = client.get_utxos(&address)?;
+/// ```
 pub trait NodeClient {
     /// Send funds to an address
     ///
@@ -58,6 +209,38 @@ pub trait NodeClient {
     /// Returns an error if the broadcast fails or the transaction is rejected.
     fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid>;
 
+    /// Check whether the node would accept a transaction into its mempool,
+    /// without broadcasting it
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::TransactionRejected`] with the node's reason
+    /// if it would reject the transaction, or another error if the check
+    /// fails or is not supported.
+    fn test_accept(&self, tx: &Transaction) -> ClientResult<()> {
+        let _ = tx;
+        Err(unsupported("test_accept"))
+    }
+
+    /// Broadcast a transaction after checking that the node accepts it
+    ///
+    /// A rejection is reported with the node's reason, as by
+    /// [`test_accept`](Self::test_accept), rather than as a generic broadcast
+    /// error. Clients that cannot test acceptance broadcast directly.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::TransactionRejected`] if the node would reject
+    /// the transaction, or another error if the broadcast fails.
+    fn broadcast_checked(&self, tx: &Transaction) -> ClientResult<Txid> {
+        match self.test_accept(tx) {
+            Ok(()) => {}
+            Err(ProgramError::IoError(e)) if e.kind() == std::io::ErrorKind::Unsupported => {}
+            Err(e) => return Err(e),
+        }
+        self.broadcast(tx)
+    }
+
     /// Generate blocks (regtest only)
     ///
     /// # Errors
@@ -72,10 +255,274 @@ pub trait NodeClient {
     /// Returns an error if the RPC call fails or the response is invalid.
     fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>>;
 
+    /// Get UTXOs for an address with at least `min_conf` confirmations
+    ///
+    /// Spending only confirmed outputs avoids building on transactions that
+    /// may be replaced or reorganized away. The default filters `get_utxos`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the RPC call fails or the response is invalid.
+    fn get_utxos_with_min_conf(&self, address: &Address, min_conf: u32) -> ClientResult<Vec<Utxo>> {
+        let mut utxos = self.get_utxos(address)?;
+        utxos.retain(|utxo| utxo.confirmations >= min_conf);
+        Ok(utxos)
+    }
+
     /// Get a new address from the wallet
     ///
     /// # Errors
     ///
     /// Returns an error if the RPC call fails or the address is invalid.
     fn get_new_address(&self) -> ClientResult<Address>;
+
+    /// Watch an address in the wallet, so its UTXOs show up in `get_utxos`
+    ///
+    /// Clients without a wallet may leave the default, which is unsupported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the import fails or is not supported.
+    fn import_address(&self, address: &Address) -> ClientResult<()> {
+        Err(unsupported(&format!("import_address (address {address})")))
+    }
+
+    /// Import the secret blinding key of a confidential address, so the
+    /// wallet can unblind outputs sent to it
+    ///
+    /// Clients without a wallet may leave the default, which is unsupported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the import fails or is not supported.
+    fn import_blinding_key(&self, address: &Address, blinding_key: &SecretKey) -> ClientResult<()> {
+        let _ = blinding_key;
+        Err(unsupported(&format!(
+            "import_blinding_key (address {address})"
+        )))
+    }
+
+    /// Get the number of blocks in the best chain, i.e. the tip height
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails or is not supported.
+    fn get_block_count(&self) -> ClientResult<u64> {
+        Err(unsupported("get_block_count"))
+    }
+
+    /// Get the hash of the block at `height` in the best chain
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such block, or the query fails or is
+    /// not supported.
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        let _ = height;
+        Err(unsupported("get_block_hash"))
+    }
+
+    /// Estimate the fee rate in sat/vB for confirmation within
+    /// `target_blocks` blocks
+    ///
+    /// The result can be passed to `SpendBuilder::estimate_fee`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no estimate is available, or the query fails or is
+    /// not supported.
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        let _ = target_blocks;
+        Err(unsupported("estimate_fee_rate"))
+    }
+
+    /// Get statistics of the node's mempool
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query fails or is not supported.
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        Err(unsupported("get_mempool_info"))
+    }
+}
+
+/// Implement `NodeClient` for pointers to a client, forwarding every
+/// method so the client's own overrides are used
+macro_rules! forward_node_client {
+    ($($pointer:ty),*) => {$(
+        impl<T: NodeClient + ?Sized> NodeClient for $pointer {
+            fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+                (**self).send_to_address(addr, amount)
+            }
+
+            fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+                (**self).get_transaction(txid)
+            }
+
+            fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+                (**self).broadcast(tx)
+            }
+
+            fn test_accept(&self, tx: &Transaction) -> ClientResult<()> {
+                (**self).test_accept(tx)
+            }
+
+            fn broadcast_checked(&self, tx: &Transaction) -> ClientResult<Txid> {
+                (**self).broadcast_checked(tx)
+            }
+
+            fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+                (**self).generate_blocks(count)
+            }
+
+            fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+                (**self).get_utxos(address)
+            }
+
+            fn get_utxos_with_min_conf(
+                &self,
+                address: &Address,
+                min_conf: u32,
+            ) -> ClientResult<Vec<Utxo>> {
+                (**self).get_utxos_with_min_conf(address, min_conf)
+            }
+
+            fn get_new_address(&self) -> ClientResult<Address> {
+                (**self).get_new_address()
+            }
+
+            fn import_address(&self, address: &Address) -> ClientResult<()> {
+                (**self).import_address(address)
+            }
+
+            fn import_blinding_key(
+                &self,
+                address: &Address,
+                blinding_key: &SecretKey,
+            ) -> ClientResult<()> {
+                (**self).import_blinding_key(address, blinding_key)
+            }
+
+            fn get_block_count(&self) -> ClientResult<u64> {
+                (**self).get_block_count()
+            }
+
+            fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+                (**self).get_block_hash(height)
+            }
+
+            fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+                (**self).estimate_fee_rate(target_blocks)
+            }
+
+            fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+                (**self).get_mempool_info()
+            }
+        }
+    )*};
+}
+
+forward_node_client!(&T, Box<T>, std::rc::Rc<T>, std::sync::Arc<T>);
+
+/// Error for a backend failure without a more specific [`ProgramError`]
+pub(crate) fn other_error(message: impl Into<String>) -> ProgramError {
+    ProgramError::IoError(std::io::Error::other(message.into()))
+}
+
+/// Reason for a rejection relayed by a light client server
+///
+/// Esplora and electrs pass on the node's error, e.g.
+/// `sendrawtransaction RPC error: {"code":-26,"message":"min relay fee not
+/// met, 100 < 250"}`, whose message is the node's reason.
+#[cfg(any(feature = "esplora", feature = "electrum"))]
+pub(crate) fn relayed_reject_reason(error: &str) -> RejectReason {
+    let error = error.trim();
+    let reason = error
+        .find('{')
+        .and_then(|start| serde_json::from_str::<serde_json::Value>(&error[start..]).ok())
+        .and_then(|error| error.get("message")?.as_str().map(str::to_string))
+        .unwrap_or_else(|| error.to_string());
+    RejectReason::from_node_reason(&reason)
+}
+
+/// Error for a method the client's backend cannot serve
+pub(crate) fn unsupported(method: &str) -> ProgramError {
+    ProgramError::IoError(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("{method} is not supported by this client"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::test_address;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_client_behind_pointers() {
+        let mock = MockClient::new();
+        let clients: Vec<Box<dyn NodeClient>> = vec![
+            Box::new(mock.clone()),
+            Box::new(Arc::new(mock.clone())),
+            Box::new(Box::new(mock.clone()) as Box<dyn NodeClient>),
+        ];
+
+        for client in &clients {
+            client.send_to_address(&test_address(), 1_000).unwrap();
+            // Overridden methods reach the mock rather than the defaults
+            client.import_address(&test_address()).unwrap();
+            assert!(client.get_block_count().is_ok());
+        }
+        assert_eq!(mock.get_utxos(&test_address()).unwrap().len(), 3);
+        assert!(mock.is_imported(&test_address()));
+
+        let shared: Arc<dyn NodeClient + Send + Sync> = Arc::new(mock);
+        assert!(std::thread::spawn(move || shared.get_block_count())
+            .join()
+            .unwrap()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_reject_reason_from_node_reason() {
+        assert!(matches!(
+            RejectReason::from_node_reason("min relay fee not met, 100 < 250"),
+            RejectReason::FeeTooLow(_)
+        ));
+        let script = RejectReason::from_node_reason(
+            "mandatory-script-verify-flag-failed (Simplicity execution failed)",
+        );
+        assert!(matches!(script, RejectReason::ScriptFailure(_)));
+        assert_eq!(
+            script.to_string(),
+            "mandatory-script-verify-flag-failed (Simplicity execution failed)"
+        );
+        assert!(matches!(
+            RejectReason::from_node_reason("missing-inputs"),
+            RejectReason::MissingInputs(_)
+        ));
+        assert!(matches!(
+            RejectReason::from_node_reason("txn-mempool-conflict"),
+            RejectReason::Other(_)
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "esplora", feature = "electrum"))]
+    fn test_relayed_reject_reason() {
+        let error = concat!(
+            "sendrawtransaction RPC error: ",
+            r#"{"code":-26,"message":"min relay fee not met, 100 < 250"}"#
+        );
+        assert_eq!(
+            relayed_reject_reason(error),
+            RejectReason::FeeTooLow("min relay fee not met, 100 < 250".to_string())
+        );
+        assert_eq!(
+            relayed_reject_reason("bad-txns-inputs-missingorspent\n"),
+            RejectReason::MissingInputs("bad-txns-inputs-missingorspent".to_string())
+        );
+    }
 }
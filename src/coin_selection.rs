@@ -0,0 +1,566 @@
+//! Coin selection strategies
+//!
+//! A [`CoinSelection`] strategy picks which UTXOs to spend to cover a target
+//! amount. Strategies operate on a single asset: filter candidates with
+//! [`utxos_of_asset`] first. Wrap a strategy in [`ZeroConfFiltered`] to only
+//! select unconfirmed UTXOs a [`ZeroConfPolicy`] accepts. Use
+//! `SpendBuilder::select` to build a spend from the selected UTXOs.
+//!
+//! For user funds, prefer [`PrivacyFirst`]: the other strategies only look
+//! at amounts, so they readily link unrelated addresses in one spend.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::coin_selection::{CoinSelection, LargestFirst};
+//! use musk::client::Utxo;
+//! use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+//!
+//! let utxo = |amount| Utxo {
+//!     txid: Txid::all_zeros(),
+//!     vout: 0,
+//!     amount,
+//!     script_pubkey: Script::new(),
+//!     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+//!     value_commitment: None,
+//!     confirmations: 0,
+//!     block_height: None,
+//! };
+//!
+//! let selected = LargestFirst.select(&[utxo(1_000), utxo(5_000), utxo(3_000)], 6_000).unwrap();
+//! assert_eq!(selected.iter().map(|utxo| utxo.amount).collect::<Vec<_>>(), [5_000, 3_000]);
+//! ```
+
+use crate::client::Utxo;
+use crate::error::SpendError;
+use crate::policy::ZeroConfPolicy;
+use elements::{confidential, AssetId, Txid};
+use std::collections::HashSet;
+
+/// Strategy for choosing UTXOs to cover a target amount
+pub trait CoinSelection {
+    /// Select UTXOs whose amounts sum to at least `target`
+    ///
+    /// # Errors
+    ///
+    /// Returns `SpendError::InsufficientFunds` if the candidates cannot cover `target`.
+    fn select(&self, utxos: &[Utxo], target: u64) -> Result<Vec<Utxo>, SpendError>;
+}
+
+/// Select the largest UTXOs first until the target is covered
+///
+/// Minimizes the number of inputs, at the cost of usually creating change.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl CoinSelection for LargestFirst {
+    fn select(&self, utxos: &[Utxo], target: u64) -> Result<Vec<Utxo>, SpendError> {
+        let mut sorted: Vec<&Utxo> = utxos.iter().collect();
+        sorted.sort_by_key(|utxo| std::cmp::Reverse(utxo.amount));
+
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for utxo in sorted {
+            if total >= target && !selected.is_empty() {
+                break;
+            }
+            total += utxo.amount;
+            selected.push(utxo.clone());
+        }
+
+        if total < target || selected.is_empty() {
+            return Err(insufficient(utxos, target));
+        }
+        Ok(selected)
+    }
+}
+
+/// Select every UTXO, e.g. to sweep or consolidate an address
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrainAll;
+
+impl CoinSelection for DrainAll {
+    fn select(&self, utxos: &[Utxo], target: u64) -> Result<Vec<Utxo>, SpendError> {
+        if utxos.is_empty() || total_amount(utxos) < target {
+            return Err(insufficient(utxos, target));
+        }
+        Ok(utxos.to_vec())
+    }
+}
+
+/// Search for a set of UTXOs matching the target without change
+///
+/// Accepts any set whose total lies in `[target, target + cost_of_change]`,
+/// where the excess is cheaper to give up as fee than to spend on a change
+/// output. Falls back to [`LargestFirst`] if no such set is found within
+/// `max_tries` search steps.
+#[derive(Debug, Clone, Copy)]
+pub struct BranchAndBound {
+    /// Largest excess over the target that is acceptable without change
+    pub cost_of_change: u64,
+    /// Maximum number of search steps before falling back
+    pub max_tries: usize,
+}
+
+impl Default for BranchAndBound {
+    fn default() -> Self {
+        Self {
+            cost_of_change: crate::spend::DUST_THRESHOLD,
+            max_tries: 100_000,
+        }
+    }
+}
+
+impl BranchAndBound {
+    /// Depth-first search over include/exclude decisions, largest UTXOs first
+    fn search(&self, sorted: &[&Utxo], target: u64) -> Option<Vec<usize>> {
+        let upper = target.saturating_add(self.cost_of_change);
+        // remaining[i] is the sum of sorted[i..]
+        let mut remaining = vec![0u64; sorted.len() + 1];
+        for i in (0..sorted.len()).rev() {
+            remaining[i] = remaining[i + 1] + sorted[i].amount;
+        }
+
+        let mut tries = 0;
+        let mut selection = Vec::new();
+        let mut best: Option<(u64, Vec<usize>)> = None;
+        let mut stack = vec![(0usize, 0u64, false)];
+
+        // Each frame is (next index, current total, whether to pop the last pick first)
+        while let Some((index, total, backtrack)) = stack.pop() {
+            if backtrack {
+                selection.pop();
+                continue;
+            }
+            tries += 1;
+            if tries > self.max_tries {
+                break;
+            }
+            if total > upper || total + remaining[index] < target {
+                continue;
+            }
+            if total >= target {
+                if best
+                    .as_ref()
+                    .map_or(true, |(waste, _)| total - target < *waste)
+                {
+                    best = Some((total - target, selection.clone()));
+                }
+                if total == target {
+                    break;
+                }
+                continue;
+            }
+            if index == sorted.len() {
+                continue;
+            }
+
+            // Explore "exclude" after "include", so push it first
+            stack.push((index + 1, total, false));
+            stack.push((0, 0, true));
+            stack.push((index + 1, total + sorted[index].amount, false));
+            selection.push(index);
+        }
+
+        best.map(|(_, selection)| selection)
+    }
+}
+
+impl CoinSelection for BranchAndBound {
+    fn select(&self, utxos: &[Utxo], target: u64) -> Result<Vec<Utxo>, SpendError> {
+        let mut sorted: Vec<&Utxo> = utxos.iter().collect();
+        sorted.sort_by_key(|utxo| std::cmp::Reverse(utxo.amount));
+
+        match self.search(&sorted, target) {
+            Some(selection) if !selection.is_empty() => Ok(selection
+                .into_iter()
+                .map(|index| sorted[index].clone())
+                .collect()),
+            _ => LargestFirst.select(utxos, target),
+        }
+    }
+}
+
+/// Select whole addresses, so spends link as few addresses as possible
+///
+/// UTXOs are grouped by scriptPubkey and explicit asset, with confidential
+/// UTXOs of an address forming one group since their asset is hidden. The
+/// smallest group covering the target is spent entirely, draining e.g. a
+/// contract address instead of leaving part of it behind to be linked by a
+/// later spend. Only if no single group covers the target are whole groups
+/// of the same asset combined, largest first. Groups are never combined
+/// across explicit assets.
+///
+/// With `prefer_confidential` set, e.g. when paying confidential outputs,
+/// confidential groups are preferred over explicit ones, whose amounts would
+/// reveal what the blinded outputs hide.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrivacyFirst {
+    /// Prefer confidential UTXOs over explicit ones
+    pub prefer_confidential: bool,
+}
+
+impl PrivacyFirst {
+    /// Rank a selection: preferred kind first, then fewest addresses, then least excess
+    fn rank(&self, groups: &[&UtxoGroup<'_>]) -> (bool, usize, u64) {
+        let confidential = groups.iter().all(|group| group.asset.is_none());
+        (
+            confidential != self.prefer_confidential,
+            groups.len(),
+            groups.iter().map(|group| group.total).sum(),
+        )
+    }
+}
+
+/// UTXOs of one scriptPubkey and explicit asset (`None` if confidential)
+struct UtxoGroup<'a> {
+    script_pubkey: &'a elements::Script,
+    asset: Option<AssetId>,
+    utxos: Vec<&'a Utxo>,
+    total: u64,
+}
+
+impl CoinSelection for PrivacyFirst {
+    fn select(&self, utxos: &[Utxo], target: u64) -> Result<Vec<Utxo>, SpendError> {
+        let mut groups: Vec<UtxoGroup<'_>> = Vec::new();
+        for utxo in utxos {
+            let asset = utxo.asset.explicit();
+            match groups
+                .iter_mut()
+                .find(|group| *group.script_pubkey == utxo.script_pubkey && group.asset == asset)
+            {
+                Some(group) => {
+                    group.utxos.push(utxo);
+                    group.total += utxo.amount;
+                }
+                None => groups.push(UtxoGroup {
+                    script_pubkey: &utxo.script_pubkey,
+                    asset,
+                    utxos: vec![utxo],
+                    total: utxo.amount,
+                }),
+            }
+        }
+
+        // For each asset, the largest groups of that asset covering the target
+        let mut assets: Vec<Option<AssetId>> = Vec::new();
+        for group in &groups {
+            if !assets.contains(&group.asset) {
+                assets.push(group.asset);
+            }
+        }
+        let candidates = assets.into_iter().filter_map(|asset| {
+            let mut same_asset: Vec<&UtxoGroup<'_>> =
+                groups.iter().filter(|group| group.asset == asset).collect();
+            // A single covering group is always preferred, the smallest one
+            if let Some(single) = same_asset
+                .iter()
+                .filter(|group| group.total >= target)
+                .min_by_key(|group| group.total)
+            {
+                return Some(vec![*single]);
+            }
+            same_asset.sort_by_key(|group| std::cmp::Reverse(group.total));
+            let mut total = 0;
+            let count = same_asset.iter().position(|group| {
+                total += group.total;
+                total >= target
+            })?;
+            same_asset.truncate(count + 1);
+            Some(same_asset)
+        });
+
+        let selection = candidates
+            .min_by_key(|selection| self.rank(selection))
+            .ok_or_else(|| insufficient(utxos, target))?;
+        Ok(selection
+            .into_iter()
+            .flat_map(|group| group.utxos.iter().map(|&utxo| utxo.clone()))
+            .collect())
+    }
+}
+
+/// Run a strategy on only the UTXOs a [`ZeroConfPolicy`] accepts
+///
+/// The status of a UTXO comes from its `confirmations`. Unconfirmed outputs
+/// of the transactions given to [`Self::with_own_transactions`] count as
+/// created by us, e.g. change.
+///
+/// # Examples
+///
+/// ```
+/// use musk::coin_selection::{CoinSelection, LargestFirst, ZeroConfFiltered};
+/// use musk::client::Utxo;
+/// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+/// use musk::{ZeroConf, ZeroConfPolicy};
+///
+/// let utxo = |amount, confirmations| Utxo {
+///     txid: Txid::all_zeros(),
+///     vout: 0,
+///     amount,
+///     script_pubkey: Script::new(),
+///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+///     value_commitment: None,
+///     confirmations,
+///     block_height: None,
+/// };
+///
+/// let strategy = ZeroConfFiltered::new(LargestFirst, ZeroConfPolicy::new(ZeroConf::Never));
+/// let selected = strategy.select(&[utxo(9_000, 0), utxo(4_000, 2)], 3_000).unwrap();
+/// assert_eq!(selected[0].amount, 4_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZeroConfFiltered<S> {
+    strategy: S,
+    policy: ZeroConfPolicy,
+    own_txids: HashSet<Txid>,
+}
+
+impl<S: CoinSelection> ZeroConfFiltered<S> {
+    /// Apply `policy` to the candidates of `strategy`
+    #[must_use]
+    pub fn new(strategy: S, policy: ZeroConfPolicy) -> Self {
+        Self {
+            strategy,
+            policy,
+            own_txids: HashSet::new(),
+        }
+    }
+
+    /// Treat the outputs of these transactions as created by us
+    #[must_use]
+    pub fn with_own_transactions(mut self, txids: impl IntoIterator<Item = Txid>) -> Self {
+        self.own_txids.extend(txids);
+        self
+    }
+
+    /// Get the policy applied to the candidates
+    #[must_use]
+    pub const fn policy(&self) -> &ZeroConfPolicy {
+        &self.policy
+    }
+}
+
+impl<S: CoinSelection> CoinSelection for ZeroConfFiltered<S> {
+    fn select(&self, utxos: &[Utxo], target: u64) -> Result<Vec<Utxo>, SpendError> {
+        let accepted: Vec<Utxo> = utxos
+            .iter()
+            .filter(|utxo| {
+                self.policy
+                    .accepts(utxo, self.own_txids.contains(&utxo.txid))
+            })
+            .cloned()
+            .collect();
+        self.strategy.select(&accepted, target)
+    }
+}
+
+/// Keep only the UTXOs holding explicit amounts of `asset`
+#[must_use]
+pub fn utxos_of_asset(utxos: &[Utxo], asset: AssetId) -> Vec<Utxo> {
+    utxos
+        .iter()
+        .filter(|utxo| utxo.asset == confidential::Asset::Explicit(asset))
+        .cloned()
+        .collect()
+}
+
+fn total_amount(utxos: &[Utxo]) -> u64 {
+    utxos.iter().map(|utxo| utxo.amount).sum()
+}
+
+fn insufficient(utxos: &[Utxo], target: u64) -> SpendError {
+    SpendError::InsufficientFunds {
+        needed: target,
+        available: total_amount(utxos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::test_utxo;
+
+    fn utxos(amounts: &[u64]) -> Vec<Utxo> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(vout, &amount)| Utxo {
+                vout: u32::try_from(vout).unwrap(),
+                amount,
+                ..test_utxo()
+            })
+            .collect()
+    }
+
+    fn amounts(selected: &[Utxo]) -> Vec<u64> {
+        selected.iter().map(|utxo| utxo.amount).collect()
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let candidates = utxos(&[1_000, 7_000, 3_000]);
+        assert_eq!(
+            amounts(&LargestFirst.select(&candidates, 8_000).unwrap()),
+            [7_000, 3_000]
+        );
+        assert!(matches!(
+            LargestFirst.select(&candidates, 20_000),
+            Err(SpendError::InsufficientFunds {
+                needed: 20_000,
+                available: 11_000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_drain_all() {
+        let candidates = utxos(&[1_000, 7_000]);
+        assert_eq!(DrainAll.select(&candidates, 0).unwrap().len(), 2);
+        assert!(DrainAll.select(&[], 0).is_err());
+    }
+
+    #[test]
+    fn test_branch_and_bound_exact_match() {
+        let candidates = utxos(&[10_000, 6_000, 5_000, 2_000]);
+        let strategy = BranchAndBound {
+            cost_of_change: 0,
+            ..BranchAndBound::default()
+        };
+        let mut selected = amounts(&strategy.select(&candidates, 8_000).unwrap());
+        selected.sort_unstable();
+        assert_eq!(selected, [2_000, 6_000]);
+    }
+
+    #[test]
+    fn test_branch_and_bound_within_cost_of_change() {
+        let candidates = utxos(&[10_000, 6_100, 2_000]);
+        let strategy = BranchAndBound {
+            cost_of_change: 200,
+            ..BranchAndBound::default()
+        };
+        let mut selected = amounts(&strategy.select(&candidates, 8_000).unwrap());
+        selected.sort_unstable();
+        assert_eq!(selected, [2_000, 6_100]);
+    }
+
+    #[test]
+    fn test_branch_and_bound_falls_back() {
+        let candidates = utxos(&[10_000, 7_000]);
+        let strategy = BranchAndBound {
+            cost_of_change: 0,
+            ..BranchAndBound::default()
+        };
+        assert_eq!(
+            amounts(&strategy.select(&candidates, 8_000).unwrap()),
+            [10_000]
+        );
+        assert!(strategy.select(&candidates, 30_000).is_err());
+    }
+
+    #[test]
+    fn test_privacy_first_drains_one_address() {
+        let address = |byte: u8| elements::Script::from(vec![0x51, byte]);
+        let at = |script_pubkey: elements::Script, vout, amount| Utxo {
+            vout,
+            amount,
+            script_pubkey,
+            ..test_utxo()
+        };
+        let candidates = vec![
+            at(address(1), 0, 9_000),
+            at(address(2), 1, 4_000),
+            at(address(2), 2, 3_000),
+            at(address(3), 3, 20_000),
+        ];
+
+        // Address 2 covers the target with the least excess and is drained
+        let selected = PrivacyFirst::default().select(&candidates, 6_000).unwrap();
+        assert_eq!(amounts(&selected), [4_000, 3_000]);
+
+        // No address covers the target: whole addresses are combined
+        let selected = PrivacyFirst::default().select(&candidates, 25_000).unwrap();
+        assert_eq!(amounts(&selected), [20_000, 9_000]);
+        assert!(PrivacyFirst::default().select(&candidates, 40_000).is_err());
+        assert!(PrivacyFirst::default().select(&[], 0).is_err());
+    }
+
+    #[test]
+    fn test_privacy_first_assets_and_confidentiality() {
+        let other = AssetId::from_slice(&[9; 32]).unwrap();
+        let blinded = confidential::Asset::new_confidential(
+            &secp256k1::Secp256k1::new(),
+            AssetId::default(),
+            confidential::AssetBlindingFactor::new(&mut secp256k1::rand::thread_rng()),
+        );
+        let candidates = vec![
+            Utxo {
+                vout: 0,
+                amount: 5_000,
+                ..test_utxo()
+            },
+            Utxo {
+                vout: 1,
+                amount: 5_000,
+                asset: confidential::Asset::Explicit(other),
+                ..test_utxo()
+            },
+            Utxo {
+                vout: 2,
+                amount: 8_000,
+                asset: blinded,
+                ..test_utxo()
+            },
+        ];
+
+        // Explicit assets are never combined
+        assert!(PrivacyFirst::default()
+            .select(&candidates[..2], 8_000)
+            .is_err());
+        assert_eq!(
+            PrivacyFirst::default().select(&candidates, 4_000).unwrap()[0].vout,
+            0
+        );
+        let strategy = PrivacyFirst {
+            prefer_confidential: true,
+        };
+        assert_eq!(strategy.select(&candidates, 4_000).unwrap()[0].vout, 2);
+    }
+
+    #[test]
+    fn test_zero_conf_filtered() {
+        use crate::policy::ZeroConf;
+        use elements::hashes::Hash;
+
+        let own = Txid::from_byte_array([1u8; 32]);
+        let mut candidates = utxos(&[9_000, 5_000, 2_000]);
+        candidates[1].txid = own;
+        candidates[2].confirmations = 3;
+
+        let never = ZeroConfFiltered::new(LargestFirst, ZeroConfPolicy::new(ZeroConf::Never));
+        assert_eq!(amounts(&never.select(&candidates, 1_000).unwrap()), [2_000]);
+        assert!(matches!(
+            never.select(&candidates, 3_000),
+            Err(SpendError::InsufficientFunds {
+                needed: 3_000,
+                available: 2_000
+            })
+        ));
+
+        let own_only =
+            ZeroConfFiltered::new(LargestFirst, ZeroConfPolicy::new(ZeroConf::OnlyFromSelf))
+                .with_own_transactions([own]);
+        assert_eq!(
+            amounts(&own_only.select(&candidates, 6_000).unwrap()),
+            [5_000, 2_000]
+        );
+    }
+
+    #[test]
+    fn test_utxos_of_asset() {
+        let mut other = test_utxo();
+        other.asset = confidential::Asset::Explicit(AssetId::from_slice(&[9; 32]).unwrap());
+        let filtered = utxos_of_asset(&[test_utxo(), other], AssetId::default());
+        assert_eq!(filtered.len(), 1);
+    }
+}
@@ -54,6 +54,19 @@ impl Network {
         }
     }
 
+    /// Whether nodes on this network relay ELIP-200 discounted confidential
+    /// transactions by default
+    ///
+    /// Liquid and Liquid testnet nodes accept discounted fees; regtest
+    /// nodes need `-acceptdiscountct=1`.
+    #[must_use]
+    pub const fn discounts_ct(self) -> bool {
+        match self {
+            Self::Regtest => false,
+            Self::Testnet | Self::Liquid => true,
+        }
+    }
+
     /// Get the default RPC URL for this network
     #[must_use]
     pub fn default_rpc_url(self) -> String {
@@ -322,5 +335,7 @@ genesis_hash = "abc123"
         assert_eq!(Network::Regtest.default_rpc_port(), 18884);
         assert_eq!(Network::Testnet.default_rpc_port(), 18892);
         assert_eq!(Network::Liquid.default_rpc_port(), 7041);
+        assert!(!Network::Regtest.discounts_ct());
+        assert!(Network::Liquid.discounts_ct());
     }
 }
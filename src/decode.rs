@@ -0,0 +1,204 @@
+//! Decoding Simplicity spends from transactions
+//!
+//! Indexers and compliance tooling see transactions, not builders. A
+//! Simplicity script-path spend reveals its program in the input witness:
+//! the stack is the encoded witness data, the encoded program, the leaf
+//! script (the program's CMR) and the control block. [`simplicity_spends`]
+//! extracts these from every input of a transaction, and
+//! [`SimplicitySpend::program`] looks the spent program up in a
+//! [`ProgramRegistry`].
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::client::Utxo;
+//! use musk::decode::simplicity_spends;
+//! use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+//! use musk::registry::ProgramRegistry;
+//! use musk::{Arguments, Program, SpendBuilder, WitnessValues};
+//!
+//! let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+//! let compiled = program.instantiate(Arguments::default()).unwrap();
+//! let utxo = Utxo {
+//!     txid: Txid::all_zeros(),
+//!     vout: 0,
+//!     amount: 100_000,
+//!     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+//!     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+//!     value_commitment: None,
+//!     confirmations: 0,
+//!     block_height: None,
+//! };
+//! let mut builder = SpendBuilder::try_new(compiled.clone(), utxo).unwrap()
+//!     .genesis_hash(elements::BlockHash::all_zeros());
+//! builder.add_output_simple(Script::new(), 99_000, elements::AssetId::default());
+//! builder.add_fee(1_000, elements::AssetId::default());
+//! let tx = builder.finalize(WitnessValues::default()).unwrap();
+//!
+//! let mut registry = ProgramRegistry::new();
+//! registry.register(compiled.clone());
+//!
+//! let spends = simplicity_spends(&tx);
+//! assert_eq!(spends[0].cmr, compiled.cmr());
+//! assert!(spends[0].program(&registry).is_some());
+//! ```
+
+use crate::program::InstantiatedProgram;
+use crate::registry::ProgramRegistry;
+use elements::taproot::ControlBlock;
+use elements::{Script, Transaction};
+use simplicityhl::simplicity::Cmr;
+
+/// Prefix of a taproot annex, the optional last witness element
+const ANNEX_TAG: u8 = 0x50;
+
+/// A Simplicity script-path spend found in a transaction input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimplicitySpend {
+    /// Index of the input
+    pub input: usize,
+    /// CMR of the spent program, committed to by the leaf script
+    pub cmr: Cmr,
+    /// Encoded Simplicity program
+    pub program: Vec<u8>,
+    /// Encoded witness data of the program
+    pub witness: Vec<u8>,
+    /// Leaf script
+    pub script: Script,
+    /// Control block proving the leaf is in the spent output's taproot tree
+    pub control_block: ControlBlock,
+}
+
+impl SimplicitySpend {
+    /// Parse the script witness of input `input`
+    ///
+    /// Returns `None` unless the witness is a taproot script-path spend of a
+    /// Simplicity leaf. An annex, if present, is ignored.
+    #[must_use]
+    pub fn from_witness(input: usize, witness: &[Vec<u8>]) -> Option<Self> {
+        let witness = match witness {
+            [rest @ .., annex] if rest.len() >= 2 && annex.first() == Some(&ANNEX_TAG) => rest,
+            _ => witness,
+        };
+        let [witness_bytes, program, script, control_block] = witness else {
+            return None;
+        };
+
+        let control_block = ControlBlock::from_slice(control_block).ok()?;
+        if control_block.leaf_version != simplicityhl::simplicity::leaf_version() {
+            return None;
+        }
+        let cmr = Cmr::from_byte_array(<[u8; 32]>::try_from(script.as_slice()).ok()?);
+
+        Some(Self {
+            input,
+            cmr,
+            program: program.clone(),
+            witness: witness_bytes.clone(),
+            script: Script::from(script.clone()),
+            control_block,
+        })
+    }
+
+    /// Look up the spent program among the registered programs
+    ///
+    /// The program must have this spend's leaf and control block, which also
+    /// tells apart programs sharing a CMR under different internal keys.
+    #[must_use]
+    pub fn program<'a>(&self, registry: &'a ProgramRegistry) -> Option<&'a InstantiatedProgram> {
+        registry.programs().find(|program| {
+            let script_version = program.script_version();
+            script_version.0 == self.script
+                && program
+                    .taproot_info()
+                    .control_block(&script_version)
+                    .as_ref()
+                    == Some(&self.control_block)
+        })
+    }
+}
+
+/// Find the Simplicity script-path spends among the inputs of `tx`
+///
+/// Key-path spends of program addresses reveal nothing and are not found.
+#[must_use]
+pub fn simplicity_spends(tx: &Transaction) -> Vec<SimplicitySpend> {
+    tx.input
+        .iter()
+        .enumerate()
+        .filter_map(|(index, input)| {
+            SimplicitySpend::from_witness(index, &input.witness.script_witness)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::spend::SpendBuilder;
+    use crate::test_fixtures::{test_genesis_hash, test_utxo, CAT_PROGRAM, SIMPLE_PROGRAM};
+    use crate::util::keypair_from_u32;
+    use simplicityhl::{Arguments, WitnessValues};
+
+    fn instantiate(source: &str) -> InstantiatedProgram {
+        Program::from_source(source)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_simplicity_spends() {
+        let simple = instantiate(SIMPLE_PROGRAM);
+        let cat = instantiate(CAT_PROGRAM);
+        let utxo = |program: &InstantiatedProgram, vout| crate::client::Utxo {
+            vout,
+            script_pubkey: program
+                .address(&elements::AddressParams::ELEMENTS)
+                .script_pubkey(),
+            ..test_utxo()
+        };
+
+        let mut builder = SpendBuilder::try_new(simple.clone(), utxo(&simple, 0))
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder
+            .add_program_input(cat.clone(), utxo(&cat, 1))
+            .unwrap();
+        builder.add_external_input(test_utxo());
+        builder.add_fee(1_000, elements::AssetId::default());
+        let tx = builder.finalize(WitnessValues::default()).unwrap();
+
+        let spends = simplicity_spends(&tx);
+        assert_eq!(spends.len(), 2);
+        assert_eq!((spends[0].input, spends[0].cmr), (0, simple.cmr()));
+        assert_eq!((spends[1].input, spends[1].cmr), (1, cat.cmr()));
+        assert_eq!(spends[1].script, cat.script_version().0);
+
+        let mut registry = ProgramRegistry::new();
+        registry.register(cat.clone());
+        assert!(spends[0].program(&registry).is_none());
+        assert_eq!(spends[1].program(&registry).unwrap().cmr(), cat.cmr());
+
+        // Same CMR, different internal key
+        let keyed = Program::from_source(CAT_PROGRAM)
+            .unwrap()
+            .instantiate_with_internal_key(
+                Arguments::default(),
+                keypair_from_u32(1).x_only_public_key().0,
+            )
+            .unwrap();
+        let mut other = ProgramRegistry::new();
+        other.register(keyed);
+        assert!(spends[1].program(&other).is_none());
+
+        let mut annexed = tx.input[0].witness.script_witness.clone();
+        annexed.push(vec![ANNEX_TAG, 1]);
+        assert_eq!(
+            SimplicitySpend::from_witness(0, &annexed),
+            Some(spends[0].clone())
+        );
+        assert!(SimplicitySpend::from_witness(0, &[vec![0; 64]]).is_none());
+    }
+}
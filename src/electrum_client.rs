@@ -0,0 +1,573 @@
+//! Electrum-based `NodeClient` implementation
+//!
+//! This module implements the `NodeClient` trait over the Electrum protocol,
+//! as served by electrs for Elements/Liquid, for wallets that already rely
+//! on that infrastructure. Connections are plain TCP or TLS.
+//!
+//! Besides the `NodeClient` queries, program addresses can be subscribed
+//! to, so the server notifies the client whenever their history changes.
+//! Electrum has no wallet, so `send_to_address`, `get_new_address` and
+//! `generate_blocks` are unsupported. A broadcast the node rejects fails with
+//! [`ProgramError::TransactionRejected`], as with `RpcClient`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use musk::ElectrumClient;
+//!
+//! let client = ElectrumClient::connect_ssl(
+//!     "blockstream.info",
+//!     995,
+//!     &elements::AddressParams::LIQUID,
+//! )?;
+//! let address = compiled_program.address(client.address_params());
+//! client.subscribe_address(&address)?;
+//!
+//! // Later, check whether anything was sent to the program
+//! for status in client.poll_notifications()? {
+//!     let utxos = client.get_utxos(&address)?;
+//! }
+//! ```
+
+use crate::client::{
+    other_error, relayed_reject_reason, unsupported, ClientResult, NodeClient, Utxo,
+};
+use crate::error::ProgramError;
+use elements::encode::{deserialize, serialize_hex};
+use elements::hashes::{sha256, Hash};
+use elements::hex::{FromHex, ToHex};
+use elements::{Address, AddressParams, BlockHash, BlockHeader, Script, Transaction, Txid};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default read timeout of connections
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Method of scripthash status notifications
+const SCRIPTHASH_SUBSCRIBE: &str = "blockchain.scripthash.subscribe";
+
+/// A byte stream to an Electrum server
+trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Transport for T {}
+
+/// A change in the history of a subscribed script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptStatus {
+    /// Electrum scripthash of the script, see [`script_hash`]
+    pub script_hash: String,
+    /// Hash of the script's new history, or `None` if it has none
+    pub status: Option<String>,
+}
+
+/// Electrum protocol client for electrs servers
+pub struct ElectrumClient {
+    connection: Mutex<Connection>,
+    params: &'static AddressParams,
+}
+
+struct Connection {
+    stream: Box<dyn Transport>,
+    buffer: Vec<u8>,
+    next_id: u64,
+    notifications: VecDeque<ScriptStatus>,
+}
+
+impl ElectrumClient {
+    /// Connect to an Electrum server over plain TCP
+    ///
+    /// `address` is `host:port`, and `params` are the address params of the
+    /// server's network.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails.
+    pub fn connect(address: &str, params: &'static AddressParams) -> ClientResult<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        Ok(Self::from_stream(stream, params))
+    }
+
+    /// Connect to an Electrum server over TLS
+    ///
+    /// The server's certificate is checked against the webpki root
+    /// certificates.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or `host` is not a valid
+    /// server name.
+    pub fn connect_ssl(
+        host: &str,
+        port: u16,
+        params: &'static AddressParams,
+    ) -> ClientResult<Self> {
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = rustls::pki_types::ServerName::try_from(host.to_string())
+            .map_err(|e| other_error(format!("Invalid server name: {e}")))?;
+        let tls = rustls::ClientConnection::new(Arc::new(config), server_name)
+            .map_err(|e| other_error(format!("TLS error: {e}")))?;
+
+        let stream = TcpStream::connect((host, port))?;
+        stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        Ok(Self::from_stream(
+            rustls::StreamOwned::new(tls, stream),
+            params,
+        ))
+    }
+
+    fn from_stream<S: Read + Write + Send + 'static>(
+        stream: S,
+        params: &'static AddressParams,
+    ) -> Self {
+        Self {
+            connection: Mutex::new(Connection {
+                stream: Box::new(stream),
+                buffer: Vec::new(),
+                next_id: 0,
+                notifications: VecDeque::new(),
+            }),
+            params,
+        }
+    }
+
+    /// Get the network address params
+    #[must_use]
+    pub const fn address_params(&self) -> &'static AddressParams {
+        self.params
+    }
+
+    /// Subscribe to changes in the history of `script_pubkey`
+    ///
+    /// Returns the current status of the script, `None` if it has no
+    /// history. Later changes are returned by
+    /// [`poll_notifications`](Self::poll_notifications).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub fn subscribe_script(&self, script_pubkey: &Script) -> ClientResult<Option<String>> {
+        let status = self.call(SCRIPTHASH_SUBSCRIBE, &[script_hash(script_pubkey).into()])?;
+        Ok(status.as_str().map(str::to_string))
+    }
+
+    /// Subscribe to changes in the history of an address, e.g. a program's
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    pub fn subscribe_address(&self, address: &Address) -> ClientResult<Option<String>> {
+        self.subscribe_script(&address.script_pubkey())
+    }
+
+    /// Take the notifications of subscribed scripts received so far
+    ///
+    /// Pings the server first, so notifications sent since the last request
+    /// are received.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ping fails.
+    pub fn poll_notifications(&self) -> ClientResult<Vec<ScriptStatus>> {
+        self.call("server.ping", &[])?;
+        let mut connection = self.connection.lock().expect("connection lock poisoned");
+        Ok(connection.notifications.drain(..).collect())
+    }
+
+    /// Make a request and wait for its result
+    fn call(&self, method: &str, params: &[serde_json::Value]) -> ClientResult<serde_json::Value> {
+        self.request(method, params)?
+            .map_err(|error| other_error(format!("Electrum error: {error}")))
+    }
+
+    /// Make a request and wait for its result or the server's error
+    fn request(
+        &self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> ClientResult<Result<serde_json::Value, serde_json::Value>> {
+        let mut connection = self.connection.lock().expect("connection lock poisoned");
+        connection.request(method, params)
+    }
+}
+
+impl Connection {
+    fn request(
+        &mut self,
+        method: &str,
+        params: &[serde_json::Value],
+    ) -> ClientResult<Result<serde_json::Value, serde_json::Value>> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        let mut line = request.to_string().into_bytes();
+        line.push(b'\n');
+        self.stream.write_all(&line)?;
+        self.stream.flush()?;
+
+        loop {
+            let mut response: serde_json::Value = serde_json::from_slice(&self.read_line()?)
+                .map_err(|e| other_error(format!("Invalid Electrum response: {e}")))?;
+
+            if response.get("method").and_then(serde_json::Value::as_str)
+                == Some(SCRIPTHASH_SUBSCRIBE)
+            {
+                self.queue_notification(&response);
+                continue;
+            }
+            if response.get("id").and_then(serde_json::Value::as_u64) != Some(id) {
+                // Other notifications, and responses to abandoned requests
+                continue;
+            }
+            if let Some(error) = response.get_mut("error").filter(|error| !error.is_null()) {
+                return Ok(Err(error.take()));
+            }
+            return Ok(Ok(response
+                .get_mut("result")
+                .map(serde_json::Value::take)
+                .unwrap_or_default()));
+        }
+    }
+
+    fn queue_notification(&mut self, notification: &serde_json::Value) {
+        let params = notification
+            .get("params")
+            .and_then(serde_json::Value::as_array);
+        if let Some([script_hash, status]) = params.map(Vec::as_slice) {
+            if let Some(script_hash) = script_hash.as_str() {
+                self.notifications.push_back(ScriptStatus {
+                    script_hash: script_hash.to_string(),
+                    status: status.as_str().map(str::to_string),
+                });
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> ClientResult<Vec<u8>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+                return Ok(self.buffer.drain(..=end).collect());
+            }
+            let mut chunk = [0u8; 4096];
+            let read = self.stream.read(&mut chunk)?;
+            if read == 0 {
+                return Err(ProgramError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Electrum server closed the connection",
+                )));
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+impl NodeClient for ElectrumClient {
+    fn send_to_address(&self, addr: &Address, _amount: u64) -> ClientResult<Txid> {
+        Err(unsupported(&format!("send_to_address (address {addr})")))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+        let tx_hex = self.call("blockchain.transaction.get", &[txid.to_string().into()])?;
+        let tx_hex = tx_hex
+            .as_str()
+            .ok_or_else(|| other_error("Invalid transaction response"))?;
+        let tx_bytes =
+            Vec::<u8>::from_hex(tx_hex).map_err(|e| other_error(format!("Invalid hex: {e}")))?;
+        deserialize(&tx_bytes)
+            .map_err(|e| other_error(format!("Failed to deserialize transaction: {e}")))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+        let txid = self
+            .request(
+                "blockchain.transaction.broadcast",
+                &[serialize_hex(tx).into()],
+            )?
+            .map_err(|error| {
+                // The server relays the node's reason as the error message
+                let message = error
+                    .get("message")
+                    .and_then(serde_json::Value::as_str)
+                    .map_or_else(|| error.to_string(), str::to_string);
+                ProgramError::TransactionRejected(relayed_reject_reason(&message))
+            })?;
+        txid.as_str()
+            .ok_or_else(|| other_error("Invalid broadcast response"))
+            .and_then(|txid| {
+                Txid::from_str(txid).map_err(|e| other_error(format!("Invalid txid: {e}")))
+            })
+    }
+
+    fn generate_blocks(&self, _count: u32) -> ClientResult<Vec<BlockHash>> {
+        Err(unsupported("generate_blocks"))
+    }
+
+    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+        let script_hash = script_hash(&address.script_pubkey());
+        let unspent = self.call("blockchain.scripthash.listunspent", &[script_hash.into()])?;
+        let unspent = unspent
+            .as_array()
+            .ok_or_else(|| other_error("Invalid listunspent response"))?;
+
+        // electrs only reports explicit values, so read outputs from their
+        // transactions, fetching each one once
+        let mut transactions = HashMap::new();
+        let mut utxos = Vec::new();
+        for item in unspent {
+            let txid = item
+                .get("tx_hash")
+                .and_then(serde_json::Value::as_str)
+                .ok_or_else(|| other_error("Missing tx_hash in listunspent"))
+                .and_then(|s| {
+                    Txid::from_str(s).map_err(|e| other_error(format!("Invalid txid: {e}")))
+                })?;
+            let vout = item
+                .get("tx_pos")
+                .and_then(serde_json::Value::as_u64)
+                .and_then(|vout| u32::try_from(vout).ok())
+                .ok_or_else(|| other_error("Missing tx_pos in listunspent"))?;
+            // Unconfirmed outputs have a height of 0, or -1 with unconfirmed parents
+            let block_height = item
+                .get("height")
+                .and_then(serde_json::Value::as_u64)
+                .filter(|height| *height > 0)
+                .and_then(|height| u32::try_from(height).ok());
+
+            if !transactions.contains_key(&txid) {
+                transactions.insert(txid, self.get_transaction(&txid)?);
+            }
+            let txout = transactions[&txid]
+                .output
+                .get(vout as usize)
+                .cloned()
+                .ok_or_else(|| other_error(format!("Output {txid}:{vout} does not exist")))?;
+
+            utxos.push(Utxo {
+                txid,
+                vout,
+                block_height,
+                ..Utxo::from(txout)
+            });
+        }
+
+        if utxos.iter().any(|utxo| utxo.block_height.is_some()) {
+            let tip_height = self.get_block_count()?;
+            for utxo in &mut utxos {
+                utxo.set_confirmations(tip_height);
+            }
+        }
+        Ok(utxos)
+    }
+
+    fn get_new_address(&self) -> ClientResult<Address> {
+        Err(unsupported("get_new_address"))
+    }
+
+    fn import_address(&self, _address: &Address) -> ClientResult<()> {
+        // electrs indexes every script
+        Ok(())
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        let header = self.call("blockchain.headers.subscribe", &[])?;
+        header
+            .get("height")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| other_error("Missing height in header"))
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        let header = self.call("blockchain.block.header", &[height.into()])?;
+        let header = header
+            .as_str()
+            .ok_or_else(|| other_error("Invalid block header response"))?;
+        let header_bytes =
+            Vec::<u8>::from_hex(header).map_err(|e| other_error(format!("Invalid hex: {e}")))?;
+        let header: BlockHeader = deserialize(&header_bytes)
+            .map_err(|e| other_error(format!("Failed to deserialize block header: {e}")))?;
+        Ok(header.block_hash())
+    }
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        // The server estimates in BTC/kvB, or -1 without enough data
+        let btc_per_kvb = self
+            .call("blockchain.estimatefee", &[target_blocks.into()])?
+            .as_f64()
+            .filter(|rate| *rate >= 0.0)
+            .ok_or_else(|| other_error(format!("No fee estimate for {target_blocks} blocks")))?;
+        Ok(btc_per_kvb * 100_000.0)
+    }
+}
+
+impl std::fmt::Debug for ElectrumClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ElectrumClient")
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Compute the Electrum scripthash of a script
+///
+/// This is the SHA256 of the script, hex encoded in reverse byte order.
+#[must_use]
+pub fn script_hash(script_pubkey: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script_pubkey.as_bytes()).to_byte_array();
+    hash.reverse();
+    hash.to_hex()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RejectReason;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::test_address;
+    use std::io::Cursor;
+
+    /// A server replaying canned response lines
+    struct FakeServer {
+        responses: Cursor<Vec<u8>>,
+        requests: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Read for FakeServer {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.responses.read(buf)
+        }
+    }
+
+    impl Write for FakeServer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.requests.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn fake_client(responses: &[serde_json::Value]) -> (ElectrumClient, Arc<Mutex<Vec<u8>>>) {
+        let lines: String = responses.iter().map(|r| format!("{r}\n")).collect();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server = FakeServer {
+            responses: Cursor::new(lines.into_bytes()),
+            requests: Arc::clone(&requests),
+        };
+        (
+            ElectrumClient::from_stream(server, &AddressParams::ELEMENTS),
+            requests,
+        )
+    }
+
+    #[test]
+    fn test_script_hash() {
+        // Example from the Electrum protocol documentation
+        let script = Script::from(
+            Vec::<u8>::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap(),
+        );
+        assert_eq!(
+            script_hash(&script),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
+    }
+
+    #[test]
+    fn test_get_utxos_reads_outputs() {
+        let address = test_address();
+        let mock = MockClient::new();
+        let txid = mock.send_to_address(&address, 50_000).unwrap();
+        let tx = mock.get_transaction(&txid).unwrap();
+        let (client, requests) = fake_client(&[
+            serde_json::json!({"jsonrpc": "2.0", "id": 0, "result": [
+                {"tx_hash": txid.to_string(), "tx_pos": 0, "height": 10},
+            ]}),
+            serde_json::json!({"jsonrpc": "2.0", "id": 1, "result": serialize_hex(&tx)}),
+            serde_json::json!({"jsonrpc": "2.0", "id": 2, "result": {"height": 12, "hex": ""}}),
+        ]);
+
+        let utxos = client.get_utxos(&address).unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].txid, txid);
+        assert_eq!(utxos[0].vout, 0);
+        assert_eq!(utxos[0].amount, 50_000);
+        assert_eq!(utxos[0].block_height, Some(10));
+        assert_eq!(utxos[0].confirmations, 3);
+        assert_eq!(utxos[0].script_pubkey, address.script_pubkey());
+
+        let requests = String::from_utf8(requests.lock().unwrap().clone()).unwrap();
+        assert!(requests.contains(&script_hash(&address.script_pubkey())));
+    }
+
+    #[test]
+    fn test_broadcast_rejection() {
+        let mock = MockClient::new();
+        let txid = mock.send_to_address(&test_address(), 50_000).unwrap();
+        let tx = mock.get_transaction(&txid).unwrap();
+        let rejection = concat!(
+            "sendrawtransaction RPC error: ",
+            r#"{"code":-26,"message":"min relay fee not met, 100 < 250"}"#
+        );
+        let (client, _) = fake_client(&[
+            serde_json::json!({"jsonrpc": "2.0", "id": 0, "result": txid.to_string()}),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {"code": 1, "message": rejection},
+            }),
+        ]);
+
+        assert_eq!(client.broadcast(&tx).unwrap(), txid);
+        let err = client.broadcast(&tx).unwrap_err();
+        assert!(matches!(
+            err,
+            ProgramError::TransactionRejected(RejectReason::FeeTooLow(reason))
+                if reason == "min relay fee not met, 100 < 250"
+        ));
+    }
+
+    #[test]
+    fn test_subscription_notifications() {
+        let address = test_address();
+        let hash = script_hash(&address.script_pubkey());
+        let (client, _) = fake_client(&[
+            serde_json::json!({"jsonrpc": "2.0", "id": 0, "result": null}),
+            // Notification arriving before the ping's response
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": SCRIPTHASH_SUBSCRIBE,
+                "params": [hash, "abcd"],
+            }),
+            serde_json::json!({"jsonrpc": "2.0", "id": 1, "result": null}),
+            serde_json::json!({"jsonrpc": "2.0", "id": 2, "error": {"code": 1, "message": "x"}}),
+        ]);
+
+        assert_eq!(client.subscribe_address(&address).unwrap(), None);
+        assert_eq!(
+            client.poll_notifications().unwrap(),
+            vec![ScriptStatus {
+                script_hash: hash,
+                status: Some("abcd".to_string()),
+            }]
+        );
+        assert!(client.get_block_count().is_err());
+        // The server has nothing more to say
+        assert!(client.get_block_count().is_err());
+        assert!(client.get_new_address().is_err());
+    }
+}
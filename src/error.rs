@@ -17,11 +17,39 @@ pub enum ProgramError {
     #[error("Failed to satisfy program: {0}")]
     SatisfactionError(String),
 
+    #[error(
+        "Witness does not match the program: missing {missing:?}, unexpected {unexpected:?}, \
+         mistyped {mistyped:?}"
+    )]
+    WitnessMismatch {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+        mistyped: Vec<String>,
+    },
+
+    #[error("Invalid witness preset: {0}")]
+    InvalidPreset(String),
+
+    #[error("Invalid witness name {0:?}")]
+    InvalidWitnessName(String),
+
+    #[error("Invalid witness value: {0}")]
+    InvalidWitness(String),
+
+    #[error("Conflicting values for witnesses {names:?}")]
+    WitnessConflict { names: Vec<String> },
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
     #[error("Invalid taproot configuration: {0}")]
     TaprootError(String),
+
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+
+    #[error("Transaction rejected: {0}")]
+    TransactionRejected(crate::client::RejectReason),
 }
 
 /// Errors that can occur during spending operations
@@ -39,6 +67,25 @@ pub enum SpendError {
     #[error("Invalid UTXO: {0}")]
     InvalidUtxo(String),
 
+    #[error("Insufficient funds: need {needed}, have {available}")]
+    InsufficientFunds { needed: u64, available: u64 },
+
+    #[error("Asset {asset} does not balance: {inputs} in, {outputs} out")]
+    AssetImbalance {
+        asset: elements::AssetId,
+        inputs: u64,
+        outputs: u64,
+    },
+
+    #[error("Failed to sign: {0}")]
+    SigningError(String),
+
+    #[error("Transaction failed verification: {0}")]
+    VerificationError(String),
+
+    #[error("UTXOs of inputs {indices:?} are not locked by their program")]
+    ProgramMismatch { indices: Vec<usize> },
+
     #[error("Program error: {0}")]
     ProgramError(#[from] ProgramError),
 
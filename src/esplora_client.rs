@@ -0,0 +1,434 @@
+//! Esplora-based `NodeClient` implementation for light clients
+//!
+//! This module implements the `NodeClient` trait on top of the HTTP API of
+//! an Esplora/Electrs server, so contracts can be deployed and spent
+//! without running a full Elements node with a wallet. Esplora indexes
+//! every address, so no import is needed before querying UTXOs.
+//!
+//! Esplora has no wallet, so `send_to_address`, `get_new_address` and
+//! `generate_blocks` are unsupported; fund program addresses from an
+//! external wallet instead. A broadcast the node rejects fails with
+//! [`ProgramError::TransactionRejected`], as with `RpcClient`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use musk::EsploraClient;
+//!
+//! let client = EsploraClient::liquid_testnet();
+//! let address = compiled_program.address(client.address_params());
+//! let utxos = client.get_utxos(&address)?;
+//!
+//! let builder = SpendBuilder::try_new(compiled_program, utxos[0].clone())?
+//!     .genesis_hash(client.genesis_hash()?);
+//! let txid = client.broadcast(&builder.finalize(witness_values)?)?;
+//! ```
+
+use crate::client::{
+    other_error, relayed_reject_reason, unsupported, ClientResult, MempoolInfo, NodeClient, Utxo,
+};
+use crate::error::ProgramError;
+use elements::encode::{deserialize, serialize_hex};
+use elements::hex::FromHex;
+use elements::{confidential, Address, AddressParams, BlockHash, Transaction, Txid};
+use std::str::FromStr;
+
+/// Base URL of Blockstream's Esplora instance for Liquid
+pub const LIQUID_URL: &str = "https://blockstream.info/liquid/api";
+
+/// Base URL of Blockstream's Esplora instance for the Liquid testnet
+pub const LIQUID_TESTNET_URL: &str = "https://blockstream.info/liquidtestnet/api";
+
+/// Default request timeout in seconds
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// HTTP client for Esplora/Electrs servers
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    base_url: String,
+    params: &'static AddressParams,
+    timeout: u64,
+}
+
+impl EsploraClient {
+    /// Create a client for the Esplora API at `base_url`
+    ///
+    /// `params` are the address params of the server's network, and
+    /// `base_url` is the API root, e.g. `http://localhost:3000`.
+    #[must_use]
+    pub fn new(base_url: &str, params: &'static AddressParams) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            params,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Create a client for Blockstream's Liquid API
+    #[must_use]
+    pub fn liquid() -> Self {
+        Self::new(LIQUID_URL, &AddressParams::LIQUID)
+    }
+
+    /// Create a client for Blockstream's Liquid testnet API
+    #[must_use]
+    pub fn liquid_testnet() -> Self {
+        Self::new(LIQUID_TESTNET_URL, &AddressParams::LIQUID_TESTNET)
+    }
+
+    /// Set the request timeout in seconds
+    #[must_use]
+    pub const fn with_timeout(mut self, seconds: u64) -> Self {
+        self.timeout = seconds;
+        self
+    }
+
+    /// Get the base URL of the API
+    #[must_use]
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Get the network address params
+    #[must_use]
+    pub const fn address_params(&self) -> &'static AddressParams {
+        self.params
+    }
+
+    /// Get the genesis hash of the server's chain
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the response is invalid.
+    pub fn genesis_hash(&self) -> ClientResult<BlockHash> {
+        self.get_block_hash(0)
+    }
+
+    /// Make a GET request, returning the response body
+    fn get(&self, path: &str) -> ClientResult<String> {
+        let request = minreq::get(format!("{}{path}", self.base_url));
+        self.send(request)
+    }
+
+    /// Make a request, failing unless the response status is 2xx
+    fn send(&self, request: minreq::Request) -> ClientResult<String> {
+        let (status, body) = self.send_any(request)?;
+        if !(200..300).contains(&status) {
+            return Err(other_error(format!(
+                "Esplora error {status}: {}",
+                body.trim()
+            )));
+        }
+        Ok(body)
+    }
+
+    /// Make a request, returning the response status and body
+    fn send_any(&self, request: minreq::Request) -> ClientResult<(i32, String)> {
+        let response = request
+            .with_timeout(self.timeout)
+            .send()
+            .map_err(|e| match e {
+                minreq::Error::IoError(e) => ProgramError::IoError(std::io::Error::new(
+                    e.kind(),
+                    format!("Esplora request failed: {e}"),
+                )),
+                e => other_error(format!("Esplora request failed: {e}")),
+            })?;
+        let body = response
+            .as_str()
+            .map_err(|e| other_error(format!("Invalid Esplora response: {e}")))?;
+        Ok((response.status_code, body.to_string()))
+    }
+}
+
+impl NodeClient for EsploraClient {
+    fn send_to_address(&self, addr: &Address, _amount: u64) -> ClientResult<Txid> {
+        Err(unsupported(&format!("send_to_address (address {addr})")))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+        let tx_hex = self.get(&format!("/tx/{txid}/hex"))?;
+        let tx_bytes = Vec::<u8>::from_hex(tx_hex.trim())
+            .map_err(|e| other_error(format!("Invalid hex: {e}")))?;
+        deserialize(&tx_bytes)
+            .map_err(|e| other_error(format!("Failed to deserialize transaction: {e}")))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+        let request = minreq::post(format!("{}/tx", self.base_url)).with_body(serialize_hex(tx));
+        let (status, body) = self.send_any(request)?;
+        parse_broadcast(status, &body)
+    }
+
+    fn generate_blocks(&self, _count: u32) -> ClientResult<Vec<BlockHash>> {
+        Err(unsupported("generate_blocks"))
+    }
+
+    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+        let json = self.get(&format!("/address/{address}/utxo"))?;
+        let mut utxos = parse_utxos(&json, address)?;
+        if utxos.iter().any(|utxo| utxo.block_height.is_some()) {
+            let tip_height = self.get_block_count()?;
+            for utxo in &mut utxos {
+                utxo.set_confirmations(tip_height);
+            }
+        }
+        Ok(utxos)
+    }
+
+    fn get_new_address(&self) -> ClientResult<Address> {
+        Err(unsupported("get_new_address"))
+    }
+
+    fn import_address(&self, _address: &Address) -> ClientResult<()> {
+        // Esplora indexes every address
+        Ok(())
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        let height = self.get("/blocks/tip/height")?;
+        height
+            .trim()
+            .parse()
+            .map_err(|e| other_error(format!("Invalid tip height: {e}")))
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        let hash = self.get(&format!("/block-height/{height}"))?;
+        BlockHash::from_str(hash.trim())
+            .map_err(|e| other_error(format!("Invalid block hash: {e}")))
+    }
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        let json = self.get("/fee-estimates")?;
+        parse_fee_estimate(&json, target_blocks)
+    }
+
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        let json = self.get("/mempool")?;
+        let mempool: serde_json::Value = serde_json::from_str(&json)
+            .map_err(|e| other_error(format!("Invalid mempool: {e}")))?;
+        let field = |name: &str| {
+            mempool
+                .get(name)
+                .and_then(serde_json::Value::as_u64)
+                .ok_or_else(|| other_error(format!("Missing {name} in mempool")))
+        };
+
+        Ok(MempoolInfo {
+            size: field("count")?,
+            vsize: field("vsize")?,
+            min_fee_rate: None,
+        })
+    }
+}
+
+/// Parse the response of `GET /fee-estimates` for `target_blocks`
+///
+/// Esplora only estimates some targets, so this takes the estimate of the
+/// largest target not above `target_blocks`.
+fn parse_fee_estimate(json: &str, target_blocks: u16) -> ClientResult<f64> {
+    let estimates: std::collections::HashMap<String, f64> = serde_json::from_str(json)
+        .map_err(|e| other_error(format!("Invalid fee estimates: {e}")))?;
+    estimates
+        .iter()
+        .filter_map(|(target, rate)| Some((target.parse::<u16>().ok()?, *rate)))
+        .filter(|(target, _)| *target <= target_blocks)
+        .max_by_key(|(target, _)| *target)
+        .map(|(_, rate)| rate)
+        .ok_or_else(|| other_error(format!("No fee estimate for {target_blocks} blocks")))
+}
+
+/// Parse the response of `GET /address/:address/utxo`
+///
+/// Confidential outputs have an amount of zero and a confidential asset,
+/// as for `Utxo::from`. Confirmations are left for the caller to count.
+fn parse_utxos(json: &str, address: &Address) -> ClientResult<Vec<Utxo>> {
+    let items: Vec<serde_json::Value> =
+        serde_json::from_str(json).map_err(|e| other_error(format!("Invalid UTXO list: {e}")))?;
+
+    items
+        .iter()
+        .map(|item| {
+            let txid = item
+                .get("txid")
+                .and_then(serde_json::Value::as_str)
+                .ok_or_else(|| other_error("Missing txid in UTXO"))
+                .and_then(|s| {
+                    Txid::from_str(s).map_err(|e| other_error(format!("Invalid txid: {e}")))
+                })?;
+            let vout = item
+                .get("vout")
+                .and_then(serde_json::Value::as_u64)
+                .and_then(|vout| u32::try_from(vout).ok())
+                .ok_or_else(|| other_error("Missing vout in UTXO"))?;
+            let amount = item
+                .get("value")
+                .and_then(serde_json::Value::as_u64)
+                .unwrap_or(0);
+            let block_height = item
+                .get("status")
+                .filter(|status| status.get("confirmed") == Some(&serde_json::Value::Bool(true)))
+                .and_then(|status| status.get("block_height"))
+                .and_then(serde_json::Value::as_u64)
+                .and_then(|height| u32::try_from(height).ok());
+
+            let asset = if let Some(asset) = item.get("asset").and_then(serde_json::Value::as_str) {
+                let asset_id = elements::AssetId::from_str(asset)
+                    .map_err(|e| other_error(format!("Invalid asset id: {e}")))?;
+                confidential::Asset::Explicit(asset_id)
+            } else if let Some(commitment) = item
+                .get("assetcommitment")
+                .and_then(serde_json::Value::as_str)
+            {
+                let bytes = Vec::<u8>::from_hex(commitment)
+                    .map_err(|e| other_error(format!("Invalid asset commitment: {e}")))?;
+                deserialize(&bytes)
+                    .map_err(|e| other_error(format!("Invalid asset commitment: {e}")))?
+            } else {
+                confidential::Asset::Null
+            };
+            let value_commitment = item
+                .get("valuecommitment")
+                .and_then(serde_json::Value::as_str)
+                .map(|commitment| {
+                    let bytes = Vec::<u8>::from_hex(commitment)
+                        .map_err(|e| other_error(format!("Invalid value commitment: {e}")))?;
+                    deserialize::<confidential::Value>(&bytes)
+                        .ok()
+                        .and_then(|value| value.commitment())
+                        .ok_or_else(|| other_error("Invalid value commitment"))
+                })
+                .transpose()?;
+
+            Ok(Utxo {
+                txid,
+                vout,
+                amount,
+                script_pubkey: address.script_pubkey(),
+                asset,
+                value_commitment,
+                confirmations: 0,
+                block_height,
+            })
+        })
+        .collect()
+}
+
+/// Parse the response to a broadcast into the txid
+fn parse_broadcast(status: i32, body: &str) -> ClientResult<Txid> {
+    // Esplora answers 400 with the node's reason when the node rejects the
+    // transaction; other failures, e.g. of a proxy, are not rejections
+    if status == 400 {
+        return Err(ProgramError::TransactionRejected(relayed_reject_reason(
+            body,
+        )));
+    }
+    if !(200..300).contains(&status) {
+        return Err(other_error(format!(
+            "Esplora error {status}: {}",
+            body.trim()
+        )));
+    }
+    Txid::from_str(body.trim()).map_err(|e| other_error(format!("Invalid txid: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::test_address;
+
+    const TXID: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+    const ASSET: &str = "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+
+    #[test]
+    fn test_parse_utxos() {
+        let address = test_address();
+        let secp = secp256k1::Secp256k1::new();
+        let blinded = confidential::Asset::new_confidential(
+            &secp,
+            elements::AssetId::default(),
+            confidential::AssetBlindingFactor::new(&mut secp256k1::rand::thread_rng()),
+        );
+        let value = confidential::Value::new_confidential(
+            &secp,
+            5_000,
+            blinded.commitment().unwrap(),
+            confidential::ValueBlindingFactor::new(&mut secp256k1::rand::thread_rng()),
+        );
+        let json = format!(
+            r#"[
+                {{"txid": "{TXID}", "vout": 1, "value": 100000, "asset": "{ASSET}",
+                  "status": {{"confirmed": true, "block_height": 10}}}},
+                {{"txid": "{TXID}", "vout": 2,
+                  "valuecommitment": "{v}", "assetcommitment": "{a}",
+                  "status": {{"confirmed": false}}}}
+            ]"#,
+            v = serialize_hex(&value),
+            a = serialize_hex(&blinded),
+        );
+
+        let utxos = parse_utxos(&json, &address).unwrap();
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos[0].txid, Txid::from_str(TXID).unwrap());
+        assert_eq!(utxos[0].vout, 1);
+        assert_eq!(utxos[0].amount, 100_000);
+        assert_eq!(
+            utxos[0].asset,
+            confidential::Asset::Explicit(elements::AssetId::from_str(ASSET).unwrap())
+        );
+        assert_eq!(utxos[0].script_pubkey, address.script_pubkey());
+        assert_eq!(utxos[0].block_height, Some(10));
+        assert_eq!(utxos[0].value_commitment, None);
+        assert_eq!(utxos[1].amount, 0);
+        assert_eq!(utxos[1].block_height, None);
+        assert_eq!(utxos[1].asset, blinded);
+        assert_eq!(utxos[1].value(), value);
+
+        assert!(parse_utxos(r#"[{"vout": 0}]"#, &address).is_err());
+        assert!(parse_utxos("not json", &address).is_err());
+    }
+
+    #[test]
+    fn test_parse_fee_estimate() {
+        let json = r#"{"1": 20.5, "2": 10.0, "6": 1.5, "144": 0.1}"#;
+        assert_eq!(parse_fee_estimate(json, 1).unwrap(), 20.5);
+        assert_eq!(parse_fee_estimate(json, 5).unwrap(), 10.0);
+        assert_eq!(parse_fee_estimate(json, 1008).unwrap(), 0.1);
+        assert!(parse_fee_estimate(json, 0).is_err());
+        assert!(parse_fee_estimate("{}", 6).is_err());
+    }
+
+    #[test]
+    fn test_parse_broadcast() {
+        assert_eq!(
+            parse_broadcast(200, &format!("{TXID}\n")).unwrap(),
+            Txid::from_str(TXID).unwrap()
+        );
+        assert!(matches!(
+            parse_broadcast(400, "sendrawtransaction RPC error: txn-mempool-conflict"),
+            Err(ProgramError::TransactionRejected(_))
+        ));
+        for status in [404, 429, 502] {
+            assert!(matches!(
+                parse_broadcast(status, "Too Many Requests"),
+                Err(ProgramError::IoError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_base_url_and_wallet_methods() {
+        let client =
+            EsploraClient::new("http://localhost:3000/", &AddressParams::ELEMENTS).with_timeout(5);
+        assert_eq!(client.base_url(), "http://localhost:3000");
+        assert_eq!(client.address_params(), &AddressParams::ELEMENTS);
+
+        let err = client.get_new_address().unwrap_err();
+        assert!(
+            matches!(err, ProgramError::IoError(e) if e.kind() == std::io::ErrorKind::Unsupported)
+        );
+        assert!(client.generate_blocks(1).is_err());
+        assert!(client.import_address(&test_address()).is_ok());
+    }
+}
@@ -0,0 +1,559 @@
+//! Subscriptions to new blocks and transactions touching watched scripts
+//!
+//! Services deploying contracts need to react when a program is funded or
+//! spent. A [`Subscription`] yields [`ChainEvent`]s for new blocks and for
+//! transactions paying to or spending from the scripts of a [`Watchlist`]:
+//!
+//! - [`ZmqSubscription`] listens to the `rawblock` and `rawtx` ZMQ
+//!   notifications of Elements Core, enabled with `-zmqpubrawblock` and
+//!   `-zmqpubrawtx`, and sees transactions as soon as they enter the mempool.
+//! - [`PollingSubscription`] works with any `NodeClient`, by polling the tip
+//!   and the UTXOs of watched addresses at an interval.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use musk::events::{ChainEvent, Subscription, Watchlist, ZmqSubscription};
+//! use std::time::Duration;
+//!
+//! let mut watchlist = Watchlist::new();
+//! watchlist.watch_address(&compiled.address(client.address_params()));
+//!
+//! let mut events = ZmqSubscription::connect(
+//!     "127.0.0.1:28332",
+//!     "127.0.0.1:28333",
+//!     watchlist,
+//! )?;
+//! while let Some(event) = events.next_event(Duration::from_secs(60))? {
+//!     if let ChainEvent::Transaction(tx) = event {
+//!         println!("program touched by {}", tx.txid());
+//!     }
+//! }
+//! ```
+
+use crate::client::{other_error, ClientResult, NodeClient};
+use elements::encode::deserialize;
+use elements::{Address, Block, BlockHash, OutPoint, Script, Transaction, Txid};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// An event on the chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainEvent {
+    /// A block was connected at the tip
+    Block {
+        /// Height of the block
+        height: u64,
+        /// Hash of the block
+        hash: BlockHash,
+    },
+    /// A transaction paying to or spending from a watched script was seen,
+    /// in the mempool or in a block
+    Transaction(Transaction),
+    /// A watched output was spent by a transaction the subscription cannot
+    /// see, as when polling
+    Spent(OutPoint),
+}
+
+/// A source of chain events
+pub trait Subscription {
+    /// Wait up to `timeout` for the next event
+    ///
+    /// Returns `None` if no event arrived in time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection to the node fails.
+    fn next_event(&mut self, timeout: Duration) -> ClientResult<Option<ChainEvent>>;
+}
+
+/// Scripts, and the outputs paying to them, to watch for transactions
+#[derive(Debug, Clone, Default)]
+pub struct Watchlist {
+    addresses: Vec<Address>,
+    scripts: HashSet<Script>,
+    outpoints: HashSet<OutPoint>,
+}
+
+impl Watchlist {
+    /// Create an empty watchlist
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch an address, e.g. a program's
+    pub fn watch_address(&mut self, address: &Address) -> &mut Self {
+        if !self.addresses.contains(address) {
+            self.addresses.push(address.clone());
+        }
+        self.watch_script(address.script_pubkey())
+    }
+
+    /// Watch a scriptPubkey
+    ///
+    /// [`PollingSubscription`] only sees scripts watched by address.
+    pub fn watch_script(&mut self, script_pubkey: Script) -> &mut Self {
+        self.scripts.insert(script_pubkey);
+        self
+    }
+
+    /// Watch an existing output, so its spend is reported
+    pub fn watch_outpoint(&mut self, outpoint: OutPoint) -> &mut Self {
+        self.outpoints.insert(outpoint);
+        self
+    }
+
+    /// Get the watched addresses
+    #[must_use]
+    pub fn addresses(&self) -> &[Address] {
+        &self.addresses
+    }
+
+    /// Check whether `tx` pays to or spends from a watched script
+    ///
+    /// Outputs of `tx` paying to a watched script are watched from then on,
+    /// so the transaction spending them is reported too.
+    pub fn touches(&mut self, tx: &Transaction) -> bool {
+        let spends = tx
+            .input
+            .iter()
+            .any(|input| self.outpoints.contains(&input.previous_output));
+        let txid = tx.txid();
+        let mut pays = false;
+        for (vout, output) in (0u32..).zip(&tx.output) {
+            if self.scripts.contains(&output.script_pubkey) {
+                self.outpoints.insert(OutPoint::new(txid, vout));
+                pays = true;
+            }
+        }
+        spends || pays
+    }
+}
+
+/// Prefix of ZMQ messages carrying a serialized block
+const RAW_BLOCK_TOPIC: &[u8] = b"rawblock";
+
+/// Prefix of ZMQ messages carrying a serialized transaction
+const RAW_TX_TOPIC: &[u8] = b"rawtx";
+
+/// Largest ZMQ frame accepted, well above the largest block
+const MAX_FRAME_SIZE: u64 = 64 << 20;
+
+/// Subscription to the ZMQ notifications of an Elements node
+///
+/// Transactions are reported when they enter the mempool and not again when
+/// confirmed, and blocks when they are connected.
+#[derive(Debug)]
+pub struct ZmqSubscription {
+    receiver: Receiver<ClientResult<(Vec<u8>, Vec<u8>)>>,
+    streams: Vec<TcpStream>,
+    watchlist: Watchlist,
+    seen: HashSet<Txid>,
+}
+
+impl ZmqSubscription {
+    /// Connect to the `rawblock` and `rawtx` publishers of a node
+    ///
+    /// The endpoints are `host:port`, as in `-zmqpubrawblock` and
+    /// `-zmqpubrawtx` without the `tcp://` scheme, and may be the same.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if connecting or the ZMQ handshake fails.
+    pub fn connect(
+        block_endpoint: &str,
+        tx_endpoint: &str,
+        watchlist: Watchlist,
+    ) -> ClientResult<Self> {
+        let endpoints = if block_endpoint == tx_endpoint {
+            vec![(block_endpoint, vec![RAW_BLOCK_TOPIC, RAW_TX_TOPIC])]
+        } else {
+            vec![
+                (block_endpoint, vec![RAW_BLOCK_TOPIC]),
+                (tx_endpoint, vec![RAW_TX_TOPIC]),
+            ]
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let mut streams = Vec::new();
+        for (endpoint, topics) in endpoints {
+            let stream = zmq_subscribe(endpoint, &topics)?;
+            streams.push(stream.try_clone()?);
+            let sender = sender.clone();
+            std::thread::spawn(move || forward_messages(stream, &sender));
+        }
+
+        Ok(Self {
+            receiver,
+            streams,
+            watchlist,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Get the watchlist, with the outputs seen so far
+    #[must_use]
+    pub const fn watchlist(&self) -> &Watchlist {
+        &self.watchlist
+    }
+
+    /// Turn a ZMQ message into an event, if it is one
+    fn event(&mut self, topic: &[u8], body: &[u8]) -> ClientResult<Option<ChainEvent>> {
+        if topic == RAW_BLOCK_TOPIC {
+            let block: Block =
+                deserialize(body).map_err(|e| other_error(format!("Invalid ZMQ block: {e}")))?;
+            return Ok(Some(ChainEvent::Block {
+                height: u64::from(block.header.height),
+                hash: block.block_hash(),
+            }));
+        }
+        let tx: Transaction =
+            deserialize(body).map_err(|e| other_error(format!("Invalid ZMQ transaction: {e}")))?;
+        if self.watchlist.touches(&tx) && self.seen.insert(tx.txid()) {
+            Ok(Some(ChainEvent::Transaction(tx)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Subscription for ZmqSubscription {
+    fn next_event(&mut self, timeout: Duration) -> ClientResult<Option<ChainEvent>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let (topic, body) = match self.receiver.recv_timeout(remaining) {
+                Ok(message) => message?,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(other_error("ZMQ connections closed"));
+                }
+            };
+            if let Some(event) = self.event(&topic, &body)? {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+impl Drop for ZmqSubscription {
+    fn drop(&mut self) {
+        // Unblock the reader threads
+        for stream in &self.streams {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Connect a ZMTP 3.0 SUB socket with the NULL mechanism
+fn zmq_subscribe(endpoint: &str, topics: &[&[u8]]) -> ClientResult<TcpStream> {
+    let mut stream = TcpStream::connect(endpoint)?;
+
+    // Greeting: signature, version 3.0, NULL mechanism, not as server
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting)?;
+
+    let mut peer_greeting = [0u8; 64];
+    stream.read_exact(&mut peer_greeting)?;
+    if peer_greeting[0] != 0xff || peer_greeting[9] != 0x7f || peer_greeting[10] < 3 {
+        return Err(other_error(format!("{endpoint} does not speak ZMTP 3")));
+    }
+
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    ready.push(11);
+    ready.extend_from_slice(b"Socket-Type");
+    ready.extend_from_slice(&3u32.to_be_bytes());
+    ready.extend_from_slice(b"SUB");
+    write_frame(&mut stream, FLAG_COMMAND, &ready)?;
+
+    for topic in topics {
+        let mut subscribe = vec![1];
+        subscribe.extend_from_slice(topic);
+        write_frame(&mut stream, 0, &subscribe)?;
+    }
+    Ok(stream)
+}
+
+/// Frame flag of a message part followed by more parts
+const FLAG_MORE: u8 = 0x01;
+
+/// Frame flag of a frame with an 8-byte size
+const FLAG_LONG: u8 = 0x02;
+
+/// Frame flag of a command frame
+const FLAG_COMMAND: u8 = 0x04;
+
+fn write_frame(stream: &mut TcpStream, flags: u8, body: &[u8]) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(body.len() + 9);
+    if let Ok(size) = u8::try_from(body.len()) {
+        frame.extend_from_slice(&[flags, size]);
+    } else {
+        frame.push(flags | FLAG_LONG);
+        frame.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(body);
+    stream.write_all(&frame)
+}
+
+/// Read a frame, returning its flags and body
+fn read_frame(stream: &mut impl Read) -> ClientResult<(u8, Vec<u8>)> {
+    let mut flags = [0u8];
+    stream.read_exact(&mut flags)?;
+    let size = if flags[0] & FLAG_LONG == 0 {
+        let mut size = [0u8];
+        stream.read_exact(&mut size)?;
+        u64::from(size[0])
+    } else {
+        let mut size = [0u8; 8];
+        stream.read_exact(&mut size)?;
+        u64::from_be_bytes(size)
+    };
+    if size > MAX_FRAME_SIZE {
+        return Err(other_error(format!(
+            "ZMQ frame of {size} bytes is too large"
+        )));
+    }
+    let mut body = vec![0u8; usize::try_from(size).expect("frame size fits in usize")];
+    stream.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+/// Read the next message, skipping commands
+fn read_message(stream: &mut impl Read) -> ClientResult<Vec<Vec<u8>>> {
+    let mut parts = Vec::new();
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & FLAG_COMMAND != 0 {
+            continue;
+        }
+        parts.push(body);
+        if flags & FLAG_MORE == 0 {
+            return Ok(parts);
+        }
+    }
+}
+
+/// Send the topic and body of each notification to `sender`, until either
+/// side closes
+fn forward_messages(mut stream: TcpStream, sender: &Sender<ClientResult<(Vec<u8>, Vec<u8>)>>) {
+    loop {
+        // Notifications are topic, body and sequence number
+        let message = read_message(&mut stream).map(|mut parts| {
+            parts.truncate(2);
+            let body = parts.pop().unwrap_or_default();
+            (parts.pop().unwrap_or_default(), body)
+        });
+        let failed = message.is_err();
+        if sender.send(message).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Subscription polling a `NodeClient`
+///
+/// Each poll fetches the tip and the UTXOs of every watched address. New
+/// UTXOs are reported with their transaction, and spent ones as
+/// [`ChainEvent::Spent`], since the spending transaction is unknown.
+/// Scripts watched without an address are not seen.
+#[derive(Debug)]
+pub struct PollingSubscription<'a, C: NodeClient + ?Sized> {
+    client: &'a C,
+    watchlist: Watchlist,
+    interval: Duration,
+    height: u64,
+    utxos: HashMap<Address, HashSet<OutPoint>>,
+    pending: VecDeque<ChainEvent>,
+}
+
+impl<'a, C: NodeClient + ?Sized> PollingSubscription<'a, C> {
+    /// Start polling `client` every `interval`
+    ///
+    /// Blocks and UTXOs existing now are not reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the initial queries fail.
+    pub fn new(client: &'a C, watchlist: Watchlist, interval: Duration) -> ClientResult<Self> {
+        let mut utxos = HashMap::new();
+        for address in watchlist.addresses() {
+            utxos.insert(address.clone(), outpoints(client, address)?);
+        }
+        Ok(Self {
+            client,
+            height: client.get_block_count()?,
+            watchlist,
+            interval,
+            utxos,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Query the client and queue the events since the last poll
+    fn poll(&mut self) -> ClientResult<()> {
+        let height = self.client.get_block_count()?;
+        for height in self.height + 1..=height {
+            let hash = self.client.get_block_hash(height)?;
+            self.pending.push_back(ChainEvent::Block { height, hash });
+        }
+        self.height = self.height.max(height);
+
+        let mut txids = HashSet::new();
+        for address in self.watchlist.addresses() {
+            let current = outpoints(self.client, address)?;
+            let known = self.utxos.entry(address.clone()).or_default();
+            for outpoint in known.difference(&current) {
+                self.pending.push_back(ChainEvent::Spent(*outpoint));
+            }
+            for outpoint in current.difference(known) {
+                if txids.insert(outpoint.txid) {
+                    let tx = self.client.get_transaction(&outpoint.txid)?;
+                    self.pending.push_back(ChainEvent::Transaction(tx));
+                }
+            }
+            *known = current;
+        }
+        Ok(())
+    }
+}
+
+impl<C: NodeClient + ?Sized> Subscription for PollingSubscription<'_, C> {
+    fn next_event(&mut self, timeout: Duration) -> ClientResult<Option<ChainEvent>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            self.poll()?;
+            if !self.pending.is_empty() {
+                continue;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            std::thread::sleep(self.interval.min(remaining));
+        }
+    }
+}
+
+/// Get the outpoints of the UTXOs of `address`, confirmed or not
+fn outpoints<C: NodeClient + ?Sized>(
+    client: &C,
+    address: &Address,
+) -> ClientResult<HashSet<OutPoint>> {
+    Ok(client
+        .get_utxos_with_min_conf(address, 0)?
+        .into_iter()
+        .map(|utxo| OutPoint::new(utxo.txid, utxo.vout))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::test_address;
+    use elements::encode::serialize;
+    use std::net::TcpListener;
+
+    fn funding_tx(client: &MockClient, address: &Address) -> Transaction {
+        let txid = client.send_to_address(address, 1_000).unwrap();
+        client.get_transaction(&txid).unwrap()
+    }
+
+    #[test]
+    fn test_watchlist_follows_outputs() {
+        let client = MockClient::new();
+        let address = test_address();
+        let funding = funding_tx(&client, &address);
+        let unrelated = funding_tx(&client, &client.get_new_address().unwrap());
+
+        let mut watchlist = Watchlist::new();
+        watchlist.watch_address(&address);
+        assert!(!watchlist.touches(&unrelated));
+        assert!(watchlist.touches(&funding));
+
+        let mut spend = unrelated;
+        spend.input[0].previous_output = OutPoint::new(funding.txid(), 0);
+        assert!(watchlist.touches(&spend));
+    }
+
+    #[test]
+    fn test_polling_subscription() {
+        let client = MockClient::new();
+        let address = test_address();
+        let mut watchlist = Watchlist::new();
+        watchlist.watch_address(&address);
+
+        let mut events =
+            PollingSubscription::new(&client, watchlist, Duration::from_millis(1)).unwrap();
+        assert_eq!(events.next_event(Duration::ZERO).unwrap(), None);
+
+        let txid = client.send_to_address(&address, 1_000).unwrap();
+        let hashes = client.generate_blocks(1).unwrap();
+        assert_eq!(
+            events.next_event(Duration::ZERO).unwrap(),
+            Some(ChainEvent::Block {
+                height: 1,
+                hash: hashes[0],
+            })
+        );
+        match events.next_event(Duration::ZERO).unwrap() {
+            Some(ChainEvent::Transaction(tx)) => assert_eq!(tx.txid(), txid),
+            event => panic!("expected the funding transaction, got {event:?}"),
+        }
+        assert_eq!(events.next_event(Duration::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn test_zmq_subscription() {
+        let client = MockClient::new();
+        let address = test_address();
+        let funding = funding_tx(&client, &address);
+        let unrelated = funding_tx(&client, &client.get_new_address().unwrap());
+
+        // A publisher of the unrelated transaction, then the funding one twice
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let publisher = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 64];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&greeting).unwrap();
+
+            let (flags, ready) = read_frame(&mut stream).unwrap();
+            assert_eq!(flags, FLAG_COMMAND);
+            assert!(ready.ends_with(b"Socket-Type\0\0\0\x03SUB"));
+            assert_eq!(read_frame(&mut stream).unwrap().1, b"\x01rawblock");
+            assert_eq!(read_frame(&mut stream).unwrap().1, b"\x01rawtx");
+
+            for tx in [&unrelated, &funding, &funding] {
+                write_frame(&mut stream, FLAG_MORE, RAW_TX_TOPIC).unwrap();
+                write_frame(&mut stream, FLAG_MORE, &serialize(tx)).unwrap();
+                write_frame(&mut stream, 0, &0u32.to_le_bytes()).unwrap();
+            }
+            // Keep the connection open until joined
+            stream
+        });
+
+        let mut watchlist = Watchlist::new();
+        watchlist.watch_address(&address);
+        let mut events = ZmqSubscription::connect(&endpoint, &endpoint, watchlist).unwrap();
+        match events.next_event(Duration::from_secs(10)).unwrap() {
+            Some(ChainEvent::Transaction(tx)) => assert_eq!(tx.txid(), funding.txid()),
+            event => panic!("expected the funding transaction, got {event:?}"),
+        }
+        assert_eq!(events.next_event(Duration::from_millis(100)).unwrap(), None);
+        drop(publisher.join().unwrap());
+    }
+}
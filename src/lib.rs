@@ -17,7 +17,7 @@
 //! let address = compiled.address(&elements::AddressParams::ELEMENTS);
 //!
 //! // Build and sign a spending transaction
-//! let builder = SpendBuilder::new(compiled, utxo);
+//! let builder = SpendBuilder::try_new(compiled, utxo)?.genesis_hash(genesis_hash);
 //! let tx = builder.finalize(witness_values)?;
 //! ```
 //!
@@ -39,35 +39,83 @@
 //! let address = compiled.address(client.address_params());
 //! let txid = client.send_to_address(&address, 100_000_000)?;
 //! ```
+//!
+//! Light clients can use the `EsploraClient` instead, with the `esplora`
+//! feature, to query and broadcast through an Esplora/Electrs server, or
+//! the `ElectrumClient`, with the `electrum` feature, to do so over the
+//! Electrum protocol.
 
 pub mod address;
+pub mod batch;
+pub mod blinding;
+pub mod cached_client;
 pub mod client;
+pub mod coin_selection;
 #[cfg(feature = "rpc")]
 pub mod config;
+pub mod decode;
+#[cfg(feature = "electrum")]
+pub mod electrum_client;
 pub mod error;
+#[cfg(feature = "esplora")]
+pub mod esplora_client;
+pub mod events;
+pub mod musig;
+pub mod pegin;
+pub mod policy;
 pub mod program;
+#[cfg(feature = "serde")]
+pub mod pset;
+pub mod rate_limited_client;
+pub mod registry;
+pub mod retry_client;
 #[cfg(feature = "rpc")]
 pub mod rpc_client;
+pub mod scenarios;
+pub mod signer;
+pub mod signing_session;
 pub mod spend;
+pub mod taptree;
+pub mod timelock;
 pub mod util;
 pub mod witness;
+#[cfg(feature = "witness-store")]
+pub mod witness_store;
 
-#[cfg(test)]
-mod mock_client;
-#[cfg(test)]
-mod test_fixtures;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod mock_client;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_fixtures;
 
 // Re-export core types
+pub use cached_client::CachedClient;
 pub use client::NodeClient;
 pub use error::{ProgramError, SpendError};
-pub use program::{InstantiatedProgram, Program, SatisfiedProgram};
+pub use policy::{ZeroConf, ZeroConfPolicy};
+pub use program::{
+    ConfidentialAddress, InstantiatedProgram, NetworkAddresses, Program, SatisfiedProgram,
+};
+pub use rate_limited_client::RateLimitedClient;
+pub use registry::ProgramRegistry;
+pub use retry_client::{RetryClient, RetryPolicy};
 pub use spend::SpendBuilder;
+pub use taptree::{TaprootTree, TaprootTreeBuilder, TaprootTreeExport};
 
 // Re-export config and RPC client when feature is enabled
 #[cfg(feature = "rpc")]
 pub use config::{ConfigError, Network, NodeConfig, RpcConfig};
 #[cfg(feature = "rpc")]
-pub use rpc_client::RpcClient;
+pub use rpc_client::{RpcClient, ScanTarget};
+
+// Re-export the light clients when their features are enabled
+#[cfg(feature = "electrum")]
+pub use electrum_client::ElectrumClient;
+#[cfg(feature = "esplora")]
+pub use esplora_client::EsploraClient;
+
+// Re-export the mock client for downstream tests
+#[cfg(any(test, feature = "test-utils"))]
+pub use mock_client::MockClient;
 
 // Re-export SimplicityHL types for convenience
 pub use simplicityhl::str::WitnessName;
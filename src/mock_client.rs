@@ -1,13 +1,18 @@
 //! Mock NodeClient implementation for testing
+//!
+//! Available to other crates with the `test-utils` feature, so spend logic
+//! can be tested without a node.
 
-#![cfg(test)]
-
-use crate::client::{ClientResult, NodeClient, Utxo};
+use crate::client::{ClientResult, MempoolInfo, NodeClient, RejectReason, Utxo};
 use crate::error::ProgramError;
 use elements::{Address, BlockHash, Transaction, Txid};
+use secp256k1::SecretKey;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+/// Fee rate estimated by a new mock client, in sat/vB
+const DEFAULT_FEE_RATE: f64 = 0.1;
+
 /// Mock client for testing without a live node
 #[derive(Clone)]
 pub struct MockClient {
@@ -18,7 +23,13 @@ struct MockClientInner {
     transactions: HashMap<Txid, Transaction>,
     utxos: HashMap<Address, Vec<Utxo>>,
     block_count: u32,
+    block_hashes: Vec<BlockHash>,
+    mempool: Vec<Txid>,
+    fee_rate: Option<f64>,
+    reject_reason: Option<RejectReason>,
     genesis_hash: BlockHash,
+    imported_addresses: Vec<Address>,
+    blinding_keys: HashMap<Address, SecretKey>,
 }
 
 impl MockClient {
@@ -32,9 +43,15 @@ impl MockClient {
                 transactions: HashMap::new(),
                 utxos: HashMap::new(),
                 block_count: 0,
+                block_hashes: Vec::new(),
+                mempool: Vec::new(),
+                fee_rate: Some(DEFAULT_FEE_RATE),
+                reject_reason: None,
                 genesis_hash: BlockHash::from_raw_hash(
                     elements::hashes::sha256d::Hash::from_byte_array([1u8; 32]),
                 ),
+                imported_addresses: Vec::new(),
+                blinding_keys: HashMap::new(),
             })),
         }
     }
@@ -61,6 +78,39 @@ impl MockClient {
     pub fn set_genesis_hash(&self, hash: BlockHash) {
         self.inner.lock().unwrap().genesis_hash = hash;
     }
+
+    /// Set the fee rate returned by `estimate_fee_rate`, `None` for no
+    /// estimate
+    pub fn set_fee_rate(&self, fee_rate: Option<f64>) {
+        self.inner.lock().unwrap().fee_rate = fee_rate;
+    }
+
+    /// Set the reason `test_accept` rejects transactions with, `None` to
+    /// accept them
+    pub fn set_reject_reason(&self, reason: Option<RejectReason>) {
+        self.inner.lock().unwrap().reject_reason = reason;
+    }
+
+    /// Check whether an address was imported with `import_address`
+    #[must_use]
+    pub fn is_imported(&self, address: &Address) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .imported_addresses
+            .contains(address)
+    }
+
+    /// Get the blinding key imported for an address
+    #[must_use]
+    pub fn blinding_key(&self, address: &Address) -> Option<SecretKey> {
+        self.inner
+            .lock()
+            .unwrap()
+            .blinding_keys
+            .get(address)
+            .copied()
+    }
 }
 
 impl Default for MockClient {
@@ -77,7 +127,7 @@ impl NodeClient for MockClient {
 
         // Create a mock transaction
         let txid = Txid::from_raw_hash(elements::hashes::sha256d::Hash::from_byte_array(
-            rand::random::<[u8; 32]>(),
+            secp256k1::rand::random::<[u8; 32]>(),
         ));
 
         let tx = Transaction {
@@ -115,6 +165,9 @@ impl NodeClient for MockClient {
             asset: confidential::Asset::Explicit(
                 AssetId::from_slice(&[0u8; 32]).expect("valid asset"),
             ),
+            value_commitment: None,
+            confirmations: 0,
+            block_height: None,
         });
 
         Ok(txid)
@@ -133,35 +186,58 @@ impl NodeClient for MockClient {
         use elements::hashes::Hash;
 
         let txid = Txid::from_raw_hash(elements::hashes::sha256d::Hash::from_byte_array(
-            rand::random::<[u8; 32]>(),
+            secp256k1::rand::random::<[u8; 32]>(),
         ));
 
         let mut inner = self.inner.lock().unwrap();
         inner.transactions.insert(txid, tx.clone());
+        inner.mempool.push(txid);
 
         Ok(txid)
     }
 
+    fn test_accept(&self, _tx: &Transaction) -> ClientResult<()> {
+        match &self.inner.lock().unwrap().reject_reason {
+            Some(reason) => Err(ProgramError::TransactionRejected(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
     fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
         use elements::hashes::Hash;
 
         let mut inner = self.inner.lock().unwrap();
         let mut hashes = Vec::new();
 
+        // The first new block confirms every unconfirmed UTXO
+        if count > 0 {
+            let height = inner.block_count + 1;
+            for utxo in inner.utxos.values_mut().flatten() {
+                if utxo.confirmations == 0 && utxo.block_height.is_none() {
+                    utxo.block_height = Some(height);
+                }
+            }
+        }
         for _ in 0..count {
             inner.block_count += 1;
             let hash = BlockHash::from_raw_hash(elements::hashes::sha256d::Hash::from_byte_array(
-                rand::random::<[u8; 32]>(),
+                secp256k1::rand::random::<[u8; 32]>(),
             ));
+            inner.block_hashes.push(hash);
             hashes.push(hash);
         }
+        inner.mempool.clear();
 
         Ok(hashes)
     }
 
     fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
         let inner = self.inner.lock().unwrap();
-        Ok(inner.utxos.get(address).cloned().unwrap_or_default())
+        let mut utxos = inner.utxos.get(address).cloned().unwrap_or_default();
+        for utxo in utxos.iter_mut().filter(|utxo| utxo.block_height.is_some()) {
+            utxo.set_confirmations(u64::from(inner.block_count));
+        }
+        Ok(utxos)
     }
 
     fn get_new_address(&self) -> ClientResult<Address> {
@@ -170,7 +246,7 @@ impl NodeClient for MockClient {
         use secp256k1::Secp256k1;
 
         let secp = Secp256k1::new();
-        let secret_bytes: [u8; 32] = rand::random();
+        let secret_bytes: [u8; 32] = secp256k1::rand::random();
         let secret_key = secp256k1::SecretKey::from_slice(&secret_bytes)
             .map_err(|e| ProgramError::IoError(std::io::Error::other(format!("Key error: {e}"))))?;
         let secp_pubkey = secp256k1::PublicKey::from_secret_key(&secp, &secret_key);
@@ -182,6 +258,63 @@ impl NodeClient for MockClient {
             &AddressParams::ELEMENTS,
         ))
     }
+
+    fn import_address(&self, address: &Address) -> ClientResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.imported_addresses.contains(address) {
+            inner.imported_addresses.push(address.clone());
+        }
+        Ok(())
+    }
+
+    fn import_blinding_key(&self, address: &Address, blinding_key: &SecretKey) -> ClientResult<()> {
+        if !address.is_blinded() {
+            return Err(ProgramError::IoError(std::io::Error::other(
+                "Address is not confidential",
+            )));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.blinding_keys.insert(address.clone(), *blinding_key);
+        Ok(())
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        Ok(u64::from(self.inner.lock().unwrap().block_count))
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        let inner = self.inner.lock().unwrap();
+        let Some(index) = height.checked_sub(1) else {
+            return Ok(inner.genesis_hash);
+        };
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| inner.block_hashes.get(index))
+            .copied()
+            .ok_or_else(|| {
+                ProgramError::IoError(std::io::Error::other("Block height out of range"))
+            })
+    }
+
+    fn estimate_fee_rate(&self, _target_blocks: u16) -> ClientResult<f64> {
+        self.inner.lock().unwrap().fee_rate.ok_or_else(|| {
+            ProgramError::IoError(std::io::Error::other("No fee estimate available"))
+        })
+    }
+
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        let inner = self.inner.lock().unwrap();
+        let vsize = inner
+            .mempool
+            .iter()
+            .map(|txid| inner.transactions[txid].vsize() as u64)
+            .sum();
+        Ok(MempoolInfo {
+            size: inner.mempool.len() as u64,
+            vsize,
+            min_fee_rate: Some(DEFAULT_FEE_RATE),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -216,6 +349,15 @@ mod tests {
         let utxos = client.get_utxos(&addr).unwrap();
         assert_eq!(utxos.len(), 1);
         assert_eq!(utxos[0].amount, 100_000_000);
+        assert_eq!(utxos[0].confirmations, 0);
+        assert!(client.get_utxos_with_min_conf(&addr, 1).unwrap().is_empty());
+
+        client.generate_blocks(3).unwrap();
+        let utxos = client.get_utxos_with_min_conf(&addr, 3).unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].confirmations, 3);
+        assert_eq!(utxos[0].block_height, Some(1));
+        assert!(client.get_utxos_with_min_conf(&addr, 4).unwrap().is_empty());
     }
 
     #[test]
@@ -247,11 +389,21 @@ mod tests {
             }],
         };
 
-        let txid = client.broadcast(&tx).unwrap();
+        let txid = client.broadcast_checked(&tx).unwrap();
 
         // Should be able to retrieve it
         let retrieved_tx = client.get_transaction(&txid).unwrap();
         assert_eq!(retrieved_tx.output.len(), 1);
+
+        client.set_reject_reason(Some(RejectReason::from_node_reason(
+            "min relay fee not met",
+        )));
+        assert!(matches!(
+            client.broadcast_checked(&tx),
+            Err(ProgramError::TransactionRejected(RejectReason::FeeTooLow(
+                _
+            )))
+        ));
     }
 
     #[test]
@@ -262,6 +414,28 @@ mod tests {
         assert_eq!(hashes.len(), 10);
     }
 
+    #[test]
+    fn test_mock_chain_queries() {
+        let client = MockClient::new();
+        let txid = client
+            .send_to_address(&crate::test_fixtures::test_address(), 1_000)
+            .unwrap();
+        let tx = client.get_transaction(&txid).unwrap();
+        client.broadcast(&tx).unwrap();
+        assert_eq!(client.get_mempool_info().unwrap().size, 1);
+
+        let hashes = client.generate_blocks(2).unwrap();
+        assert_eq!(client.get_block_count().unwrap(), 2);
+        assert_eq!(client.get_block_hash(0).unwrap(), client.genesis_hash());
+        assert_eq!(client.get_block_hash(2).unwrap(), hashes[1]);
+        assert!(client.get_block_hash(3).is_err());
+        assert_eq!(client.get_mempool_info().unwrap().size, 0);
+
+        assert_eq!(client.estimate_fee_rate(6).unwrap(), DEFAULT_FEE_RATE);
+        client.set_fee_rate(None);
+        assert!(client.estimate_fee_rate(6).is_err());
+    }
+
     #[test]
     fn test_mock_get_new_address() {
         let client = MockClient::new();
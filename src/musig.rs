@@ -0,0 +1,384 @@
+//! MuSig2 key aggregation and multi-party signing
+//!
+//! Multi-party contracts can commit to one aggregated public key instead of
+//! one key per party, so the program and its spends look like a single-key
+//! contract and no party's key is revealed. Signing takes two rounds:
+//!
+//! 1. Every signer makes a fresh nonce pair for the sighash with
+//!    [`MusigKeys::nonce`] and shares the [`PublicNonce`].
+//! 2. With all public nonces, every signer makes a [`PartialSignature`] with
+//!    [`MusigKeys::partial_sign`], and anyone combines them into a BIP340
+//!    signature with [`MusigKeys::aggregate`].
+//!
+//! Nonces and partial signatures are passed in the order of the public keys.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::musig::MusigKeys;
+//! use secp256k1::{Keypair, Secp256k1};
+//!
+//! let alice = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+//! let bob = Keypair::from_seckey_slice(&Secp256k1::new(), &[2; 32]).unwrap();
+//! let keys = MusigKeys::new(vec![alice.public_key(), bob.public_key()]).unwrap();
+//! let sighash = [7u8; 32];
+//!
+//! let (alice_secret, alice_nonce) = keys.nonce(&alice, sighash).unwrap();
+//! let (bob_secret, bob_nonce) = keys.nonce(&bob, sighash).unwrap();
+//! let nonces = [alice_nonce, bob_nonce];
+//!
+//! let partials = [
+//!     keys.partial_sign(alice_secret, &alice, &nonces, sighash).unwrap(),
+//!     keys.partial_sign(bob_secret, &bob, &nonces, sighash).unwrap(),
+//! ];
+//! let signature = keys.aggregate(&nonces, &partials, sighash).unwrap();
+//! assert_eq!(signature.len(), 64);
+//! ```
+
+use crate::error::SpendError;
+use elements::secp256k1_zkp::{
+    MusigAggNonce, MusigKeyAggCache, MusigPartialSignature, MusigPubNonce, MusigSecNonce,
+    MusigSession, MusigSessionId,
+};
+use secp256k1::{All, Keypair, Message, PublicKey, Secp256k1, XOnlyPublicKey};
+
+/// Public keys of the signers and their aggregate
+#[derive(Clone)]
+pub struct MusigKeys {
+    pubkeys: Vec<PublicKey>,
+    cache: MusigKeyAggCache,
+    secp: Secp256k1<All>,
+}
+
+/// Secret nonce of one signing session
+///
+/// A nonce must never sign twice, so this can be neither cloned nor
+/// serialized, and [`MusigKeys::partial_sign`] consumes it.
+pub struct SecretNonce(MusigSecNonce);
+
+/// Public nonce a signer shares with the other signers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicNonce(MusigPubNonce);
+
+/// Signature share of one signer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialSignature(MusigPartialSignature);
+
+impl MusigKeys {
+    /// Aggregate the public keys of the signers
+    ///
+    /// The aggregate depends on the order of `pubkeys`, which every party
+    /// must agree on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pubkeys` is empty.
+    pub fn new(pubkeys: Vec<PublicKey>) -> Result<Self, SpendError> {
+        if pubkeys.is_empty() {
+            return Err(SpendError::SigningError(
+                "MuSig2 needs at least one public key".into(),
+            ));
+        }
+        let secp = Secp256k1::new();
+        let cache = MusigKeyAggCache::new(&secp, &pubkeys);
+        Ok(Self {
+            pubkeys,
+            cache,
+            secp,
+        })
+    }
+
+    /// Get the public keys of the signers, in aggregation order
+    #[must_use]
+    pub fn pubkeys(&self) -> &[PublicKey] {
+        &self.pubkeys
+    }
+
+    /// Get the aggregated x-only public key
+    #[must_use]
+    pub fn aggregate_pubkey(&self) -> XOnlyPublicKey {
+        self.cache.agg_pk()
+    }
+
+    /// Get the serialized aggregated public key, to pass as a program's `Pubkey`
+    #[must_use]
+    pub fn aggregate_pubkey_bytes(&self) -> [u8; 32] {
+        self.aggregate_pubkey().serialize()
+    }
+
+    /// Make a fresh nonce pair for signing `sighash` with `keypair`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `keypair` is not one of the signers or the nonce
+    /// cannot be generated.
+    pub fn nonce(
+        &self,
+        keypair: &Keypair,
+        sighash: [u8; 32],
+    ) -> Result<(SecretNonce, PublicNonce), SpendError> {
+        let pubkey = keypair.public_key();
+        self.signer_index(&pubkey)?;
+        let session_id =
+            MusigSessionId::assume_unique_per_nonce_gen(secp256k1::rand::random::<[u8; 32]>());
+        let (secret, public) = self
+            .cache
+            .nonce_gen(
+                &self.secp,
+                session_id,
+                pubkey,
+                Message::from_digest(sighash),
+                None,
+            )
+            .map_err(|e| SpendError::SigningError(format!("Cannot generate nonce: {e}")))?;
+        Ok((SecretNonce(secret), PublicNonce(public)))
+    }
+
+    /// Make the partial signature of `keypair` over `sighash`
+    ///
+    /// `nonces` are the public nonces of all signers, in key order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `keypair` is not one of the signers, there is not
+    /// one nonce per signer, or signing fails.
+    pub fn partial_sign(
+        &self,
+        secret_nonce: SecretNonce,
+        keypair: &Keypair,
+        nonces: &[PublicNonce],
+        sighash: [u8; 32],
+    ) -> Result<PartialSignature, SpendError> {
+        self.signer_index(&keypair.public_key())?;
+        let session = self.session(nonces, sighash)?;
+        session
+            .partial_sign(&self.secp, secret_nonce.0, keypair, &self.cache)
+            .map(PartialSignature)
+            .map_err(|e| SpendError::SigningError(format!("Cannot sign: {e}")))
+    }
+
+    /// Combine the partial signatures of all signers into a BIP340 signature
+    ///
+    /// Every partial signature is checked against its signer's key and nonce,
+    /// so a bad share is attributed to its signer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is not one nonce and one partial signature
+    /// per signer, or a partial signature is invalid.
+    pub fn aggregate(
+        &self,
+        nonces: &[PublicNonce],
+        partials: &[PartialSignature],
+        sighash: [u8; 32],
+    ) -> Result<[u8; 64], SpendError> {
+        let session = self.session(nonces, sighash)?;
+        self.check_count("partial signatures", partials.len())?;
+        for (index, ((partial, nonce), pubkey)) in
+            partials.iter().zip(nonces).zip(&self.pubkeys).enumerate()
+        {
+            if !session.partial_verify(&self.secp, &self.cache, partial.0, nonce.0, *pubkey) {
+                return Err(SpendError::SigningError(format!(
+                    "Invalid partial signature of signer {index}"
+                )));
+            }
+        }
+        let partials: Vec<_> = partials.iter().map(|partial| partial.0).collect();
+        Ok(session.partial_sig_agg(&partials).serialize())
+    }
+
+    fn session(
+        &self,
+        nonces: &[PublicNonce],
+        sighash: [u8; 32],
+    ) -> Result<MusigSession, SpendError> {
+        self.check_count("nonces", nonces.len())?;
+        let nonces: Vec<_> = nonces.iter().map(|nonce| nonce.0).collect();
+        let agg_nonce = MusigAggNonce::new(&self.secp, &nonces);
+        Ok(MusigSession::new(
+            &self.secp,
+            &self.cache,
+            agg_nonce,
+            Message::from_digest(sighash),
+        ))
+    }
+
+    fn check_count(&self, what: &str, count: usize) -> Result<(), SpendError> {
+        if count != self.pubkeys.len() {
+            return Err(SpendError::SigningError(format!(
+                "Expected {} {what}, got {count}",
+                self.pubkeys.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn signer_index(&self, pubkey: &PublicKey) -> Result<usize, SpendError> {
+        self.pubkeys
+            .iter()
+            .position(|key| key == pubkey)
+            .ok_or_else(|| SpendError::SigningError(format!("{pubkey} is not a signer")))
+    }
+}
+
+impl PublicNonce {
+    /// Serialize the nonce for sending to the other signers
+    #[must_use]
+    pub fn serialize(&self) -> [u8; 66] {
+        self.0.serialize()
+    }
+
+    /// Parse a nonce received from another signer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a valid public nonce.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, SpendError> {
+        MusigPubNonce::from_slice(bytes)
+            .map(Self)
+            .map_err(|e| SpendError::SigningError(format!("Invalid public nonce: {e}")))
+    }
+}
+
+impl PartialSignature {
+    /// Serialize the partial signature for sending to the aggregator
+    #[must_use]
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0.serialize()
+    }
+
+    /// Parse a partial signature received from another signer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a valid partial signature.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, SpendError> {
+        MusigPartialSignature::from_slice(bytes)
+            .map(Self)
+            .map_err(|e| SpendError::SigningError(format!("Invalid partial signature: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::keypair_from_u32;
+    use secp256k1::schnorr;
+
+    fn sign(keys: &MusigKeys, keypairs: &[Keypair], sighash: [u8; 32]) -> [u8; 64] {
+        let (secrets, nonces): (Vec<_>, Vec<_>) = keypairs
+            .iter()
+            .map(|keypair| keys.nonce(keypair, sighash).unwrap())
+            .unzip();
+        let nonces: Vec<_> = nonces
+            .iter()
+            .map(|nonce| PublicNonce::from_slice(&nonce.serialize()).unwrap())
+            .collect();
+        let partials: Vec<_> = secrets
+            .into_iter()
+            .zip(keypairs)
+            .map(|(secret, keypair)| {
+                let partial = keys
+                    .partial_sign(secret, keypair, &nonces, sighash)
+                    .unwrap();
+                PartialSignature::from_slice(&partial.serialize()).unwrap()
+            })
+            .collect();
+        keys.aggregate(&nonces, &partials, sighash).unwrap()
+    }
+
+    #[test]
+    fn test_musig_signature_verifies_against_aggregate() {
+        let keypairs: Vec<_> = (1..=3).map(keypair_from_u32).collect();
+        let keys = MusigKeys::new(keypairs.iter().map(Keypair::public_key).collect()).unwrap();
+        let sighash = [3u8; 32];
+
+        let signature = schnorr::Signature::from_slice(&sign(&keys, &keypairs, sighash)).unwrap();
+        assert!(Secp256k1::new()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(sighash),
+                &keys.aggregate_pubkey()
+            )
+            .is_ok());
+
+        let reordered =
+            MusigKeys::new(keypairs.iter().rev().map(Keypair::public_key).collect()).unwrap();
+        assert_ne!(reordered.aggregate_pubkey(), keys.aggregate_pubkey());
+    }
+
+    #[test]
+    fn test_musig_spend() {
+        use crate::program::Program;
+        use crate::spend::SpendBuilder;
+        use crate::test_fixtures::{test_genesis_hash, test_utxo, P2PK_PROGRAM};
+        use crate::witness::WitnessBuilder;
+        use simplicityhl::str::WitnessName;
+        use simplicityhl::value::ValueConstructible;
+        use simplicityhl::{Arguments, Value};
+        use std::collections::HashMap;
+
+        let keypairs = [keypair_from_u32(1), keypair_from_u32(2)];
+        let keys = MusigKeys::new(keypairs.iter().map(Keypair::public_key).collect()).unwrap();
+        let program = Program::from_source(P2PK_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::from(HashMap::from([(
+                WitnessName::from_str_unchecked("PK"),
+                Value::u256(simplicityhl::num::U256::from_byte_array(
+                    keys.aggregate_pubkey_bytes(),
+                )),
+            )])))
+            .unwrap();
+        let utxo = crate::client::Utxo {
+            script_pubkey: program
+                .address(&elements::AddressParams::ELEMENTS)
+                .script_pubkey(),
+            ..test_utxo()
+        };
+        let mut builder = SpendBuilder::try_new(program, utxo)
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_output_simple(
+            elements::Script::new(),
+            99_000_000,
+            elements::AssetId::default(),
+        );
+        builder.add_fee(1_000_000, elements::AssetId::default());
+
+        let signature = sign(&keys, &keypairs, builder.sighash_all().unwrap());
+        let witness = WitnessBuilder::new()
+            .with("SIG", Value::byte_array(signature))
+            .build();
+        let tx = builder.clone().finalize(witness).unwrap();
+        builder.verify(&tx).unwrap();
+    }
+
+    #[test]
+    fn test_musig_rejects_bad_input() {
+        let keypairs = [keypair_from_u32(1), keypair_from_u32(2)];
+        let keys = MusigKeys::new(keypairs.iter().map(Keypair::public_key).collect()).unwrap();
+        let sighash = [4u8; 32];
+
+        assert!(MusigKeys::new(Vec::new()).is_err());
+        assert!(keys.nonce(&keypair_from_u32(3), sighash).is_err());
+
+        let (first_secret, first_nonce) = keys.nonce(&keypairs[0], sighash).unwrap();
+        let (second_secret, second_nonce) = keys.nonce(&keypairs[1], sighash).unwrap();
+        let nonces = [first_nonce, second_nonce];
+        assert!(keys
+            .partial_sign(first_secret, &keypairs[0], &nonces[..1], sighash)
+            .is_err());
+
+        // A share made over another sighash is attributed to its signer
+        let (first_secret, first_nonce) = keys.nonce(&keypairs[0], sighash).unwrap();
+        let nonces = [first_nonce, second_nonce];
+        let partials = [
+            keys.partial_sign(first_secret, &keypairs[0], &nonces, [5u8; 32])
+                .unwrap(),
+            keys.partial_sign(second_secret, &keypairs[1], &nonces, sighash)
+                .unwrap(),
+        ];
+        let error = keys.aggregate(&nonces, &partials, sighash).unwrap_err();
+        assert!(error.to_string().contains("signer 0"));
+    }
+}
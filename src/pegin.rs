@@ -0,0 +1,240 @@
+//! Peg-in claims
+//!
+//! A peg-in input claims bitcoin sent to the federation's peg-in address on
+//! the mainchain. Instead of spending an Elements output, it proves the
+//! mainchain deposit with the deposit transaction and a merkle proof of its
+//! inclusion in a block, carried in the input's peg-in witness. Combine a
+//! [`PeginInput`] with program inputs via `SpendBuilder::add_pegin_input`.
+//!
+//! The claim script (usually a wallet P2WPKH script) authorizes the claim,
+//! so peg-in inputs are signed outside musk like external inputs.
+
+use crate::client::Utxo;
+use crate::error::SpendError;
+use elements::bitcoin;
+use elements::hashes::Hash;
+use elements::{confidential, AssetId, Script};
+
+/// A mainchain deposit to be claimed as a peg-in input
+#[derive(Debug, Clone)]
+pub struct PeginInput {
+    mainchain_tx: bitcoin::Transaction,
+    merkle_proof: bitcoin::MerkleBlock,
+    vout: u32,
+    claim_script: Script,
+    asset: AssetId,
+    parent_genesis_hash: bitcoin::BlockHash,
+}
+
+impl PeginInput {
+    /// Create a peg-in claim of output `vout` of `mainchain_tx`
+    ///
+    /// `merkle_proof` is the proof returned by the mainchain node's
+    /// `gettxoutproof`, `asset` is the pegged asset (the sidechain's policy
+    /// asset) and `parent_genesis_hash` identifies the mainchain.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `vout` is out of range or `merkle_proof` does not
+    /// commit to `mainchain_tx`.
+    pub fn new(
+        mainchain_tx: bitcoin::Transaction,
+        merkle_proof: bitcoin::MerkleBlock,
+        vout: u32,
+        claim_script: Script,
+        asset: AssetId,
+        parent_genesis_hash: bitcoin::BlockHash,
+    ) -> Result<Self, SpendError> {
+        if mainchain_tx.output.get(vout as usize).is_none() {
+            return Err(SpendError::InvalidUtxo(format!(
+                "Mainchain transaction has no output {vout}"
+            )));
+        }
+
+        let mut matches = Vec::new();
+        let mut indexes = Vec::new();
+        merkle_proof
+            .extract_matches(&mut matches, &mut indexes)
+            .map_err(|e| SpendError::InvalidUtxo(format!("Invalid merkle proof: {e}")))?;
+        if !matches.contains(&mainchain_tx.compute_txid()) {
+            return Err(SpendError::InvalidUtxo(
+                "Merkle proof does not include the mainchain transaction".into(),
+            ));
+        }
+
+        Ok(Self {
+            mainchain_tx,
+            merkle_proof,
+            vout,
+            claim_script,
+            asset,
+            parent_genesis_hash,
+        })
+    }
+
+    /// Get the amount being pegged in, in satoshis
+    #[must_use]
+    pub fn amount(&self) -> u64 {
+        self.mainchain_tx.output[self.vout as usize].value.to_sat()
+    }
+
+    /// Get the claimed output as the UTXO it is spent as
+    ///
+    /// Elements treats a peg-in as spending an explicit output of the pegged
+    /// asset locked by the claim script, at the mainchain outpoint.
+    #[must_use]
+    pub fn utxo(&self) -> Utxo {
+        Utxo {
+            txid: elements::Txid::from_byte_array(self.mainchain_tx.compute_txid().to_byte_array()),
+            vout: self.vout,
+            amount: self.amount(),
+            script_pubkey: self.claim_script.clone(),
+            asset: confidential::Asset::Explicit(self.asset),
+            value_commitment: None,
+            confirmations: 0,
+            block_height: None,
+        }
+    }
+
+    /// Serialize the peg-in witness stack
+    ///
+    /// The stack is the amount, pegged asset, mainchain genesis hash, claim
+    /// script, mainchain transaction and merkle proof. The transaction is
+    /// serialized without its witness, which the txid does not commit to.
+    #[must_use]
+    pub fn pegin_witness(&self) -> Vec<Vec<u8>> {
+        let mut stripped = self.mainchain_tx.clone();
+        for input in &mut stripped.input {
+            input.witness = bitcoin::Witness::new();
+        }
+
+        vec![
+            self.amount().to_le_bytes().to_vec(),
+            elements::encode::serialize(&self.asset),
+            bitcoin::consensus::serialize(&self.parent_genesis_hash),
+            self.claim_script.as_bytes().to_vec(),
+            bitcoin::consensus::serialize(&stripped),
+            bitcoin::consensus::serialize(&self.merkle_proof),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::spend::SpendBuilder;
+    use crate::test_fixtures::{test_genesis_hash, test_utxo, SIMPLE_PROGRAM};
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness};
+    use simplicityhl::{Arguments, WitnessValues};
+
+    fn deposit() -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[[7u8; 64]]),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    fn merkle_proof(tx: &bitcoin::Transaction) -> bitcoin::MerkleBlock {
+        let header = bitcoin::block::Header {
+            version: bitcoin::block::Version::TWO,
+            prev_blockhash: bitcoin::BlockHash::all_zeros(),
+            merkle_root: bitcoin::TxMerkleNode::from_raw_hash(tx.compute_txid().to_raw_hash()),
+            time: 0,
+            bits: bitcoin::CompactTarget::from_consensus(0x207f_ffff),
+            nonce: 0,
+        };
+        let txid = tx.compute_txid();
+        bitcoin::MerkleBlock::from_header_txids_with_predicate(&header, &[txid], |id| *id == txid)
+    }
+
+    #[test]
+    fn test_pegin_witness() {
+        let tx = deposit();
+        let pegin = PeginInput::new(
+            tx.clone(),
+            merkle_proof(&tx),
+            0,
+            Script::from(vec![0x00, 0x14]),
+            AssetId::default(),
+            bitcoin::BlockHash::all_zeros(),
+        )
+        .unwrap();
+
+        assert_eq!(pegin.amount(), 50_000);
+        assert_eq!(pegin.utxo().script_pubkey, Script::from(vec![0x00, 0x14]));
+
+        let witness = pegin.pegin_witness();
+        assert_eq!(witness.len(), 6);
+        assert_eq!(witness[0], 50_000u64.to_le_bytes());
+        let decoded: bitcoin::Transaction = bitcoin::consensus::deserialize(&witness[4]).unwrap();
+        assert_eq!(decoded.compute_txid(), tx.compute_txid());
+        assert!(decoded.input[0].witness.is_empty());
+    }
+
+    #[test]
+    fn test_spend_with_pegin_input() {
+        let tx = deposit();
+        let pegin = PeginInput::new(
+            tx.clone(),
+            merkle_proof(&tx),
+            0,
+            Script::from(vec![0x00, 0x14]),
+            AssetId::default(),
+            bitcoin::BlockHash::all_zeros(),
+        )
+        .unwrap();
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+
+        let mut builder =
+            SpendBuilder::new_unchecked(program, test_utxo()).genesis_hash(test_genesis_hash());
+        builder.add_pegin_input(&pegin);
+        builder.add_fee(1_000, AssetId::default());
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+
+        let spend = builder.finalize(WitnessValues::default()).unwrap();
+        assert!(!spend.input[0].is_pegin);
+        assert!(spend.input[1].is_pegin);
+        assert_eq!(spend.input[1].witness.pegin_witness, pegin.pegin_witness());
+        assert_eq!(
+            spend.output.last().unwrap().value.explicit(),
+            Some(100_049_000)
+        );
+    }
+
+    #[test]
+    fn test_pegin_rejects_invalid_claims() {
+        let tx = deposit();
+        let new = |vout, proof| {
+            PeginInput::new(
+                tx.clone(),
+                proof,
+                vout,
+                Script::new(),
+                AssetId::default(),
+                bitcoin::BlockHash::all_zeros(),
+            )
+        };
+
+        assert!(new(1, merkle_proof(&tx)).is_err());
+
+        let mut other = deposit();
+        other.lock_time = LockTime::from_consensus(1);
+        assert!(new(0, merkle_proof(&other)).is_err());
+    }
+}
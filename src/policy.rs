@@ -0,0 +1,241 @@
+//! Policies governing which UTXOs may be selected for spending
+//!
+//! Services differ in how much risk they accept from unconfirmed outputs.
+//! A [`ZeroConfPolicy`] captures that decision once so that coin selection
+//! (see [`ZeroConfFiltered`](crate::coin_selection::ZeroConfFiltered)) and
+//! higher-level wallet code can consult it consistently, with optional
+//! per-asset overrides (e.g. accept zero-conf L-BTC change but never
+//! unconfirmed issued assets).
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::policy::{UtxoStatus, ZeroConf, ZeroConfPolicy};
+//!
+//! let policy = ZeroConfPolicy::new(ZeroConf::OnlyFromSelf);
+//!
+//! let own_change = UtxoStatus::unconfirmed(true);
+//! let foreign = UtxoStatus::unconfirmed(false);
+//! assert!(policy.default_rule().allows(own_change));
+//! assert!(!policy.default_rule().allows(foreign));
+//! assert!(policy.default_rule().allows(UtxoStatus::confirmed(1)));
+//! ```
+
+use crate::client::Utxo;
+use elements::confidential;
+use elements::AssetId;
+use std::collections::HashMap;
+
+/// Rule deciding whether unconfirmed UTXOs may be selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ZeroConf {
+    /// Only confirmed UTXOs may be selected
+    #[default]
+    Never,
+    /// Unconfirmed UTXOs may be selected if they were created by us (e.g. change)
+    OnlyFromSelf,
+    /// Any UTXO may be selected regardless of confirmation status
+    Always,
+}
+
+impl ZeroConf {
+    /// Check whether a UTXO with the given status passes this rule
+    #[must_use]
+    pub const fn allows(self, status: UtxoStatus) -> bool {
+        if status.is_confirmed() {
+            return true;
+        }
+        match self {
+            Self::Never => false,
+            Self::OnlyFromSelf => status.from_self,
+            Self::Always => true,
+        }
+    }
+}
+
+/// Confirmation status of a UTXO, as known to the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UtxoStatus {
+    /// Number of confirmations (0 for mempool outputs)
+    pub confirmations: u32,
+    /// Whether the transaction creating the UTXO was built by us
+    pub from_self: bool,
+}
+
+impl UtxoStatus {
+    /// Status of a UTXO with the given number of confirmations
+    #[must_use]
+    pub const fn confirmed(confirmations: u32) -> Self {
+        Self {
+            confirmations,
+            from_self: false,
+        }
+    }
+
+    /// Status of an unconfirmed UTXO
+    #[must_use]
+    pub const fn unconfirmed(from_self: bool) -> Self {
+        Self {
+            confirmations: 0,
+            from_self,
+        }
+    }
+
+    /// Status of `utxo`, with its confirmations as reported by the client
+    #[must_use]
+    pub const fn of(utxo: &Utxo, from_self: bool) -> Self {
+        Self {
+            confirmations: utxo.confirmations,
+            from_self,
+        }
+    }
+
+    /// Whether the UTXO has at least one confirmation
+    #[must_use]
+    pub const fn is_confirmed(&self) -> bool {
+        self.confirmations > 0
+    }
+}
+
+/// Zero-conf acceptance policy with optional per-asset overrides
+///
+/// # Examples
+///
+/// ```
+/// use musk::policy::{UtxoStatus, ZeroConf, ZeroConfPolicy};
+/// use musk::elements::AssetId;
+///
+/// let lbtc = AssetId::default();
+/// let policy = ZeroConfPolicy::new(ZeroConf::Never).with_asset(lbtc, ZeroConf::Always);
+///
+/// assert_eq!(policy.rule_for_asset(&lbtc), ZeroConf::Always);
+/// assert!(policy.rule_for_asset(&lbtc).allows(UtxoStatus::unconfirmed(false)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ZeroConfPolicy {
+    default: ZeroConf,
+    assets: HashMap<AssetId, ZeroConf>,
+}
+
+impl ZeroConfPolicy {
+    /// Create a policy applying `default` to every asset
+    #[must_use]
+    pub fn new(default: ZeroConf) -> Self {
+        Self {
+            default,
+            assets: HashMap::new(),
+        }
+    }
+
+    /// Override the rule for a specific asset
+    #[must_use]
+    pub fn with_asset(mut self, asset: AssetId, rule: ZeroConf) -> Self {
+        self.assets.insert(asset, rule);
+        self
+    }
+
+    /// Get the rule applied to assets without an override
+    #[must_use]
+    pub const fn default_rule(&self) -> ZeroConf {
+        self.default
+    }
+
+    /// Get the rule applied to an asset
+    #[must_use]
+    pub fn rule_for_asset(&self, asset: &AssetId) -> ZeroConf {
+        self.assets.get(asset).copied().unwrap_or(self.default)
+    }
+
+    /// Get the rule applied to a UTXO's asset
+    ///
+    /// Confidential and null assets use the default rule, since the asset
+    /// cannot be determined without unblinding.
+    #[must_use]
+    pub fn rule_for(&self, utxo: &Utxo) -> ZeroConf {
+        match utxo.asset {
+            confidential::Asset::Explicit(asset) => self.rule_for_asset(&asset),
+            _ => self.default,
+        }
+    }
+
+    /// Check whether a UTXO may be selected for spending
+    ///
+    /// The UTXO's `confirmations` decide whether it is confirmed;
+    /// `from_self` tells whether we built the transaction creating it.
+    #[must_use]
+    pub fn accepts(&self, utxo: &Utxo, from_self: bool) -> bool {
+        self.rule_for(utxo).allows(UtxoStatus::of(utxo, from_self))
+    }
+
+    /// Keep only the UTXOs this policy accepts, each paired with whether we
+    /// built the transaction creating it
+    pub fn filter<I>(&self, utxos: I) -> Vec<Utxo>
+    where
+        I: IntoIterator<Item = (Utxo, bool)>,
+    {
+        utxos
+            .into_iter()
+            .filter(|(utxo, from_self)| self.accepts(utxo, *from_self))
+            .map(|(utxo, _)| utxo)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::test_utxo;
+
+    fn issued_asset() -> AssetId {
+        AssetId::from_slice(&[7u8; 32]).expect("valid asset")
+    }
+
+    fn confirmed_utxo(confirmations: u32) -> Utxo {
+        Utxo {
+            confirmations,
+            block_height: Some(100),
+            ..test_utxo()
+        }
+    }
+
+    #[test]
+    fn test_confirmed_always_accepted() {
+        let policy = ZeroConfPolicy::new(ZeroConf::Never);
+        assert!(policy.accepts(&confirmed_utxo(1), false));
+    }
+
+    #[test]
+    fn test_never_rejects_unconfirmed() {
+        let policy = ZeroConfPolicy::new(ZeroConf::Never);
+        assert!(!policy.accepts(&test_utxo(), true));
+    }
+
+    #[test]
+    fn test_only_from_self() {
+        let policy = ZeroConfPolicy::new(ZeroConf::OnlyFromSelf);
+        assert!(policy.accepts(&test_utxo(), true));
+        assert!(!policy.accepts(&test_utxo(), false));
+    }
+
+    #[test]
+    fn test_per_asset_override() {
+        let mut utxo = test_utxo();
+        utxo.asset = confidential::Asset::Explicit(issued_asset());
+
+        let policy =
+            ZeroConfPolicy::new(ZeroConf::Always).with_asset(issued_asset(), ZeroConf::Never);
+        assert!(policy.accepts(&test_utxo(), false));
+        assert!(!policy.accepts(&utxo, true));
+    }
+
+    #[test]
+    fn test_filter() {
+        let policy = ZeroConfPolicy::new(ZeroConf::OnlyFromSelf);
+        let selected = policy.filter(vec![
+            (confirmed_utxo(6), false),
+            (test_utxo(), false),
+            (test_utxo(), true),
+        ]);
+        assert_eq!(selected.len(), 2);
+    }
+}
@@ -1,16 +1,38 @@
 //! Program compilation and instantiation
 
-use crate::address::create_taproot_info;
+use crate::address::{create_taproot_info, create_taproot_info_with_key};
+use crate::client::NodeClient;
 use crate::error::ProgramError;
+use crate::timelock::Timelocks;
+use crate::util::SigningKey;
 use elements::taproot::TaprootSpendInfo;
-use simplicityhl::{Arguments, CompiledProgram, Parameters, TemplateProgram, WitnessValues};
+use secp256k1::XOnlyPublicKey;
+use simplicityhl::parse::ParseFromStr;
+use simplicityhl::simplicity::dag::{DagLike, InternalSharing};
+use simplicityhl::simplicity::node::Inner;
+use simplicityhl::str::WitnessName;
+use simplicityhl::{
+    Arguments, CompiledProgram, Parameters, TemplateProgram, WitnessTypes, WitnessValues,
+};
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::sync::Arc;
 
+/// Comment prefix declaring a witness preset in program source
+///
+/// A line `// @preset refund: SIG, TIMEOUT` declares the preset `refund`
+/// needing the witnesses `SIG` and `TIMEOUT`.
+pub const PRESET_ANNOTATION: &str = "// @preset ";
+
+/// Named witness presets, mapping each spend path to the witnesses it needs
+pub type WitnessPresets = BTreeMap<String, Vec<String>>;
+
 /// A Simplicity program template with parameterized values
 pub struct Program {
     source: Arc<str>,
     template: TemplateProgram,
+    witness_types: WitnessTypes,
+    presets: WitnessPresets,
 }
 
 impl Program {
@@ -32,8 +54,88 @@ impl Program {
         let source = Arc::from(source);
         let template =
             TemplateProgram::new(Arc::clone(&source)).map_err(ProgramError::ParseError)?;
+        // The template keeps the witness types to itself, so analyze the source again
+        let witness_types = simplicityhl::parse::Program::parse_from_str(&source)
+            .and_then(|parsed| simplicityhl::ast::Program::analyze(&parsed))
+            .map(|program| program.witness_types().clone())
+            .map_err(|e| ProgramError::ParseError(e.to_string()))?;
+
+        let mut program = Self {
+            source: Arc::clone(&source),
+            template,
+            witness_types,
+            presets: WitnessPresets::new(),
+        };
+        for line in source.lines() {
+            let Some(preset) = line.trim().strip_prefix(PRESET_ANNOTATION) else {
+                continue;
+            };
+            let (name, witnesses) = preset.split_once(':').ok_or_else(|| {
+                ProgramError::InvalidPreset(format!("Expected `name: WITNESS, ...` in {line:?}"))
+            })?;
+            let witnesses: Vec<&str> = witnesses
+                .split(',')
+                .map(str::trim)
+                .filter(|witness| !witness.is_empty())
+                .collect();
+            program = program.with_preset(name.trim(), &witnesses)?;
+        }
+        Ok(program)
+    }
+
+    /// Add a named witness preset, listing the witnesses a spend path needs
+    ///
+    /// Presets can also be declared in the source with [`PRESET_ANNOTATION`]
+    /// comments, so they ship with the program.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::Program;
+    ///
+    /// let source = r#"
+    /// // @preset claim: PREIMAGE
+    /// fn main() {
+    ///     let preimage: u256 = witness::PREIMAGE;
+    ///     let timeout: u32 = witness::TIMEOUT;
+    /// }
+    /// "#;
+    /// let program = Program::from_source(source)?.with_preset("refund", &["TIMEOUT"])?;
+    /// assert_eq!(program.presets()["claim"], ["PREIMAGE"]);
+    /// assert!(program.with_preset("typo", &["TIMOUT"]).is_err());
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidPreset`] if a witness is not a witness
+    /// of the program, or the preset already exists.
+    pub fn with_preset(mut self, name: &str, witnesses: &[&str]) -> Result<Self, ProgramError> {
+        if self.presets.contains_key(name) {
+            return Err(ProgramError::InvalidPreset(format!(
+                "{name} is declared twice"
+            )));
+        }
+        if let Some(unknown) = witnesses.iter().find(|witness| {
+            self.witness_types
+                .get(&WitnessName::from_str_unchecked(witness))
+                .is_none()
+        }) {
+            return Err(ProgramError::InvalidPreset(format!(
+                "{name} needs {unknown}, which is not a witness of the program"
+            )));
+        }
+        self.presets.insert(
+            name.to_string(),
+            witnesses.iter().map(ToString::to_string).collect(),
+        );
+        Ok(self)
+    }
 
-        Ok(Self { source, template })
+    /// Get the witness presets of the program
+    #[must_use]
+    pub const fn presets(&self) -> &WitnessPresets {
+        &self.presets
     }
 
     /// Load a program from a file
@@ -86,7 +188,7 @@ impl Program {
     pub fn instantiate(&self, arguments: Arguments) -> Result<InstantiatedProgram, ProgramError> {
         let compiled = self
             .template
-            .instantiate(arguments, false)
+            .instantiate(arguments.clone(), false)
             .map_err(ProgramError::InstantiationError)?;
 
         let taproot_info = create_taproot_info(&compiled)?;
@@ -94,6 +196,91 @@ impl Program {
         Ok(InstantiatedProgram {
             inner: compiled,
             taproot_info,
+            witness_types: self.witness_types.clone(),
+            presets: self.presets.clone(),
+            source: Arc::clone(&self.source),
+            arguments,
+        })
+    }
+
+    /// Instantiate the program with a custom taproot internal key
+    ///
+    /// The resulting address can be spent either by satisfying the program
+    /// (script path) or by a signature from the holder of `internal_key`
+    /// (key path), e.g. for a cooperative close.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments};
+    /// use secp256k1::{Keypair, Secp256k1};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+    /// let internal_key = keypair.x_only_public_key().0;
+    /// let compiled = program
+    ///     .instantiate_with_internal_key(Arguments::default(), internal_key)
+    ///     .unwrap();
+    /// assert_eq!(compiled.internal_key(), internal_key);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if instantiation fails or the taproot tree cannot be built.
+    pub fn instantiate_with_internal_key(
+        &self,
+        arguments: Arguments,
+        internal_key: XOnlyPublicKey,
+    ) -> Result<InstantiatedProgram, ProgramError> {
+        let compiled = self
+            .template
+            .instantiate(arguments.clone(), false)
+            .map_err(ProgramError::InstantiationError)?;
+
+        let taproot_info = create_taproot_info_with_key(&compiled, internal_key)?;
+
+        Ok(InstantiatedProgram {
+            inner: compiled,
+            taproot_info,
+            witness_types: self.witness_types.clone(),
+            presets: self.presets.clone(),
+            source: Arc::clone(&self.source),
+            arguments,
+        })
+    }
+
+    /// Derive programs and addresses for a series of argument sets
+    ///
+    /// The template is parsed once and reused, and derivation is lazy, so
+    /// this is suitable for generating large numbers of per-deposit addresses.
+    /// Each argument set carries a caller-chosen key (e.g. a deposit index)
+    /// that is passed through to the result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Arguments, Program, elements};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let addresses: Vec<_> = program
+    ///     .derive_addresses((0..3).map(|i| (i, Arguments::default())), &elements::AddressParams::ELEMENTS)
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(addresses.len(), 3);
+    /// ```
+    pub fn derive_addresses<'a, K, I>(
+        &'a self,
+        arguments: I,
+        params: &'static elements::AddressParams,
+    ) -> impl Iterator<Item = Result<(K, InstantiatedProgram, elements::Address), ProgramError>> + 'a
+    where
+        I: IntoIterator<Item = (K, Arguments)>,
+        I::IntoIter: 'a,
+    {
+        arguments.into_iter().map(move |(key, arguments)| {
+            let program = self.instantiate(arguments)?;
+            let address = program.address(params);
+            Ok((key, program, address))
         })
     }
 
@@ -119,6 +306,10 @@ impl Program {
 pub struct InstantiatedProgram {
     inner: CompiledProgram,
     taproot_info: TaprootSpendInfo,
+    witness_types: WitnessTypes,
+    presets: WitnessPresets,
+    source: Arc<str>,
+    arguments: Arguments,
 }
 
 impl InstantiatedProgram {
@@ -153,7 +344,72 @@ impl InstantiatedProgram {
     /// ```
     #[must_use]
     pub fn address(&self, params: &'static elements::AddressParams) -> elements::Address {
-        let blinder = None;
+        self.address_with_blinder(params, None)
+    }
+
+    /// Generate a confidential taproot address for this program
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments, elements};
+    /// use secp256k1::{Secp256k1, SecretKey};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let blinding_key = SecretKey::from_slice(&[7; 32]).unwrap();
+    /// let blinding_pubkey = blinding_key.public_key(&Secp256k1::new());
+    /// let address = compiled.confidential_address(&elements::AddressParams::ELEMENTS, blinding_pubkey);
+    /// assert!(address.is_blinded());
+    /// ```
+    #[must_use]
+    pub fn confidential_address(
+        &self,
+        params: &'static elements::AddressParams,
+        blinding_pubkey: secp256k1::PublicKey,
+    ) -> elements::Address {
+        self.address_with_blinder(params, Some(blinding_pubkey))
+    }
+
+    /// Generate a confidential address with a fresh blinding keypair
+    ///
+    /// The returned secret blinding key is needed to unblind outputs sent to
+    /// the address and must be stored by the caller, or imported into the node
+    /// with [`ConfidentialAddress::import_into`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments, elements};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let confidential = compiled.new_confidential_address(
+    ///     &elements::AddressParams::ELEMENTS,
+    ///     &mut secp256k1::rand::thread_rng(),
+    /// );
+    /// assert!(confidential.address.is_blinded());
+    /// assert_eq!(confidential.blinding_key_hex().len(), 64);
+    /// ```
+    #[must_use]
+    pub fn new_confidential_address<R: secp256k1::rand::Rng + ?Sized>(
+        &self,
+        params: &'static elements::AddressParams,
+        rng: &mut R,
+    ) -> ConfidentialAddress {
+        let blinding_key = secp256k1::SecretKey::new(rng);
+        let blinding_pubkey = blinding_key.public_key(&secp256k1::Secp256k1::signing_only());
+        ConfidentialAddress {
+            address: self.confidential_address(params, blinding_pubkey),
+            blinding_key,
+        }
+    }
+
+    fn address_with_blinder(
+        &self,
+        params: &'static elements::AddressParams,
+        blinder: Option<secp256k1::PublicKey>,
+    ) -> elements::Address {
         elements::Address::p2tr(
             &secp256k1::Secp256k1::new(),
             self.taproot_info.internal_key(),
@@ -163,12 +419,175 @@ impl InstantiatedProgram {
         )
     }
 
+    /// Generate the taproot address for regtest, testnet and Liquid at once
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let addresses = compiled.addresses_all_networks();
+    /// assert!(addresses.regtest.to_string().starts_with("ert1p"));
+    /// assert!(addresses.testnet.to_string().starts_with("tex1p"));
+    /// assert!(addresses.liquid.to_string().starts_with("ex1p"));
+    /// ```
+    #[must_use]
+    pub fn addresses_all_networks(&self) -> NetworkAddresses {
+        NetworkAddresses::derive(|params| self.address(params))
+    }
+
+    /// Generate the confidential address for regtest, testnet and Liquid at once
+    #[must_use]
+    pub fn confidential_addresses_all_networks(
+        &self,
+        blinding_pubkey: secp256k1::PublicKey,
+    ) -> NetworkAddresses {
+        NetworkAddresses::derive(|params| self.confidential_address(params, blinding_pubkey))
+    }
+
+    /// Check whether a scriptPubkey is this program's taproot output
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments, elements};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let address = compiled.address(&elements::AddressParams::ELEMENTS);
+    /// assert!(compiled.owns_script(&address.script_pubkey()));
+    /// assert!(!compiled.owns_script(&elements::Script::new()));
+    /// ```
+    #[must_use]
+    pub fn owns_script(&self, script_pubkey: &elements::Script) -> bool {
+        *script_pubkey == elements::Script::new_v1_p2tr_tweaked(self.taproot_info.output_key())
+    }
+
+    /// Check whether an address pays to this program
+    ///
+    /// Only the taproot output is compared: the network and any blinding key
+    /// of `address` are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments, elements};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let address = compiled.address(&elements::AddressParams::LIQUID);
+    /// assert!(compiled.owns_address(&address));
+    /// ```
+    #[must_use]
+    pub fn owns_address(&self, address: &elements::Address) -> bool {
+        self.owns_script(&address.script_pubkey())
+    }
+
     /// Get the taproot spend info
     #[must_use]
     pub const fn taproot_info(&self) -> &TaprootSpendInfo {
         &self.taproot_info
     }
 
+    /// Get the taproot internal key
+    #[must_use]
+    pub fn internal_key(&self) -> XOnlyPublicKey {
+        self.taproot_info.internal_key()
+    }
+
+    /// Get the taproot output key (x-only)
+    #[must_use]
+    pub fn output_key(&self) -> XOnlyPublicKey {
+        self.taproot_info.output_key().into_inner()
+    }
+
+    /// Sign a key-path sighash for this program's output key
+    ///
+    /// `key` is the untweaked internal key the program was instantiated with;
+    /// it is tweaked by the program's merkle root, so the signature is valid
+    /// for key-path spends of the program's addresses.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Arguments, Program};
+    /// use secp256k1::{schnorr, Keypair, Message, Secp256k1};
+    ///
+    /// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program
+    ///     .instantiate_with_internal_key(Arguments::default(), keypair.x_only_public_key().0)
+    ///     .unwrap();
+    ///
+    /// let sighash = [7u8; 32];
+    /// let signature = compiled.sign_keypath(&keypair, sighash).unwrap();
+    /// let signature = schnorr::Signature::from_slice(&signature).unwrap();
+    /// assert!(Secp256k1::new()
+    ///     .verify_schnorr(&signature, &Message::from_digest(sighash), &compiled.output_key())
+    ///     .is_ok());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `key` is not the program's internal key.
+    pub fn sign_keypath<K: SigningKey + ?Sized>(
+        &self,
+        key: &K,
+        sighash: [u8; 32],
+    ) -> Result<[u8; 64], ProgramError> {
+        let keypair = key.keypair();
+        if keypair.x_only_public_key().0 != self.internal_key() {
+            return Err(ProgramError::TaprootError(
+                "Key is not the internal key of the program".into(),
+            ));
+        }
+        Ok(crate::util::sign_keypath(
+            &keypair,
+            self.taproot_info.merkle_root(),
+            sighash,
+        ))
+    }
+
+    /// Get the tapleaf hash of this program's leaf
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments};
+    /// use musk::elements::taproot::TapLeafHash;
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let (script, version) = compiled.script_version();
+    /// assert_eq!(compiled.leaf_hash(), TapLeafHash::from_script(&script, version));
+    /// ```
+    #[must_use]
+    pub fn leaf_hash(&self) -> elements::taproot::TapLeafHash {
+        let (script, version) = self.script_version();
+        elements::taproot::TapLeafHash::from_script(&script, version)
+    }
+
+    /// Get the taproot tweak applied to the internal key
+    ///
+    /// This is `hash_TapTweak(internal_key || merkle_root)`, the value added
+    /// to the internal key to obtain the output key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// assert_eq!(compiled.tap_tweak(), compiled.taproot_info().tap_tweak());
+    /// ```
+    #[must_use]
+    pub fn tap_tweak(&self) -> elements::taproot::TapTweakHash {
+        self.taproot_info.tap_tweak()
+    }
+
     /// Get the script and leaf version for taproot spending
     ///
     /// # Examples
@@ -187,6 +606,107 @@ impl InstantiatedProgram {
         (script, simplicityhl::simplicity::leaf_version())
     }
 
+    /// Get the timelock jets used anywhere in the program
+    ///
+    /// This includes branches a particular spend may not take; see
+    /// [`SatisfiedProgram::timelocks`] for the jets a spend actually executes.
+    #[must_use]
+    pub fn timelocks(&self) -> Timelocks {
+        Timelocks::from_jets(
+            self.inner
+                .commit()
+                .post_order_iter::<InternalSharing>()
+                .filter_map(|data| match data.node.inner() {
+                    Inner::Jet(jet) => Some(*jet),
+                    _ => None,
+                }),
+        )
+    }
+
+    /// Get the types of the witnesses the program needs
+    #[must_use]
+    pub const fn witness_types(&self) -> &WitnessTypes {
+        &self.witness_types
+    }
+
+    /// Get the witness presets of the program template
+    #[must_use]
+    pub const fn presets(&self) -> &WitnessPresets {
+        &self.presets
+    }
+
+    /// Get the source code of the program template
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Get the arguments the program was instantiated with
+    #[must_use]
+    pub const fn arguments(&self) -> &Arguments {
+        &self.arguments
+    }
+
+    /// Check that `witness_values` has exactly the witnesses the program needs
+    ///
+    /// This is much cheaper than [`Self::satisfy`], which runs it first, and
+    /// reports every missing, unexpected and mistyped witness at once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Arguments, Program, ProgramError};
+    ///
+    /// let program = Program::from_source(
+    ///     "fn main() { let x: u32 = witness::X; assert!(jet::eq_32(x, 1)); }",
+    /// )
+    /// .unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    ///
+    /// assert!(compiled.check_witness(&WitnessBuilder::new().with_u32("X", 1).build()).is_ok());
+    /// let wrong = WitnessBuilder::new().with_u8("X", 1).with_u8("Y", 2).build();
+    /// assert!(matches!(
+    ///     compiled.check_witness(&wrong),
+    ///     Err(ProgramError::WitnessMismatch { .. })
+    /// ));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the witnesses that are missing, not used by
+    /// the program or of the wrong type.
+    pub fn check_witness(&self, witness_values: &WitnessValues) -> Result<(), ProgramError> {
+        let mut missing = Vec::new();
+        let mut mistyped = Vec::new();
+        for (name, ty) in self.witness_types.iter() {
+            match witness_values.get(name) {
+                None => missing.push(name.to_string()),
+                Some(value) if value.ty() != ty => {
+                    mistyped.push(format!("{name} (expected {ty}, got {})", value.ty()));
+                }
+                Some(_) => {}
+            }
+        }
+        let mut unexpected: Vec<_> = witness_values
+            .iter()
+            .filter(|(name, _)| self.witness_types.get(name).is_none())
+            .map(|(name, _)| name.to_string())
+            .collect();
+
+        if missing.is_empty() && unexpected.is_empty() && mistyped.is_empty() {
+            return Ok(());
+        }
+        missing.sort();
+        unexpected.sort();
+        mistyped.sort();
+        Err(ProgramError::WitnessMismatch {
+            missing,
+            unexpected,
+            mistyped,
+        })
+    }
+
     /// Satisfy the program with witness values, producing a satisfied program
     ///
     /// # Examples
@@ -203,6 +723,7 @@ impl InstantiatedProgram {
     ///
     /// Returns an error if the witness values are invalid or incomplete.
     pub fn satisfy(&self, witness_values: WitnessValues) -> Result<SatisfiedProgram, ProgramError> {
+        self.check_witness(&witness_values)?;
         let satisfied = self
             .inner
             .satisfy(witness_values)
@@ -229,6 +750,129 @@ impl InstantiatedProgram {
     pub const fn inner(&self) -> &CompiledProgram {
         &self.inner
     }
+
+    /// Derive a diversified copy of this program for `index`
+    ///
+    /// The script leaf is unchanged, but the internal key is replaced by a
+    /// NUMS key tweaked with the program's CMR and `index`, so each index gets
+    /// a distinct, unlinkable address while the key path stays unspendable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Program, Arguments, elements};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let user_1 = compiled.diversify(1).unwrap();
+    /// let user_2 = compiled.diversify(2).unwrap();
+    ///
+    /// let params = &elements::AddressParams::ELEMENTS;
+    /// assert_ne!(user_1.address(params), user_2.address(params));
+    /// assert_eq!(user_1.cmr(), user_2.cmr());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the internal key cannot be derived or the taproot
+    /// tree cannot be built.
+    pub fn diversify(&self, index: u32) -> Result<Self, ProgramError> {
+        let internal_key = crate::util::nums_internal_key(self.diversification_tweak(index))
+            .map_err(|e| ProgramError::TaprootError(format!("Invalid internal key: {e}")))?;
+        let taproot_info = create_taproot_info_with_key(&self.inner, internal_key)?;
+        Ok(self.with_taproot_info(taproot_info))
+    }
+
+    /// Get the NUMS tweak used by [`Self::diversify`] for `index`
+    ///
+    /// Revealing the tweak lets a third party check with
+    /// `util::verify_nums_internal_key` that the diversified key path is unspendable.
+    #[must_use]
+    pub fn diversification_tweak(&self, index: u32) -> [u8; 32] {
+        let mut data = self.cmr().as_ref().to_vec();
+        data.extend_from_slice(&index.to_be_bytes());
+        crate::util::nums_tweak(&data)
+    }
+
+    /// Bind this program to a different taproot tree containing its leaf
+    pub(crate) fn with_taproot_info(&self, taproot_info: TaprootSpendInfo) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            taproot_info,
+            witness_types: self.witness_types.clone(),
+            presets: self.presets.clone(),
+            source: Arc::clone(&self.source),
+            arguments: self.arguments.clone(),
+        }
+    }
+}
+
+/// The same program address rendered for each supported network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkAddresses {
+    /// Elements regtest address
+    pub regtest: elements::Address,
+    /// Liquid testnet address
+    pub testnet: elements::Address,
+    /// Liquid mainnet address
+    pub liquid: elements::Address,
+}
+
+impl NetworkAddresses {
+    fn derive<F>(address: F) -> Self
+    where
+        F: Fn(&'static elements::AddressParams) -> elements::Address,
+    {
+        Self {
+            regtest: address(&elements::AddressParams::ELEMENTS),
+            testnet: address(&elements::AddressParams::LIQUID_TESTNET),
+            liquid: address(&elements::AddressParams::LIQUID),
+        }
+    }
+
+    /// Iterate over `(network name, address)` pairs
+    ///
+    /// Network names match those used in `musk.toml`.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &elements::Address)> {
+        [
+            ("regtest", &self.regtest),
+            ("testnet", &self.testnet),
+            ("liquidv1", &self.liquid),
+        ]
+        .into_iter()
+    }
+}
+
+/// A confidential address together with its secret blinding key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfidentialAddress {
+    /// Confidential (blinded) address
+    pub address: elements::Address,
+    /// Secret blinding key for unblinding outputs sent to `address`
+    pub blinding_key: secp256k1::SecretKey,
+}
+
+impl ConfidentialAddress {
+    /// Get the blinding key in the hex form accepted by `importblindingkey`
+    #[must_use]
+    pub fn blinding_key_hex(&self) -> String {
+        use elements::hex::ToHex;
+        self.blinding_key.secret_bytes().to_hex()
+    }
+
+    /// Import the address and its blinding key into a node's wallet
+    ///
+    /// The wallet then watches the address and unblinds outputs sent to it,
+    /// so `NodeClient::get_utxos` finds confidential deposits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either import fails or the client does not
+    /// support it.
+    pub fn import_into<C: NodeClient + ?Sized>(&self, client: &C) -> Result<(), ProgramError> {
+        client.import_address(&self.address)?;
+        client.import_blinding_key(&self.address, &self.blinding_key)
+    }
 }
 
 /// A satisfied Simplicity program ready to be encoded in a transaction witness
@@ -262,6 +906,23 @@ impl SatisfiedProgram {
         self.inner.redeem().to_vec_with_witness()
     }
 
+    /// Get the timelock jets executed by this spend
+    ///
+    /// Branches not taken by the witness are pruned, so only the timelocks
+    /// this spend must satisfy are reported.
+    #[must_use]
+    pub fn timelocks(&self) -> Timelocks {
+        Timelocks::from_jets(
+            self.inner
+                .redeem()
+                .post_order_iter::<InternalSharing>()
+                .filter_map(|data| match data.node.inner() {
+                    Inner::Jet(jet) => Some(*jet),
+                    _ => None,
+                }),
+        )
+    }
+
     /// Get the underlying satisfied program
     ///
     /// # Examples
@@ -283,6 +944,12 @@ impl SatisfiedProgram {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_fixtures::PARAMETERIZED_PROGRAM;
+    use simplicityhl::num::U256;
+    use simplicityhl::str::WitnessName;
+    use simplicityhl::value::ValueConstructible;
+    use simplicityhl::Value;
+    use std::collections::HashMap;
 
     #[test]
     fn test_from_source_valid() {
@@ -319,6 +986,162 @@ mod tests {
         assert!(address.to_string().starts_with("ert1p"));
     }
 
+    #[test]
+    fn test_custom_internal_key_changes_address() {
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let default = program.instantiate(Arguments::default()).unwrap();
+        let internal_key = crate::util::keypair_from_u32(1).x_only_public_key().0;
+        let custom = program
+            .instantiate_with_internal_key(Arguments::default(), internal_key)
+            .unwrap();
+
+        assert_eq!(default.cmr(), custom.cmr());
+        assert_eq!(custom.internal_key(), internal_key);
+        assert_ne!(
+            default.address(&elements::AddressParams::ELEMENTS),
+            custom.address(&elements::AddressParams::ELEMENTS)
+        );
+    }
+
+    #[test]
+    fn test_addresses_all_networks() {
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let compiled = program.instantiate(Arguments::default()).unwrap();
+        let addresses = compiled.addresses_all_networks();
+
+        assert_eq!(
+            addresses.liquid,
+            compiled.address(&elements::AddressParams::LIQUID)
+        );
+        assert_eq!(addresses.iter().count(), 3);
+
+        let blinding_pubkey = crate::util::keypair_from_u32(7).public_key();
+        let confidential = compiled.confidential_addresses_all_networks(blinding_pubkey);
+        assert!(confidential.iter().all(|(_, address)| address.is_blinded()));
+        assert_eq!(confidential.testnet.to_unconfidential(), addresses.testnet);
+    }
+
+    #[test]
+    fn test_owns_address() {
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let compiled = program.instantiate(Arguments::default()).unwrap();
+        let internal_key = crate::util::keypair_from_u32(1).x_only_public_key().0;
+        let other = program
+            .instantiate_with_internal_key(Arguments::default(), internal_key)
+            .unwrap();
+
+        let blinding_pubkey = crate::util::keypair_from_u32(7).public_key();
+        for (_, address) in compiled.addresses_all_networks().iter().chain(
+            compiled
+                .confidential_addresses_all_networks(blinding_pubkey)
+                .iter(),
+        ) {
+            assert!(compiled.owns_address(address));
+            assert!(!other.owns_address(address));
+        }
+    }
+
+    #[test]
+    fn test_derive_addresses() {
+        let program = Program::from_source(PARAMETERIZED_PROGRAM).unwrap();
+        let arguments = |value: u32| {
+            Arguments::from(HashMap::from([(
+                WitnessName::from_str_unchecked("VALUE"),
+                Value::u32(value),
+            )]))
+        };
+
+        let derived: Vec<_> = program
+            .derive_addresses(
+                (40..43).map(|value| (value, arguments(value))),
+                &elements::AddressParams::ELEMENTS,
+            )
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(derived.len(), 3);
+        for (value, compiled, address) in &derived {
+            let expected = program.instantiate(arguments(*value)).unwrap();
+            assert_eq!(compiled.cmr(), expected.cmr());
+            assert_eq!(
+                *address,
+                expected.address(&elements::AddressParams::ELEMENTS)
+            );
+        }
+        assert_ne!(derived[0].2, derived[1].2);
+
+        let wrong_type = Arguments::from(HashMap::from([(
+            WitnessName::from_str_unchecked("VALUE"),
+            Value::u256(U256::from_byte_array([0; 32])),
+        )]));
+        let mut results =
+            program.derive_addresses([(0, wrong_type)], &elements::AddressParams::ELEMENTS);
+        assert!(results.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_new_confidential_address() {
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let compiled = program.instantiate(Arguments::default()).unwrap();
+        let params = &elements::AddressParams::LIQUID_TESTNET;
+
+        let mut rng = secp256k1::rand::thread_rng();
+        let first = compiled.new_confidential_address(params, &mut rng);
+        let second = compiled.new_confidential_address(params, &mut rng);
+
+        let blinding_pubkey = first
+            .blinding_key
+            .public_key(&secp256k1::Secp256k1::signing_only());
+        assert_eq!(first.address.blinding_pubkey, Some(blinding_pubkey));
+        assert_eq!(first.address.to_unconfidential(), compiled.address(params));
+        assert_ne!(first.address, second.address);
+    }
+
+    #[test]
+    fn test_tweak_reproduces_output_key() {
+        use elements::hashes::Hash;
+
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let compiled = program.instantiate(Arguments::default()).unwrap();
+        let secp = secp256k1::Secp256k1::verification_only();
+
+        let (tweaked, parity) = compiled
+            .internal_key()
+            .add_tweak(&secp, &compiled.tap_tweak().to_scalar())
+            .unwrap();
+        assert_eq!(tweaked, compiled.output_key());
+        assert_eq!(parity, compiled.taproot_info().output_key_parity());
+
+        let merkle_root = compiled.taproot_info().merkle_root().unwrap();
+        assert_eq!(
+            merkle_root.to_byte_array(),
+            compiled.leaf_hash().to_byte_array()
+        );
+    }
+
+    #[test]
+    fn test_diversify() {
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let compiled = program.instantiate(Arguments::default()).unwrap();
+        let params = &elements::AddressParams::ELEMENTS;
+
+        let first = compiled.diversify(0).unwrap();
+        assert_eq!(
+            first.address(params),
+            compiled.diversify(0).unwrap().address(params)
+        );
+        assert_ne!(first.address(params), compiled.address(params));
+        assert_ne!(
+            first.address(params),
+            compiled.diversify(1).unwrap().address(params)
+        );
+        assert_eq!(first.leaf_hash(), compiled.leaf_hash());
+        assert!(crate::util::verify_nums_internal_key(
+            &first.internal_key(),
+            compiled.diversification_tweak(0)
+        ));
+    }
+
     #[test]
     fn test_satisfy_empty_witness() {
         let program = Program::from_source("fn main() { assert!(true); }").unwrap();
@@ -327,12 +1150,114 @@ mod tests {
         assert!(satisfied.is_ok());
     }
 
+    #[test]
+    fn test_witness_presets() {
+        let source = r#"
+// @preset claim: A, B
+// @preset refund: B
+fn main() {
+    let a: u8 = witness::A;
+    let b: u8 = witness::B;
+}
+"#;
+        let program = Program::from_source(source).unwrap();
+        assert_eq!(program.presets()["claim"], ["A", "B"]);
+        assert_eq!(program.presets()["refund"], ["B"]);
+
+        let program = program.with_preset("none", &[]).unwrap();
+        let compiled = program.instantiate(Arguments::default()).unwrap();
+        assert_eq!(compiled.presets().len(), 3);
+        assert!(compiled.presets()["none"].is_empty());
+
+        for invalid in [
+            "// @preset claim: C\nfn main() {}",
+            "// @preset claim A\nfn main() {}",
+        ] {
+            assert!(matches!(
+                Program::from_source(invalid),
+                Err(ProgramError::InvalidPreset(_))
+            ));
+        }
+        assert!(program.with_preset("claim", &["A"]).is_err());
+    }
+
+    #[test]
+    fn test_check_witness() {
+        use crate::test_fixtures::P2PK_PROGRAM;
+        use crate::witness::WitnessBuilder;
+
+        let compiled = Program::from_source(P2PK_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::from(HashMap::from([(
+                WitnessName::from_str_unchecked("PK"),
+                Value::u256(U256::from_byte_array([1; 32])),
+            )])))
+            .unwrap();
+        assert_eq!(compiled.witness_types().iter().count(), 1);
+        compiled
+            .check_witness(&WitnessBuilder::new().with_bytes("SIG", [0; 64]).build())
+            .unwrap();
+
+        let wrong = WitnessBuilder::new()
+            .with_bytes("SIG", [0; 32])
+            .with_u32("EXTRA", 1)
+            .build();
+        match compiled.check_witness(&wrong) {
+            Err(ProgramError::WitnessMismatch {
+                missing,
+                unexpected,
+                mistyped,
+            }) => {
+                assert!(missing.is_empty());
+                assert_eq!(unexpected, vec!["EXTRA".to_string()]);
+                assert_eq!(mistyped.len(), 1);
+                assert!(mistyped[0].starts_with("SIG"));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(matches!(
+            compiled.satisfy(WitnessValues::default()),
+            Err(ProgramError::WitnessMismatch { missing, .. }) if missing == ["SIG"]
+        ));
+    }
+
+    #[test]
+    fn test_sign_keypath() {
+        use crate::util::keypair_from_u32;
+        use secp256k1::{schnorr, Message, Secp256k1};
+
+        let keypair = keypair_from_u32(2);
+        let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+        let compiled = program
+            .instantiate_with_internal_key(Arguments::default(), keypair.x_only_public_key().0)
+            .unwrap();
+
+        let sighash = [3u8; 32];
+        let signature = compiled
+            .sign_keypath(&keypair.secret_key(), sighash)
+            .unwrap();
+        let signature = schnorr::Signature::from_slice(&signature).unwrap();
+        assert!(Secp256k1::new()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(sighash),
+                &compiled.output_key()
+            )
+            .is_ok());
+
+        assert!(compiled
+            .sign_keypath(&keypair_from_u32(3), sighash)
+            .is_err());
+        let nums = program.instantiate(Arguments::default()).unwrap();
+        assert!(nums.sign_keypath(&keypair, sighash).is_err());
+    }
+
     #[test]
     fn test_encode() {
         let program = Program::from_source("fn main() { assert!(true); }").unwrap();
         let compiled = program.instantiate(Arguments::default()).unwrap();
         let satisfied = compiled.satisfy(WitnessValues::default()).unwrap();
-        let (program_bytes, witness) = satisfied.encode();
+        let (program_bytes, _witness) = satisfied.encode();
         assert!(!program_bytes.is_empty());
     }
 
@@ -0,0 +1,298 @@
+//! Proprietary PSET fields for Simplicity spends
+//!
+//! A PSET only describes a program input by its taproot leaf, which commits
+//! to the program's CMR but not the program itself. So that several musk
+//! signers can work on the same PSET without exchanging anything else,
+//! [`set_program`] stores the program in proprietary fields of the input,
+//! and [`add_witness`] the witness values filled in so far:
+//!
+//! | Subtype | Value |
+//! |---------|-------|
+//! | [`PSET_IN_SOURCE`] | SimplicityHL source of the program template, UTF-8 |
+//! | [`PSET_IN_ARGUMENTS`] | Arguments, as JSON from `witness::arguments_to_json` |
+//! | [`PSET_IN_CMR`] | CMR of the program, 32 bytes |
+//! | [`PSET_IN_WITNESS`] | Witness values so far, as JSON from `witness::to_json` |
+//!
+//! All fields use the prefix [`PROPRIETARY_PREFIX`] and an empty key.
+//! `SpendBuilder::to_pset` sets the program fields of every program input.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::pset;
+//! use musk::witness::WitnessBuilder;
+//! use musk::{Arguments, Program};
+//!
+//! let program = Program::from_source(
+//!     "fn main() { let x: u32 = witness::X; assert!(jet::eq_32(x, 7)); }",
+//! )
+//! .unwrap();
+//! let compiled = program.instantiate(Arguments::default()).unwrap();
+//!
+//! let mut input = musk::elements::pset::Input::default();
+//! pset::set_program(&mut input, &compiled).unwrap();
+//! pset::add_witness(&mut input, &compiled, &WitnessBuilder::new().with_u32("X", 7).build())
+//!     .unwrap();
+//!
+//! let restored = pset::program(&input).unwrap().unwrap();
+//! assert_eq!(restored.cmr(), compiled.cmr());
+//! let witness = pset::witness(&input, &restored).unwrap();
+//! assert!(restored.satisfy(witness).is_ok());
+//! ```
+
+use crate::error::ProgramError;
+use crate::program::{InstantiatedProgram, Program};
+use crate::witness;
+use elements::pset::raw::ProprietaryKey;
+use elements::pset::Input;
+use simplicityhl::simplicity::Cmr;
+use simplicityhl::{Parameters, WitnessValues};
+use std::collections::HashMap;
+
+/// Prefix of musk's proprietary PSET keys
+pub const PROPRIETARY_PREFIX: &[u8] = b"musk";
+
+/// Subtype of the SimplicityHL source of an input's program
+pub const PSET_IN_SOURCE: u8 = 0x00;
+
+/// Subtype of the arguments of an input's program
+pub const PSET_IN_ARGUMENTS: u8 = 0x01;
+
+/// Subtype of the CMR of an input's program
+pub const PSET_IN_CMR: u8 = 0x02;
+
+/// Subtype of the witness values of an input filled in so far
+pub const PSET_IN_WITNESS: u8 = 0x03;
+
+/// Build the proprietary key of `subtype`
+#[must_use]
+pub fn proprietary_key(subtype: u8) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype,
+        key: Vec::new(),
+    }
+}
+
+/// Store `program` in the proprietary fields of `input`
+///
+/// # Errors
+///
+/// Returns an error if the program's arguments cannot be serialized.
+pub fn set_program(input: &mut Input, program: &InstantiatedProgram) -> Result<(), ProgramError> {
+    let arguments = witness::arguments_to_json(program.arguments())?;
+    input.proprietary.insert(
+        proprietary_key(PSET_IN_SOURCE),
+        program.source().as_bytes().to_vec(),
+    );
+    input
+        .proprietary
+        .insert(proprietary_key(PSET_IN_ARGUMENTS), arguments.into_bytes());
+    input.proprietary.insert(
+        proprietary_key(PSET_IN_CMR),
+        program.cmr().as_ref().to_vec(),
+    );
+    Ok(())
+}
+
+/// Get the CMR of the program stored in `input`, if any
+///
+/// # Errors
+///
+/// Returns an error if the stored CMR is not 32 bytes.
+pub fn cmr(input: &Input) -> Result<Option<Cmr>, ProgramError> {
+    field(input, PSET_IN_CMR)
+        .map(|bytes| {
+            <[u8; 32]>::try_from(bytes)
+                .map(Cmr::from_byte_array)
+                .map_err(|_| invalid("CMR is not 32 bytes"))
+        })
+        .transpose()
+}
+
+/// Rebuild the program stored in `input`, if any
+///
+/// The program is instantiated with the input's taproot internal key, and
+/// must match both the stored CMR and the input's taproot merkle root, so a
+/// program sharing its taproot tree with other leaves cannot be rebuilt.
+///
+/// # Errors
+///
+/// Returns an error if a field is missing or malformed, or the program does
+/// not match the input.
+pub fn program(input: &Input) -> Result<Option<InstantiatedProgram>, ProgramError> {
+    let Some(source) = field(input, PSET_IN_SOURCE) else {
+        return Ok(None);
+    };
+    let source = std::str::from_utf8(source).map_err(|_| invalid("source is not UTF-8"))?;
+    let arguments = field(input, PSET_IN_ARGUMENTS)
+        .ok_or_else(|| invalid("arguments are missing"))
+        .and_then(|json| {
+            std::str::from_utf8(json).map_err(|_| invalid("arguments are not UTF-8"))
+        })?;
+
+    let template = Program::from_source(source)?;
+    let arguments = witness::arguments_from_json(arguments, template.parameters())?;
+    let program = match input.tap_internal_key {
+        Some(internal_key) => template.instantiate_with_internal_key(arguments, internal_key)?,
+        None => template.instantiate(arguments)?,
+    };
+
+    if cmr(input)? != Some(program.cmr()) {
+        return Err(invalid("program does not match its CMR"));
+    }
+    if input.tap_merkle_root.is_some()
+        && input.tap_merkle_root != program.taproot_info().merkle_root()
+    {
+        return Err(invalid("program does not match the taproot merkle root"));
+    }
+    Ok(Some(program))
+}
+
+/// Get the witness values of `input` filled in so far
+///
+/// # Errors
+///
+/// Returns an error if the stored witness values are malformed or do not
+/// fit `program`.
+pub fn witness(
+    input: &Input,
+    program: &InstantiatedProgram,
+) -> Result<WitnessValues, ProgramError> {
+    let Some(json) = field(input, PSET_IN_WITNESS) else {
+        return Ok(WitnessValues::default());
+    };
+    let json = std::str::from_utf8(json).map_err(|_| invalid("witness is not UTF-8"))?;
+    witness::from_json_partial(json, &witness_schema(program))
+}
+
+/// Add witness values to those already in `input`
+///
+/// Each signer adds the witnesses it is responsible for.
+///
+/// # Errors
+///
+/// Returns an error if a witness does not fit `program`, or
+/// [`ProgramError::WitnessConflict`] if it already has a different value.
+pub fn add_witness(
+    input: &mut Input,
+    program: &InstantiatedProgram,
+    witness_values: &WitnessValues,
+) -> Result<(), ProgramError> {
+    let values = witness::merge([&witness(input, program)?, witness_values])?;
+    let json = witness::to_json(&values)?;
+    // Check the types before storing, as readers will
+    witness::from_json_partial(&json, &witness_schema(program))?;
+    input
+        .proprietary
+        .insert(proprietary_key(PSET_IN_WITNESS), json.into_bytes());
+    Ok(())
+}
+
+fn field(input: &Input, subtype: u8) -> Option<&[u8]> {
+    input
+        .proprietary
+        .get(&proprietary_key(subtype))
+        .map(Vec::as_slice)
+}
+
+fn witness_schema(program: &InstantiatedProgram) -> Parameters {
+    Parameters::from(
+        program
+            .witness_types()
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.clone()))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+fn invalid(message: &str) -> ProgramError {
+    ProgramError::SerializationError(format!("Invalid musk PSET field: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spend::SpendBuilder;
+    use crate::test_fixtures::{test_genesis_hash, test_utxo};
+    use crate::witness::WitnessBuilder;
+    use simplicityhl::Arguments;
+
+    const TWO_PARTY_PROGRAM: &str = r#"
+fn main() {
+    let a: u32 = witness::A;
+    let b: u32 = witness::B;
+    assert!(jet::eq_32(a, b));
+}
+"#;
+
+    #[test]
+    fn test_signers_fill_witness_in_pset() {
+        let program = Program::from_source(TWO_PARTY_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let utxo = crate::client::Utxo {
+            script_pubkey: program
+                .address(&elements::AddressParams::ELEMENTS)
+                .script_pubkey(),
+            ..test_utxo()
+        };
+        let mut builder = SpendBuilder::try_new(program, utxo)
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_output_simple(
+            elements::Script::new(),
+            99_000_000,
+            elements::AssetId::default(),
+        );
+        builder.add_fee(1_000_000, elements::AssetId::default());
+        let mut pset = builder.to_pset().unwrap();
+
+        // Each signer only has the PSET
+        for (name, value) in [("A", 5), ("B", 5)] {
+            let input = &mut pset.inputs_mut()[0];
+            let program = super::program(input).unwrap().unwrap();
+            let witness = WitnessBuilder::new().with_u32(name, value).build();
+            add_witness(input, &program, &witness).unwrap();
+        }
+
+        let input = &pset.inputs()[0];
+        let program = super::program(input).unwrap().unwrap();
+        let witness = super::witness(input, &program).unwrap();
+        let tx = builder.clone().finalize(witness).unwrap();
+        builder.verify(&tx).unwrap();
+
+        let input = &mut pset.inputs_mut()[0];
+        let conflicting = WitnessBuilder::new().with_u32("A", 6).build();
+        assert!(matches!(
+            add_witness(input, &program, &conflicting),
+            Err(ProgramError::WitnessConflict { .. })
+        ));
+        let mistyped = WitnessBuilder::new().with_u8("C", 1).build();
+        assert!(add_witness(input, &program, &mistyped).is_err());
+    }
+
+    #[test]
+    fn test_program_must_match_input() {
+        let program = Program::from_source(TWO_PARTY_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let mut input = Input::default();
+        assert!(super::program(&input).unwrap().is_none());
+        assert!(cmr(&input).unwrap().is_none());
+
+        set_program(&mut input, &program).unwrap();
+        assert_eq!(cmr(&input).unwrap(), Some(program.cmr()));
+
+        input
+            .proprietary
+            .insert(proprietary_key(PSET_IN_CMR), vec![0; 32]);
+        assert!(super::program(&input).is_err());
+        input
+            .proprietary
+            .insert(proprietary_key(PSET_IN_CMR), vec![0; 31]);
+        assert!(cmr(&input).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+//! Node client that limits the load on another client's backend
+//!
+//! Bulk operations, such as scanning the UTXOs of thousands of addresses,
+//! can flood a shared Elements node or get a client banned from a hosted
+//! Esplora API. [`RateLimitedClient`] spaces out the requests to the wrapped
+//! client and bounds how many run at the same time, blocking callers until
+//! they may proceed.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use musk::{EsploraClient, NodeClient, RateLimitedClient};
+//! use std::time::Duration;
+//!
+//! let client = RateLimitedClient::new(EsploraClient::liquid())
+//!     .with_max_rate(10, Duration::from_secs(1))
+//!     .with_max_concurrent(4);
+//! for address in &addresses {
+//!     let utxos = client.get_utxos(address)?;
+//! }
+//! ```
+
+use crate::client::{ClientResult, MempoolInfo, NodeClient, Utxo};
+use elements::{Address, BlockHash, Transaction, Txid};
+use secp256k1::SecretKey;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Node client that limits the rate and concurrency of requests to another
+/// client
+///
+/// Without limits every request passes straight through. Each method of
+/// [`NodeClient`] counts as one request, even if the wrapped client makes
+/// several calls to its backend to serve it.
+#[derive(Debug)]
+pub struct RateLimitedClient<C> {
+    inner: C,
+    interval: Duration,
+    burst: u32,
+    max_concurrent: Option<usize>,
+    state: Mutex<State>,
+    finished: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Earliest start of the next request when no burst is left
+    next_start: Instant,
+    /// Requests currently running
+    running: usize,
+}
+
+impl<C: NodeClient> RateLimitedClient<C> {
+    /// Wrap a client without any limits
+    #[must_use]
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            interval: Duration::ZERO,
+            burst: 1,
+            max_concurrent: None,
+            state: Mutex::new(State {
+                next_start: Instant::now(),
+                running: 0,
+            }),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Allow at most `requests` requests in every `period`, evenly spaced
+    ///
+    /// A `requests` of 0 is treated as 1.
+    #[must_use]
+    pub fn with_max_rate(mut self, requests: u32, period: Duration) -> Self {
+        self.interval = period / requests.max(1);
+        self
+    }
+
+    /// Allow up to `burst` requests back to back after an idle spell, before
+    /// spacing out the rest; the default is 1
+    ///
+    /// A `burst` of 0 is treated as 1.
+    #[must_use]
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Allow at most `max_concurrent` requests to run at the same time
+    ///
+    /// A `max_concurrent` of 0 is treated as 1.
+    #[must_use]
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent.max(1));
+        self
+    }
+
+    /// Get the wrapped client
+    #[must_use]
+    pub const fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the client
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("rate limit lock poisoned")
+    }
+
+    /// Wait for a turn, then run `call` against the wrapped client
+    fn limit<T>(&self, call: impl FnOnce(&C) -> ClientResult<T>) -> ClientResult<T> {
+        self.wait_for_rate();
+        let _permit = self.acquire_permit();
+        call(&self.inner)
+    }
+
+    /// Reserve the next start time and sleep until it
+    fn wait_for_rate(&self) {
+        if self.interval.is_zero() {
+            return;
+        }
+        let start = {
+            let mut state = self.state();
+            let now = Instant::now();
+            // An idle client builds up credit for at most `burst` requests
+            let credit = self
+                .interval
+                .checked_mul(self.burst - 1)
+                .unwrap_or(Duration::MAX);
+            let start = match state.next_start.checked_duration_since(now) {
+                Some(ahead) if ahead > credit => state.next_start - credit,
+                _ => now,
+            };
+            state.next_start = state.next_start.max(now) + self.interval;
+            start
+        };
+        if let Some(wait) = start.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Wait until fewer than `max_concurrent` requests run
+    fn acquire_permit(&self) -> Option<Permit<'_, C>> {
+        let max_concurrent = self.max_concurrent?;
+        let mut state = self.state();
+        while state.running >= max_concurrent {
+            state = self.finished.wait(state).expect("rate limit lock poisoned");
+        }
+        state.running += 1;
+        Some(Permit { client: self })
+    }
+}
+
+/// Slot of a running request, freed when dropped
+struct Permit<'a, C> {
+    client: &'a RateLimitedClient<C>,
+}
+
+impl<C> Drop for Permit<'_, C> {
+    fn drop(&mut self) {
+        // Free the slot even if the lock was poisoned by a panicking request
+        let mut state = self
+            .client
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        state.running -= 1;
+        self.client.finished.notify_one();
+    }
+}
+
+impl<C: NodeClient> NodeClient for RateLimitedClient<C> {
+    fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+        self.limit(|client| client.send_to_address(addr, amount))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+        self.limit(|client| client.get_transaction(txid))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+        self.limit(|client| client.broadcast(tx))
+    }
+
+    fn test_accept(&self, tx: &Transaction) -> ClientResult<()> {
+        self.limit(|client| client.test_accept(tx))
+    }
+
+    fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+        self.limit(|client| client.generate_blocks(count))
+    }
+
+    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+        self.limit(|client| client.get_utxos(address))
+    }
+
+    fn get_utxos_with_min_conf(&self, address: &Address, min_conf: u32) -> ClientResult<Vec<Utxo>> {
+        self.limit(|client| client.get_utxos_with_min_conf(address, min_conf))
+    }
+
+    fn get_new_address(&self) -> ClientResult<Address> {
+        self.limit(NodeClient::get_new_address)
+    }
+
+    fn import_address(&self, address: &Address) -> ClientResult<()> {
+        self.limit(|client| client.import_address(address))
+    }
+
+    fn import_blinding_key(&self, address: &Address, blinding_key: &SecretKey) -> ClientResult<()> {
+        self.limit(|client| client.import_blinding_key(address, blinding_key))
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        self.limit(NodeClient::get_block_count)
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        self.limit(|client| client.get_block_hash(height))
+    }
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        self.limit(|client| client.estimate_fee_rate(target_blocks))
+    }
+
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        self.limit(NodeClient::get_mempool_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::test_address;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Client whose `get_block_count` takes a while, recording how many
+    /// calls overlap
+    struct SlowClient {
+        inner: MockClient,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl SlowClient {
+        fn new() -> Self {
+            Self {
+                inner: MockClient::new(),
+                running: AtomicUsize::new(0),
+                max_running: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl NodeClient for SlowClient {
+        fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+            self.inner.send_to_address(addr, amount)
+        }
+
+        fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+            self.inner.get_transaction(txid)
+        }
+
+        fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+            self.inner.broadcast(tx)
+        }
+
+        fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+            self.inner.generate_blocks(count)
+        }
+
+        fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+            self.inner.get_utxos(address)
+        }
+
+        fn get_new_address(&self) -> ClientResult<Address> {
+            self.inner.get_new_address()
+        }
+
+        fn get_block_count(&self) -> ClientResult<u64> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.inner.get_block_count()
+        }
+    }
+
+    #[test]
+    fn test_requests_are_spaced_out() {
+        let client =
+            RateLimitedClient::new(MockClient::new()).with_max_rate(100, Duration::from_secs(1));
+        let start = Instant::now();
+        for _ in 0..5 {
+            client.get_utxos(&test_address()).unwrap();
+        }
+        // The first request starts at once, the others 10ms apart
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_burst() {
+        let client = RateLimitedClient::new(MockClient::new())
+            .with_max_rate(1, Duration::from_secs(60))
+            .with_burst(3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            client.get_block_count().unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_concurrency_is_bounded() {
+        let client = RateLimitedClient::new(SlowClient::new()).with_max_concurrent(2);
+        std::thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| client.get_block_count().unwrap());
+            }
+        });
+        assert!(client.inner().max_running.load(Ordering::SeqCst) <= 2);
+        assert_eq!(client.state().running, 0);
+    }
+}
@@ -0,0 +1,181 @@
+//! Reverse lookup from scriptPubkeys to programs
+//!
+//! Nodes report UTXOs by scriptPubkey, not by contract. A [`ProgramRegistry`]
+//! remembers every program a service has handed out addresses for, so
+//! incoming UTXOs can be matched back to the program that can spend them.
+//! Confidential and unconfidential addresses of a program share the same
+//! scriptPubkey, so both forms resolve to the same entry.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::registry::ProgramRegistry;
+//! use musk::{Arguments, Program, elements};
+//!
+//! let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+//! let compiled = program.instantiate(Arguments::default()).unwrap();
+//! let address = compiled.address(&elements::AddressParams::ELEMENTS);
+//!
+//! let mut registry = ProgramRegistry::new();
+//! registry.register(compiled);
+//!
+//! assert!(registry.get_address(&address).is_some());
+//! ```
+
+use crate::client::Utxo;
+use crate::error::SpendError;
+use crate::program::InstantiatedProgram;
+use crate::spend::{NeedsGenesis, SpendBuilder};
+use elements::{Address, Script};
+use std::collections::HashMap;
+
+/// Lookup table from scriptPubkey to the program that produced it
+#[derive(Clone, Default)]
+pub struct ProgramRegistry {
+    programs: HashMap<Script, InstantiatedProgram>,
+}
+
+impl ProgramRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a program, returning its scriptPubkey
+    ///
+    /// A program with the same scriptPubkey that was registered earlier is replaced.
+    pub fn register(&mut self, program: InstantiatedProgram) -> Script {
+        let script_pubkey = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .script_pubkey();
+        self.programs.insert(script_pubkey.clone(), program);
+        script_pubkey
+    }
+
+    /// Remove a program by scriptPubkey
+    pub fn unregister(&mut self, script_pubkey: &Script) -> Option<InstantiatedProgram> {
+        self.programs.remove(script_pubkey)
+    }
+
+    /// Look up the program for a scriptPubkey
+    #[must_use]
+    pub fn get(&self, script_pubkey: &Script) -> Option<&InstantiatedProgram> {
+        self.programs.get(script_pubkey)
+    }
+
+    /// Look up the program for an address, confidential or not
+    #[must_use]
+    pub fn get_address(&self, address: &Address) -> Option<&InstantiatedProgram> {
+        self.get(&address.script_pubkey())
+    }
+
+    /// Get the number of registered programs
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    /// Check whether no programs are registered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Iterate over the registered programs, in no particular order
+    pub fn programs(&self) -> impl Iterator<Item = &InstantiatedProgram> {
+        self.programs.values()
+    }
+
+    /// Pair each UTXO with the program that can spend it, skipping unknown UTXOs
+    pub fn match_utxos<I>(&self, utxos: I) -> Vec<(Utxo, &InstantiatedProgram)>
+    where
+        I: IntoIterator<Item = Utxo>,
+    {
+        utxos
+            .into_iter()
+            .filter_map(|utxo| {
+                let program = self.get(&utxo.script_pubkey)?;
+                Some((utxo, program))
+            })
+            .collect()
+    }
+
+    /// Create a spend builder for a UTXO of a registered program
+    ///
+    /// The builder still needs the genesis hash before it can be signed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no registered program matches the UTXO's scriptPubkey.
+    pub fn spend(&self, utxo: Utxo) -> Result<SpendBuilder<NeedsGenesis>, SpendError> {
+        let program = self.get(&utxo.script_pubkey).cloned().ok_or_else(|| {
+            SpendError::InvalidUtxo(format!(
+                "No registered program for {}:{}",
+                utxo.txid, utxo.vout
+            ))
+        })?;
+        SpendBuilder::try_new(program, utxo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::test_fixtures::{test_utxo, CAT_PROGRAM, SIMPLE_PROGRAM};
+    use simplicityhl::Arguments;
+
+    fn instantiate(source: &str) -> InstantiatedProgram {
+        Program::from_source(source)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_lookup_confidential_and_unconfidential() {
+        let program = instantiate(SIMPLE_PROGRAM);
+        let blinding_pubkey = crate::util::keypair_from_u32(7).public_key();
+
+        let mut registry = ProgramRegistry::new();
+        registry.register(program.clone());
+
+        for (_, address) in program.addresses_all_networks().iter().chain(
+            program
+                .confidential_addresses_all_networks(blinding_pubkey)
+                .iter(),
+        ) {
+            let found = registry.get_address(address).unwrap();
+            assert_eq!(found.cmr(), program.cmr());
+        }
+    }
+
+    #[test]
+    fn test_match_utxos() {
+        let simple = instantiate(SIMPLE_PROGRAM);
+
+        let mut registry = ProgramRegistry::new();
+        let simple_spk = registry.register(simple.clone());
+        registry.register(instantiate(CAT_PROGRAM));
+        assert_eq!(registry.len(), 2);
+
+        let mut owned = test_utxo();
+        owned.script_pubkey = simple_spk;
+        let matched = registry.match_utxos(vec![owned.clone(), test_utxo()]);
+
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].1.cmr(), simple.cmr());
+        assert!(registry.spend(owned).is_ok());
+        assert!(registry.spend(test_utxo()).is_err());
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut registry = ProgramRegistry::new();
+        let script_pubkey = registry.register(instantiate(SIMPLE_PROGRAM));
+
+        assert!(registry.unregister(&script_pubkey).is_some());
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,400 @@
+//! Node client that retries transient failures
+//!
+//! [`RetryClient`] wraps any [`NodeClient`] and retries calls that fail with a
+//! transport error, such as a refused or reset connection or a timeout, with
+//! exponential backoff. Errors reported by the node itself, such as a rejected
+//! transaction or an unsupported method, are returned straight away.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use musk::{NodeClient, RetryClient, RetryPolicy, RpcClient};
+//! use std::time::Duration;
+//!
+//! let client = RetryClient::new(RpcClient::from_url(url, user, password)?)
+//!     .with_policy(RetryPolicy::new().with_max_retries(5));
+//! let utxos = client.get_utxos(&address)?;
+//! ```
+
+use crate::client::{ClientResult, MempoolInfo, NodeClient, Utxo};
+use crate::error::ProgramError;
+use elements::{Address, BlockHash, Transaction, Txid};
+use secp256k1::SecretKey;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+/// Exponential backoff between the attempts of a call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+}
+
+impl RetryPolicy {
+    /// Retry 3 times, waiting 100ms and doubling the wait up to 5s
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+        }
+    }
+
+    /// Set the number of retries after the first attempt
+    #[must_use]
+    pub const fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the wait before the first retry
+    #[must_use]
+    pub const fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    /// Set the longest wait between two attempts
+    #[must_use]
+    pub const fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the factor by which the wait grows after each retry, at least 1
+    #[must_use]
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Get the number of retries after the first attempt
+    #[must_use]
+    pub const fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Get the wait before retry number `retry`, counting from 0
+    #[must_use]
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = i32::try_from(retry).unwrap_or(i32::MAX);
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        // `min` also turns an infinite or NaN delay into the maximum
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check whether an error is a transport failure that may succeed on retry
+///
+/// Only I/O errors of a connection-level kind count, e.g. a refused or reset
+/// connection or a timeout. Rejected transactions, unsupported methods and
+/// errors returned by the node are not transient.
+#[must_use]
+pub fn is_transient(error: &ProgramError) -> bool {
+    match error {
+        ProgramError::IoError(e) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+                | ErrorKind::TimedOut
+                | ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+/// Node client that retries transient failures of another client
+///
+/// `send_to_address` and `generate_blocks` are never retried: a request that
+/// reached the node before the connection failed may already have paid or
+/// mined, and repeating it would do so twice. Every other call only reads
+/// from the node or is safe to repeat.
+#[derive(Debug, Clone)]
+pub struct RetryClient<C> {
+    inner: C,
+    policy: RetryPolicy,
+    is_retryable: fn(&ProgramError) -> bool,
+}
+
+impl<C: NodeClient> RetryClient<C> {
+    /// Wrap a client with the default [`RetryPolicy`], retrying the errors
+    /// for which [`is_transient`] holds
+    #[must_use]
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            policy: RetryPolicy::new(),
+            is_retryable: is_transient,
+        }
+    }
+
+    /// Set the backoff between attempts
+    #[must_use]
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set which errors are retried, instead of [`is_transient`]
+    #[must_use]
+    pub fn with_classifier(mut self, is_retryable: fn(&ProgramError) -> bool) -> Self {
+        self.is_retryable = is_retryable;
+        self
+    }
+
+    /// Get the backoff between attempts
+    #[must_use]
+    pub const fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Get the wrapped client
+    #[must_use]
+    pub const fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Unwrap the client
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    /// Run `call` until it succeeds, fails with an error that is not
+    /// retryable, or runs out of retries
+    fn retry<T>(&self, mut call: impl FnMut(&C) -> ClientResult<T>) -> ClientResult<T> {
+        let mut retry = 0;
+        loop {
+            match call(&self.inner) {
+                Err(e) if retry < self.policy.max_retries && (self.is_retryable)(&e) => {
+                    std::thread::sleep(self.policy.delay(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<C: NodeClient> NodeClient for RetryClient<C> {
+    fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+        self.inner.send_to_address(addr, amount)
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+        self.retry(|client| client.get_transaction(txid))
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+        self.retry(|client| client.broadcast(tx))
+    }
+
+    fn test_accept(&self, tx: &Transaction) -> ClientResult<()> {
+        self.retry(|client| client.test_accept(tx))
+    }
+
+    fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+        self.inner.generate_blocks(count)
+    }
+
+    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+        self.retry(|client| client.get_utxos(address))
+    }
+
+    fn get_utxos_with_min_conf(&self, address: &Address, min_conf: u32) -> ClientResult<Vec<Utxo>> {
+        self.retry(|client| client.get_utxos_with_min_conf(address, min_conf))
+    }
+
+    fn get_new_address(&self) -> ClientResult<Address> {
+        self.retry(NodeClient::get_new_address)
+    }
+
+    fn import_address(&self, address: &Address) -> ClientResult<()> {
+        self.retry(|client| client.import_address(address))
+    }
+
+    fn import_blinding_key(&self, address: &Address, blinding_key: &SecretKey) -> ClientResult<()> {
+        self.retry(|client| client.import_blinding_key(address, blinding_key))
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        self.retry(NodeClient::get_block_count)
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        self.retry(|client| client.get_block_hash(height))
+    }
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        self.retry(|client| client.estimate_fee_rate(target_blocks))
+    }
+
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        self.retry(NodeClient::get_mempool_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::RejectReason;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::test_address;
+    use std::cell::Cell;
+
+    /// Client whose calls fail with `kind` until `failures` runs out
+    struct FlakyClient {
+        inner: MockClient,
+        kind: ErrorKind,
+        failures: Cell<u32>,
+        calls: Cell<u32>,
+    }
+
+    impl FlakyClient {
+        fn new(kind: ErrorKind, failures: u32) -> Self {
+            Self {
+                inner: MockClient::new(),
+                kind,
+                failures: Cell::new(failures),
+                calls: Cell::new(0),
+            }
+        }
+
+        fn check(&self) -> ClientResult<()> {
+            self.calls.set(self.calls.get() + 1);
+            if self.failures.get() == 0 {
+                return Ok(());
+            }
+            self.failures.set(self.failures.get() - 1);
+            Err(ProgramError::IoError(std::io::Error::new(
+                self.kind, "flaky",
+            )))
+        }
+    }
+
+    impl NodeClient for FlakyClient {
+        fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+            self.check()?;
+            self.inner.send_to_address(addr, amount)
+        }
+
+        fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+            self.check()?;
+            self.inner.get_transaction(txid)
+        }
+
+        fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+            self.check()?;
+            self.inner.broadcast(tx)
+        }
+
+        fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+            self.check()?;
+            self.inner.generate_blocks(count)
+        }
+
+        fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+            self.check()?;
+            self.inner.get_utxos(address)
+        }
+
+        fn get_new_address(&self) -> ClientResult<Address> {
+            self.check()?;
+            self.inner.get_new_address()
+        }
+
+        fn get_block_count(&self) -> ClientResult<u64> {
+            self.check()?;
+            self.inner.get_block_count()
+        }
+    }
+
+    fn no_delay() -> RetryPolicy {
+        RetryPolicy::new().with_initial_delay(Duration::ZERO)
+    }
+
+    #[test]
+    fn test_transient_errors_are_retried() {
+        let client = RetryClient::new(FlakyClient::new(ErrorKind::ConnectionReset, 2))
+            .with_policy(no_delay());
+        assert!(client.get_block_count().is_ok());
+        assert_eq!(client.inner().calls.get(), 3);
+
+        client.inner().failures.set(2);
+        client.inner().calls.set(0);
+        assert!(client.get_utxos(&test_address()).is_ok());
+        assert_eq!(client.inner().calls.get(), 3);
+    }
+
+    #[test]
+    fn test_retries_run_out() {
+        let client = RetryClient::new(FlakyClient::new(ErrorKind::TimedOut, 10))
+            .with_policy(no_delay().with_max_retries(2));
+        let error = client.get_block_count().unwrap_err();
+        assert!(matches!(error, ProgramError::IoError(e) if e.kind() == ErrorKind::TimedOut));
+        assert_eq!(client.inner().calls.get(), 3);
+    }
+
+    #[test]
+    fn test_other_errors_are_not_retried() {
+        let client =
+            RetryClient::new(FlakyClient::new(ErrorKind::Other, 1)).with_policy(no_delay());
+        assert!(client.get_block_count().is_err());
+        assert_eq!(client.inner().calls.get(), 1);
+
+        let mock = MockClient::new();
+        mock.set_reject_reason(Some(RejectReason::Other("txn-mempool-conflict".into())));
+        let txid = mock.send_to_address(&test_address(), 1_000).unwrap();
+        let tx = mock.get_transaction(&txid).unwrap();
+        let client = RetryClient::new(mock).with_policy(no_delay());
+        assert!(matches!(
+            client.broadcast_checked(&tx),
+            Err(ProgramError::TransactionRejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_payments_are_not_retried() {
+        let client = RetryClient::new(FlakyClient::new(ErrorKind::ConnectionReset, 1))
+            .with_policy(no_delay());
+        assert!(client.send_to_address(&test_address(), 1_000).is_err());
+        assert_eq!(client.inner().calls.get(), 1);
+    }
+
+    #[test]
+    fn test_custom_classifier() {
+        let client = RetryClient::new(FlakyClient::new(ErrorKind::Other, 1))
+            .with_policy(no_delay())
+            .with_classifier(|_| true);
+        assert!(client.get_block_count().is_ok());
+        assert_eq!(client.inner().calls.get(), 2);
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let policy = RetryPolicy::new()
+            .with_initial_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(500))
+            .with_multiplier(2.0);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(3), Duration::from_millis(500));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(500));
+    }
+}
@@ -26,12 +26,54 @@
 //! let txid = client.send_to_address(&address, 100_000_000)?;
 //! ```
 
-use crate::client::{ClientResult, NodeClient, Utxo};
+use crate::client::{ClientResult, MempoolInfo, NodeClient, RejectReason, Utxo};
 use crate::config::{Network, NodeConfig};
 use crate::error::ProgramError;
+use elements::secp256k1_zkp::PedersenCommitment;
 use elements::{encode::deserialize, hex::FromHex, Address, BlockHash, Transaction, Txid};
+use secp256k1::SecretKey;
 use std::str::FromStr;
 
+/// What `RpcClient::scan_utxos` looks for in the UTXO set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanTarget {
+    /// An output descriptor, e.g. `addr(...)` or `raw(...)`
+    Descriptor(String),
+    /// A scriptPubkey, e.g. of a program address
+    Script(elements::Script),
+}
+
+impl ScanTarget {
+    /// Get the descriptor of the target, as `scantxoutset` takes it
+    #[must_use]
+    pub fn descriptor(&self) -> String {
+        use elements::hex::ToHex;
+
+        match self {
+            Self::Descriptor(descriptor) => descriptor.clone(),
+            Self::Script(script) => format!("raw({})", script.as_bytes().to_hex()),
+        }
+    }
+}
+
+impl From<&str> for ScanTarget {
+    fn from(descriptor: &str) -> Self {
+        Self::Descriptor(descriptor.to_string())
+    }
+}
+
+impl From<elements::Script> for ScanTarget {
+    fn from(script: elements::Script) -> Self {
+        Self::Script(script)
+    }
+}
+
+impl From<&Address> for ScanTarget {
+    fn from(address: &Address) -> Self {
+        Self::Script(address.script_pubkey())
+    }
+}
+
 /// RPC client for Elements/Liquid nodes
 ///
 /// This implementation uses JSON-RPC to communicate with Elements nodes.
@@ -179,7 +221,16 @@ impl RpcClient {
 
         let request = self.client.build_request(method, Some(&raw_params));
         let response = self.client.send_request(request).map_err(|e| {
-            ProgramError::IoError(std::io::Error::other(format!("RPC request failed: {e}")))
+            // Transport failures are reported as aborted connections, so
+            // callers can tell them apart from errors returned by the node
+            let kind = match e {
+                jsonrpc::Error::Transport(_) => std::io::ErrorKind::ConnectionAborted,
+                _ => std::io::ErrorKind::Other,
+            };
+            ProgramError::IoError(std::io::Error::new(
+                kind,
+                format!("RPC request failed: {e}"),
+            ))
         })?;
 
         response
@@ -206,13 +257,105 @@ impl RpcClient {
         self.call("getblockchaininfo", &[])
     }
 
-    /// Get the current block count
+    /// Find the UTXOs of a descriptor or script in the node's UTXO set
+    ///
+    /// Unlike `get_utxos`, this uses `scantxoutset`, so the address need not
+    /// be imported into the wallet and no rescan is needed, which suits
+    /// one-shot contracts. Only confirmed outputs are in the UTXO set, and
+    /// scanning it takes a while.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let utxos = client.scan_utxos(&compiled.address(client.address_params()))?;
+    /// let utxos = client.scan_utxos("addr(ert1p...)")?;
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the RPC call fails.
-    pub fn get_block_count(&self) -> ClientResult<u64> {
-        self.call("getblockcount", &[])
+    /// Returns an error if the RPC call fails, e.g. because another scan is
+    /// in progress, or the response is invalid.
+    pub fn scan_utxos(&self, target: impl Into<ScanTarget>) -> ClientResult<Vec<Utxo>> {
+        let result: serde_json::Value = self.call(
+            "scantxoutset",
+            &[
+                "start".into(),
+                serde_json::json!([target.into().descriptor()]),
+            ],
+        )?;
+        let invalid = |message: &str| {
+            ProgramError::IoError(std::io::Error::other(format!(
+                "Invalid scantxoutset response: {message}"
+            )))
+        };
+
+        let tip = result
+            .get("height")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| invalid("missing height"))?;
+        let unspents = result
+            .get("unspents")
+            .and_then(serde_json::Value::as_array)
+            .ok_or_else(|| invalid("missing unspents"))?;
+
+        unspents
+            .iter()
+            .map(|item| {
+                let txid = item
+                    .get("txid")
+                    .and_then(serde_json::Value::as_str)
+                    .and_then(|txid| Txid::from_str(txid).ok())
+                    .ok_or_else(|| invalid("missing or invalid txid"))?;
+                let vout = item
+                    .get("vout")
+                    .and_then(serde_json::Value::as_u64)
+                    .and_then(|vout| u32::try_from(vout).ok())
+                    .ok_or_else(|| invalid("missing or invalid vout"))?;
+                let script_pubkey = item
+                    .get("scriptPubKey")
+                    .and_then(serde_json::Value::as_str)
+                    .and_then(|hex| Vec::<u8>::from_hex(hex).ok())
+                    .map(elements::Script::from)
+                    .ok_or_else(|| invalid("missing or invalid scriptPubKey"))?;
+
+                // Confidential outputs have commitments instead
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let amount = item
+                    .get("amount")
+                    .and_then(serde_json::Value::as_f64)
+                    .map_or(0, |amount_btc| (amount_btc * 100_000_000.0).round() as u64);
+                let asset = if let Some(asset) = item.get("asset").and_then(|v| v.as_str()) {
+                    elements::AssetId::from_str(asset)
+                        .map(elements::confidential::Asset::Explicit)
+                        .map_err(|_| invalid("invalid asset"))?
+                } else if let Some(commitment) =
+                    item.get("assetcommitment").and_then(|v| v.as_str())
+                {
+                    Vec::<u8>::from_hex(commitment)
+                        .ok()
+                        .and_then(|bytes| deserialize(&bytes).ok())
+                        .ok_or_else(|| invalid("invalid asset commitment"))?
+                } else {
+                    elements::confidential::Asset::Null
+                };
+
+                let mut utxo = Utxo {
+                    txid,
+                    vout,
+                    amount,
+                    script_pubkey,
+                    asset,
+                    value_commitment: amount_commitment(item),
+                    confirmations: 0,
+                    block_height: item
+                        .get("height")
+                        .and_then(serde_json::Value::as_u64)
+                        .and_then(|height| u32::try_from(height).ok()),
+                };
+                utxo.set_confirmations(tip);
+                Ok(utxo)
+            })
+            .collect()
     }
 
     /// Get wallet balance
@@ -223,77 +366,32 @@ impl RpcClient {
     pub fn get_balance(&self) -> ClientResult<f64> {
         self.call("getbalance", &[])
     }
-}
-
-impl NodeClient for RpcClient {
-    fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
-        let addr_str = addr.to_string();
-        // Convert satoshis to BTC (Elements uses BTC units in RPC)
-        #[allow(clippy::cast_precision_loss)]
-        let amount_btc = amount as f64 / 100_000_000.0;
-
-        let txid_str: String = self.call("sendtoaddress", &[addr_str.into(), amount_btc.into()])?;
-
-        Txid::from_str(&txid_str)
-            .map_err(|e| ProgramError::IoError(std::io::Error::other(format!("Invalid txid: {e}"))))
-    }
-
-    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
-        let result: serde_json::Value = self.call("gettransaction", &[txid.to_string().into()])?;
-
-        let tx_hex = result.get("hex").and_then(|v| v.as_str()).ok_or_else(|| {
-            ProgramError::IoError(std::io::Error::other(
-                "Invalid transaction response: missing hex field",
-            ))
-        })?;
-
-        let tx_bytes = Vec::<u8>::from_hex(tx_hex).map_err(|e| {
-            ProgramError::IoError(std::io::Error::other(format!("Invalid hex: {e}")))
-        })?;
-
-        deserialize(&tx_bytes).map_err(|e| {
-            ProgramError::IoError(std::io::Error::other(format!(
-                "Failed to deserialize transaction: {e}"
-            )))
-        })
-    }
-
-    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
-        use elements::encode::serialize_hex;
-
-        let txid_str: String = self.call("sendrawtransaction", &[serialize_hex(tx).into()])?;
-
-        Txid::from_str(&txid_str)
-            .map_err(|e| ProgramError::IoError(std::io::Error::other(format!("Invalid txid: {e}"))))
-    }
 
-    fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
-        let address: String = self.call("getnewaddress", &[])?;
-
-        let hashes: Vec<String> =
-            self.call("generatetoaddress", &[count.into(), address.into()])?;
-
-        hashes
-            .iter()
-            .map(|s| {
-                BlockHash::from_str(s).map_err(|e| {
-                    ProgramError::IoError(std::io::Error::other(format!("Invalid block hash: {e}")))
-                })
-            })
-            .collect()
-    }
-
-    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+    /// List the wallet's UTXOs for an address with at least `min_conf`
+    /// confirmations
+    fn list_unspent(&self, address: &Address, min_conf: u32) -> ClientResult<Vec<Utxo>> {
         // Use listunspent with address filter
         let result: Vec<serde_json::Value> = self.call(
             "listunspent",
             &[
-                serde_json::json!(1),                     // minconf
+                serde_json::json!(min_conf),              // minconf
                 serde_json::json!(9_999_999),             // maxconf
                 serde_json::json!([address.to_string()]), // addresses
             ],
         )?;
 
+        // Heights follow from the tip, fetched only if an output is confirmed
+        let has_confirmed = result.iter().any(|item| {
+            item.get("confirmations")
+                .and_then(serde_json::Value::as_u64)
+                > Some(0)
+        });
+        let tip = if has_confirmed {
+            Some(self.get_block_count()?)
+        } else {
+            None
+        };
+
         let mut utxos = Vec::new();
         for item in result {
             let txid_str = item.get("txid").and_then(|v| v.as_str()).ok_or_else(|| {
@@ -347,17 +445,120 @@ impl NodeClient for RpcClient {
                 elements::confidential::Asset::Null
             };
 
+            let confirmations = item
+                .get("confirmations")
+                .and_then(serde_json::Value::as_u64)
+                .and_then(|confirmations| u32::try_from(confirmations).ok())
+                .unwrap_or(0);
+            let block_height = tip
+                .filter(|_| confirmations > 0)
+                .and_then(|tip| (tip + 1).checked_sub(u64::from(confirmations)))
+                .and_then(|height| u32::try_from(height).ok());
+
             utxos.push(Utxo {
                 txid,
                 vout,
                 amount,
                 script_pubkey,
                 asset,
+                value_commitment: amount_commitment(item),
+                confirmations,
+                block_height,
             });
         }
 
         Ok(utxos)
     }
+}
+
+impl NodeClient for RpcClient {
+    fn send_to_address(&self, addr: &Address, amount: u64) -> ClientResult<Txid> {
+        let addr_str = addr.to_string();
+        // Convert satoshis to BTC (Elements uses BTC units in RPC)
+        #[allow(clippy::cast_precision_loss)]
+        let amount_btc = amount as f64 / 100_000_000.0;
+
+        let txid_str: String = self.call("sendtoaddress", &[addr_str.into(), amount_btc.into()])?;
+
+        Txid::from_str(&txid_str)
+            .map_err(|e| ProgramError::IoError(std::io::Error::other(format!("Invalid txid: {e}"))))
+    }
+
+    fn get_transaction(&self, txid: &Txid) -> ClientResult<Transaction> {
+        let result: serde_json::Value = self.call("gettransaction", &[txid.to_string().into()])?;
+
+        let tx_hex = result.get("hex").and_then(|v| v.as_str()).ok_or_else(|| {
+            ProgramError::IoError(std::io::Error::other(
+                "Invalid transaction response: missing hex field",
+            ))
+        })?;
+
+        let tx_bytes = Vec::<u8>::from_hex(tx_hex).map_err(|e| {
+            ProgramError::IoError(std::io::Error::other(format!("Invalid hex: {e}")))
+        })?;
+
+        deserialize(&tx_bytes).map_err(|e| {
+            ProgramError::IoError(std::io::Error::other(format!(
+                "Failed to deserialize transaction: {e}"
+            )))
+        })
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> ClientResult<Txid> {
+        use elements::encode::serialize_hex;
+
+        let txid_str: String = self.call("sendrawtransaction", &[serialize_hex(tx).into()])?;
+
+        Txid::from_str(&txid_str)
+            .map_err(|e| ProgramError::IoError(std::io::Error::other(format!("Invalid txid: {e}"))))
+    }
+
+    fn test_accept(&self, tx: &Transaction) -> ClientResult<()> {
+        use elements::encode::serialize_hex;
+
+        let results: Vec<serde_json::Value> = self.call(
+            "testmempoolaccept",
+            &[serde_json::json!([serialize_hex(tx)])],
+        )?;
+        let result = results.first().ok_or_else(|| {
+            ProgramError::IoError(std::io::Error::other("Empty testmempoolaccept response"))
+        })?;
+
+        if result.get("allowed").and_then(serde_json::Value::as_bool) == Some(true) {
+            return Ok(());
+        }
+        let reason = result
+            .get("reject-reason")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or("unknown");
+        Err(ProgramError::TransactionRejected(
+            RejectReason::from_node_reason(reason),
+        ))
+    }
+
+    fn generate_blocks(&self, count: u32) -> ClientResult<Vec<BlockHash>> {
+        let address: String = self.call("getnewaddress", &[])?;
+
+        let hashes: Vec<String> =
+            self.call("generatetoaddress", &[count.into(), address.into()])?;
+
+        hashes
+            .iter()
+            .map(|s| {
+                BlockHash::from_str(s).map_err(|e| {
+                    ProgramError::IoError(std::io::Error::other(format!("Invalid block hash: {e}")))
+                })
+            })
+            .collect()
+    }
+
+    fn get_utxos(&self, address: &Address) -> ClientResult<Vec<Utxo>> {
+        self.list_unspent(address, 1)
+    }
+
+    fn get_utxos_with_min_conf(&self, address: &Address, min_conf: u32) -> ClientResult<Vec<Utxo>> {
+        self.list_unspent(address, min_conf)
+    }
 
     fn get_new_address(&self) -> ClientResult<Address> {
         let addr_str: String = self.call("getnewaddress", &[])?;
@@ -366,6 +567,74 @@ impl NodeClient for RpcClient {
             ProgramError::IoError(std::io::Error::other(format!("Invalid address: {e}")))
         })
     }
+
+    fn import_address(&self, address: &Address) -> ClientResult<()> {
+        // Watch-only import without a rescan, as fresh addresses have no history
+        let _: serde_json::Value = self.call(
+            "importaddress",
+            &[address.to_string().into(), "".into(), false.into()],
+        )?;
+        Ok(())
+    }
+
+    fn import_blinding_key(&self, address: &Address, blinding_key: &SecretKey) -> ClientResult<()> {
+        use elements::hex::ToHex;
+
+        let _: serde_json::Value = self.call(
+            "importblindingkey",
+            &[
+                address.to_string().into(),
+                blinding_key.secret_bytes().to_hex().into(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_block_count(&self) -> ClientResult<u64> {
+        self.call("getblockcount", &[])
+    }
+
+    fn get_block_hash(&self, height: u64) -> ClientResult<BlockHash> {
+        let hash_str: String = self.call("getblockhash", &[height.into()])?;
+        BlockHash::from_str(&hash_str).map_err(|e| {
+            ProgramError::IoError(std::io::Error::other(format!("Invalid block hash: {e}")))
+        })
+    }
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> ClientResult<f64> {
+        let result: serde_json::Value = self.call("estimatesmartfee", &[target_blocks.into()])?;
+
+        // The node estimates in BTC/kvB, and omits the rate without enough data
+        let btc_per_kvb = result
+            .get("feerate")
+            .and_then(serde_json::Value::as_f64)
+            .ok_or_else(|| {
+                ProgramError::IoError(std::io::Error::other(format!(
+                    "No fee estimate available: {}",
+                    result.get("errors").unwrap_or(&serde_json::Value::Null)
+                )))
+            })?;
+        Ok(btc_per_kvb * 100_000.0)
+    }
+
+    fn get_mempool_info(&self) -> ClientResult<MempoolInfo> {
+        let result: serde_json::Value = self.call("getmempoolinfo", &[])?;
+        let field = |name: &str| {
+            result.get(name).ok_or_else(|| {
+                ProgramError::IoError(std::io::Error::other(format!(
+                    "Missing {name} in getmempoolinfo"
+                )))
+            })
+        };
+
+        Ok(MempoolInfo {
+            size: field("size")?.as_u64().unwrap_or_default(),
+            vsize: field("bytes")?.as_u64().unwrap_or_default(),
+            min_fee_rate: field("mempoolminfee")?
+                .as_f64()
+                .map(|btc_per_kvb| btc_per_kvb * 100_000.0),
+        })
+    }
 }
 
 impl std::fmt::Debug for RpcClient {
@@ -376,3 +645,10 @@ impl std::fmt::Debug for RpcClient {
             .finish_non_exhaustive()
     }
 }
+
+/// Parse the `amountcommitment` the node reports for a confidential output
+fn amount_commitment(item: &serde_json::Value) -> Option<PedersenCommitment> {
+    item.get("amountcommitment")
+        .and_then(serde_json::Value::as_str)
+        .and_then(|hex| PedersenCommitment::from_str(hex).ok())
+}
@@ -0,0 +1,412 @@
+//! Runnable end-to-end scenarios
+//!
+//! Each scenario drives a complete contract lifecycle against any
+//! `NodeClient`: fund the program address, build and sign the spend,
+//! broadcast it and mine a confirmation block. They double as executable
+//! documentation and as the backbone of the regtest test suite.
+//!
+//! Confirmation uses `generate_blocks`, so scenarios are intended for regtest
+//! nodes (or the test `MockClient`).
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use musk::scenarios::{fund_spend_confirm, ScenarioConfig};
+//! use musk::{Arguments, Program, RpcClient, WitnessValues};
+//!
+//! let mut client = RpcClient::from_url("http://127.0.0.1:18884", "user", "pass")?;
+//! let config = ScenarioConfig::regtest(client.genesis_hash()?);
+//!
+//! let program = Program::from_source("fn main() { assert!(true); }")?
+//!     .instantiate(Arguments::default())?;
+//! let report = fund_spend_confirm(&client, &program, &config, |_| WitnessValues::default())?;
+//! println!("spent in {}", report.spend_txid);
+//! ```
+
+use crate::client::{NodeClient, Utxo};
+use crate::error::{ProgramError, SpendError};
+use crate::program::{InstantiatedProgram, Program};
+use crate::spend::SpendBuilder;
+use elements::{confidential, Address, AddressParams, BlockHash, Transaction, Txid};
+use secp256k1::{Keypair, Message, XOnlyPublicKey};
+use simplicityhl::num::U256;
+use simplicityhl::str::WitnessName;
+use simplicityhl::types::{ResolvedType, TypeConstructible, UIntType};
+use simplicityhl::value::ValueConstructible;
+use simplicityhl::{Arguments, Value, WitnessValues};
+use std::collections::HashMap;
+
+/// Hash time-locked contract
+///
+/// The recipient can spend by revealing the preimage of `param::HASH` and
+/// signing with `param::RECIPIENT_PK`. The sender can reclaim the funds with a
+/// signature from `param::SENDER_PK` once the chain reaches `param::TIMEOUT`.
+pub const HTLC_PROGRAM: &str = r"
+fn sha2(string: u256) -> u256 {
+    let hasher: Ctx8 = jet::sha_256_ctx_8_init();
+    let hasher: Ctx8 = jet::sha_256_ctx_8_add_32(hasher, string);
+    jet::sha_256_ctx_8_finalize(hasher)
+}
+
+fn checksig(pk: Pubkey, sig: Signature) {
+    let msg: u256 = jet::sig_all_hash();
+    jet::bip_0340_verify((pk, msg), sig);
+}
+
+fn complete_spend(preimage: u256, recipient_sig: Signature) {
+    let hash: u256 = sha2(preimage);
+    let expected_hash: u256 = param::HASH;
+    assert!(jet::eq_256(hash, expected_hash));
+    let recipient_pk: Pubkey = param::RECIPIENT_PK;
+    checksig(recipient_pk, recipient_sig);
+}
+
+fn cancel_spend(sender_sig: Signature) {
+    let timeout: Height = param::TIMEOUT;
+    jet::check_lock_height(timeout);
+    let sender_pk: Pubkey = param::SENDER_PK;
+    checksig(sender_pk, sender_sig)
+}
+
+fn main() {
+    match witness::COMPLETE_OR_CANCEL {
+        Left(preimage_sig: (u256, Signature)) => {
+            let (preimage, recipient_sig): (u256, Signature) = preimage_sig;
+            complete_spend(preimage, recipient_sig);
+        },
+        Right(sender_sig: Signature) => cancel_spend(sender_sig),
+    }
+}
+";
+
+/// Network and amount settings shared by all scenarios
+#[derive(Debug, Clone)]
+pub struct ScenarioConfig {
+    /// Address params of the connected network
+    pub params: &'static AddressParams,
+    /// Genesis block hash (required for sighash computation)
+    pub genesis_hash: BlockHash,
+    /// Amount sent to the program address, in satoshis
+    pub amount: u64,
+    /// Fee paid by the spending transaction, in satoshis
+    pub fee: u64,
+    /// Check the spend with `NodeClient::test_accept` before broadcasting,
+    /// to report the node's reason if it is rejected
+    pub test_accept: bool,
+}
+
+impl ScenarioConfig {
+    /// Default settings for an Elements regtest node
+    #[must_use]
+    pub const fn regtest(genesis_hash: BlockHash) -> Self {
+        Self {
+            params: &AddressParams::ELEMENTS,
+            genesis_hash,
+            amount: 1_000_000,
+            fee: 3_000,
+            test_accept: true,
+        }
+    }
+}
+
+/// Outcome of a scenario run
+#[derive(Debug, Clone)]
+pub struct ScenarioReport {
+    /// Program address that was funded
+    pub address: Address,
+    /// Transaction funding the program address
+    pub funding_txid: Txid,
+    /// Transaction spending the program output
+    pub spend_txid: Txid,
+    /// The broadcast spending transaction
+    pub spend_tx: Transaction,
+}
+
+/// Fund a program address and return the resulting UTXO
+///
+/// # Errors
+///
+/// Returns an error if funding fails or the funding output cannot be found.
+pub fn fund<C: NodeClient + ?Sized>(
+    client: &C,
+    program: &InstantiatedProgram,
+    config: &ScenarioConfig,
+) -> Result<Utxo, SpendError> {
+    let address = program.address(config.params);
+    let txid = client.send_to_address(&address, config.amount)?;
+    let tx = client.get_transaction(&txid)?;
+
+    let script_pubkey = address.script_pubkey();
+    let (vout, txout) = tx
+        .output
+        .iter()
+        .enumerate()
+        .find(|(_, txout)| txout.script_pubkey == script_pubkey)
+        .ok_or_else(|| {
+            SpendError::InvalidUtxo(format!("Funding transaction {txid} does not pay {address}"))
+        })?;
+
+    let confidential::Value::Explicit(amount) = txout.value else {
+        return Err(SpendError::InvalidUtxo(
+            "Funding output is confidential".into(),
+        ));
+    };
+
+    Ok(Utxo {
+        txid,
+        vout: u32::try_from(vout).expect("output index fits in u32"),
+        amount,
+        script_pubkey,
+        asset: txout.asset,
+        value_commitment: None,
+        confirmations: 0,
+        block_height: None,
+    })
+}
+
+/// Fund a program, spend it back to the node's wallet and confirm the spend
+///
+/// `witness` receives the `sighash_all` of the spending transaction and
+/// returns the witness values satisfying the program.
+///
+/// # Errors
+///
+/// Returns an error if any step of the flow fails.
+pub fn fund_spend_confirm<C, F>(
+    client: &C,
+    program: &InstantiatedProgram,
+    config: &ScenarioConfig,
+    witness: F,
+) -> Result<ScenarioReport, SpendError>
+where
+    C: NodeClient + ?Sized,
+    F: FnOnce([u8; 32]) -> WitnessValues,
+{
+    let utxo = fund(client, program, config)?;
+    let funding_txid = utxo.txid;
+    let confidential::Asset::Explicit(asset) = utxo.asset else {
+        return Err(SpendError::InvalidUtxo("Non-explicit asset".into()));
+    };
+    let send_amount = utxo
+        .amount
+        .checked_sub(config.fee)
+        .ok_or_else(|| SpendError::BuildError("Fee exceeds funded amount".into()))?;
+
+    let destination = client.get_new_address()?;
+    let mut builder =
+        SpendBuilder::try_new(program.clone(), utxo)?.genesis_hash(config.genesis_hash);
+    builder.add_output_simple(destination.script_pubkey(), send_amount, asset);
+    builder.add_fee(config.fee, asset);
+
+    let sighash = builder.sighash_all()?;
+    let spend_tx = builder.finalize(witness(sighash))?;
+    let spend_txid = if config.test_accept {
+        client.broadcast_checked(&spend_tx)?
+    } else {
+        client.broadcast(&spend_tx)?
+    };
+    client.generate_blocks(1)?;
+
+    Ok(ScenarioReport {
+        address: program.address(config.params),
+        funding_txid,
+        spend_txid,
+        spend_tx,
+    })
+}
+
+/// Instantiate the [`HTLC_PROGRAM`]
+///
+/// # Errors
+///
+/// Returns an error if the program cannot be compiled.
+pub fn htlc_program(
+    hash: [u8; 32],
+    recipient_pk: XOnlyPublicKey,
+    sender_pk: XOnlyPublicKey,
+    timeout: u32,
+) -> Result<InstantiatedProgram, ProgramError> {
+    let arguments = HashMap::from([
+        (
+            WitnessName::from_str_unchecked("HASH"),
+            Value::u256(U256::from_byte_array(hash)),
+        ),
+        (
+            WitnessName::from_str_unchecked("RECIPIENT_PK"),
+            Value::u256(U256::from_byte_array(recipient_pk.serialize())),
+        ),
+        (
+            WitnessName::from_str_unchecked("SENDER_PK"),
+            Value::u256(U256::from_byte_array(sender_pk.serialize())),
+        ),
+        (
+            WitnessName::from_str_unchecked("TIMEOUT"),
+            Value::u32(timeout),
+        ),
+    ]);
+
+    Program::from_source(HTLC_PROGRAM)?.instantiate(Arguments::from(arguments))
+}
+
+/// Lock funds in an HTLC and claim them by revealing the preimage
+///
+/// The sender funds the HTLC from the node's wallet; `recipient` then
+/// completes the swap by revealing `preimage` and signing the spend.
+///
+/// # Errors
+///
+/// Returns an error if any step of the flow fails.
+pub fn htlc_swap<C: NodeClient + ?Sized>(
+    client: &C,
+    config: &ScenarioConfig,
+    recipient: &Keypair,
+    sender_pk: XOnlyPublicKey,
+    preimage: [u8; 32],
+    timeout: u32,
+) -> Result<ScenarioReport, SpendError> {
+    use elements::hashes::{sha256, Hash};
+
+    let hash = sha256::Hash::hash(&preimage).to_byte_array();
+    let program = htlc_program(hash, recipient.x_only_public_key().0, sender_pk, timeout)?;
+
+    fund_spend_confirm(client, &program, config, |sighash| {
+        let signature = recipient.sign_schnorr(Message::from_digest(sighash));
+        htlc_complete_witness(preimage, signature.serialize())
+    })
+}
+
+/// Witness values for the HTLC's claim path
+#[must_use]
+pub fn htlc_complete_witness(preimage: [u8; 32], recipient_sig: [u8; 64]) -> WitnessValues {
+    let preimage_sig = Value::tuple([
+        Value::u256(U256::from_byte_array(preimage)),
+        Value::byte_array(recipient_sig),
+    ]);
+    htlc_witness(Value::left(preimage_sig, ResolvedType::byte_array(64)))
+}
+
+/// Witness values for the HTLC's timeout path
+#[must_use]
+pub fn htlc_cancel_witness(sender_sig: [u8; 64]) -> WitnessValues {
+    let preimage_sig = ResolvedType::tuple([
+        ResolvedType::from(UIntType::U256),
+        ResolvedType::byte_array(64),
+    ]);
+    htlc_witness(Value::right(preimage_sig, Value::byte_array(sender_sig)))
+}
+
+fn htlc_witness(complete_or_cancel: Value) -> WitnessValues {
+    WitnessValues::from(HashMap::from([(
+        WitnessName::from_str_unchecked("COMPLETE_OR_CANCEL"),
+        complete_or_cancel,
+    )]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_client::MockClient;
+    use crate::test_fixtures::{test_genesis_hash, SIMPLE_PROGRAM};
+    use crate::util::keypair_from_u32;
+
+    #[test]
+    fn test_fund_spend_confirm() {
+        let client = MockClient::new();
+        let config = ScenarioConfig::regtest(test_genesis_hash());
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+
+        let report =
+            fund_spend_confirm(&client, &program, &config, |_| WitnessValues::default()).unwrap();
+
+        assert_eq!(
+            report.spend_tx.input[0].previous_output.txid,
+            report.funding_txid
+        );
+        assert_eq!(report.spend_tx.output.len(), 2);
+        assert!(client.get_transaction(&report.spend_txid).is_ok());
+    }
+
+    #[test]
+    fn test_scenario_with_client_chosen_at_runtime() {
+        let client: Box<dyn NodeClient> = Box::new(MockClient::new());
+        let config = ScenarioConfig::regtest(test_genesis_hash());
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+
+        let report =
+            fund_spend_confirm(&*client, &program, &config, |_| WitnessValues::default()).unwrap();
+        assert!(client.get_transaction(&report.spend_txid).is_ok());
+    }
+
+    #[test]
+    fn test_fee_exceeding_amount_rejected() {
+        let client = MockClient::new();
+        let mut config = ScenarioConfig::regtest(test_genesis_hash());
+        config.fee = config.amount + 1;
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+
+        assert!(
+            fund_spend_confirm(&client, &program, &config, |_| WitnessValues::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_rejected_spend_reports_reason() {
+        use crate::client::RejectReason;
+
+        let client = MockClient::new();
+        client.set_reject_reason(Some(RejectReason::from_node_reason(
+            "mandatory-script-verify-flag-failed",
+        )));
+        let config = ScenarioConfig::regtest(test_genesis_hash());
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+
+        assert!(matches!(
+            fund_spend_confirm(&client, &program, &config, |_| WitnessValues::default()),
+            Err(SpendError::ProgramError(ProgramError::TransactionRejected(
+                RejectReason::ScriptFailure(_)
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_htlc_swap() {
+        let client = MockClient::new();
+        let config = ScenarioConfig::regtest(test_genesis_hash());
+        let recipient = keypair_from_u32(1);
+        let sender_pk = keypair_from_u32(2).x_only_public_key().0;
+
+        let report = htlc_swap(&client, &config, &recipient, sender_pk, [7u8; 32], 1000).unwrap();
+
+        // The witness stack carries the satisfied HTLC and its control block
+        assert_eq!(report.spend_tx.input[0].witness.script_witness.len(), 4);
+    }
+
+    #[test]
+    fn test_htlc_cancel_witness_satisfies() {
+        let sender = keypair_from_u32(2);
+        let program = htlc_program(
+            [0u8; 32],
+            keypair_from_u32(1).x_only_public_key().0,
+            sender.x_only_public_key().0,
+            1000,
+        )
+        .unwrap();
+
+        let signature = sender.sign_schnorr(Message::from_digest([0u8; 32]));
+        assert!(program
+            .satisfy(htlc_cancel_witness(signature.serialize()))
+            .is_ok());
+    }
+}
@@ -0,0 +1,417 @@
+//! Signing with keys held outside the host process
+//!
+//! A [`Signer`] derives keys from a BIP32 root and makes Schnorr signatures
+//! over sighashes, so spends can be signed without the caller ever holding
+//! the secret keys. [`SoftwareSigner`] keeps an extended private key in
+//! memory and serves as the reference implementation. [`KmsSigner`] keeps
+//! the keys in a KMS or HSM instead, behind the [`Kms`] interface.
+//!
+//! An [`AsyncSigner`] instead hands sighashes to a remote service, such as an
+//! HSM farm or a co-signing API, and awaits the signatures, which
+//! `SpendBuilder::finalize_with_signer` folds into the witness values.
+//!
+//! Hardware wallets such as Blockstream Jade are not supported yet. A Jade
+//! signer over serial or BLE is blocked on the device: Jade's RPC only signs
+//! transactions it parses itself and messages with ECDSA, and has no way to
+//! make a BIP340 signature over an arbitrary Simplicity sighash, which is
+//! what spending a program requires. It will be added as a [`Signer`]
+//! implementation once the firmware can sign such sighashes; see the Known
+//! Limitations section of the README.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::elements::bitcoin::bip32::DerivationPath;
+//! use musk::elements::bitcoin::NetworkKind;
+//! use musk::signer::{Signer, SoftwareSigner};
+//!
+//! let signer = SoftwareSigner::from_seed(NetworkKind::Test, &[7u8; 32]).unwrap();
+//! let path: DerivationPath = "m/86'/1'/0'/0/0".parse().unwrap();
+//!
+//! let xpub = signer.get_xpub(&path).unwrap();
+//! let signature = signer.sign_schnorr([0u8; 32], &path).unwrap();
+//! assert_eq!(xpub.to_x_only_pub().serialize().len(), 32);
+//! assert_eq!(signature.len(), 64);
+//! ```
+
+use crate::error::SpendError;
+use elements::bitcoin::bip32::{ChainCode, ChildNumber, DerivationPath, Fingerprint, Xpriv, Xpub};
+use elements::bitcoin::NetworkKind;
+use secp256k1::{schnorr, All, Keypair, Message, PublicKey, Secp256k1};
+use std::collections::BTreeMap;
+use std::future::Future;
+
+/// A source of extended public keys and Schnorr signatures
+pub trait Signer {
+    /// Get the extended public key at `derivation`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key cannot be derived or the signer cannot be
+    /// reached.
+    fn get_xpub(&self, derivation: &DerivationPath) -> Result<Xpub, SpendError>;
+
+    /// Sign `sighash` with the key at `derivation`
+    ///
+    /// Returns a BIP340 signature that verifies against the x-only public key
+    /// of [`Signer::get_xpub`] at the same path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key cannot be derived, the signer cannot be
+    /// reached or it refuses to sign.
+    fn sign_schnorr(
+        &self,
+        sighash: [u8; 32],
+        derivation: &DerivationPath,
+    ) -> Result<[u8; 64], SpendError>;
+}
+
+/// A remote service signing sighashes asynchronously
+///
+/// # Examples
+///
+/// ```
+/// use musk::signer::AsyncSigner;
+/// use musk::SpendError;
+/// use std::future::Future;
+///
+/// /// Client of a co-signing API
+/// struct CoSigner;
+///
+/// impl AsyncSigner for CoSigner {
+///     fn sign_sighash(
+///         &self,
+///         _index: usize,
+///         _sighash: [u8; 32],
+///     ) -> impl Future<Output = Result<[u8; 64], SpendError>> + Send {
+///         async { Err(SpendError::SigningError("Co-signer unavailable".into())) }
+///     }
+/// }
+/// ```
+pub trait AsyncSigner {
+    /// Sign the `sighash_all` of program input `index`
+    ///
+    /// The future resolves to a BIP340 signature over `sighash`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the service cannot be reached or refuses to sign.
+    fn sign_sighash(
+        &self,
+        index: usize,
+        sighash: [u8; 32],
+    ) -> impl Future<Output = Result<[u8; 64], SpendError>> + Send;
+}
+
+/// Signer holding an extended private key in memory
+#[derive(Clone)]
+pub struct SoftwareSigner {
+    xpriv: Xpriv,
+    secp: Secp256k1<All>,
+}
+
+impl SoftwareSigner {
+    /// Create a signer from an extended private key
+    #[must_use]
+    pub fn new(xpriv: Xpriv) -> Self {
+        Self {
+            xpriv,
+            secp: Secp256k1::new(),
+        }
+    }
+
+    /// Create a signer from a BIP32 seed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `seed` does not give a valid master key.
+    pub fn from_seed(network: NetworkKind, seed: &[u8]) -> Result<Self, SpendError> {
+        let xpriv = Xpriv::new_master(network, seed)
+            .map_err(|e| SpendError::SigningError(format!("Invalid seed: {e}")))?;
+        Ok(Self::new(xpriv))
+    }
+
+    fn derive(&self, derivation: &DerivationPath) -> Result<Xpriv, SpendError> {
+        self.xpriv
+            .derive_priv(&self.secp, derivation)
+            .map_err(|e| SpendError::SigningError(format!("Cannot derive {derivation}: {e}")))
+    }
+}
+
+impl Signer for SoftwareSigner {
+    fn get_xpub(&self, derivation: &DerivationPath) -> Result<Xpub, SpendError> {
+        Ok(Xpub::from_priv(&self.secp, &self.derive(derivation)?))
+    }
+
+    fn sign_schnorr(
+        &self,
+        sighash: [u8; 32],
+        derivation: &DerivationPath,
+    ) -> Result<[u8; 64], SpendError> {
+        let keypair = self.derive(derivation)?.to_keypair(&self.secp);
+        let signature = self
+            .secp
+            .sign_schnorr(&Message::from_digest(sighash), &keypair);
+        Ok(signature.serialize())
+    }
+}
+
+/// Interface of a key management service holding secp256k1 keys
+///
+/// Implement this over the client of an HSM or cloud KMS that can make
+/// BIP340 signatures, and wrap it in a [`KmsSigner`].
+pub trait Kms {
+    /// Get the public key of the key `key_id`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key does not exist or the service cannot be
+    /// reached.
+    fn public_key(&self, key_id: &str) -> Result<PublicKey, SpendError>;
+
+    /// Make a BIP340 signature over `digest` with the key `key_id`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key does not exist, the service cannot be
+    /// reached or it refuses to sign.
+    fn sign_schnorr(&self, key_id: &str, digest: [u8; 32]) -> Result<[u8; 64], SpendError>;
+}
+
+/// Signer delegating to keys held in a [`Kms`]
+///
+/// KMS keys are not derived from each other, so every derivation path the
+/// signer serves is mapped to a key id. The extended public keys it returns
+/// carry the KMS public key with a zero chain code: they identify the key
+/// but cannot derive children. Every signature the KMS returns is verified
+/// before it is used.
+///
+/// # Examples
+///
+/// ```
+/// use musk::elements::bitcoin::bip32::DerivationPath;
+/// use musk::signer::{InMemoryKms, KmsSigner, Signer};
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let mut kms = InMemoryKms::new();
+/// kms.insert("contract-key", Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap());
+/// let path: DerivationPath = "m/0".parse().unwrap();
+/// let mut signer = KmsSigner::new(kms);
+/// signer.map_key(path.clone(), "contract-key");
+///
+/// let signature = signer.sign_schnorr([0u8; 32], &path).unwrap();
+/// assert_eq!(signature.len(), 64);
+/// ```
+pub struct KmsSigner<K> {
+    kms: K,
+    keys: BTreeMap<DerivationPath, String>,
+}
+
+impl<K: Kms> KmsSigner<K> {
+    /// Create a signer over `kms` with no keys mapped
+    #[must_use]
+    pub const fn new(kms: K) -> Self {
+        Self {
+            kms,
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Serve `derivation` with the KMS key `key_id`
+    pub fn map_key(&mut self, derivation: DerivationPath, key_id: &str) -> &mut Self {
+        self.keys.insert(derivation, key_id.to_owned());
+        self
+    }
+
+    /// Get the KMS client
+    #[must_use]
+    pub const fn kms(&self) -> &K {
+        &self.kms
+    }
+
+    fn key_id(&self, derivation: &DerivationPath) -> Result<&str, SpendError> {
+        self.keys
+            .get(derivation)
+            .map(String::as_str)
+            .ok_or_else(|| SpendError::SigningError(format!("No KMS key for {derivation}")))
+    }
+}
+
+impl<K: Kms> Signer for KmsSigner<K> {
+    fn get_xpub(&self, derivation: &DerivationPath) -> Result<Xpub, SpendError> {
+        let public_key = self.kms.public_key(self.key_id(derivation)?)?;
+        Ok(Xpub {
+            network: NetworkKind::Main,
+            depth: u8::try_from(derivation.len()).unwrap_or(u8::MAX),
+            parent_fingerprint: Fingerprint::default(),
+            child_number: derivation
+                .into_iter()
+                .last()
+                .copied()
+                .unwrap_or(ChildNumber::Normal { index: 0 }),
+            public_key,
+            chain_code: ChainCode::from([0u8; 32]),
+        })
+    }
+
+    fn sign_schnorr(
+        &self,
+        sighash: [u8; 32],
+        derivation: &DerivationPath,
+    ) -> Result<[u8; 64], SpendError> {
+        let key_id = self.key_id(derivation)?;
+        let public_key = self.kms.public_key(key_id)?;
+        let signature = self.kms.sign_schnorr(key_id, sighash)?;
+
+        let valid = schnorr::Signature::from_slice(&signature).is_ok_and(|parsed| {
+            Secp256k1::verification_only()
+                .verify_schnorr(
+                    &parsed,
+                    &Message::from_digest(sighash),
+                    &public_key.x_only_public_key().0,
+                )
+                .is_ok()
+        });
+        if !valid {
+            return Err(SpendError::SigningError(format!(
+                "KMS returned an invalid signature for {key_id}"
+            )));
+        }
+        Ok(signature)
+    }
+}
+
+/// Example [`Kms`] holding its keys in memory
+///
+/// Useful for development and tests of code written against a real KMS.
+#[derive(Clone, Default)]
+pub struct InMemoryKms {
+    keys: BTreeMap<String, Keypair>,
+}
+
+impl InMemoryKms {
+    /// Create a KMS without keys
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store `keypair` under `key_id`, replacing any previous key
+    pub fn insert(&mut self, key_id: &str, keypair: Keypair) -> &mut Self {
+        self.keys.insert(key_id.to_owned(), keypair);
+        self
+    }
+
+    fn keypair(&self, key_id: &str) -> Result<&Keypair, SpendError> {
+        self.keys
+            .get(key_id)
+            .ok_or_else(|| SpendError::SigningError(format!("Unknown KMS key {key_id}")))
+    }
+}
+
+impl Kms for InMemoryKms {
+    fn public_key(&self, key_id: &str) -> Result<PublicKey, SpendError> {
+        self.keypair(key_id).map(Keypair::public_key)
+    }
+
+    fn sign_schnorr(&self, key_id: &str, digest: [u8; 32]) -> Result<[u8; 64], SpendError> {
+        Ok(crate::util::sign_schnorr_with(
+            self.keypair(key_id)?,
+            digest,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_software_signer_signs_for_xpub() {
+        let signer = SoftwareSigner::from_seed(NetworkKind::Test, &[1u8; 32]).unwrap();
+        let sighash = [9u8; 32];
+
+        for path in ["m", "m/0/1", "m/86'/1'/0'/0/5"] {
+            let path: DerivationPath = path.parse().unwrap();
+            let xpub = signer.get_xpub(&path).unwrap();
+            let signature = signer.sign_schnorr(sighash, &path).unwrap();
+
+            let signature = schnorr::Signature::from_slice(&signature).unwrap();
+            assert!(Secp256k1::new()
+                .verify_schnorr(
+                    &signature,
+                    &Message::from_digest(sighash),
+                    &xpub.to_x_only_pub()
+                )
+                .is_ok());
+        }
+
+        let first = signer.get_xpub(&"m/0".parse().unwrap()).unwrap();
+        let second = signer.get_xpub(&"m/1".parse().unwrap()).unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_kms_signer() {
+        use crate::util::keypair_from_u32;
+
+        let keypair = keypair_from_u32(5);
+        let mut kms = InMemoryKms::new();
+        kms.insert("a", keypair).insert("b", keypair_from_u32(6));
+        let path: DerivationPath = "m/86'/1'/0'".parse().unwrap();
+        let mut signer = KmsSigner::new(kms);
+        signer.map_key(path.clone(), "a");
+
+        let xpub = signer.get_xpub(&path).unwrap();
+        assert_eq!(xpub.public_key, keypair.public_key());
+        assert_eq!(xpub.depth, 3);
+
+        let sighash = [2u8; 32];
+        let signature = signer.sign_schnorr(sighash, &path).unwrap();
+        let signature = schnorr::Signature::from_slice(&signature).unwrap();
+        assert!(Secp256k1::new()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(sighash),
+                &xpub.to_x_only_pub()
+            )
+            .is_ok());
+
+        assert!(signer
+            .sign_schnorr(sighash, &"m/1".parse().unwrap())
+            .is_err());
+        signer.map_key("m/1".parse().unwrap(), "missing");
+        assert!(signer
+            .sign_schnorr(sighash, &"m/1".parse().unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_kms_signer_rejects_bad_signatures() {
+        struct BrokenKms;
+
+        impl Kms for BrokenKms {
+            fn public_key(&self, _key_id: &str) -> Result<PublicKey, SpendError> {
+                Ok(crate::util::keypair_from_u32(1).public_key())
+            }
+
+            fn sign_schnorr(
+                &self,
+                _key_id: &str,
+                digest: [u8; 32],
+            ) -> Result<[u8; 64], SpendError> {
+                Ok(crate::util::sign_schnorr_with(
+                    &crate::util::keypair_from_u32(2),
+                    digest,
+                ))
+            }
+        }
+
+        let mut signer = KmsSigner::new(BrokenKms);
+        signer.map_key(DerivationPath::master(), "key");
+        assert!(signer
+            .sign_schnorr([0u8; 32], &DerivationPath::master())
+            .is_err());
+    }
+}
@@ -0,0 +1,519 @@
+//! Coordination of multi-party signing
+//!
+//! A covenant spent by several parties needs signatures from each of them,
+//! usually collected over some transport. A [`SigningSession`] records which
+//! participant must sign which witness of which input with which key, checks
+//! every signature it receives against the input's sighash, merges copies of
+//! the session signed by different participants, and turns a complete
+//! session into the witness values for `SpendBuilder::finalize_inputs`.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::signing_session::SigningSession;
+//! use musk::util::sign_schnorr_with;
+//! use secp256k1::{Keypair, Secp256k1};
+//!
+//! let alice = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+//! let bob = Keypair::from_seckey_slice(&Secp256k1::new(), &[2; 32]).unwrap();
+//! let mut session = SigningSession::new(vec![[7u8; 32]]);
+//! session
+//!     .require("alice", 0, "ALICE_SIG", alice.x_only_public_key().0)
+//!     .unwrap()
+//!     .require("bob", 0, "BOB_SIG", bob.x_only_public_key().0)
+//!     .unwrap();
+//!
+//! // Each participant signs their own copy
+//! let mut bobs_copy = session.clone();
+//! for request in bobs_copy.requests("bob") {
+//!     let signature = sign_schnorr_with(&bob, request.sighash);
+//!     bobs_copy.add_signature("bob", request.input, &request.witness, signature).unwrap();
+//! }
+//! for request in session.requests("alice") {
+//!     let signature = sign_schnorr_with(&alice, request.sighash);
+//!     session.add_signature("alice", request.input, &request.witness, signature).unwrap();
+//! }
+//! assert!(!session.is_complete());
+//!
+//! session.merge(&bobs_copy).unwrap();
+//! assert!(session.is_complete());
+//! let witness_values = session.witness_values().unwrap();
+//! assert_eq!(witness_values.len(), 1);
+//! ```
+
+use crate::error::SpendError;
+use crate::spend::{Ready, SpendBuilder};
+use crate::witness::witness_name;
+use secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+use simplicityhl::value::ValueConstructible;
+use simplicityhl::{Value, WitnessValues};
+use std::collections::HashMap;
+
+/// Signatures to collect for a spend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningSession {
+    sighashes: Vec<[u8; 32]>,
+    slots: Vec<SignatureSlot>,
+}
+
+/// A signature one participant must provide
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureSlot {
+    /// Name of the participant
+    pub participant: String,
+    /// Index of the signed program input
+    pub input: usize,
+    /// Name of the witness holding the signature
+    pub witness: String,
+    /// Key the signature must verify against
+    pub pubkey: XOnlyPublicKey,
+    /// Signature, once provided
+    pub signature: Option<[u8; 64]>,
+}
+
+/// A signature still to be made by a participant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureRequest {
+    /// Index of the program input to sign
+    pub input: usize,
+    /// Name of the witness holding the signature
+    pub witness: String,
+    /// Sighash to sign
+    pub sighash: [u8; 32],
+}
+
+impl SigningSession {
+    /// Create a session over the `sighash_all` of every program input
+    #[must_use]
+    pub const fn new(sighashes: Vec<[u8; 32]>) -> Self {
+        Self {
+            sighashes,
+            slots: Vec::new(),
+        }
+    }
+
+    /// Create a session for the program inputs of `builder`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a sighash cannot be computed.
+    pub fn from_builder(builder: &SpendBuilder<Ready>) -> Result<Self, SpendError> {
+        Ok(Self::new(builder.sighash_all_inputs()?))
+    }
+
+    /// Require `participant` to sign `input` into `witness` with `pubkey`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `input` is out of range, `witness` is not a valid
+    /// witness name or the witness of the input is already required.
+    pub fn require(
+        &mut self,
+        participant: &str,
+        input: usize,
+        witness: &str,
+        pubkey: XOnlyPublicKey,
+    ) -> Result<&mut Self, SpendError> {
+        self.sighash(input)?;
+        witness_name(witness)?;
+        if self.slot(input, witness).is_some() {
+            return Err(SpendError::SigningError(format!(
+                "Witness {witness} of input {input} is already required"
+            )));
+        }
+        self.slots.push(SignatureSlot {
+            participant: participant.to_owned(),
+            input,
+            witness: witness.to_owned(),
+            pubkey,
+            signature: None,
+        });
+        Ok(self)
+    }
+
+    /// Get the sighashes the signatures commit to, in input order
+    #[must_use]
+    pub fn sighashes(&self) -> &[[u8; 32]] {
+        &self.sighashes
+    }
+
+    /// Get every required signature, in the order they were required
+    #[must_use]
+    pub fn slots(&self) -> &[SignatureSlot] {
+        &self.slots
+    }
+
+    /// Get the signatures `participant` still has to make
+    #[must_use]
+    pub fn requests(&self, participant: &str) -> Vec<SignatureRequest> {
+        self.slots
+            .iter()
+            .filter(|slot| slot.participant == participant && slot.signature.is_none())
+            .map(|slot| SignatureRequest {
+                input: slot.input,
+                witness: slot.witness.clone(),
+                sighash: self.sighashes[slot.input],
+            })
+            .collect()
+    }
+
+    /// Get the signatures that are still missing
+    pub fn missing(&self) -> impl Iterator<Item = &SignatureSlot> {
+        self.slots.iter().filter(|slot| slot.signature.is_none())
+    }
+
+    /// Check whether every required signature was provided
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.missing().next().is_none()
+    }
+
+    /// Add the signature of `participant` for `witness` of `input`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `participant` was not asked for this signature or
+    /// the signature does not verify against the required key.
+    pub fn add_signature(
+        &mut self,
+        participant: &str,
+        input: usize,
+        witness: &str,
+        signature: [u8; 64],
+    ) -> Result<&mut Self, SpendError> {
+        let sighash = self.sighash(input)?;
+        let index = self
+            .slot(input, witness)
+            .filter(|&index| self.slots[index].participant == participant)
+            .ok_or_else(|| {
+                SpendError::SigningError(format!(
+                    "{participant} is not asked to sign witness {witness} of input {input}"
+                ))
+            })?;
+        let slot = &mut self.slots[index];
+        Self::verify(slot, sighash, signature)?;
+        slot.signature = Some(signature);
+        Ok(self)
+    }
+
+    /// Merge the signatures of another copy of this session
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `other` is not a copy of this session, or one of
+    /// its signatures does not verify.
+    pub fn merge(&mut self, other: &Self) -> Result<&mut Self, SpendError> {
+        let same_slots = self.slots.len() == other.slots.len()
+            && self.slots.iter().zip(&other.slots).all(|(ours, theirs)| {
+                ours.participant == theirs.participant
+                    && ours.input == theirs.input
+                    && ours.witness == theirs.witness
+                    && ours.pubkey == theirs.pubkey
+            });
+        if self.sighashes != other.sighashes || !same_slots {
+            return Err(SpendError::SigningError(
+                "Cannot merge signatures of a different session".into(),
+            ));
+        }
+        for (ours, theirs) in self.slots.iter_mut().zip(&other.slots) {
+            if let (None, Some(signature)) = (ours.signature, theirs.signature) {
+                Self::verify(ours, self.sighashes[ours.input], signature)?;
+                ours.signature = Some(signature);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Build the signature witness values of every program input
+    ///
+    /// Inputs without required signatures get empty witness values.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a signature is missing.
+    pub fn witness_values(&self) -> Result<Vec<WitnessValues>, SpendError> {
+        let mut values = vec![HashMap::new(); self.sighashes.len()];
+        for slot in &self.slots {
+            let signature = slot.signature.ok_or_else(|| {
+                SpendError::SigningError(format!(
+                    "{} has not signed witness {} of input {}",
+                    slot.participant, slot.witness, slot.input
+                ))
+            })?;
+            values[slot.input].insert(witness_name(&slot.witness)?, Value::byte_array(signature));
+        }
+        Ok(values.into_iter().map(WitnessValues::from).collect())
+    }
+
+    /// Serialize to a JSON string, for sending to the participants
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, SpendError> {
+        use elements::hex::ToHex;
+
+        let state = SessionState {
+            sighashes: self
+                .sighashes
+                .iter()
+                .map(|sighash| sighash.to_hex())
+                .collect(),
+            slots: self
+                .slots
+                .iter()
+                .map(|slot| SlotState {
+                    participant: slot.participant.clone(),
+                    input: slot.input,
+                    witness: slot.witness.clone(),
+                    pubkey: slot.pubkey.to_string(),
+                    signature: slot.signature.map(|signature| signature.to_hex()),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&state)
+            .map_err(|e| SpendError::SigningError(format!("Cannot serialize session: {e}")))
+    }
+
+    /// Parse from a JSON string
+    ///
+    /// Witness names and signatures are checked again, so a session received
+    /// from an untrusted participant cannot carry invalid ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is malformed or a witness name or
+    /// signature is invalid.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, SpendError> {
+        use elements::hex::FromHex;
+        use std::str::FromStr;
+
+        let invalid = |e: &dyn std::fmt::Display| {
+            SpendError::SigningError(format!("Invalid signing session: {e}"))
+        };
+        let state: SessionState = serde_json::from_str(json).map_err(|e| invalid(&e))?;
+        let bytes = |hex: &str| Vec::<u8>::from_hex(hex).map_err(|e| invalid(&e));
+        let sighashes = state
+            .sighashes
+            .iter()
+            .map(|sighash| {
+                <[u8; 32]>::try_from(bytes(sighash)?).map_err(|_| invalid(&"sighash length"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut session = Self::new(sighashes);
+        for slot in state.slots {
+            let pubkey = XOnlyPublicKey::from_str(&slot.pubkey).map_err(|e| invalid(&e))?;
+            session.require(&slot.participant, slot.input, &slot.witness, pubkey)?;
+            if let Some(signature) = slot.signature {
+                let signature = <[u8; 64]>::try_from(bytes(&signature)?)
+                    .map_err(|_| invalid(&"signature length"))?;
+                session.add_signature(&slot.participant, slot.input, &slot.witness, signature)?;
+            }
+        }
+        Ok(session)
+    }
+
+    fn sighash(&self, input: usize) -> Result<[u8; 32], SpendError> {
+        self.sighashes.get(input).copied().ok_or_else(|| {
+            SpendError::SigningError(format!(
+                "Input {input} out of range for {} inputs",
+                self.sighashes.len()
+            ))
+        })
+    }
+
+    fn slot(&self, input: usize, witness: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.input == input && slot.witness == witness)
+    }
+
+    fn verify(
+        slot: &SignatureSlot,
+        sighash: [u8; 32],
+        signature: [u8; 64],
+    ) -> Result<(), SpendError> {
+        let invalid = || {
+            SpendError::SigningError(format!(
+                "Invalid signature of {} for witness {} of input {}",
+                slot.participant, slot.witness, slot.input
+            ))
+        };
+        let signature = schnorr::Signature::from_slice(&signature).map_err(|_| invalid())?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &Message::from_digest(sighash), &slot.pubkey)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Portable state of a signing session
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SessionState {
+    sighashes: Vec<String>,
+    slots: Vec<SlotState>,
+}
+
+/// Portable state of a signature slot
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SlotState {
+    participant: String,
+    input: usize,
+    witness: String,
+    pubkey: String,
+    signature: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProgramError;
+    use crate::program::Program;
+    use crate::test_fixtures::{test_genesis_hash, test_utxo, P2PK_PROGRAM};
+    use crate::util::{keypair_from_u32, sign_schnorr_with};
+    use simplicityhl::num::U256;
+    use simplicityhl::str::WitnessName;
+    use simplicityhl::Arguments;
+
+    fn pubkey(key: u32) -> XOnlyPublicKey {
+        keypair_from_u32(key).x_only_public_key().0
+    }
+
+    #[test]
+    fn test_session_tracks_and_checks_signatures() {
+        let mut session = SigningSession::new(vec![[1u8; 32], [2u8; 32]]);
+        session
+            .require("alice", 0, "SIG", pubkey(1))
+            .unwrap()
+            .require("bob", 1, "SIG", pubkey(2))
+            .unwrap();
+        assert!(session.require("carol", 2, "SIG", pubkey(3)).is_err());
+        assert!(session.require("carol", 0, "SIG", pubkey(3)).is_err());
+        assert!(matches!(
+            session.require("carol", 0, "SIG\")", pubkey(3)),
+            Err(SpendError::ProgramError(ProgramError::InvalidWitnessName(
+                _
+            )))
+        ));
+
+        assert_eq!(
+            session.requests("alice"),
+            vec![SignatureRequest {
+                input: 0,
+                witness: "SIG".into(),
+                sighash: [1u8; 32],
+            }]
+        );
+        // Wrong key, wrong participant and wrong sighash
+        assert!(session
+            .add_signature(
+                "alice",
+                0,
+                "SIG",
+                sign_schnorr_with(&keypair_from_u32(2), [1u8; 32])
+            )
+            .is_err());
+        assert!(session
+            .add_signature(
+                "bob",
+                0,
+                "SIG",
+                sign_schnorr_with(&keypair_from_u32(1), [1u8; 32])
+            )
+            .is_err());
+        assert!(session
+            .add_signature(
+                "alice",
+                0,
+                "SIG",
+                sign_schnorr_with(&keypair_from_u32(1), [2u8; 32])
+            )
+            .is_err());
+
+        session
+            .add_signature(
+                "alice",
+                0,
+                "SIG",
+                sign_schnorr_with(&keypair_from_u32(1), [1u8; 32]),
+            )
+            .unwrap();
+        assert!(session.requests("alice").is_empty());
+        assert_eq!(session.missing().count(), 1);
+        assert!(session.witness_values().is_err());
+
+        let mut other = SigningSession::new(vec![[1u8; 32], [2u8; 32]]);
+        other.require("bob", 1, "SIG", pubkey(2)).unwrap();
+        assert!(session.merge(&other).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_session_round_trip_and_finalize() {
+        let program = Program::from_source(P2PK_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::from(HashMap::from([(
+                WitnessName::from_str_unchecked("PK"),
+                Value::u256(U256::from_byte_array(pubkey(1).serialize())),
+            )])))
+            .unwrap();
+        let script_pubkey = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .script_pubkey();
+        let utxos = (0..2)
+            .map(|vout| crate::client::Utxo {
+                vout,
+                script_pubkey: script_pubkey.clone(),
+                ..test_utxo()
+            })
+            .collect();
+        let mut builder = SpendBuilder::from_utxos(program, utxos)
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_output_simple(
+            elements::Script::new(),
+            199_000_000,
+            elements::AssetId::default(),
+        );
+        builder.add_fee(1_000_000, elements::AssetId::default());
+
+        // Both inputs are locked to key 1, held by two devices of one owner
+        let mut session = SigningSession::from_builder(&builder).unwrap();
+        session
+            .require("laptop", 0, "SIG", pubkey(1))
+            .unwrap()
+            .require("phone", 1, "SIG", pubkey(1))
+            .unwrap();
+
+        // Witness names from other participants are checked
+        let json = session.to_json().unwrap();
+        assert!(SigningSession::from_json(&json.replace("\"SIG\"", "\"witness::SIG\"")).is_err());
+
+        let mut copies = Vec::new();
+        for participant in ["laptop", "phone"] {
+            let mut copy = SigningSession::from_json(&session.to_json().unwrap()).unwrap();
+            for request in copy.requests(participant) {
+                let signature = sign_schnorr_with(&keypair_from_u32(1), request.sighash);
+                copy.add_signature(participant, request.input, &request.witness, signature)
+                    .unwrap();
+            }
+            copies.push(copy.to_json().unwrap());
+        }
+        for copy in &copies {
+            session
+                .merge(&SigningSession::from_json(copy).unwrap())
+                .unwrap();
+        }
+        assert!(session.is_complete());
+
+        let tx = builder
+            .clone()
+            .finalize_inputs(session.witness_values().unwrap())
+            .unwrap();
+        builder.verify(&tx).unwrap();
+    }
+}
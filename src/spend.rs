@@ -1,54 +1,713 @@
 //! Transaction construction and spending utilities
 
-use crate::client::Utxo;
+use crate::client::{NodeClient, Utxo};
+use crate::coin_selection::CoinSelection;
 use crate::error::SpendError;
+use crate::pegin::PeginInput;
 use crate::program::{InstantiatedProgram, SatisfiedProgram};
-use elements::hashes::Hash;
+use crate::signer::AsyncSigner;
+use crate::taptree::TaprootTree;
+use elements::confidential::{AssetBlindingFactor, ValueBlindingFactor};
+use elements::encode::{serialize, VarInt};
+use elements::hashes::{sha256, Hash, HashEngine};
+use elements::hex::{FromHex, ToHex};
 use elements::pset::PartiallySignedTransaction as Psbt;
+use elements::secp256k1_zkp::{PedersenCommitment, RangeProof, ZERO_TWEAK};
+use elements::sighash::{Prevouts, SighashCache};
+use elements::taproot::ControlBlock;
 use elements::{
-    confidential, AssetIssuance, LockTime, Script, Sequence, Transaction, TxIn, TxInWitness, TxOut,
-    TxOutWitness,
+    confidential, Address, AssetIssuance, ContractHash, LockTime, SchnorrSighashType, Script,
+    Sequence, Transaction, TxIn, TxInWitness, TxOut, TxOutSecrets, TxOutWitness,
 };
+use secp256k1::rand::{CryptoRng, RngCore};
+use secp256k1::{schnorr, Keypair, Message, Secp256k1};
 use simplicityhl::simplicity::jet::elements::{ElementsEnv, ElementsUtxo};
+use simplicityhl::simplicity::jet::Elements;
+use simplicityhl::simplicity::{BitIter, BitMachine, RedeemNode};
 use simplicityhl::WitnessValues;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Size of the 52-bit rangeproof `blind` gives each blinded amount
+const RANGEPROOF_LEN: usize = 4174;
+
+/// Largest number of inputs a surjection proof is made over
+///
+/// The proof hides which input an output's asset comes from among up to 3
+/// of the transaction's inputs.
+const SURJECTION_PROOF_MAX_USED: usize = 3;
+
+/// Estimated weight added by blinding one issuance amount
+///
+/// The amount gets a rangeproof in the input witness, with a 3-byte length
+/// prefix in place of an empty proof's 1 byte, and its 33-byte commitment
+/// replaces a 9-byte explicit value.
+const BLINDED_ISSUANCE_WEIGHT: usize = RANGEPROOF_LEN + 2 + 24 * 4;
+
+/// Size of a surjection proof for an output of a transaction with
+/// `num_inputs` inputs
+///
+/// The proof stores the input count, a bitmap of the inputs used and one
+/// 32-byte scalar plus one per used input.
+const fn surjection_proof_len(num_inputs: usize) -> usize {
+    let used = if num_inputs < SURJECTION_PROOF_MAX_USED {
+        num_inputs
+    } else {
+        SURJECTION_PROOF_MAX_USED
+    };
+    2 + num_inputs.div_ceil(8) + 32 * (1 + used)
+}
+
+/// Estimated weight added by blinding `output` in a transaction with
+/// `num_inputs` inputs
+///
+/// The output's witness gets a rangeproof and a surjection proof, each with
+/// a length prefix in place of an empty proof's 1 byte. Explicit values and
+/// assets are replaced by 33-byte commitments, which count as non-witness
+/// data.
+fn blinding_weight(output: &TxOut, num_inputs: usize) -> usize {
+    let proof_len = |len: usize| VarInt(len as u64).size() - 1 + len;
+    let commitments_len = 2 * 33 - serialize(&output.value).len() - serialize(&output.asset).len();
+    proof_len(RANGEPROOF_LEN) + proof_len(surjection_proof_len(num_inputs)) + commitments_len * 4
+}
+
+/// Smallest change output `add_change` will create, in satoshis
+pub const DUST_THRESHOLD: u64 = 546;
 
 /// Builder for constructing spending transactions
-pub struct SpendBuilder {
-    program: InstantiatedProgram,
-    utxo: Utxo,
+///
+/// Program inputs are UTXOs locked by Simplicity programs, each spent via
+/// its own program's leaf. They come first, followed by any external inputs
+/// and then any peg-in inputs, which are left unsigned.
+///
+/// Every sighash commits to the chain's genesis hash, so a new builder is a
+/// `SpendBuilder<NeedsGenesis>` that can be filled in but not signed. Setting
+/// the hash with [`Self::genesis_hash`] turns it into a `SpendBuilder<Ready>`,
+/// the default, which computes sighashes and finalizes:
+///
+/// ```compile_fail
+/// use musk::client::Utxo;
+/// use musk::elements::{self, confidential, hashes::Hash, Txid};
+/// use musk::{Arguments, Program, SpendBuilder};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let utxo = Utxo {
+///     txid: Txid::all_zeros(),
+///     vout: 0,
+///     amount: 100_000,
+///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+///     value_commitment: None,
+///     confirmations: 0,
+///     block_height: None,
+/// };
+///
+/// // No genesis hash: there is no sighash to sign yet
+/// let sighash = SpendBuilder::try_new(compiled, utxo).unwrap().sighash_all();
+/// ```
+///
+/// Outputs are not tracked by the type state: a spend without outputs still
+/// compiles, and `finalize` and the other finalizing methods reject it at run
+/// time with [`SpendError::FinalizationError`]. Outputs are added and removed
+/// through `&mut self` methods, some depending on amounts known only at run
+/// time, e.g. [`Self::add_change`] and [`Self::remove_output`], so a
+/// type-level output count could not follow them.
+///
+/// Apart from that transition, the methods configuring a spend take and
+/// return `&mut Self`, wrapped in a `Result` if they can fail, so they chain:
+///
+/// ```
+/// use musk::client::Utxo;
+/// use musk::elements::{self, confidential, hashes::Hash, LockTime, Script, Txid};
+/// use musk::{Arguments, Program, SpendBuilder};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let asset = elements::AssetId::default();
+/// let utxo = Utxo {
+///     txid: Txid::all_zeros(),
+///     vout: 0,
+///     amount: 100_000,
+///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+///     asset: confidential::Asset::Explicit(asset),
+///     value_commitment: None,
+///     confirmations: 0,
+///     block_height: None,
+/// };
+///
+/// let mut builder = SpendBuilder::try_new(compiled, utxo)
+///     .unwrap()
+///     .genesis_hash(elements::BlockHash::all_zeros());
+/// builder
+///     .set_lock_time(LockTime::from_consensus(100))
+///     .signal_rbf()
+///     .add_output_simple(Script::new(), 60_000, asset)
+///     .add_fee(500, asset)
+///     .add_change(Script::from(vec![0x51]))
+///     .unwrap();
+/// assert_eq!(builder.outputs().len(), 3);
+/// ```
+///
+/// Builders can be cloned, e.g. to retry a spend with a different fee, as
+/// `finalize` consumes the builder. The `Debug` output identifies programs by
+/// CMR and omits witnesses and proofs, so it is compact enough for audit logs.
+#[derive(Clone)]
+pub struct SpendBuilder<S = Ready> {
+    inputs: Vec<(InstantiatedProgram, Utxo)>,
+    external_utxos: Vec<Utxo>,
+    pegin_inputs: Vec<(Utxo, Vec<Vec<u8>>)>,
     outputs: Vec<TxOut>,
+    version: u32,
     lock_time: LockTime,
     sequence: Sequence,
     genesis_hash: elements::BlockHash,
+    discount_ct: bool,
+    issuances: BTreeMap<usize, InputIssuance>,
+    state: PhantomData<S>,
+}
+
+/// State of a [`SpendBuilder`] whose genesis hash is not set yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeedsGenesis;
+
+/// State of a [`SpendBuilder`] that can compute sighashes and finalize
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ready;
+
+/// Input and output totals of one asset in a spend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetTotals {
+    /// Amount spent from inputs or issued
+    pub inputs: u64,
+    /// Amount sent to outputs, including the fee
+    pub outputs: u64,
+}
+
+impl AssetTotals {
+    /// Get the amount left for change, or `None` if the outputs exceed the inputs
+    #[must_use]
+    pub const fn remaining(&self) -> Option<u64> {
+        self.inputs.checked_sub(self.outputs)
+    }
+}
+
+/// An asset issuance made by a program input
+#[derive(Clone)]
+struct InputIssuance {
+    issuance: AssetIssuance,
+    asset: elements::AssetId,
+    token: elements::AssetId,
+    blinded: bool,
+    amount_rangeproof: Option<Box<RangeProof>>,
+    inflation_keys_rangeproof: Option<Box<RangeProof>>,
+}
+
+impl InputIssuance {
+    /// Create a new issuance by the input spending `outpoint`
+    fn new(outpoint: elements::OutPoint, issuance: AssetIssuance, blinded: bool) -> Self {
+        let entropy = elements::AssetId::generic_issuance_entropy(
+            outpoint,
+            ContractHash::from_byte_array(issuance.asset_entropy),
+        );
+        Self {
+            issuance,
+            asset: elements::AssetId::from_entropy(entropy),
+            token: elements::AssetId::reissuance_token_from_entropy(entropy, blinded),
+            blinded,
+            amount_rangeproof: None,
+            inflation_keys_rangeproof: None,
+        }
+    }
+
+    /// Count the issuance amounts that still need a rangeproof
+    fn pending_proofs(&self) -> usize {
+        if !self.blinded {
+            return 0;
+        }
+        [self.issuance.amount, self.issuance.inflation_keys]
+            .iter()
+            .filter(|value| value.is_explicit())
+            .count()
+    }
+}
+
+impl<S> std::fmt::Debug for SpendBuilder<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outputs: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| (&output.script_pubkey, output.asset, output.value))
+            .collect();
+        f.debug_struct("SpendBuilder")
+            .field(
+                "inputs",
+                &self
+                    .inputs
+                    .iter()
+                    .map(|(program, utxo)| (program.cmr(), utxo))
+                    .collect::<Vec<_>>(),
+            )
+            .field("external_utxos", &self.external_utxos)
+            .field(
+                "pegin_utxos",
+                &self
+                    .pegin_inputs
+                    .iter()
+                    .map(|(utxo, _)| utxo)
+                    .collect::<Vec<_>>(),
+            )
+            .field("outputs", &outputs)
+            .field("issuing_inputs", &self.issuances.keys().collect::<Vec<_>>())
+            .field("version", &self.version)
+            .field("lock_time", &self.lock_time)
+            .field("sequence", &self.sequence)
+            .field("genesis_hash", &self.genesis_hash)
+            .finish_non_exhaustive()
+    }
 }
 
-impl SpendBuilder {
+impl SpendBuilder<NeedsGenesis> {
     /// Create a new spend builder for the given program and UTXO
+    ///
+    /// The UTXO is not checked against the program, so a UTXO of another
+    /// program is only rejected when its spend is broadcast.
+    #[deprecated(note = "use `try_new`, which checks that the UTXO is locked by the program")]
     #[must_use]
     pub fn new(program: InstantiatedProgram, utxo: Utxo) -> Self {
+        Self::new_unchecked(program, utxo)
+    }
+
+    /// Create a spend builder without checking the UTXO against the program
+    pub(crate) fn new_unchecked(program: InstantiatedProgram, utxo: Utxo) -> Self {
         Self {
-            program,
-            utxo,
+            inputs: vec![(program, utxo)],
+            external_utxos: Vec::new(),
+            pegin_inputs: Vec::new(),
             outputs: Vec::new(),
+            version: 2,
             lock_time: LockTime::ZERO,
             sequence: Sequence::MAX,
-            genesis_hash: elements::BlockHash::from_byte_array([0u8; 32]), // Default, should be set
+            genesis_hash: elements::BlockHash::from_byte_array([0u8; 32]), // Set by genesis_hash
+            discount_ct: false,
+            issuances: BTreeMap::new(),
+            state: PhantomData,
+        }
+    }
+
+    /// Create a spend builder, checking that `utxo` is locked by `program`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the UTXO's scriptPubkey is not the program's
+    /// taproot output.
+    pub fn try_new(program: InstantiatedProgram, utxo: Utxo) -> Result<Self, SpendError> {
+        Self::from_inputs(vec![(program, utxo)])
+    }
+
+    /// Create a spend builder for the program at `leaf` of a multi-leaf tree
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `leaf` is out of range or is not a Simplicity
+    /// program, or the UTXO is not locked by the tree.
+    pub fn from_tree(tree: &TaprootTree, leaf: usize, utxo: Utxo) -> Result<Self, SpendError> {
+        Self::try_new(tree.program(leaf)?, utxo)
+    }
+
+    /// Create a spend builder spending several UTXOs of the same program
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `utxos` is empty or any UTXO is not locked by
+    /// `program`.
+    pub fn from_utxos(program: InstantiatedProgram, utxos: Vec<Utxo>) -> Result<Self, SpendError> {
+        Self::from_inputs(
+            utxos
+                .into_iter()
+                .map(|utxo| (program.clone(), utxo))
+                .collect(),
+        )
+    }
+
+    /// Create a spend builder where each input is locked by its own program
+    ///
+    /// This lets a single transaction close several different contracts.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `inputs` is empty or any UTXO is not locked by
+    /// its program.
+    pub fn from_inputs(inputs: Vec<(InstantiatedProgram, Utxo)>) -> Result<Self, SpendError> {
+        let mut inputs = inputs.into_iter();
+        let (program, utxo) = inputs
+            .next()
+            .ok_or_else(|| SpendError::InvalidUtxo("No UTXOs to spend".into()))?;
+        let mut builder = Self::new_unchecked(program, utxo);
+        builder.inputs.extend(inputs);
+        builder.validate_inputs()?;
+        Ok(builder)
+    }
+
+    /// Create a spend builder from the UTXOs `strategy` selects for `target`
+    ///
+    /// To leave out unconfirmed UTXOs a [`ZeroConfPolicy`](crate::ZeroConfPolicy)
+    /// rejects, wrap the strategy in
+    /// [`ZeroConfFiltered`](crate::coin_selection::ZeroConfFiltered).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::coin_selection::LargestFirst;
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Txid};
+    /// use musk::{Arguments, Program, SpendBuilder};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let utxo = |vout, amount| Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout,
+    ///     amount,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let candidates = [utxo(0, 5_000), utxo(1, 20_000), utxo(2, 8_000)];
+    /// let builder = SpendBuilder::select(compiled.clone(), &candidates, 25_000, &LargestFirst).unwrap();
+    /// assert_eq!(builder.inputs().len(), 2);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the candidates cannot cover `target`.
+    pub fn select<S: CoinSelection + ?Sized>(
+        program: InstantiatedProgram,
+        candidates: &[Utxo],
+        target: u64,
+        strategy: &S,
+    ) -> Result<Self, SpendError> {
+        Self::from_utxos(program, strategy.select(candidates, target)?)
+    }
+
+    /// Create a child spend accelerating an unconfirmed parent (CPFP)
+    ///
+    /// The child spends output `vout` of `parent`, which must be locked by
+    /// `program`, and pays it to `destination` minus a fee chosen so that
+    /// parent and child together reach `fee_rate` sat/vB. If the parent alone
+    /// already pays that rate, the child pays `fee_rate` for its own size.
+    /// `witness_values` is only used to estimate the child's size, as in
+    /// [`Self::estimate_fee`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the spent output is not locked by `program`, it
+    /// or the parent's fee is not explicit, or the output cannot cover the
+    /// fee without leaving dust.
+    pub fn cpfp(
+        program: InstantiatedProgram,
+        parent: &Transaction,
+        vout: u32,
+        destination: Script,
+        fee_rate: f64,
+        witness_values: WitnessValues,
+    ) -> Result<Self, SpendError> {
+        let output = parent.output.get(vout as usize).ok_or_else(|| {
+            SpendError::InvalidUtxo(format!("Parent transaction has no output {vout}"))
+        })?;
+        let (confidential::Asset::Explicit(asset), confidential::Value::Explicit(amount)) =
+            (output.asset, output.value)
+        else {
+            return Err(SpendError::InvalidUtxo(
+                "Cannot fee-bump from a confidential output".into(),
+            ));
+        };
+        let parent_fee = parent
+            .output
+            .iter()
+            .filter(|output| output.is_fee())
+            .map(|output| {
+                output.value.explicit().ok_or_else(|| {
+                    SpendError::BuildError("Parent fee output is not explicit".into())
+                })
+            })
+            .sum::<Result<u64, _>>()?;
+
+        let utxo = Utxo {
+            txid: parent.txid(),
+            vout,
+            amount,
+            script_pubkey: output.script_pubkey.clone(),
+            asset: output.asset,
+            value_commitment: None,
+            confirmations: 0,
+            block_height: None,
+        };
+        let mut builder = Self::try_new(program, utxo)?;
+        builder.add_output_simple(destination, amount, asset);
+
+        let child_fee = builder.estimate_fee(fee_rate, witness_values)?;
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let parent_target = (parent.vsize() as f64 * fee_rate).ceil() as u64;
+        let fee = child_fee + parent_target.saturating_sub(parent_fee);
+
+        let remaining = amount
+            .checked_sub(fee)
+            .filter(|&remaining| remaining >= DUST_THRESHOLD)
+            .ok_or(SpendError::InsufficientFunds {
+                needed: fee + DUST_THRESHOLD,
+                available: amount,
+            })?;
+        builder.outputs[0].value = confidential::Value::Explicit(remaining);
+        builder.add_fee(fee, asset);
+        Ok(builder)
+    }
+}
+
+impl<S> SpendBuilder<S> {
+    /// Add a program input, placed after the existing program inputs
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `utxo` is not locked by `program`.
+    pub fn add_program_input(
+        &mut self,
+        program: InstantiatedProgram,
+        utxo: Utxo,
+    ) -> Result<&mut Self, SpendError> {
+        if !program.owns_script(&utxo.script_pubkey) {
+            return Err(SpendError::ProgramMismatch {
+                indices: vec![self.inputs.len()],
+            });
+        }
+        self.inputs.push((program, utxo));
+        Ok(self)
+    }
+
+    /// Add a UTXO locked by the program of one of the existing inputs
+    ///
+    /// The input is placed after the existing program inputs. This lets coin
+    /// selection and fee iteration add inputs until the outputs and the
+    /// estimated fee are covered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    /// use musk::{Arguments, Program, SpendBuilder, WitnessValues};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let asset = elements::AssetId::default();
+    /// let mut utxos = (0..5).map(|vout| Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout,
+    ///     amount: 20_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(asset),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// });
+    ///
+    /// let mut builder = SpendBuilder::try_new(compiled, utxos.next().unwrap()).unwrap();
+    /// builder.add_output_simple(Script::new(), 50_000, asset);
+    /// let mut available = 20_000;
+    /// while available < 50_000 + builder.estimate_fee(0.1, WitnessValues::default()).unwrap() {
+    ///     let utxo = utxos.next().unwrap();
+    ///     available += utxo.amount;
+    ///     builder.add_input(utxo).unwrap();
+    /// }
+    /// assert_eq!(builder.inputs().len(), 3);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no program of the existing inputs locks `utxo`.
+    pub fn add_input(&mut self, utxo: Utxo) -> Result<&mut Self, SpendError> {
+        let program = self
+            .inputs
+            .iter()
+            .map(|(program, _)| program)
+            .find(|program| program.owns_script(&utxo.script_pubkey))
+            .cloned()
+            .ok_or_else(|| SpendError::ProgramMismatch {
+                indices: vec![self.inputs.len()],
+            })?;
+        self.inputs.push((program, utxo));
+        Ok(self)
+    }
+
+    /// Add several UTXOs, each locked by the program of an existing input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no program of the existing inputs locks one of
+    /// `utxos`; the UTXOs before it have been added.
+    pub fn add_inputs<I>(&mut self, utxos: I) -> Result<&mut Self, SpendError>
+    where
+        I: IntoIterator<Item = Utxo>,
+    {
+        for utxo in utxos {
+            self.add_input(utxo)?;
+        }
+        Ok(self)
+    }
+
+    /// Check that every program input's UTXO is locked by its program
+    ///
+    /// A UTXO of another program would otherwise only be rejected when its
+    /// spend is broadcast.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the indices of all mismatching inputs.
+    pub fn validate_inputs(&self) -> Result<(), SpendError> {
+        let indices: Vec<usize> = self
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, (program, utxo))| !program.owns_script(&utxo.script_pubkey))
+            .map(|(index, _)| index)
+            .collect();
+        if indices.is_empty() {
+            Ok(())
+        } else {
+            Err(SpendError::ProgramMismatch { indices })
         }
     }
 
-    /// Set the genesis block hash (required for sighash computation)
+    /// Get the program inputs of this transaction, in input order
+    #[must_use]
+    pub fn inputs(&self) -> &[(InstantiatedProgram, Utxo)] {
+        &self.inputs
+    }
+
+    /// Add an input that is not controlled by the program
+    ///
+    /// External inputs (e.g. a wallet UTXO paying the fee) are placed after
+    /// the program inputs and are covered by every sighash, but their
+    /// witnesses are left empty for signing elsewhere, e.g. with
+    /// `signrawtransactionwithwallet` or a PSET signer.
+    pub fn add_external_input(&mut self, utxo: Utxo) -> &mut Self {
+        self.external_utxos.push(utxo);
+        self
+    }
+
+    /// Get the external UTXOs spent by this transaction, in input order
     #[must_use]
-    pub const fn genesis_hash(mut self, hash: elements::BlockHash) -> Self {
-        self.genesis_hash = hash;
+    pub fn external_utxos(&self) -> &[Utxo] {
+        &self.external_utxos
+    }
+
+    /// Add a peg-in input claiming a mainchain deposit
+    ///
+    /// Peg-in inputs are placed after the external inputs. Like external
+    /// inputs, they are covered by every sighash but must be signed for their
+    /// claim script elsewhere; only their peg-in witness is filled in.
+    pub fn add_pegin_input(&mut self, pegin: &PeginInput) -> &mut Self {
+        self.pegin_inputs
+            .push((pegin.utxo(), pegin.pegin_witness()));
         self
     }
 
+    /// Iterate over all spent UTXOs in input order
+    fn all_utxos(&self) -> impl Iterator<Item = &Utxo> {
+        self.inputs
+            .iter()
+            .map(|(_, utxo)| utxo)
+            .chain(&self.external_utxos)
+            .chain(self.pegin_inputs.iter().map(|(utxo, _)| utxo))
+    }
+
+    /// Set the genesis block hash, making the builder ready to sign
+    ///
+    /// Sighashes commit to the genesis hash, so they can only be computed,
+    /// and the spend finalized, once it is set.
+    #[must_use]
+    pub fn genesis_hash(self, hash: elements::BlockHash) -> SpendBuilder<Ready> {
+        SpendBuilder {
+            inputs: self.inputs,
+            external_utxos: self.external_utxos,
+            pegin_inputs: self.pegin_inputs,
+            outputs: self.outputs,
+            version: self.version,
+            lock_time: self.lock_time,
+            sequence: self.sequence,
+            genesis_hash: hash,
+            discount_ct: self.discount_ct,
+            issuances: self.issuances,
+            state: PhantomData,
+        }
+    }
+
     /// Add an output to the transaction
     pub fn add_output(&mut self, output: TxOut) -> &mut Self {
         self.outputs.push(output);
         self
     }
 
+    /// Get the outputs added so far, in output order
+    #[must_use]
+    pub fn outputs(&self) -> &[TxOut] {
+        &self.outputs
+    }
+
+    /// Remove and return the output at `index`
+    ///
+    /// Later outputs move down by one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of range.
+    pub fn remove_output(&mut self, index: usize) -> Result<TxOut, SpendError> {
+        self.check_output(index)?;
+        Ok(self.outputs.remove(index))
+    }
+
+    /// Replace the output at `index`, returning the old output
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of range.
+    pub fn replace_output(&mut self, index: usize, output: TxOut) -> Result<TxOut, SpendError> {
+        self.check_output(index)?;
+        Ok(std::mem::replace(&mut self.outputs[index], output))
+    }
+
+    /// Check that `index` refers to an output
+    fn check_output(&self, index: usize) -> Result<(), SpendError> {
+        if index < self.outputs.len() {
+            Ok(())
+        } else {
+            Err(SpendError::BuildError(format!(
+                "Output {index} out of range ({} outputs)",
+                self.outputs.len()
+            )))
+        }
+    }
+
+    /// Get the total of the explicit fee outputs added so far
+    #[must_use]
+    pub fn fee(&self) -> u64 {
+        self.outputs
+            .iter()
+            .filter(|output| output.is_fee())
+            .filter_map(|output| output.value.explicit())
+            .sum()
+    }
+
     /// Add a simple output with explicit value
     pub fn add_output_simple(
         &mut self,
@@ -67,99 +726,808 @@ impl SpendBuilder {
     }
 
     /// Add a fee output
+    ///
+    /// The fee is usually paid in the chain's policy asset (L-BTC on Liquid),
+    /// but may be paid in any asset on chains whose nodes accept it. The
+    /// policy asset can also be supplied by an external input, see
+    /// [`Self::add_asset_change`].
     pub fn add_fee(&mut self, amount: u64, asset: elements::AssetId) -> &mut Self {
         self.outputs.push(TxOut::new_fee(amount, asset));
         self
     }
 
-    /// Set the lock time
-    #[must_use]
-    pub const fn lock_time(mut self, lock_time: LockTime) -> Self {
-        self.lock_time = lock_time;
-        self
+    /// Add an output burning `amount` of `asset`
+    ///
+    /// The output pays to an empty `OP_RETURN` script, which is provably
+    /// unspendable, and keeps an explicit amount and asset so the burn can be
+    /// audited on chain.
+    pub fn add_burn(&mut self, amount: u64, asset: elements::AssetId) -> &mut Self {
+        self.add_output_simple(Script::new_op_return(&[]), amount, asset)
     }
 
-    /// Set the sequence number
-    #[must_use]
-    pub const fn sequence(mut self, sequence: Sequence) -> Self {
-        self.sequence = sequence;
+    /// Issue a new asset from program input `index`
+    ///
+    /// Issues `amount` of a new asset and `token_amount` of its reissuance
+    /// token, both derived from the input's outpoint and `contract_hash`,
+    /// and returns their IDs. The issued amounts count as inputs for
+    /// [`Self::add_change`], so outputs paying them out must be added.
+    ///
+    /// With `blinded`, [`Self::blind`] replaces both amounts with commitments
+    /// and rangeproofs, so the issuance does not reveal the supply. This
+    /// changes the reissuance token ID, and requires at least one output
+    /// to be blinded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is not a program input, the input already
+    /// issues an asset, or both amounts are zero.
+    pub fn issue_asset(
+        &mut self,
+        index: usize,
+        amount: u64,
+        token_amount: u64,
+        contract_hash: ContractHash,
+        blinded: bool,
+    ) -> Result<(elements::AssetId, elements::AssetId), SpendError> {
+        self.check_input(index)?;
+        if self.issuances.contains_key(&index) {
+            return Err(SpendError::BuildError(format!(
+                "Input {index} already issues an asset"
+            )));
+        }
+        if amount == 0 && token_amount == 0 {
+            return Err(SpendError::BuildError("Nothing to issue".into()));
+        }
+
+        let explicit = |amount| {
+            if amount == 0 {
+                confidential::Value::Null
+            } else {
+                confidential::Value::Explicit(amount)
+            }
+        };
+        let utxo = &self.inputs[index].1;
+        let issuance = InputIssuance::new(
+            elements::OutPoint::new(utxo.txid, utxo.vout),
+            AssetIssuance {
+                asset_blinding_nonce: ZERO_TWEAK,
+                asset_entropy: contract_hash.to_byte_array(),
+                amount: explicit(amount),
+                inflation_keys: explicit(token_amount),
+            },
+            blinded,
+        );
+        let ids = (issuance.asset, issuance.token);
+        self.issuances.insert(index, issuance);
+        Ok(ids)
+    }
+
+    /// Add an output to a confidential address, to be blinded by [`Self::blind`]
+    ///
+    /// The output keeps its explicit amount and asset and carries the
+    /// address's blinding public key as its nonce until it is blinded, so
+    /// the fee and change can still be computed against it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `address` is not confidential.
+    pub fn add_confidential_output(
+        &mut self,
+        address: &Address,
+        amount: u64,
+        asset: elements::AssetId,
+    ) -> Result<&mut Self, SpendError> {
+        if address.blinding_pubkey.is_none() {
+            return Err(SpendError::BuildError(format!(
+                "Address {address} is not confidential"
+            )));
+        }
+        Ok(self.add_output_to_address(address, amount, asset))
+    }
+
+    /// Add an output paying `address`
+    ///
+    /// If `address` is confidential, its blinding public key is set as the
+    /// output nonce, marking the output for [`Self::blind`]; otherwise this
+    /// is the same as [`Self::add_output_simple`] with the address's
+    /// scriptPubkey.
+    pub fn add_output_to_address(
+        &mut self,
+        address: &Address,
+        amount: u64,
+        asset: elements::AssetId,
+    ) -> &mut Self {
+        self.outputs.push(TxOut {
+            value: confidential::Value::Explicit(amount),
+            script_pubkey: address.script_pubkey(),
+            asset: confidential::Asset::Explicit(asset),
+            nonce: address
+                .blinding_pubkey
+                .map_or(confidential::Nonce::Null, confidential::Nonce::Confidential),
+            witness: TxOutWitness::empty(),
+        });
         self
     }
 
-    /// Compute the `sighash_all` for this transaction
+    /// Blind all outputs added with [`Self::add_confidential_output`]
     ///
-    /// This is used to generate witness values that include signatures
+    /// Commitments, rangeproofs and surjection proofs are produced in
+    /// process, and the last blinded output's value blinding factor is
+    /// chosen so the commitments balance against the inputs, replacing a
+    /// round-trip through the node's `rawblindrawtransaction`. Explicit
+    /// inputs need no secrets; `input_secrets` supplies the unblinded asset,
+    /// amount and blinding factors of confidential inputs by input index.
+    ///
+    /// Issuances made with `blinded` set (see [`Self::issue_asset`]) are
+    /// blinded too.
+    ///
+    /// Other outputs, such as the fee or a payout to an exchange that only
+    /// accepts explicit amounts, stay explicit and enter the balance with zero
+    /// blinding factors, so blinded and explicit outputs can be mixed freely.
+    /// Without outputs to blind, explicit spends are left unchanged.
+    ///
+    /// Blinding fixes the outputs, so it must happen after the fee and change
+    /// are added and before any sighash is computed.
     ///
     /// # Errors
     ///
-    /// Returns an error if the control block cannot be found.
-    pub fn sighash_all(&self) -> Result<[u8; 32], SpendError> {
-        let tx = self.build_unsigned_tx();
-        let utxo = ElementsUtxo {
-            script_pubkey: self.utxo.script_pubkey.clone(),
-            value: confidential::Value::Explicit(self.utxo.amount),
-            asset: self.utxo.asset,
-        };
+    /// Returns an error if a confidential input has no secrets, confidential
+    /// inputs or blinded issuances have no blinded output to balance their
+    /// blinding factors, or the outputs cannot be blinded.
+    pub fn blind<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        input_secrets: &BTreeMap<usize, TxOutSecrets>,
+    ) -> Result<&mut Self, SpendError> {
+        let spent_utxo_secrets = self
+            .all_utxos()
+            .enumerate()
+            .map(
+                |(index, utxo)| match (input_secrets.get(&index), utxo.asset) {
+                    (Some(secrets), _) => Ok(*secrets),
+                    (None, confidential::Asset::Explicit(asset))
+                        if utxo.value_commitment.is_none() =>
+                    {
+                        Ok(TxOutSecrets::new(
+                            asset,
+                            AssetBlindingFactor::zero(),
+                            utxo.amount,
+                            ValueBlindingFactor::zero(),
+                        ))
+                    }
+                    (None, _) => Err(SpendError::InvalidUtxo(format!(
+                        "Missing blinding secrets for confidential input {index}"
+                    ))),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let (script, _version) = self.program.script_version();
-        let control_block = self
-            .program
-            .taproot_info()
-            .control_block(&(script, self.program.script_version().1))
-            .ok_or_else(|| SpendError::BuildError("Control block not found".into()))?;
+        let pending: Vec<bool> = self
+            .issuances
+            .values()
+            .filter(|issuance| {
+                issuance.issuance.amount.is_explicit()
+                    || issuance.issuance.inflation_keys.is_explicit()
+            })
+            .map(|issuance| issuance.blinded)
+            .collect();
+        let blind_issuances = pending.contains(&true);
+        if blind_issuances && pending.contains(&false) {
+            return Err(SpendError::BuildError(
+                "Cannot blind some issuances but not others".into(),
+            ));
+        }
 
-        let env = ElementsEnv::new(
-            &tx,
-            vec![utxo],
-            0,
-            self.program.cmr(),
-            control_block,
-            None,
-            self.genesis_hash,
-        );
+        let to_blind = self
+            .outputs
+            .iter()
+            .any(|output| output.nonce.is_confidential() && output.value.is_explicit());
+        if !to_blind {
+            let blinded_inputs = spent_utxo_secrets.iter().any(|secrets| {
+                secrets.asset_bf != AssetBlindingFactor::zero()
+                    || secrets.value_bf != ValueBlindingFactor::zero()
+            });
+            if blinded_inputs || blind_issuances {
+                return Err(SpendError::BuildError(
+                    "Confidential inputs and blinded issuances need a blinded output to balance"
+                        .into(),
+                ));
+            }
+            return Ok(self);
+        }
 
-        Ok(*env.c_tx_env().sighash_all().as_byte_array())
+        let mut tx = self.build_unsigned_tx();
+        tx.blind(rng, &Secp256k1::new(), &spent_utxo_secrets, blind_issuances)
+            .map_err(|e| SpendError::BuildError(format!("Blinding failed: {e}")))?;
+        for (index, issuance) in &mut self.issuances {
+            let input = &mut tx.input[*index];
+            issuance.issuance = input.asset_issuance;
+            issuance.amount_rangeproof = input.witness.amount_rangeproof.take();
+            issuance.inflation_keys_rangeproof = input.witness.inflation_keys_rangeproof.take();
+        }
+        self.outputs = tx.output;
+        Ok(self)
     }
 
-    /// Build the unsigned transaction
-    fn build_unsigned_tx(&self) -> Transaction {
-        Transaction {
-            version: 2,
-            lock_time: self.lock_time,
-            input: vec![TxIn {
-                previous_output: elements::OutPoint::new(self.utxo.txid, self.utxo.vout),
-                is_pegin: false,
-                script_sig: Script::new(),
-                sequence: self.sequence,
-                asset_issuance: AssetIssuance::null(),
-                witness: TxInWitness::empty(),
-            }],
-            output: self.outputs.clone(),
+    /// Add change outputs returning the unspent balance of every asset
+    ///
+    /// For each asset, the change is the input amount minus all outputs added
+    /// so far, including the fee, so the fee must be added first. Assets that
+    /// are fully spent get no change output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Arguments, Program, SpendBuilder};
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Txid};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let script_pubkey = compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey();
+    /// let asset = elements::AssetId::default();
+    /// let utxo = Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout: 0,
+    ///     amount: 100_000,
+    ///     script_pubkey: script_pubkey.clone(),
+    ///     asset: confidential::Asset::Explicit(asset),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let mut builder = SpendBuilder::try_new(compiled, utxo).unwrap();
+    /// builder.add_output_simple(script_pubkey.clone(), 60_000, asset);
+    /// builder.add_fee(500, asset);
+    /// builder.add_change(script_pubkey).unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an input or output is confidential, the outputs
+    /// exceed the inputs for some asset, or a change amount would be dust.
+    pub fn add_change(&mut self, script_pubkey: Script) -> Result<&mut Self, SpendError> {
+        for (asset, amount) in self.change_amounts()? {
+            self.add_output_simple(script_pubkey.clone(), amount, asset);
         }
+        Ok(self)
     }
 
-    /// Finalize the transaction with witness values
+    /// Add change outputs like [`Self::add_change`], to a scriptPubkey
+    /// obtained from `change` only if there is change
+    ///
+    /// This lets a fresh change address be generated on demand, e.g. by a
+    /// wallet, without wasting one on spends that are fully spent.
     ///
     /// # Errors
     ///
-    /// Returns an error if the program cannot be satisfied or the transaction cannot be finalized.
-    pub fn finalize(self, witness_values: WitnessValues) -> Result<Transaction, SpendError> {
-        let satisfied = self.program.satisfy(witness_values)?;
-        self.finalize_with_satisfied(&satisfied)
+    /// Returns an error if the change cannot be computed (see
+    /// [`Self::add_change`]) or `change` fails.
+    pub fn add_change_with<F>(&mut self, change: F) -> Result<&mut Self, SpendError>
+    where
+        F: FnOnce() -> Result<Script, SpendError>,
+    {
+        let amounts = self.change_amounts()?;
+        if !amounts.is_empty() {
+            let script_pubkey = change()?;
+            for (asset, amount) in amounts {
+                self.add_output_simple(script_pubkey.clone(), amount, asset);
+            }
+        }
+        Ok(self)
     }
 
-    /// Finalize the transaction with a pre-satisfied program
+    /// Add change outputs like [`Self::add_change`], to a new address of
+    /// `client`'s wallet requested only if there is change
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut builder =
+    ///     SpendBuilder::try_new(compiled, utxo)?.genesis_hash(client.genesis_hash()?);
+    /// builder
+    ///     .add_output_to_address(&destination, 60_000, asset)
+    ///     .add_fee(500, asset)
+    ///     .add_change_from_client(&client)?;
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns an error if the control block cannot be found or transaction extraction fails.
-    pub fn finalize_with_satisfied(
-        self,
-        satisfied: &SatisfiedProgram,
-    ) -> Result<Transaction, SpendError> {
-        let mut psbt = Psbt::from_tx(self.build_unsigned_tx());
+    /// Returns an error if the change cannot be computed (see
+    /// [`Self::add_change`]) or the client cannot provide an address.
+    pub fn add_change_from_client<C: NodeClient + ?Sized>(
+        &mut self,
+        client: &C,
+    ) -> Result<&mut Self, SpendError> {
+        self.add_change_with(|| Ok(client.get_new_address()?.script_pubkey()))
+    }
+
+    /// Get the positive change amount of every asset, rejecting dust
+    fn change_amounts(&self) -> Result<Vec<(elements::AssetId, u64)>, SpendError> {
+        let amounts: Vec<_> = self
+            .balances()?
+            .into_iter()
+            .filter(|&(_, amount)| amount > 0)
+            .collect();
+        if let Some((asset, amount)) = amounts.iter().find(|(_, amount)| *amount < DUST_THRESHOLD) {
+            return Err(SpendError::BuildError(format!(
+                "Change of {amount} for asset {asset} is below the dust threshold"
+            )));
+        }
+        Ok(amounts)
+    }
+
+    /// Add a change output returning the unspent balance of `asset` only
+    ///
+    /// Unlike [`Self::add_change`], other assets are left unbalanced, so each
+    /// asset's change can go to a different place. For example, a contract
+    /// holding only an issued asset can spend it alongside an external wallet
+    /// input paying the fee in the policy asset, with the asset's change
+    /// returned to the contract and the policy asset's change to the wallet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an input or output is confidential, the outputs
+    /// exceed the inputs for some asset, or the change amount would be dust.
+    pub fn add_asset_change(
+        &mut self,
+        asset: elements::AssetId,
+        script_pubkey: Script,
+    ) -> Result<&mut Self, SpendError> {
+        let amount = self.balances()?.get(&asset).copied().unwrap_or(0);
+        if amount > 0 && amount < DUST_THRESHOLD {
+            return Err(SpendError::BuildError(format!(
+                "Change of {amount} for asset {asset} is below the dust threshold"
+            )));
+        }
+        if amount > 0 {
+            self.add_output_simple(script_pubkey, amount, asset);
+        }
+        Ok(self)
+    }
+
+    /// Total the inputs and outputs of every asset, including the fee
+    ///
+    /// Issued amounts count as inputs of their asset or reissuance token.
+    /// Assets are totalled independently, so a spend moving several assets
+    /// can be checked per asset with [`Self::check_balances`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an input, issuance or output is confidential.
+    pub fn asset_totals(&self) -> Result<BTreeMap<elements::AssetId, AssetTotals>, SpendError> {
+        let mut totals: BTreeMap<_, AssetTotals> = BTreeMap::new();
+        for utxo in self.all_utxos() {
+            let confidential::Asset::Explicit(asset) = utxo.asset else {
+                return Err(SpendError::InvalidUtxo(
+                    "Cannot compute change for a confidential input".into(),
+                ));
+            };
+            totals.entry(asset).or_default().inputs += utxo.amount;
+        }
+        for issuance in self.issuances.values() {
+            for (asset, value) in [
+                (issuance.asset, issuance.issuance.amount),
+                (issuance.token, issuance.issuance.inflation_keys),
+            ] {
+                match value {
+                    confidential::Value::Null => {}
+                    confidential::Value::Explicit(amount) => {
+                        totals.entry(asset).or_default().inputs += amount;
+                    }
+                    confidential::Value::Confidential(_) => {
+                        return Err(SpendError::BuildError(
+                            "Cannot compute change for a blinded issuance".into(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for output in &self.outputs {
+            let (confidential::Asset::Explicit(asset), confidential::Value::Explicit(amount)) =
+                (output.asset, output.value)
+            else {
+                return Err(SpendError::BuildError(
+                    "Cannot compute change with confidential outputs".into(),
+                ));
+            };
+            totals.entry(asset).or_default().outputs += amount;
+        }
+        Ok(totals)
+    }
+
+    /// Check that every asset's outputs, including the fee, match its inputs
+    ///
+    /// A spend that does not balance is rejected by nodes; checking each
+    /// asset separately catches e.g. a forgotten token change output that a
+    /// check of the total amount would miss.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SpendError::AssetImbalance`] for the first asset that does
+    /// not balance, or an error if an amount is confidential.
+    pub fn check_balances(&self) -> Result<(), SpendError> {
+        match self
+            .asset_totals()?
+            .into_iter()
+            .find(|(_, totals)| totals.inputs != totals.outputs)
+        {
+            Some((asset, totals)) => Err(SpendError::AssetImbalance {
+                asset,
+                inputs: totals.inputs,
+                outputs: totals.outputs,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Compute the input amount of every asset minus the outputs so far
+    fn balances(&self) -> Result<BTreeMap<elements::AssetId, u64>, SpendError> {
+        self.asset_totals()?
+            .into_iter()
+            .map(|(asset, totals)| {
+                let balance = totals.remaining().ok_or_else(|| {
+                    SpendError::BuildError(format!("Outputs exceed inputs for asset {asset}"))
+                })?;
+                Ok((asset, balance))
+            })
+            .collect()
+    }
+
+    /// Send everything left after the outputs so far to `script_pubkey`
+    ///
+    /// Adds an output to `script_pubkey` and a fee output, with the fee
+    /// estimated at `fee_rate` sat/vB as in [`Self::estimate_fee`] and
+    /// subtracted from the swept amount. Without prior outputs, this empties
+    /// the spent UTXOs completely. All inputs must hold the same explicit
+    /// asset.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Arguments, Program, SpendBuilder, WitnessValues};
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let utxo = |vout| Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout,
+    ///     amount: 50_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let mut builder = SpendBuilder::from_utxos(compiled.clone(), vec![utxo(0), utxo(1)])
+    ///     .unwrap()
+    ///     .genesis_hash(elements::BlockHash::all_zeros());
+    /// builder.drain_to(Script::new(), 0.1, WitnessValues::default()).unwrap();
+    /// let tx = builder.finalize(WitnessValues::default()).unwrap();
+    /// assert_eq!(tx.output.len(), 2);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program inputs do not all share one program,
+    /// the inputs hold different or confidential assets, an output is
+    /// confidential, or the swept amount after the fee would be dust.
+    pub fn drain_to(
+        &mut self,
+        script_pubkey: Script,
+        fee_rate: f64,
+        witness_values: WitnessValues,
+    ) -> Result<&mut Self, SpendError> {
+        let witness_values = self.shared_witness(witness_values)?;
+        self.drain_to_inputs(script_pubkey, fee_rate, witness_values)
+    }
+
+    /// Send everything left after the outputs so far to `script_pubkey`,
+    /// estimating the fee with one set of witness values per program input
+    ///
+    /// See [`Self::drain_to`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of program inputs, or as [`Self::drain_to`] does.
+    pub fn drain_to_inputs(
+        &mut self,
+        script_pubkey: Script,
+        fee_rate: f64,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<&mut Self, SpendError> {
+        let mut assets = self.all_utxos().map(|utxo| utxo.asset);
+        let Some(confidential::Asset::Explicit(asset)) = assets.next() else {
+            return Err(SpendError::InvalidUtxo(
+                "Cannot drain a confidential input".into(),
+            ));
+        };
+        if assets.any(|other| other != confidential::Asset::Explicit(asset)) {
+            return Err(SpendError::InvalidUtxo(
+                "Cannot drain inputs of different assets".into(),
+            ));
+        }
+
+        let total: u64 = self.all_utxos().map(|utxo| utxo.amount).sum();
+        let spent = self
+            .outputs
+            .iter()
+            .map(|output| {
+                output.value.explicit().ok_or_else(|| {
+                    SpendError::BuildError("Cannot drain with confidential outputs".into())
+                })
+            })
+            .sum::<Result<u64, _>>()?;
+
+        self.add_output_simple(script_pubkey.clone(), 0, asset);
+        self.add_fee(0, asset);
+        let fee = self.estimate_fee_inputs(fee_rate, witness_values);
+        self.outputs.truncate(self.outputs.len() - 2);
+        let fee = fee?;
+
+        let amount = total
+            .checked_sub(spent + fee)
+            .filter(|&amount| amount >= DUST_THRESHOLD)
+            .ok_or(SpendError::InsufficientFunds {
+                needed: spent + fee + DUST_THRESHOLD,
+                available: total,
+            })?;
+        self.add_output_simple(script_pubkey, amount, asset);
+        self.add_fee(fee, asset);
+        Ok(self)
+    }
+
+    /// Set the transaction version (2 by default)
+    pub fn set_version(&mut self, version: u32) -> &mut Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the lock time
+    pub fn set_lock_time(&mut self, lock_time: LockTime) -> &mut Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Set the sequence number of every input
+    pub fn set_sequence(&mut self, sequence: Sequence) -> &mut Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Estimate fees with the ELIP-200 discounted size
+    ///
+    /// Enable this on networks whose nodes relay discounted confidential
+    /// transactions (see `Network::discounts_ct`); otherwise confidential
+    /// spends are overcharged for their proofs.
+    pub fn set_discount_ct(&mut self, enabled: bool) -> &mut Self {
+        self.discount_ct = enabled;
+        self
+    }
+
+    /// Signal replaceability (BIP125) on every input
+    ///
+    /// Sets the sequence number to `0xfffffffd`, which keeps the lock time
+    /// enforced.
+    pub fn signal_rbf(&mut self) -> &mut Self {
+        self.set_sequence(Sequence::ENABLE_RBF_NO_LOCKTIME)
+    }
+
+    /// Set the lock time
+    #[deprecated(note = "use `set_lock_time`, which chains with the other methods")]
+    #[must_use]
+    pub const fn lock_time(mut self, lock_time: LockTime) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Set the sequence number
+    #[deprecated(note = "use `set_sequence`, which chains with the other methods")]
+    #[must_use]
+    pub const fn sequence(mut self, sequence: Sequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Check that the lock time and sequence can satisfy the timelocks of
+    /// every program input's spend
+    ///
+    /// Each program is satisfied with its witness values, so only timelock
+    /// jets on the branches the spend executes are considered (see
+    /// [`Timelocks::check`](crate::timelock::Timelocks::check)). This
+    /// catches e.g. a timeout path spent with a zero lock time before the
+    /// node rejects it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of inputs, a program cannot be satisfied, or a timelock cannot be met.
+    pub fn check_timelocks(&self, witness_values: Vec<WitnessValues>) -> Result<(), SpendError> {
+        self.check_witness_count(&witness_values)?;
+        for ((program, _), values) in self.inputs.iter().zip(witness_values) {
+            program
+                .satisfy(values)?
+                .timelocks()
+                .check(self.lock_time, self.sequence)?;
+        }
+        Ok(())
+    }
+
+    /// Build the Simplicity environment of program input `index` of `tx`
+    fn env_with<T: Deref<Target = Transaction>>(
+        &self,
+        tx: T,
+        utxos: &[ElementsUtxo],
+        index: usize,
+    ) -> Result<ElementsEnv<T>, SpendError> {
+        let program = &self.inputs[index].0;
+        Ok(ElementsEnv::new(
+            tx,
+            utxos.to_vec(),
+            u32::try_from(index).expect("input index fits in u32"),
+            program.cmr(),
+            Self::control_block(program)?,
+            None,
+            self.genesis_hash,
+        ))
+    }
+
+    /// Get the spent outputs in the form the Simplicity environment expects
+    fn elements_utxos(&self) -> Vec<ElementsUtxo> {
+        self.all_utxos()
+            .map(|utxo| ElementsUtxo {
+                script_pubkey: utxo.script_pubkey.clone(),
+                value: utxo.value(),
+                asset: utxo.asset,
+            })
+            .collect()
+    }
+
+    /// Compute the taproot key-path sighash of input `index` using `cache`
+    fn sighash_keypath_with(
+        cache: &mut SighashCache<&Transaction>,
+        prevouts: &[TxOut],
+        index: usize,
+        genesis_hash: elements::BlockHash,
+    ) -> Result<[u8; 32], SpendError> {
+        let sighash = cache
+            .taproot_key_spend_signature_hash(
+                index,
+                &Prevouts::All(prevouts),
+                SchnorrSighashType::Default,
+                genesis_hash,
+            )
+            .map_err(|e| SpendError::SighashError(e.to_string()))?;
+
+        Ok(sighash.to_byte_array())
+    }
+
+    /// Export the unsigned spend as a PSET for external signers
+    ///
+    /// Each input carries the spent output; program inputs additionally carry
+    /// the taproot internal key and merkle root of their program, and its
+    /// leaf with the control block. With the `serde` feature, program inputs
+    /// also carry the program itself (see [`crate::pset`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a control block cannot be found.
+    pub fn to_pset(&self) -> Result<Psbt, SpendError> {
+        let mut pset = Psbt::from_tx(self.build_unsigned_tx());
+        for (input, prevout) in pset.inputs_mut().iter_mut().zip(self.prevouts()) {
+            input.witness_utxo = Some(prevout);
+        }
+        for (input, (program, _)) in pset.inputs_mut().iter_mut().zip(&self.inputs) {
+            let taproot_info = program.taproot_info();
+            input.tap_internal_key = Some(taproot_info.internal_key());
+            input.tap_merkle_root = taproot_info.merkle_root();
+            input
+                .tap_scripts
+                .insert(Self::control_block(program)?, program.script_version());
+            #[cfg(feature = "serde")]
+            crate::pset::set_program(input, program)?;
+        }
+        Ok(pset)
+    }
+
+    /// Get the control block of `program`'s leaf in its taproot tree
+    fn control_block(program: &InstantiatedProgram) -> Result<ControlBlock, SpendError> {
+        program
+            .taproot_info()
+            .control_block(&program.script_version())
+            .ok_or_else(|| SpendError::BuildError("Control block not found".into()))
+    }
+
+    /// Get the outputs being spent, in input order
+    fn prevouts(&self) -> Vec<TxOut> {
+        self.all_utxos()
+            .map(|utxo| TxOut {
+                asset: utxo.asset,
+                value: utxo.value(),
+                nonce: confidential::Nonce::Null,
+                script_pubkey: utxo.script_pubkey.clone(),
+                witness: TxOutWitness::empty(),
+            })
+            .collect()
+    }
+
+    /// Check that `index` refers to a program input
+    fn check_input(&self, index: usize) -> Result<(), SpendError> {
+        if index < self.inputs.len() {
+            Ok(())
+        } else {
+            Err(SpendError::BuildError(format!(
+                "Input {index} is not a program input ({} program inputs)",
+                self.inputs.len()
+            )))
+        }
+    }
+
+    /// Check that the transaction has outputs, which consensus requires
+    ///
+    /// Unlike the genesis hash, outputs are not part of the type state, so
+    /// this is checked at run time.
+    fn check_outputs(&self) -> Result<(), SpendError> {
+        if self.outputs.is_empty() {
+            Err(SpendError::FinalizationError(
+                "Transaction has no outputs".into(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Build the unsigned transaction
+    fn build_unsigned_tx(&self) -> Transaction {
+        let mut input: Vec<TxIn> = self
+            .all_utxos()
+            .map(|utxo| TxIn {
+                previous_output: elements::OutPoint::new(utxo.txid, utxo.vout),
+                is_pegin: false,
+                script_sig: Script::new(),
+                sequence: self.sequence,
+                asset_issuance: AssetIssuance::null(),
+                witness: TxInWitness::empty(),
+            })
+            .collect();
+
+        for (index, issuance) in &self.issuances {
+            let txin = &mut input[*index];
+            txin.asset_issuance = issuance.issuance;
+            txin.witness
+                .amount_rangeproof
+                .clone_from(&issuance.amount_rangeproof);
+            txin.witness
+                .inflation_keys_rangeproof
+                .clone_from(&issuance.inflation_keys_rangeproof);
+        }
+
+        let pegin_start = input.len() - self.pegin_inputs.len();
+        for (txin, (_, pegin_witness)) in input[pegin_start..].iter_mut().zip(&self.pegin_inputs) {
+            txin.is_pegin = true;
+            txin.witness.pegin_witness = pegin_witness.clone();
+        }
+
+        Transaction {
+            version: self.version,
+            lock_time: self.lock_time,
+            input,
+            output: self.outputs.clone(),
+        }
+    }
 
-        let (script, version) = self.program.script_version();
+    /// Build the script-path witness stack for a satisfied program
+    fn script_witness(
+        program: &InstantiatedProgram,
+        satisfied: &SatisfiedProgram,
+    ) -> Result<Vec<Vec<u8>>, SpendError> {
+        let (script, version) = program.script_version();
         let control_block = satisfied
             .taproot_info()
             .control_block(&(script.clone(), version))
@@ -167,15 +1535,1806 @@ impl SpendBuilder {
 
         let (program_bytes, witness_bytes) = satisfied.encode();
 
-        psbt.inputs_mut()[0].final_script_witness = Some(vec![
+        Ok(vec![
             witness_bytes,
             program_bytes,
             script.into_bytes(),
             control_block.serialize(),
-        ]);
+        ])
+    }
+
+    /// Estimate the weight of the finalized transaction
+    ///
+    /// The Simplicity program and witness are encoded exactly as `finalize`
+    /// would, so signatures in `witness_values` may be placeholders of the
+    /// right type (e.g. `[0; 64]`). A fee output is accounted for if none was
+    /// added yet, as are the proofs and commitments of confidential outputs
+    /// that still need blinding, whose surjection proofs grow with the number
+    /// of inputs. Witnesses of external inputs are not included.
+    ///
+    /// All program inputs must share one program, which is satisfied with
+    /// `witness_values`; see [`Self::estimate_weight_inputs`] for inputs of
+    /// different programs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program inputs do not all share one program,
+    /// or it cannot be satisfied with `witness_values`.
+    pub fn estimate_weight(&self, witness_values: WitnessValues) -> Result<usize, SpendError> {
+        self.estimate_weight_inputs(self.shared_witness(witness_values)?)
+    }
+
+    /// Estimate the weight of the finalized transaction with one set of
+    /// witness values per program input
+    ///
+    /// Estimated as in [`Self::estimate_weight`], satisfying each input's
+    /// program with its own witness values as [`Self::finalize_inputs`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of program inputs, or a program cannot be satisfied with its witness.
+    pub fn estimate_weight_inputs(
+        &self,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<usize, SpendError> {
+        let tx = self.estimated_tx(witness_values)?;
+        let blinding: usize = tx
+            .output
+            .iter()
+            .filter(|out| {
+                (out.value.is_confidential() && out.witness.rangeproof_len() == 0)
+                    || (out.value.is_explicit() && out.nonce.is_confidential())
+            })
+            .map(|out| blinding_weight(out, tx.input.len()))
+            .sum();
+        Ok(tx.weight() + blinding + self.pending_issuance_proofs() * BLINDED_ISSUANCE_WEIGHT)
+    }
+
+    /// Count the issuance amounts that [`Self::blind`] will add a rangeproof to
+    fn pending_issuance_proofs(&self) -> usize {
+        self.issuances
+            .values()
+            .map(InputIssuance::pending_proofs)
+            .sum()
+    }
+
+    /// Estimate the ELIP-200 discounted weight of the finalized transaction
+    ///
+    /// Estimated as in [`Self::estimate_weight`], except that the proofs and
+    /// commitments of confidential outputs are not counted, see
+    /// [`discount_weight`]. Blinded issuances are not discounted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program inputs do not all share one program,
+    /// or it cannot be satisfied with `witness_values`.
+    pub fn estimate_discount_weight(
+        &self,
+        witness_values: WitnessValues,
+    ) -> Result<usize, SpendError> {
+        self.estimate_discount_weight_inputs(self.shared_witness(witness_values)?)
+    }
+
+    /// Estimate the ELIP-200 discounted weight of the finalized transaction
+    /// with one set of witness values per program input
+    ///
+    /// See [`Self::estimate_discount_weight`] and
+    /// [`Self::estimate_weight_inputs`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of program inputs, or a program cannot be satisfied with its witness.
+    pub fn estimate_discount_weight_inputs(
+        &self,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<usize, SpendError> {
+        Ok(discount_weight(&self.estimated_tx(witness_values)?)
+            + self.pending_issuance_proofs() * BLINDED_ISSUANCE_WEIGHT)
+    }
+
+    /// Build the transaction as `estimate_weight` sizes it, without pending proofs
+    fn estimated_tx(&self, witness_values: Vec<WitnessValues>) -> Result<Transaction, SpendError> {
+        self.check_witness_count(&witness_values)?;
+        let mut tx = self.build_unsigned_tx();
+        if !tx.output.iter().any(TxOut::is_fee) {
+            tx.output
+                .push(TxOut::new_fee(0, elements::AssetId::default()));
+        }
+        for ((input, (program, _)), values) in
+            tx.input.iter_mut().zip(&self.inputs).zip(witness_values)
+        {
+            let satisfied = program.satisfy(values)?;
+            input.witness.script_witness = Self::script_witness(program, &satisfied)?;
+        }
+        Ok(tx)
+    }
+
+    /// Use `witness_values` for every program input, which must all share
+    /// one program
+    ///
+    /// Witness values only satisfy the program they were made for, so a
+    /// single set cannot be used for inputs of different programs.
+    fn shared_witness(
+        &self,
+        witness_values: WitnessValues,
+    ) -> Result<Vec<WitnessValues>, SpendError> {
+        let mut cmrs = self.inputs.iter().map(|(program, _)| program.cmr());
+        let first = cmrs.next();
+        if cmrs.any(|cmr| Some(cmr) != first) {
+            return Err(SpendError::BuildError(
+                "Program inputs have different programs; pass one witness per input".into(),
+            ));
+        }
+        Ok(vec![witness_values; self.inputs.len()])
+    }
+
+    /// Check that there is one set of witness values per program input
+    fn check_witness_count(&self, witness_values: &[WitnessValues]) -> Result<(), SpendError> {
+        if witness_values.len() == self.inputs.len() {
+            Ok(())
+        } else {
+            Err(SpendError::BuildError(format!(
+                "Expected {} witnesses, got {}",
+                self.inputs.len(),
+                witness_values.len()
+            )))
+        }
+    }
+
+    /// Estimate the fee for the finalized transaction at `fee_rate` sat/vB
+    ///
+    /// See [`Self::estimate_weight`] for how the transaction size is estimated.
+    /// If discount CT is enabled with [`Self::set_discount_ct`], the
+    /// discounted size from [`Self::estimate_discount_weight`] is used instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Arguments, Program, SpendBuilder, WitnessValues};
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let utxo = Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout: 0,
+    ///     amount: 100_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let mut builder = SpendBuilder::try_new(compiled, utxo).unwrap();
+    /// builder.add_output_simple(Script::new(), 99_000, elements::AssetId::default());
+    /// let fee = builder.estimate_fee(0.1, WitnessValues::default()).unwrap();
+    /// assert!(fee > 0 && fee < 1_000);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fee_rate` is negative or not finite, the program
+    /// inputs do not all share one program, or it cannot be satisfied with
+    /// `witness_values`.
+    pub fn estimate_fee(
+        &self,
+        fee_rate: f64,
+        witness_values: WitnessValues,
+    ) -> Result<u64, SpendError> {
+        self.estimate_fee_inputs(fee_rate, self.shared_witness(witness_values)?)
+    }
+
+    /// Estimate the fee for the finalized transaction at `fee_rate` sat/vB
+    /// with one set of witness values per program input
+    ///
+    /// See [`Self::estimate_fee`] and [`Self::estimate_weight_inputs`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fee_rate` is negative or not finite, the number
+    /// of witnesses does not match the number of program inputs, or a program
+    /// cannot be satisfied with its witness.
+    pub fn estimate_fee_inputs(
+        &self,
+        fee_rate: f64,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<u64, SpendError> {
+        if !fee_rate.is_finite() || fee_rate < 0.0 {
+            return Err(SpendError::BuildError(format!(
+                "Invalid fee rate: {fee_rate}"
+            )));
+        }
+        let weight = if self.discount_ct {
+            self.estimate_discount_weight_inputs(witness_values)?
+        } else {
+            self.estimate_weight_inputs(witness_values)?
+        };
+        let vsize = weight.div_ceil(4);
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        Ok((vsize as f64 * fee_rate).ceil() as u64)
+    }
+
+    /// Add a fee paying `fee_rate` sat/vB and change outputs to `change`
+    ///
+    /// The fee depends on the size of the transaction, which depends on its
+    /// change outputs, so the fee is estimated, the fee and change outputs
+    /// added and the fee re-estimated until it stops growing. Change of the
+    /// fee asset below the dust threshold is added to the fee instead.
+    /// `witness_values` is only used to estimate the size, as in
+    /// [`Self::estimate_fee`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    /// use musk::{Arguments, Program, SpendBuilder, WitnessValues};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let asset = elements::AssetId::default();
+    /// let utxo = Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout: 0,
+    ///     amount: 100_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(asset),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let mut builder = SpendBuilder::try_new(compiled, utxo).unwrap();
+    /// builder.add_output_simple(Script::new(), 60_000, asset);
+    /// builder
+    ///     .add_fee_and_change(0.1, asset, Script::from(vec![0x51]), WitnessValues::default())
+    ///     .unwrap();
+    /// assert_eq!(builder.outputs().len(), 3);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fee_rate` is invalid, the program inputs do not
+    /// all share one program, a fee output was already added, change cannot
+    /// be computed (see [`Self::add_change`]), the inputs do not cover the
+    /// outputs and fee, or the fee does not settle.
+    pub fn add_fee_and_change(
+        &mut self,
+        fee_rate: f64,
+        asset: elements::AssetId,
+        change: Script,
+        witness_values: WitnessValues,
+    ) -> Result<&mut Self, SpendError> {
+        let witness_values = self.shared_witness(witness_values)?;
+        self.add_fee_and_change_inputs(fee_rate, asset, change, witness_values)
+    }
+
+    /// Add a fee paying `fee_rate` sat/vB and change outputs to `change`,
+    /// estimating the size with one set of witness values per program input
+    ///
+    /// See [`Self::add_fee_and_change`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of program inputs, or as [`Self::add_fee_and_change`] does.
+    pub fn add_fee_and_change_inputs(
+        &mut self,
+        fee_rate: f64,
+        asset: elements::AssetId,
+        change: Script,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<&mut Self, SpendError> {
+        const MAX_ROUNDS: usize = 10;
+
+        if self.outputs.iter().any(TxOut::is_fee) {
+            return Err(SpendError::BuildError(
+                "A fee output was already added".into(),
+            ));
+        }
+
+        let available = self.balances()?.get(&asset).copied().unwrap_or(0);
+        let mut fee = self.estimate_fee_inputs(fee_rate, witness_values.clone())?;
+        for _ in 0..MAX_ROUNDS {
+            let leftover = available
+                .checked_sub(fee)
+                .ok_or(SpendError::InsufficientFunds {
+                    needed: fee,
+                    available,
+                })?;
+            let mut trial = self.clone();
+            if leftover < DUST_THRESHOLD {
+                trial.add_fee(fee + leftover, asset);
+            } else {
+                trial.add_fee(fee, asset);
+            }
+            trial.add_change(change.clone())?;
+
+            let needed = trial.estimate_fee_inputs(fee_rate, witness_values.clone())?;
+            if needed <= fee {
+                *self = trial;
+                return Ok(self);
+            }
+            fee = needed;
+        }
+        Err(SpendError::BuildError(format!(
+            "Fee did not settle after {MAX_ROUNDS} rounds"
+        )))
+    }
+
+    /// Rebuild a replaceable spend with the fee raised to `fee_rate` sat/vB
+    ///
+    /// The builder must spend the same inputs as `original`, which must
+    /// signal replaceability. The outputs and lock time are copied from
+    /// `original`, and the fee increase is taken from its last explicit
+    /// output of the fee asset, which is usually the change. The returned
+    /// builder still has to be finalized; signatures must be remade since the
+    /// sighash changes with the fee. `witness_values` is only used to
+    /// estimate the size, as in [`Self::estimate_fee`].
+    ///
+    /// Nodes only accept the replacement if its fee exceeds the original's by
+    /// at least `incremental_fee_rate` sat/vB of the replacement's size
+    /// (BIP125 rule 4), the node's `incrementalrelayfee`. The fee is raised
+    /// above `fee_rate` if needed to meet that.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program inputs do not all share one program,
+    /// `original` does not spend the builder's inputs, does not signal
+    /// replaceability or has no explicit fee output, if a fee rate is
+    /// negative, if the fee at `fee_rate` is not higher than the original,
+    /// or if the change cannot cover the increase without becoming dust.
+    pub fn bump_fee(
+        self,
+        original: &Transaction,
+        fee_rate: f64,
+        incremental_fee_rate: f64,
+        witness_values: WitnessValues,
+    ) -> Result<Self, SpendError> {
+        let witness_values = self.shared_witness(witness_values)?;
+        self.bump_fee_inputs(original, fee_rate, incremental_fee_rate, witness_values)
+    }
+
+    /// Rebuild a replaceable spend with the fee raised to `fee_rate` sat/vB,
+    /// estimating the size with one set of witness values per program input
+    ///
+    /// See [`Self::bump_fee`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of program inputs, or as [`Self::bump_fee`] does.
+    pub fn bump_fee_inputs(
+        mut self,
+        original: &Transaction,
+        fee_rate: f64,
+        incremental_fee_rate: f64,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<Self, SpendError> {
+        if !original
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .eq(self
+                .all_utxos()
+                .map(|utxo| elements::OutPoint::new(utxo.txid, utxo.vout)))
+        {
+            return Err(SpendError::BuildError(
+                "Original transaction spends different inputs".into(),
+            ));
+        }
+        if !original.input.iter().any(|input| input.sequence.is_rbf()) {
+            return Err(SpendError::BuildError(
+                "Original transaction does not signal replaceability".into(),
+            ));
+        }
+
+        self.outputs.clone_from(&original.output);
+        self.lock_time = original.lock_time;
+        if !self.sequence.is_rbf() {
+            self.sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+        }
+
+        let fee_index = self
+            .outputs
+            .iter()
+            .position(TxOut::is_fee)
+            .ok_or_else(|| SpendError::BuildError("Original transaction has no fee".into()))?;
+        let (confidential::Asset::Explicit(fee_asset), confidential::Value::Explicit(old_fee)) =
+            (self.outputs[fee_index].asset, self.outputs[fee_index].value)
+        else {
+            return Err(SpendError::BuildError("Fee output is not explicit".into()));
+        };
+
+        let new_fee = self.estimate_fee_inputs(fee_rate, witness_values.clone())?;
+        if new_fee <= old_fee {
+            return Err(SpendError::BuildError(format!(
+                "New fee {new_fee} does not exceed the original fee {old_fee}"
+            )));
+        }
+        // BIP125 rule 4: the increase must pay for relaying the replacement
+        let min_increase = self.estimate_fee_inputs(incremental_fee_rate, witness_values)?;
+        let new_fee = new_fee.max(old_fee + min_increase);
+
+        let change = self
+            .outputs
+            .iter_mut()
+            .rev()
+            .find(|output| {
+                !output.is_fee() && output.asset == confidential::Asset::Explicit(fee_asset)
+            })
+            .ok_or_else(|| {
+                SpendError::BuildError("No change output to take the fee from".into())
+            })?;
+        let confidential::Value::Explicit(amount) = change.value else {
+            return Err(SpendError::BuildError(
+                "Change output is confidential".into(),
+            ));
+        };
+        let remaining = amount
+            .checked_sub(new_fee - old_fee)
+            .filter(|&remaining| remaining >= DUST_THRESHOLD)
+            .ok_or(SpendError::InsufficientFunds {
+                needed: new_fee - old_fee + DUST_THRESHOLD,
+                available: amount,
+            })?;
+        change.value = confidential::Value::Explicit(remaining);
+        self.outputs[fee_index].value = confidential::Value::Explicit(new_fee);
+        Ok(self)
+    }
+}
+
+impl SpendBuilder<Ready> {
+    /// Compute the `sighash_all` for this transaction
+    ///
+    /// This is used to generate witness values that include signatures
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the control block cannot be found.
+    pub fn sighash_all(&self) -> Result<[u8; 32], SpendError> {
+        self.sighash_all_input(0)
+    }
+
+    /// Compute the `sighash_all` for the input at `index`
+    ///
+    /// Each input commits to its own index, so multi-input spends need a
+    /// separate signature per input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of range or the control block cannot be found.
+    pub fn sighash_all_input(&self, index: usize) -> Result<[u8; 32], SpendError> {
+        self.check_input(index)?;
+        self.sighash_all_from(&self.tx_hash(), index)
+    }
+
+    /// Compute the `sighash_all` of every program input, in input order
+    ///
+    /// The sighashes differ only in the input index and the input's program
+    /// and control block, so the hash of the rest of the transaction is
+    /// computed once and each sighash takes constant time on top of it.
+    /// Calling [`Self::sighash_all_input`] per input instead hashes the
+    /// whole transaction for every input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a control block cannot be found.
+    pub fn sighash_all_inputs(&self) -> Result<Vec<[u8; 32]>, SpendError> {
+        let tx_hash = self.tx_hash();
+        (0..self.inputs.len())
+            .map(|index| self.sighash_all_from(&tx_hash, index))
+            .collect()
+    }
+
+    /// Compute the `sighash_all` of program input `index` from the hash of
+    /// the transaction, as `jet::sig_all_hash` does
+    ///
+    /// It commits to the genesis hash, which is hashed twice, the
+    /// transaction, the input's taproot environment and the input index.
+    fn sighash_all_from(&self, tx_hash: &[u8; 32], index: usize) -> Result<[u8; 32], SpendError> {
+        let tap_env_hash = Self::tap_env_hash(&self.inputs[index].0)?;
+        let mut engine = sha256::Hash::engine();
+        engine.input(self.genesis_hash.as_byte_array());
+        engine.input(self.genesis_hash.as_byte_array());
+        engine.input(tx_hash);
+        engine.input(&tap_env_hash);
+        engine.input(
+            &u32::try_from(index)
+                .expect("input index fits in u32")
+                .to_be_bytes(),
+        );
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Compute the hash of the unsigned transaction, as `jet::tx_hash` does
+    ///
+    /// It commits to everything `sighash_all` covers except the genesis
+    /// hash, the input index and the input's taproot environment.
+    fn tx_hash(&self) -> [u8; 32] {
+        let tx = self.build_unsigned_tx();
+        let mut engine = sha256::Hash::engine();
+        engine.input(&tx.version.to_be_bytes());
+        engine.input(&tx.lock_time.to_consensus_u32().to_be_bytes());
+        engine.input(&Self::inputs_hash_of(&tx));
+        engine.input(&Self::outputs_hash_of(&tx.output));
+        engine.input(&Self::issuances_hash_of(&tx));
+        engine.input(&Self::output_surjection_proofs_hash_of(&tx.output));
+        engine.input(&Self::input_utxos_hash_of(&self.prevouts()));
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Compute the hash of `program`'s taproot leaf, merkle path and
+    /// internal key, as `jet::tap_env_hash` does
+    fn tap_env_hash(program: &InstantiatedProgram) -> Result<[u8; 32], SpendError> {
+        let control_block = Self::control_block(program)?.serialize();
+        let (script, version) = program.script_version();
+        let leaf_hash = elements::taproot::TapLeafHash::from_script(&script, version);
+        // The control block is the leaf version, the internal key and then
+        // the merkle path
+        let path_hash = sha256::Hash::hash(&control_block[33..]);
+        let mut engine = sha256::Hash::engine();
+        engine.input(leaf_hash.as_byte_array());
+        engine.input(path_hash.as_byte_array());
+        engine.input(&control_block[1..33]);
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Compute the hash of the output at `index`, as `jet::output_hash` does
+    ///
+    /// It commits to the output's asset, amount, nonce, scriptPubkey and
+    /// rangeproof. Contracts that sign only some outputs (e.g. their own
+    /// output at `jet::current_index`) hash these instead of using
+    /// `jet::sig_all_hash`; these methods give the signer the same values.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no output at `index`.
+    pub fn output_hash(&self, index: usize) -> Result<[u8; 32], SpendError> {
+        let output = self.outputs.get(index).ok_or_else(|| {
+            SpendError::BuildError(format!(
+                "Output {index} out of range ({} outputs)",
+                self.outputs.len()
+            ))
+        })?;
+        Ok(Self::output_hash_of(output))
+    }
+
+    /// Compute the hash of all outputs, as `jet::outputs_hash` does
+    ///
+    /// Unlike the hashes of the individual outputs, it does not commit to
+    /// each output's hash but to the lists of their asset and amounts,
+    /// nonces, scriptPubkeys and rangeproofs.
+    #[must_use]
+    pub fn outputs_hash(&self) -> [u8; 32] {
+        Self::outputs_hash_of(&self.outputs)
+    }
+
+    /// Compute the hash of the input at `index`, as `jet::input_hash` does
+    ///
+    /// It commits to the input's outpoint (and peg-in parent chain), sequence
+    /// and annex, but not to the output it spends; see
+    /// [`Self::input_utxo_hash`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no input at `index`.
+    pub fn input_hash(&self, index: usize) -> Result<[u8; 32], SpendError> {
+        let tx = self.build_unsigned_tx();
+        let input = Self::tx_input(&tx, index)?;
+        let mut engine = sha256::Hash::engine();
+        Self::add_outpoint(&mut engine, input);
+        engine.input(&input.sequence.to_consensus_u32().to_be_bytes());
+        // The unsigned transaction has no annexes
+        engine.input(&[0]);
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Compute the hash of all inputs, as `jet::inputs_hash` does
+    #[must_use]
+    pub fn inputs_hash(&self) -> [u8; 32] {
+        Self::inputs_hash_of(&self.build_unsigned_tx())
+    }
+
+    /// Compute the hash of the output spent by the input at `index`, as
+    /// `jet::input_utxo_hash` does
+    ///
+    /// It commits to the spent output's asset, amount and scriptPubkey.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no input at `index`.
+    pub fn input_utxo_hash(&self, index: usize) -> Result<[u8; 32], SpendError> {
+        let prevouts = self.prevouts();
+        let prevout = prevouts.get(index).ok_or_else(|| {
+            SpendError::BuildError(format!(
+                "Input {index} out of range ({} inputs)",
+                prevouts.len()
+            ))
+        })?;
+        let mut engine = sha256::Hash::engine();
+        engine.input(&serialize(&prevout.asset));
+        engine.input(&serialize(&prevout.value));
+        engine.input(&Self::script_hash(&prevout.script_pubkey));
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Compute the hash of the outputs spent by all inputs, as
+    /// `jet::input_utxos_hash` does
+    #[must_use]
+    pub fn input_utxos_hash(&self) -> [u8; 32] {
+        Self::input_utxos_hash_of(&self.prevouts())
+    }
+
+    fn outputs_hash_of(outputs: &[TxOut]) -> [u8; 32] {
+        let mut amounts = sha256::Hash::engine();
+        let mut nonces = sha256::Hash::engine();
+        let mut scripts = sha256::Hash::engine();
+        let mut range_proofs = sha256::Hash::engine();
+        for output in outputs {
+            amounts.input(&serialize(&output.asset));
+            amounts.input(&serialize(&output.value));
+            nonces.input(&serialize(&output.nonce));
+            scripts.input(&Self::script_hash(&output.script_pubkey));
+            range_proofs.input(&Self::range_proof_hash(output));
+        }
+        Self::hash_of_hashes([amounts, nonces, scripts, range_proofs])
+    }
+
+    fn inputs_hash_of(tx: &Transaction) -> [u8; 32] {
+        let mut outpoints = sha256::Hash::engine();
+        let mut sequences = sha256::Hash::engine();
+        let mut annexes = sha256::Hash::engine();
+        for input in &tx.input {
+            Self::add_outpoint(&mut outpoints, input);
+            sequences.input(&input.sequence.to_consensus_u32().to_be_bytes());
+            annexes.input(&[0]);
+        }
+        Self::hash_of_hashes([outpoints, sequences, annexes])
+    }
+
+    fn input_utxos_hash_of(prevouts: &[TxOut]) -> [u8; 32] {
+        let mut amounts = sha256::Hash::engine();
+        let mut scripts = sha256::Hash::engine();
+        for prevout in prevouts {
+            amounts.input(&serialize(&prevout.asset));
+            amounts.input(&serialize(&prevout.value));
+            scripts.input(&Self::script_hash(&prevout.script_pubkey));
+        }
+        Self::hash_of_hashes([amounts, scripts])
+    }
+
+    /// Hash the issuances of all inputs, as `jet::issuances_hash` does
+    ///
+    /// Inputs without an issuance contribute zero bytes. A new issuance
+    /// commits to its contract hash and a reissuance to its blinding nonce
+    /// and entropy; the token ID depends on whether the issued amount is
+    /// blinded, and a reissuance's token amount is hashed as zero.
+    fn issuances_hash_of(tx: &Transaction) -> [u8; 32] {
+        let mut asset_amounts = sha256::Hash::engine();
+        let mut token_amounts = sha256::Hash::engine();
+        let mut range_proofs = sha256::Hash::engine();
+        let mut blinding_entropies = sha256::Hash::engine();
+        for input in &tx.input {
+            if !input.has_issuance() {
+                asset_amounts.input(&[0, 0]);
+                token_amounts.input(&[0, 0]);
+                range_proofs.input(&Self::issuance_proof_hash(None));
+                range_proofs.input(&Self::issuance_proof_hash(None));
+                blinding_entropies.input(&[0]);
+                continue;
+            }
+
+            let issuance = &input.asset_issuance;
+            let new_issuance = issuance.asset_blinding_nonce == ZERO_TWEAK;
+            let entropy = if new_issuance {
+                elements::AssetId::generic_issuance_entropy(
+                    input.previous_output,
+                    ContractHash::from_byte_array(issuance.asset_entropy),
+                )
+            } else {
+                sha256::Midstate::from_byte_array(issuance.asset_entropy)
+            };
+            let asset = elements::AssetId::from_entropy(entropy);
+            let token = elements::AssetId::reissuance_token_from_entropy(
+                entropy,
+                issuance.amount.is_confidential(),
+            );
+            let token_amount = if new_issuance {
+                issuance.inflation_keys
+            } else {
+                confidential::Value::Explicit(0)
+            };
+
+            asset_amounts.input(&serialize(&confidential::Asset::Explicit(asset)));
+            asset_amounts.input(&Self::issuance_amount(issuance.amount));
+            token_amounts.input(&serialize(&confidential::Asset::Explicit(token)));
+            token_amounts.input(&Self::issuance_amount(token_amount));
+            range_proofs.input(&Self::issuance_proof_hash(
+                input
+                    .witness
+                    .amount_rangeproof
+                    .as_deref()
+                    .filter(|_| issuance.amount.is_confidential()),
+            ));
+            range_proofs.input(&Self::issuance_proof_hash(
+                input
+                    .witness
+                    .inflation_keys_rangeproof
+                    .as_deref()
+                    .filter(|_| new_issuance && token_amount.is_confidential()),
+            ));
+            blinding_entropies.input(&[1]);
+            if new_issuance {
+                blinding_entropies.input(&[0; 32]);
+            } else {
+                blinding_entropies.input(issuance.asset_blinding_nonce.as_ref());
+            }
+            blinding_entropies.input(&issuance.asset_entropy);
+        }
+        Self::hash_of_hashes([
+            asset_amounts,
+            token_amounts,
+            range_proofs,
+            blinding_entropies,
+        ])
+    }
+
+    /// Encode an issuance amount the way the jets hash it, with a null
+    /// amount as an explicit zero
+    fn issuance_amount(value: confidential::Value) -> Vec<u8> {
+        if value.is_null() {
+            serialize(&confidential::Value::Explicit(0))
+        } else {
+            serialize(&value)
+        }
+    }
+
+    fn issuance_proof_hash(proof: Option<&RangeProof>) -> [u8; 32] {
+        let proof = proof.map(RangeProof::serialize).unwrap_or_default();
+        sha256::Hash::hash(&proof).to_byte_array()
+    }
+
+    /// Hash the surjection proofs of all outputs, as
+    /// `jet::output_surjection_proofs_hash` does
+    fn output_surjection_proofs_hash_of(outputs: &[TxOut]) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        for output in outputs {
+            let proof = output
+                .witness
+                .surjection_proof
+                .as_ref()
+                .map(|proof| proof.serialize())
+                .unwrap_or_default();
+            engine.input(sha256::Hash::hash(&proof).as_byte_array());
+        }
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Hash an output the way `jet::output_hash` does
+    ///
+    /// Confidential assets, amounts and nonces are hashed in their consensus
+    /// encoding, which is the prefix byte and 32 bytes of data the jets use.
+    fn output_hash_of(output: &TxOut) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&serialize(&output.asset));
+        engine.input(&serialize(&output.value));
+        engine.input(&serialize(&output.nonce));
+        engine.input(&Self::script_hash(&output.script_pubkey));
+        engine.input(&Self::range_proof_hash(output));
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Add an outpoint to a hash: the peg-in flag and parent genesis hash,
+    /// then the txid and vout
+    fn add_outpoint(engine: &mut sha256::HashEngine, input: &TxIn) {
+        match input.pegin_data() {
+            Some(pegin) => {
+                engine.input(&[1]);
+                engine.input(pegin.genesis_hash.as_byte_array());
+            }
+            None => engine.input(&[0]),
+        }
+        engine.input(input.previous_output.txid.as_byte_array());
+        engine.input(&input.previous_output.vout.to_be_bytes());
+    }
+
+    fn tx_input(tx: &Transaction, index: usize) -> Result<&TxIn, SpendError> {
+        tx.input.get(index).ok_or_else(|| {
+            SpendError::BuildError(format!(
+                "Input {index} out of range ({} inputs)",
+                tx.input.len()
+            ))
+        })
+    }
+
+    fn script_hash(script: &Script) -> [u8; 32] {
+        sha256::Hash::hash(script.as_bytes()).to_byte_array()
+    }
+
+    fn range_proof_hash(output: &TxOut) -> [u8; 32] {
+        let proof = output
+            .witness
+            .rangeproof
+            .as_ref()
+            .map(|proof| proof.serialize())
+            .unwrap_or_default();
+        sha256::Hash::hash(&proof).to_byte_array()
+    }
+
+    /// Hash the concatenation of the hashes computed by `engines`
+    fn hash_of_hashes<const N: usize>(engines: [sha256::HashEngine; N]) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        for part in engines {
+            engine.input(sha256::Hash::from_engine(part).as_byte_array());
+        }
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Build the Simplicity environment of program input `index`
+    ///
+    /// This is the environment the program runs in when the unsigned
+    /// transaction is spent: its inputs, outputs and spent UTXOs, and the
+    /// input's program CMR, control block and the genesis hash. It can be used
+    /// to compute introspection values such as custom sighashes, or to run the
+    /// program locally.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Txid};
+    /// use musk::{Arguments, Program, SpendBuilder};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let utxo = Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout: 0,
+    ///     amount: 100_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let builder = SpendBuilder::try_new(compiled, utxo)
+    ///     .unwrap()
+    ///     .genesis_hash(elements::BlockHash::all_zeros());
+    /// let env = builder.elements_env(0).unwrap();
+    /// let sighash = env.c_tx_env().sighash_all();
+    /// assert_eq!(sighash.as_byte_array(), &builder.sighash_all().unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is not a program input or its control
+    /// block cannot be found.
+    pub fn elements_env(&self, index: usize) -> Result<ElementsEnv<Arc<Transaction>>, SpendError> {
+        self.check_input(index)?;
+        self.env_with(
+            Arc::new(self.build_unsigned_tx()),
+            &self.elements_utxos(),
+            index,
+        )
+    }
+
+    /// Compute the taproot key-path sighash for this transaction
+    ///
+    /// Only meaningful for programs instantiated with a spendable internal
+    /// key (see `Program::instantiate_with_internal_key`). The signature must
+    /// be made with the internal key tweaked by the taproot merkle root.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sighash cannot be computed.
+    pub fn sighash_keypath(&self) -> Result<[u8; 32], SpendError> {
+        self.sighash_keypath_input(0)
+    }
+
+    /// Compute the taproot key-path sighash for the input at `index`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `index` is out of range or the sighash cannot be computed.
+    pub fn sighash_keypath_input(&self, index: usize) -> Result<[u8; 32], SpendError> {
+        self.check_input(index)?;
+        let tx = self.build_unsigned_tx();
+        let prevouts = self.prevouts();
+        Self::sighash_keypath_with(
+            &mut SighashCache::new(&tx),
+            &prevouts,
+            index,
+            self.genesis_hash,
+        )
+    }
+
+    /// Compute the taproot key-path sighash of every program input, in input order
+    ///
+    /// One sighash cache is shared by all inputs, so the transaction-wide
+    /// hashes are computed only once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a sighash cannot be computed.
+    pub fn sighash_keypath_inputs(&self) -> Result<Vec<[u8; 32]>, SpendError> {
+        let tx = self.build_unsigned_tx();
+        let prevouts = self.prevouts();
+        let mut cache = SighashCache::new(&tx);
+        (0..self.inputs.len())
+            .map(|index| {
+                Self::sighash_keypath_with(&mut cache, &prevouts, index, self.genesis_hash)
+            })
+            .collect()
+    }
+
+    /// Finalize the transaction with witness values
+    ///
+    /// The same witness is used for every input, which is only valid if it
+    /// does not depend on the input (e.g. contains no signatures) and
+    /// satisfies every input's program; otherwise use [`Self::finalize_inputs`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a program cannot be satisfied or the transaction cannot be finalized.
+    pub fn finalize(self, witness_values: WitnessValues) -> Result<Transaction, SpendError> {
+        let witness_values = vec![witness_values; self.inputs.len()];
+        self.finalize_inputs(witness_values)
+    }
+
+    /// Finalize the transaction with one set of witness values per input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of inputs, the transaction has no outputs, or any input cannot be
+    /// satisfied.
+    pub fn finalize_inputs(
+        self,
+        witness_values: Vec<WitnessValues>,
+    ) -> Result<Transaction, SpendError> {
+        if witness_values.len() != self.inputs.len() {
+            return Err(SpendError::FinalizationError(format!(
+                "Expected {} witnesses, got {}",
+                self.inputs.len(),
+                witness_values.len()
+            )));
+        }
+        self.check_outputs()?;
+
+        let mut tx = self.build_unsigned_tx();
+        for ((input, (program, _)), values) in
+            tx.input.iter_mut().zip(&self.inputs).zip(witness_values)
+        {
+            let satisfied = program.satisfy(values)?;
+            input.witness.script_witness = Self::script_witness(program, &satisfied)?;
+        }
+        Ok(tx)
+    }
+
+    /// Finalize the transaction, building each input's witness with `witness`
+    ///
+    /// `witness` is called with the index and `sighash_all` of every program
+    /// input in input order, and returns the witness values satisfying that
+    /// input's program. This is where a local key or a remote signer signs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    /// use musk::{Arguments, Program, SpendBuilder, WitnessValues};
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program.instantiate(Arguments::default()).unwrap();
+    /// let utxo = Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout: 0,
+    ///     amount: 100_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let mut builder = SpendBuilder::try_new(compiled, utxo)
+    ///     .unwrap()
+    ///     .genesis_hash(elements::BlockHash::all_zeros());
+    /// builder.add_output_simple(Script::new(), 99_000, elements::AssetId::default());
+    /// let tx = builder
+    ///     .finalize_with(|_index, _sighash| Ok(WitnessValues::default()))
+    ///     .unwrap();
+    /// assert_eq!(tx.input.len(), 1);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a sighash cannot be computed, `witness` fails, or
+    /// any input cannot be satisfied.
+    pub fn finalize_with<F>(self, mut witness: F) -> Result<Transaction, SpendError>
+    where
+        F: FnMut(usize, [u8; 32]) -> Result<WitnessValues, SpendError>,
+    {
+        let witness_values = self
+            .sighash_all_inputs()?
+            .into_iter()
+            .enumerate()
+            .map(|(index, sighash)| witness(index, sighash))
+            .collect::<Result<_, _>>()?;
+        self.finalize_inputs(witness_values)
+    }
+
+    /// Finalize the transaction with signatures from an asynchronous signer
+    ///
+    /// `signer` is asked to sign the `sighash_all` of every program input in
+    /// input order, and `witness` folds each input's index and signature into
+    /// the witness values satisfying that input's program.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a sighash cannot be computed, `signer` or `witness`
+    /// fails, or any input cannot be satisfied.
+    pub async fn finalize_with_signer<S, F>(
+        self,
+        signer: &S,
+        mut witness: F,
+    ) -> Result<Transaction, SpendError>
+    where
+        S: AsyncSigner,
+        F: FnMut(usize, [u8; 64]) -> Result<WitnessValues, SpendError>,
+    {
+        let sighashes = self.sighash_all_inputs()?;
+        let mut witness_values = Vec::with_capacity(sighashes.len());
+        for (index, sighash) in sighashes.into_iter().enumerate() {
+            let signature = signer.sign_sighash(index, sighash).await?;
+            witness_values.push(witness(index, signature)?);
+        }
+        self.finalize_inputs(witness_values)
+    }
+
+    /// Finalize the program inputs into a PSET instead of a transaction
+    ///
+    /// The PSET is the one [`Self::to_pset`] exports, with the final script
+    /// witness of every program input filled in, for pipelines that only
+    /// accept PSETs. External and peg-in inputs are left for their signers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of witnesses does not match the number
+    /// of inputs, or any input cannot be satisfied.
+    pub fn finalize_to_pset(self, witness_values: Vec<WitnessValues>) -> Result<Psbt, SpendError> {
+        let mut pset = self.to_pset()?;
+        let program_inputs = self.inputs.len();
+        let tx = self.finalize_inputs(witness_values)?;
+        for (input, txin) in pset
+            .inputs_mut()
+            .iter_mut()
+            .zip(tx.input)
+            .take(program_inputs)
+        {
+            input.final_script_witness = Some(txin.witness.script_witness);
+        }
+        Ok(pset)
+    }
+
+    /// Finalize the transaction with a pre-satisfied program
+    ///
+    /// The satisfied program is used for every input, so every program input
+    /// must be locked by the program it was satisfied from.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transaction has no outputs or a control block
+    /// cannot be found.
+    pub fn finalize_with_satisfied(
+        self,
+        satisfied: &SatisfiedProgram,
+    ) -> Result<Transaction, SpendError> {
+        self.check_outputs()?;
+        let mut tx = self.build_unsigned_tx();
+        for (input, (program, _)) in tx.input.iter_mut().zip(&self.inputs) {
+            input.witness.script_witness = Self::script_witness(program, satisfied)?;
+        }
+        Ok(tx)
+    }
+
+    /// Check that `tx` spends this builder's inputs and passes script validation
+    ///
+    /// Every program input is checked as a node would: a key-path signature
+    /// must verify against the taproot output key, and a script-path spend
+    /// must reveal the program's leaf with a control block committing to the
+    /// spent output, and its Simplicity program must have the leaf's CMR and
+    /// run successfully against the transaction. External and peg-in inputs
+    /// are not checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first input that fails.
+    pub fn verify(&self, tx: &Transaction) -> Result<(), SpendError> {
+        let fail = |index: usize, message: String| {
+            SpendError::VerificationError(format!("Input {index}: {message}"))
+        };
+
+        let utxos: Vec<&Utxo> = self.all_utxos().collect();
+        if tx.input.len() != utxos.len() {
+            return Err(SpendError::VerificationError(format!(
+                "Expected {} inputs, got {}",
+                utxos.len(),
+                tx.input.len()
+            )));
+        }
+        for (index, (txin, utxo)) in tx.input.iter().zip(&utxos).enumerate() {
+            if txin.previous_output != elements::OutPoint::new(utxo.txid, utxo.vout) {
+                return Err(fail(index, "does not spend the expected UTXO".into()));
+            }
+        }
+
+        let secp = Secp256k1::verification_only();
+        let prevouts = self.prevouts();
+        let elements_utxos = self.elements_utxos();
+        let shared_tx = Arc::new(tx.clone());
+        let mut cache = SighashCache::new(tx);
+        for (index, (program, utxo)) in self.inputs.iter().enumerate() {
+            if !program.owns_script(&utxo.script_pubkey) {
+                return Err(fail(index, "UTXO is not locked by the program".into()));
+            }
+            let output_key = program.taproot_info().output_key().into_inner();
+
+            let witness = &tx.input[index].witness.script_witness;
+            if let [signature] = witness.as_slice() {
+                let sighash =
+                    Self::sighash_keypath_with(&mut cache, &prevouts, index, self.genesis_hash)?;
+                let sig = schnorr::Signature::from_slice(signature)
+                    .map_err(|e| fail(index, format!("invalid signature: {e}")))?;
+                secp.verify_schnorr(&sig, &Message::from_digest(sighash), &output_key)
+                    .map_err(|_| fail(index, "key-path signature does not verify".into()))?;
+                continue;
+            }
+
+            let [witness_bytes, program_bytes, script, control_block] = witness.as_slice() else {
+                return Err(fail(
+                    index,
+                    format!("expected a 4-element script witness, got {}", witness.len()),
+                ));
+            };
+            let (expected_script, _) = program.script_version();
+            if script.as_slice() != expected_script.as_bytes() {
+                return Err(fail(index, "leaf script is not the program's".into()));
+            }
+            let control_block = ControlBlock::from_slice(control_block)
+                .map_err(|e| fail(index, format!("invalid control block: {e}")))?;
+            let tweaked_key = program.taproot_info().output_key();
+            if !control_block.verify_taproot_commitment(&secp, &tweaked_key, &expected_script) {
+                return Err(fail(
+                    index,
+                    "control block does not commit to the output key".into(),
+                ));
+            }
+
+            let redeem = RedeemNode::<Elements>::decode(
+                BitIter::new(program_bytes.iter().copied()),
+                BitIter::new(witness_bytes.iter().copied()),
+            )
+            .map_err(|e| fail(index, format!("invalid Simplicity program: {e}")))?;
+            if redeem.cmr() != program.cmr() {
+                return Err(fail(
+                    index,
+                    "Simplicity program does not match the leaf".into(),
+                ));
+            }
+
+            let env = self.env_with(Arc::clone(&shared_tx), &elements_utxos, index)?;
+            BitMachine::for_program(&redeem)
+                .map_err(|e| fail(index, format!("program exceeds limits: {e}")))?
+                .exec(&redeem, &env)
+                .map_err(|e| fail(index, format!("program execution failed: {e}")))?;
+        }
+        Ok(())
+    }
+
+    /// Finalize the transaction as a key-path spend
+    ///
+    /// The signature is checked against the taproot output key before it is
+    /// placed in the witness, so a signature made with the untweaked internal
+    /// key or over the wrong sighash is rejected here rather than by the node.
+    ///
+    /// The witness is the signature alone, so a cooperative spend never
+    /// reveals the Simplicity program. This requires a program instantiated
+    /// with a spendable internal key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::client::Utxo;
+    /// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+    /// use musk::util::sign_keypath;
+    /// use musk::{Arguments, Program, SpendBuilder};
+    /// use secp256k1::{Keypair, Secp256k1};
+    ///
+    /// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let compiled = program
+    ///     .instantiate_with_internal_key(Arguments::default(), keypair.x_only_public_key().0)
+    ///     .unwrap();
+    /// let utxo = Utxo {
+    ///     txid: Txid::all_zeros(),
+    ///     vout: 0,
+    ///     amount: 100_000,
+    ///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+    ///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+    ///     value_commitment: None,
+    ///     confirmations: 0,
+    ///     block_height: None,
+    /// };
+    ///
+    /// let mut builder = SpendBuilder::try_new(compiled.clone(), utxo).unwrap()
+    ///     .genesis_hash(elements::BlockHash::all_zeros());
+    /// builder.add_output_simple(Script::new(), 99_000, elements::AssetId::default());
+    /// builder.add_fee(1_000, elements::AssetId::default());
+    ///
+    /// let sighash = builder.sighash_keypath().unwrap();
+    /// let merkle_root = compiled.taproot_info().merkle_root();
+    /// let tx = builder
+    ///     .finalize_keypath(sign_keypath(&keypair, merkle_root, sighash))
+    ///     .unwrap();
+    /// assert_eq!(tx.input[0].witness.script_witness.len(), 1);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signature is malformed or does not verify.
+    pub fn finalize_keypath(self, signature: [u8; 64]) -> Result<Transaction, SpendError> {
+        self.finalize_keypath_inputs(vec![signature])
+    }
+
+    /// Finalize the transaction as a key-path spend with one signature per input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of signatures does not match the number
+    /// of inputs, the transaction has no outputs, or any signature is
+    /// malformed or does not verify.
+    pub fn finalize_keypath_inputs(
+        self,
+        signatures: Vec<[u8; 64]>,
+    ) -> Result<Transaction, SpendError> {
+        if signatures.len() != self.inputs.len() {
+            return Err(SpendError::FinalizationError(format!(
+                "Expected {} signatures, got {}",
+                self.inputs.len(),
+                signatures.len()
+            )));
+        }
+        self.check_outputs()?;
+
+        let secp = Secp256k1::verification_only();
+        let sighashes = self.sighash_keypath_inputs()?;
+        let mut tx = self.build_unsigned_tx();
+        for (index, (signature, sighash)) in signatures.into_iter().zip(sighashes).enumerate() {
+            let output_key = self.inputs[index]
+                .0
+                .taproot_info()
+                .output_key()
+                .into_inner();
+            let sig = schnorr::Signature::from_slice(&signature)
+                .map_err(|e| SpendError::FinalizationError(format!("Invalid signature: {e}")))?;
+
+            secp.verify_schnorr(&sig, &Message::from_digest(sighash), &output_key)
+                .map_err(|_| {
+                    SpendError::FinalizationError(
+                        "Signature does not verify against the taproot output key".into(),
+                    )
+                })?;
+
+            tx.input[index].witness.script_witness = vec![signature.to_vec()];
+        }
+        Ok(tx)
+    }
+
+    /// Sign and finalize the transaction as a key-path spend
+    ///
+    /// `keypair` is the untweaked internal keypair; each input's taproot
+    /// tweak is applied before signing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `keypair` is not the internal key of every input's
+    /// program or the transaction cannot be finalized.
+    pub fn finalize_keypath_with_keypair(
+        self,
+        keypair: &Keypair,
+    ) -> Result<Transaction, SpendError> {
+        let sighashes = self.sighash_keypath_inputs()?;
+        let signatures = self
+            .inputs
+            .iter()
+            .zip(sighashes)
+            .enumerate()
+            .map(|(index, ((program, _), sighash))| {
+                let taproot_info = program.taproot_info();
+                if keypair.x_only_public_key().0 != taproot_info.internal_key() {
+                    return Err(SpendError::FinalizationError(format!(
+                        "Keypair does not match the taproot internal key of input {index}"
+                    )));
+                }
+                Ok(crate::util::sign_keypath(
+                    keypair,
+                    taproot_info.merkle_root(),
+                    sighash,
+                ))
+            })
+            .collect::<Result<_, SpendError>>()?;
+        self.finalize_keypath_inputs(signatures)
+    }
+
+    /// Export the builder's inputs, outputs and settings
+    ///
+    /// Programs are referenced by CMR, so [`SpendState::resume`] needs the
+    /// same programs to rebuild the builder.
+    #[must_use]
+    pub fn export_state(&self) -> SpendState {
+        SpendState {
+            inputs: self
+                .inputs
+                .iter()
+                .map(|(program, utxo)| ProgramInputState {
+                    cmr: program.cmr().to_string(),
+                    utxo: UtxoState::from(utxo),
+                })
+                .collect(),
+            external_utxos: self.external_utxos.iter().map(UtxoState::from).collect(),
+            pegin_inputs: self
+                .pegin_inputs
+                .iter()
+                .map(|(utxo, witness)| PeginInputState {
+                    utxo: UtxoState::from(utxo),
+                    pegin_witness: witness.iter().map(|item| item.to_hex()).collect(),
+                })
+                .collect(),
+            outputs: self
+                .outputs
+                .iter()
+                .map(elements::encode::serialize_hex)
+                .collect(),
+            version: self.version,
+            lock_time: self.lock_time.to_consensus_u32(),
+            sequence: self.sequence.to_consensus_u32(),
+            genesis_hash: self.genesis_hash.to_string(),
+            discount_ct: self.discount_ct,
+            issuances: self
+                .issuances
+                .iter()
+                .map(|(&input, issuance)| IssuanceState {
+                    input,
+                    issuance: elements::encode::serialize_hex(&issuance.issuance),
+                    blinded: issuance.blinded,
+                    amount_rangeproof: issuance
+                        .amount_rangeproof
+                        .as_ref()
+                        .map(|proof| proof.serialize().to_hex()),
+                    inflation_keys_rangeproof: issuance
+                        .inflation_keys_rangeproof
+                        .as_ref()
+                        .map(|proof| proof.serialize().to_hex()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Portable state of a [`SpendBuilder`]
+///
+/// A spend prepared on one machine can be exported, reviewed and resumed on
+/// another, e.g. by an approver who adds witnesses and finalizes it. Binary
+/// fields are hex encoded, and programs are referenced by their CMR rather
+/// than stored.
+///
+/// # Examples
+///
+/// ```
+/// use musk::client::Utxo;
+/// use musk::elements::{self, confidential, hashes::Hash, Script, Txid};
+/// use musk::spend::SpendState;
+/// use musk::{Arguments, Program, SpendBuilder};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let utxo = Utxo {
+///     txid: Txid::all_zeros(),
+///     vout: 0,
+///     amount: 100_000,
+///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+///     value_commitment: None,
+///     confirmations: 0,
+///     block_height: None,
+/// };
+///
+/// let mut builder = SpendBuilder::try_new(compiled.clone(), utxo).unwrap()
+///     .genesis_hash(elements::BlockHash::all_zeros());
+/// builder.add_output_simple(Script::new(), 99_000, elements::AssetId::default());
+/// let json = builder.export_state().to_json().unwrap();
+///
+/// let resumed = SpendState::from_json(&json).unwrap().resume(&[compiled]).unwrap();
+/// assert_eq!(resumed.export_state(), builder.export_state());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpendState {
+    /// Program inputs in input order
+    pub inputs: Vec<ProgramInputState>,
+    /// External inputs in input order
+    pub external_utxos: Vec<UtxoState>,
+    /// Peg-in inputs in input order
+    pub pegin_inputs: Vec<PeginInputState>,
+    /// Consensus-encoded outputs
+    pub outputs: Vec<String>,
+    /// Transaction version
+    #[cfg_attr(feature = "serde", serde(default = "default_version"))]
+    pub version: u32,
+    /// Transaction lock time
+    pub lock_time: u32,
+    /// Sequence number of every input
+    pub sequence: u32,
+    /// Genesis block hash
+    pub genesis_hash: String,
+    /// Whether fees are estimated with the ELIP-200 discount
+    pub discount_ct: bool,
+    /// Asset issuances of program inputs
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub issuances: Vec<IssuanceState>,
+}
+
+/// Portable state of an asset issuance
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IssuanceState {
+    /// Index of the issuing program input
+    pub input: usize,
+    /// Consensus-encoded issuance
+    pub issuance: String,
+    /// Whether the issuance amounts are blinded
+    pub blinded: bool,
+    /// Rangeproof of the blinded issuance amount
+    pub amount_rangeproof: Option<String>,
+    /// Rangeproof of the blinded reissuance token amount
+    pub inflation_keys_rangeproof: Option<String>,
+}
+
+/// Transaction version of spend states exported before it was recorded
+#[cfg(feature = "serde")]
+const fn default_version() -> u32 {
+    2
+}
+
+/// Portable state of a program input
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProgramInputState {
+    /// CMR of the program locking the UTXO
+    pub cmr: String,
+    /// Spent UTXO
+    pub utxo: UtxoState,
+}
+
+/// Portable state of a peg-in input
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeginInputState {
+    /// Claimed output
+    pub utxo: UtxoState,
+    /// Peg-in witness stack
+    pub pegin_witness: Vec<String>,
+}
+
+/// Portable description of a UTXO
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UtxoState {
+    /// Transaction ID
+    pub txid: String,
+    /// Output index
+    pub vout: u32,
+    /// Amount in satoshis
+    pub amount: u64,
+    /// scriptPubkey
+    pub script_pubkey: String,
+    /// Consensus-encoded asset, explicit or confidential
+    pub asset: String,
+    /// Hex-encoded value commitment of a confidential output
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub value_commitment: Option<String>,
+}
+
+impl From<&Utxo> for UtxoState {
+    fn from(utxo: &Utxo) -> Self {
+        Self {
+            txid: utxo.txid.to_string(),
+            vout: utxo.vout,
+            amount: utxo.amount,
+            script_pubkey: utxo.script_pubkey.as_bytes().to_hex(),
+            asset: elements::encode::serialize_hex(&utxo.asset),
+            value_commitment: utxo
+                .value_commitment
+                .map(|commitment| commitment.serialize().to_hex()),
+        }
+    }
+}
+
+impl UtxoState {
+    /// Decode the UTXO
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any field is malformed.
+    pub fn utxo(&self) -> Result<Utxo, SpendError> {
+        let invalid = |field: &str, e: &dyn std::fmt::Display| {
+            SpendError::InvalidUtxo(format!("Invalid {field}: {e}"))
+        };
+        Ok(Utxo {
+            txid: elements::Txid::from_str(&self.txid).map_err(|e| invalid("txid", &e))?,
+            vout: self.vout,
+            amount: self.amount,
+            script_pubkey: Script::from(
+                Vec::<u8>::from_hex(&self.script_pubkey)
+                    .map_err(|e| invalid("scriptPubkey", &e))?,
+            ),
+            asset: decode_hex(&self.asset).map_err(|e| invalid("asset", &e))?,
+            value_commitment: self
+                .value_commitment
+                .as_deref()
+                .map(PedersenCommitment::from_str)
+                .transpose()
+                .map_err(|e| invalid("value commitment", &e))?,
+            confirmations: 0,
+            block_height: None,
+        })
+    }
+}
+
+/// Decode a consensus-encoded hex string
+fn decode_hex<T: elements::encode::Decodable>(hex: &str) -> Result<T, String> {
+    let bytes = Vec::<u8>::from_hex(hex).map_err(|e| e.to_string())?;
+    elements::encode::deserialize(&bytes).map_err(|e| e.to_string())
+}
+
+impl SpendState {
+    /// Rebuild the [`SpendBuilder`]
+    ///
+    /// Each program input is matched to the program in `programs` with its
+    /// CMR that locks its UTXO, so programs sharing a CMR under different
+    /// internal keys are told apart.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any field is malformed or no program in `programs`
+    /// matches a program input.
+    pub fn resume(&self, programs: &[InstantiatedProgram]) -> Result<SpendBuilder, SpendError> {
+        let inputs = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let utxo = input.utxo.utxo()?;
+                let program = programs
+                    .iter()
+                    .find(|program| {
+                        program.cmr().to_string() == input.cmr
+                            && program.owns_script(&utxo.script_pubkey)
+                    })
+                    .ok_or_else(|| {
+                        SpendError::BuildError(format!(
+                            "No program with CMR {} locks input {index}",
+                            input.cmr
+                        ))
+                    })?;
+                Ok((program.clone(), utxo))
+            })
+            .collect::<Result<Vec<_>, SpendError>>()?;
+
+        let genesis_hash = elements::BlockHash::from_str(&self.genesis_hash)
+            .map_err(|e| SpendError::BuildError(format!("Invalid genesis hash: {e}")))?;
+        let mut builder = SpendBuilder::from_inputs(inputs)?.genesis_hash(genesis_hash);
+        builder.external_utxos = self
+            .external_utxos
+            .iter()
+            .map(UtxoState::utxo)
+            .collect::<Result<_, _>>()?;
+        builder.pegin_inputs = self
+            .pegin_inputs
+            .iter()
+            .map(|pegin| {
+                let witness = pegin
+                    .pegin_witness
+                    .iter()
+                    .map(|item| Vec::<u8>::from_hex(item))
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| SpendError::InvalidUtxo(format!("Invalid peg-in witness: {e}")))?;
+                Ok((pegin.utxo.utxo()?, witness))
+            })
+            .collect::<Result<_, SpendError>>()?;
+        builder.outputs = self
+            .outputs
+            .iter()
+            .map(|output| {
+                decode_hex(output)
+                    .map_err(|e| SpendError::BuildError(format!("Invalid output: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+        builder.version = self.version;
+        builder.lock_time = LockTime::from_consensus(self.lock_time);
+        builder.sequence = Sequence::from_consensus(self.sequence);
+        builder.discount_ct = self.discount_ct;
+        for state in &self.issuances {
+            builder.check_input(state.input)?;
+            let invalid = |e: String| SpendError::BuildError(format!("Invalid issuance: {e}"));
+            let rangeproof = |proof: Option<&str>| {
+                proof
+                    .map(|hex| {
+                        let bytes = Vec::<u8>::from_hex(hex).map_err(|e| invalid(e.to_string()))?;
+                        RangeProof::from_slice(&bytes)
+                            .map(Box::new)
+                            .map_err(|e| invalid(e.to_string()))
+                    })
+                    .transpose()
+            };
+
+            let utxo = &builder.inputs[state.input].1;
+            let mut issuance = InputIssuance::new(
+                elements::OutPoint::new(utxo.txid, utxo.vout),
+                decode_hex(&state.issuance).map_err(invalid)?,
+                state.blinded,
+            );
+            issuance.amount_rangeproof = rangeproof(state.amount_rangeproof.as_deref())?;
+            issuance.inflation_keys_rangeproof =
+                rangeproof(state.inflation_keys_rangeproof.as_deref())?;
+            builder.issuances.insert(state.input, issuance);
+        }
+        Ok(builder)
+    }
+
+    /// Serialize to a JSON string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, SpendError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| SpendError::BuildError(format!("Cannot serialize spend state: {e}")))
+    }
+
+    /// Parse from a JSON string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is malformed.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, SpendError> {
+        serde_json::from_str(json)
+            .map_err(|e| SpendError::BuildError(format!("Invalid spend state: {e}")))
+    }
+}
 
-        psbt.extract_tx()
-            .map_err(|e| SpendError::FinalizationError(e.to_string()))
+/// Compute the ELIP-200 discounted weight of `tx`
+///
+/// Confidential outputs are charged as if they were explicit: their
+/// rangeproofs and surjection proofs are not counted, nor is the extra size
+/// of value and nonce commitments over an explicit value and null nonce.
+/// Fee outputs are not discounted.
+#[must_use]
+pub fn discount_weight(tx: &Transaction) -> usize {
+    let serialized_len = |len: usize| len + if len < 0xfd { 1 } else { 3 };
+    let discount: usize = tx
+        .output
+        .iter()
+        .filter(|output| !output.is_fee())
+        .map(|output| {
+            let mut discount = 0;
+            if output.asset.is_confidential() {
+                discount += serialized_len(
+                    output
+                        .witness
+                        .surjection_proof
+                        .as_ref()
+                        .map_or(0, |proof| proof.serialize().len()),
+                );
+            }
+            if output.value.is_confidential() {
+                discount += serialized_len(output.witness.rangeproof_len()) + (33 - 9) * 4;
+            }
+            if output.nonce.is_confidential() {
+                discount += 32 * 4;
+            }
+            discount
+        })
+        .sum();
+    tx.weight() - discount
+}
+
+/// Size and fee summary of a finalized transaction
+///
+/// # Examples
+///
+/// ```
+/// use musk::spend::{simple_spend, TxReport};
+/// use musk::client::Utxo;
+/// use musk::elements::{self, confidential, hashes::Hash, BlockHash, Script, Txid};
+/// use musk::{Arguments, Program, WitnessValues};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let utxo = Utxo {
+///     txid: Txid::all_zeros(),
+///     vout: 0,
+///     amount: 100_000,
+///     script_pubkey: compiled.address(&elements::AddressParams::ELEMENTS).script_pubkey(),
+///     asset: confidential::Asset::Explicit(elements::AssetId::default()),
+///     value_commitment: None,
+///     confirmations: 0,
+///     block_height: None,
+/// };
+///
+/// let tx = simple_spend(
+///     compiled,
+///     utxo,
+///     Script::new(),
+///     99_000,
+///     1_000,
+///     BlockHash::all_zeros(),
+///     WitnessValues::default(),
+/// )
+/// .unwrap();
+/// let report = TxReport::new(&tx);
+/// assert_eq!(report.fee, 1_000);
+/// assert!(report.fee_rate > 1.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxReport {
+    /// Transaction weight in weight units
+    pub weight: usize,
+    /// Virtual size in vbytes, with witness data discounted
+    pub vsize: usize,
+    /// ELIP-200 discounted virtual size in vbytes, see [`discount_weight`]
+    pub discount_vsize: usize,
+    /// Total of the explicit fee outputs, in satoshis
+    pub fee: u64,
+    /// Effective fee rate in sat/vB
+    pub fee_rate: f64,
+}
+
+impl TxReport {
+    /// Summarize the size and fee of `tx`
+    ///
+    /// Confidential fee outputs are not counted, as their amount is hidden.
+    #[must_use]
+    pub fn new(tx: &Transaction) -> Self {
+        let vsize = tx.vsize();
+        let fee = tx
+            .output
+            .iter()
+            .filter(|output| output.is_fee())
+            .filter_map(|output| output.value.explicit())
+            .sum();
+        #[allow(clippy::cast_precision_loss)]
+        let fee_rate = fee as f64 / vsize as f64;
+        Self {
+            weight: tx.weight(),
+            vsize,
+            discount_vsize: discount_weight(tx).div_ceil(4),
+            fee,
+            fee_rate,
+        }
     }
 }
 
@@ -197,8 +3356,1570 @@ pub fn simple_spend(
         return Err(SpendError::InvalidUtxo("Non-explicit asset".into()));
     };
 
-    let mut builder = SpendBuilder::new(program, utxo).genesis_hash(genesis_hash);
+    let mut builder = SpendBuilder::try_new(program, utxo)?.genesis_hash(genesis_hash);
     builder.add_output_simple(destination, amount, asset);
     builder.add_fee(fee, asset);
     builder.finalize(witness_values)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::test_fixtures::{test_genesis_hash, test_utxo, CAT_PROGRAM, SIMPLE_PROGRAM};
+    use crate::util::keypair_from_u32;
+    use elements::schnorr::TapTweak;
+    use simplicityhl::Arguments;
+
+    fn program_utxo(program: &InstantiatedProgram, vout: u32) -> Utxo {
+        Utxo {
+            vout,
+            script_pubkey: program
+                .address(&elements::AddressParams::ELEMENTS)
+                .script_pubkey(),
+            ..test_utxo()
+        }
+    }
+
+    fn keypath_builder() -> SpendBuilder {
+        let internal_key = keypair_from_u32(1).x_only_public_key().0;
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate_with_internal_key(Arguments::default(), internal_key)
+            .unwrap();
+
+        let mut utxo = test_utxo();
+        utxo.script_pubkey = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .script_pubkey();
+
+        let mut builder = SpendBuilder::try_new(program, utxo)
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_output_simple(Script::new(), 99_000_000, elements::AssetId::default());
+        builder.add_fee(1_000_000, elements::AssetId::default());
+        builder
+    }
+
+    #[test]
+    fn test_finalize_keypath() {
+        let builder = keypath_builder();
+        let merkle_root = builder.inputs[0].0.taproot_info().merkle_root();
+        let tweaked = keypair_from_u32(1).tap_tweak(&Secp256k1::new(), merkle_root);
+
+        let sighash = builder.sighash_keypath().unwrap();
+        let signature = tweaked
+            .to_inner()
+            .sign_schnorr(Message::from_digest(sighash))
+            .serialize();
+
+        let tx = builder.finalize_keypath(signature).unwrap();
+        assert_eq!(tx.input[0].witness.script_witness, vec![signature.to_vec()]);
+    }
+
+    #[test]
+    fn test_finalize_keypath_with_keypair() {
+        let tx = keypath_builder()
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(tx.input[0].witness.script_witness.len(), 1);
+
+        assert!(keypath_builder()
+            .finalize_keypath_with_keypair(&keypair_from_u32(2))
+            .is_err());
+    }
+
+    #[test]
+    fn test_estimate_fee_matches_finalized_weight() {
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let build = || {
+            let mut builder = SpendBuilder::new_unchecked(program.clone(), test_utxo())
+                .genesis_hash(test_genesis_hash());
+            builder.add_output_simple(Script::new(), 99_000_000, elements::AssetId::default());
+            builder.add_fee(1_000_000, elements::AssetId::default());
+            builder
+        };
+
+        let weight = build().estimate_weight(WitnessValues::default()).unwrap();
+        let tx = build().finalize(WitnessValues::default()).unwrap();
+        assert_eq!(weight, tx.weight());
+
+        let fee = build().estimate_fee(1.0, WitnessValues::default()).unwrap();
+        assert_eq!(fee, tx.vsize() as u64);
+        assert!(build()
+            .estimate_fee(-1.0, WitnessValues::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_estimate_weight_of_blinded_outputs() {
+        let asset = elements::AssetId::default();
+        let blinding_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let address = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .to_confidential(blinding_key.public_key(&Secp256k1::new()));
+
+        assert_eq!(surjection_proof_len(1), 67);
+        for num_inputs in [1, 2, 5, 9] {
+            let utxos = (0..num_inputs)
+                .map(|vout| program_utxo(&program, vout))
+                .collect();
+            let mut builder = SpendBuilder::from_utxos(program.clone(), utxos)
+                .unwrap()
+                .genesis_hash(test_genesis_hash());
+            builder
+                .add_confidential_output(&address, 60_000_000, asset)
+                .unwrap()
+                .add_confidential_output(&address, 30_000_000, asset)
+                .unwrap()
+                .add_fee(1_000, asset);
+            let estimate = builder.estimate_weight(WitnessValues::default()).unwrap();
+
+            builder
+                .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+                .unwrap();
+            let tx = builder.finalize(WitnessValues::default()).unwrap();
+            for out in &tx.output[..2] {
+                let proof = out.witness.surjection_proof.as_ref().unwrap();
+                assert_eq!(proof.len(), surjection_proof_len(tx.input.len()));
+            }
+            assert_eq!(estimate, tx.weight(), "{num_inputs} inputs");
+        }
+    }
+
+    #[test]
+    fn test_estimate_weight_counts_missing_fee_output() {
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let destination = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .script_pubkey();
+        let mut without_fee = SpendBuilder::new_unchecked(program.clone(), test_utxo());
+        without_fee.add_output_simple(
+            destination.clone(),
+            99_000_000,
+            elements::AssetId::default(),
+        );
+        let mut with_fee = SpendBuilder::new_unchecked(program, test_utxo());
+        with_fee.add_output_simple(destination, 99_000_000, elements::AssetId::default());
+        with_fee.add_fee(1_000_000, elements::AssetId::default());
+
+        assert_eq!(
+            without_fee
+                .estimate_weight(WitnessValues::default())
+                .unwrap(),
+            with_fee.estimate_weight(WitnessValues::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_add_change() {
+        let asset = elements::AssetId::default();
+        let mut builder = keypath_builder();
+        builder.outputs.clear();
+        builder.add_output_simple(Script::new(), 60_000_000, asset);
+        builder.add_fee(1_000, asset);
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+
+        let change = builder.outputs.last().unwrap();
+        assert_eq!(change.value, confidential::Value::Explicit(39_999_000));
+        assert_eq!(change.script_pubkey, Script::from(vec![0x51]));
+
+        let outputs = builder.outputs.len();
+        builder.add_change(Script::new()).unwrap();
+        assert_eq!(builder.outputs.len(), outputs);
+    }
+
+    #[test]
+    fn test_bump_fee() {
+        let asset = elements::AssetId::default();
+        let build = || {
+            let mut builder = keypath_builder();
+            builder.signal_rbf();
+            builder.outputs.clear();
+            builder.add_output_simple(Script::new(), 60_000_000, asset);
+            builder.add_fee(100, asset);
+            builder.add_change(Script::from(vec![0x51])).unwrap();
+            builder
+        };
+        let original = build()
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert!(original.input[0].sequence.is_rbf());
+
+        let bumped = keypath_builder()
+            .bump_fee(&original, 10.0, 0.1, WitnessValues::default())
+            .unwrap();
+        let fee = bumped.outputs[1].value.explicit().unwrap();
+        let change = bumped.outputs[2].value.explicit().unwrap();
+        assert!(fee > 100);
+        assert_eq!(fee + change, 100 + 39_999_900);
+        assert_eq!(bumped.outputs[0], original.output[0]);
+        assert!(bumped
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .is_ok());
+
+        assert!(keypath_builder()
+            .bump_fee(&original, 0.0, 0.1, WitnessValues::default())
+            .is_err());
+        assert!(keypath_builder()
+            .bump_fee(&original, 10.0, -1.0, WitnessValues::default())
+            .is_err());
+
+        // A rate barely above the original's is raised to pay for relay
+        let min_bump = keypath_builder()
+            .bump_fee(&original, 2.0, 10.0, WitnessValues::default())
+            .unwrap();
+        let min_increase = min_bump
+            .estimate_fee(10.0, WitnessValues::default())
+            .unwrap();
+        assert_eq!(
+            min_bump.outputs[1].value.explicit().unwrap(),
+            100 + min_increase
+        );
+
+        let mut final_builder = build();
+        final_builder.set_sequence(Sequence::MAX);
+        let final_tx = final_builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert!(keypath_builder()
+            .bump_fee(&final_tx, 10.0, 0.1, WitnessValues::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_cpfp() {
+        let asset = elements::AssetId::default();
+        let mut parent_builder = keypath_builder();
+        let program = parent_builder.inputs[0].0.clone();
+        let script_pubkey = parent_builder.inputs[0].1.script_pubkey.clone();
+        parent_builder.outputs.clear();
+        parent_builder.add_output_simple(script_pubkey, 60_000_000, asset);
+        parent_builder.add_fee(1, asset);
+        parent_builder.add_change(Script::new()).unwrap();
+        let parent = parent_builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+
+        let child = SpendBuilder::cpfp(
+            program.clone(),
+            &parent,
+            0,
+            Script::from(vec![0x51]),
+            2.0,
+            WitnessValues::default(),
+        )
+        .unwrap()
+        .genesis_hash(test_genesis_hash());
+        assert_eq!(child.inputs[0].1.txid, parent.txid());
+        let fee = child.outputs[1].value.explicit().unwrap();
+        let sent = child.outputs[0].value.explicit().unwrap();
+        assert_eq!(fee + sent, 60_000_000);
+
+        let child_tx = child
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert!(1 + fee >= 2 * (parent.vsize() + child_tx.vsize()) as u64);
+
+        assert!(SpendBuilder::cpfp(
+            program.clone(),
+            &parent,
+            5,
+            Script::new(),
+            2.0,
+            WitnessValues::default()
+        )
+        .is_err());
+        // The change output is not locked by the program
+        assert!(matches!(
+            SpendBuilder::cpfp(
+                program,
+                &parent,
+                2,
+                Script::new(),
+                2.0,
+                WitnessValues::default()
+            ),
+            Err(SpendError::ProgramMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tx_report() {
+        let tx = keypath_builder()
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        let report = TxReport::new(&tx);
+        assert_eq!(report.weight, tx.weight());
+        assert_eq!(report.vsize, tx.weight().div_ceil(4));
+        assert_eq!(report.discount_vsize, report.vsize);
+        assert_eq!(report.fee, 1_000_000);
+        assert!((report.fee_rate * report.vsize as f64 - 1_000_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_drain_to() {
+        let mut builder = keypath_builder();
+        builder.outputs.clear();
+        builder.add_external_input(Utxo {
+            vout: 1,
+            ..test_utxo()
+        });
+        builder
+            .drain_to(Script::from(vec![0x51]), 1.0, WitnessValues::default())
+            .unwrap();
+        assert_eq!(builder.outputs.len(), 2);
+        let fee = builder.outputs[1].value.explicit().unwrap();
+        assert!(builder.outputs[1].is_fee() && fee > 0);
+        assert_eq!(
+            builder.outputs[0].value.explicit().unwrap() + fee,
+            200_000_000
+        );
+
+        let mut mixed = keypath_builder();
+        mixed.add_external_input(Utxo {
+            asset: confidential::Asset::Explicit(elements::AssetId::from_slice(&[9; 32]).unwrap()),
+            ..test_utxo()
+        });
+        assert!(mixed
+            .drain_to(Script::new(), 1.0, WitnessValues::default())
+            .is_err());
+
+        let mut overspent = keypath_builder();
+        let outputs = overspent.outputs.len();
+        assert!(overspent
+            .drain_to(Script::new(), 1.0, WitnessValues::default())
+            .is_err());
+        assert_eq!(overspent.outputs.len(), outputs);
+    }
+
+    #[test]
+    fn test_add_output_to_address() {
+        let asset = elements::AssetId::default();
+        let secp = Secp256k1::new();
+        let blinding_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let mut builder = keypath_builder();
+        let address = builder.inputs[0]
+            .0
+            .address(&elements::AddressParams::ELEMENTS)
+            .to_confidential(blinding_key.public_key(&secp));
+        builder.outputs.clear();
+        builder.add_output_to_address(&address, 1_000, asset);
+        builder.add_output_to_address(&address.to_unconfidential(), 2_000, asset);
+
+        assert_eq!(
+            builder.outputs[0].nonce,
+            confidential::Nonce::Confidential(blinding_key.public_key(&secp))
+        );
+        assert_eq!(builder.outputs[0].script_pubkey, address.script_pubkey());
+        assert!(builder.outputs[1].nonce.is_null());
+        assert_eq!(
+            builder.outputs[1].value,
+            confidential::Value::Explicit(2_000)
+        );
+    }
+
+    #[test]
+    fn test_add_burn() {
+        let asset = elements::AssetId::default();
+        let mut builder = keypath_builder();
+        builder.outputs.clear();
+        builder.add_burn(40_000_000, asset);
+        builder.add_fee(1_000, asset);
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+
+        let burn = &builder.outputs[0];
+        assert!(burn.script_pubkey.is_provably_unspendable());
+        assert_eq!(burn.value, confidential::Value::Explicit(40_000_000));
+        assert_eq!(burn.asset, confidential::Asset::Explicit(asset));
+        assert_eq!(
+            builder.outputs.last().unwrap().value,
+            confidential::Value::Explicit(59_999_000)
+        );
+    }
+
+    #[test]
+    fn test_add_change_errors() {
+        let asset = elements::AssetId::default();
+
+        let mut dust = keypath_builder();
+        dust.outputs.clear();
+        dust.add_output_simple(Script::new(), 100_000_000 - DUST_THRESHOLD + 1, asset);
+        assert!(dust.add_change(Script::new()).is_err());
+
+        let mut overspent = keypath_builder();
+        overspent.add_fee(1, asset);
+        assert!(overspent.add_change(Script::new()).is_err());
+
+        let mut foreign = keypath_builder();
+        foreign.outputs.clear();
+        foreign.add_output_simple(
+            Script::new(),
+            1,
+            elements::AssetId::from_slice(&[9; 32]).unwrap(),
+        );
+        assert!(foreign.add_change(Script::new()).is_err());
+    }
+
+    #[test]
+    fn test_blind() {
+        let asset = elements::AssetId::default();
+        let secp = Secp256k1::new();
+        let blinding_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let mut builder = keypath_builder();
+        let address = builder.inputs[0]
+            .0
+            .address(&elements::AddressParams::ELEMENTS)
+            .to_confidential(blinding_key.public_key(&secp));
+        builder.outputs.clear();
+        builder
+            .add_confidential_output(&address, 60_000_000, asset)
+            .unwrap();
+        builder.add_fee(1_000, asset);
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+        assert!(builder
+            .add_confidential_output(&address.to_unconfidential(), 1, asset)
+            .is_err());
+
+        builder
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .unwrap();
+        let blinded = builder.outputs[0].clone();
+        assert!(blinded.value.is_confidential());
+        assert!(blinded.asset.is_confidential());
+        assert!(blinded.witness.rangeproof_len() > 0);
+        assert!(builder.outputs[1].is_fee());
+
+        let prevouts = builder.prevouts();
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        tx.verify_tx_amt_proofs(&secp, &prevouts).unwrap();
+
+        let mut explicit = tx.clone();
+        explicit.output[0].value = confidential::Value::Explicit(60_000_000);
+        explicit.output[0].asset = confidential::Asset::Explicit(asset);
+        explicit.output[0].nonce = confidential::Nonce::Null;
+        explicit.output[0].witness = TxOutWitness::empty();
+        assert!(discount_weight(&tx).abs_diff(explicit.weight()) <= 2);
+        assert!(TxReport::new(&tx).discount_vsize < TxReport::new(&tx).vsize);
+
+        let mut foreign = keypath_builder();
+        foreign.inputs[0].1.asset = blinded.asset;
+        assert!(foreign
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_blind_mixed_outputs() {
+        let asset = elements::AssetId::default();
+        let secp = Secp256k1::new();
+        let blinding_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let mut builder = keypath_builder();
+        let program = builder.inputs[0].0.clone();
+        let address = program
+            .address(&elements::AddressParams::ELEMENTS)
+            .to_confidential(blinding_key.public_key(&secp));
+
+        // Nothing to blind: the spend stays as it is
+        let explicit = builder.outputs.clone();
+        builder
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .unwrap();
+        assert_eq!(builder.outputs, explicit);
+
+        builder.outputs.clear();
+        builder
+            .add_confidential_output(&address, 60_000_000, asset)
+            .unwrap()
+            .add_fee(1_000, asset)
+            .add_change(Script::from(vec![0x51]))
+            .unwrap()
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .unwrap();
+        let parent = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        let (utxo, secrets) =
+            crate::blinding::unblind_utxo(parent.txid(), 0, &parent.output[0], blinding_key)
+                .unwrap();
+        let input_secrets = BTreeMap::from([(0, secrets)]);
+
+        // A confidential input mixed with a blinded output, an explicit
+        // payout, an explicit fee and explicit change
+        let mut builder = SpendBuilder::new_unchecked(program.clone(), utxo.clone())
+            .genesis_hash(test_genesis_hash());
+        builder
+            .add_confidential_output(&address, 30_000_000, asset)
+            .unwrap()
+            .add_output_simple(Script::from(vec![0x51, 0x01]), 20_000_000, asset)
+            .add_fee(1_000, asset)
+            .add_output_simple(Script::from(vec![0x51]), 9_999_000, asset)
+            .blind(&mut secp256k1::rand::thread_rng(), &input_secrets)
+            .unwrap();
+        assert!(builder.outputs[0].value.is_confidential());
+        assert!(builder.outputs[1..]
+            .iter()
+            .all(|output| output.value.is_explicit()));
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        tx.verify_tx_amt_proofs(&secp, &parent.output[..1]).unwrap();
+        assert_eq!(utxo.value(), parent.output[0].value);
+
+        // The signature commits to the spent output as it is on chain
+        let sighash = SpendBuilder::<Ready>::sighash_keypath_with(
+            &mut SighashCache::new(&tx),
+            &parent.output[..1],
+            0,
+            test_genesis_hash(),
+        )
+        .unwrap();
+        let signature =
+            schnorr::Signature::from_slice(&tx.input[0].witness.script_witness[0]).unwrap();
+        let output_key =
+            secp256k1::XOnlyPublicKey::from_slice(&parent.output[0].script_pubkey.as_bytes()[2..])
+                .unwrap();
+        secp.verify_schnorr(&signature, &Message::from_digest(sighash), &output_key)
+            .unwrap();
+
+        // Only explicit outputs cannot balance a confidential input
+        let mut builder = SpendBuilder::new_unchecked(program, utxo);
+        builder
+            .add_output_simple(Script::new(), 59_999_000, asset)
+            .add_fee(1_000, asset);
+        assert!(builder
+            .blind(&mut secp256k1::rand::thread_rng(), &input_secrets)
+            .is_err());
+    }
+
+    #[test]
+    fn test_issue_asset() {
+        let policy = elements::AssetId::default();
+        let mut builder = keypath_builder();
+        builder.outputs.clear();
+        let contract_hash = ContractHash::from_byte_array([5; 32]);
+        let (asset, token) = builder
+            .issue_asset(0, 21_000_000, 1, contract_hash, false)
+            .unwrap();
+        assert_ne!(asset, token);
+        assert!(builder.issue_asset(0, 1, 0, contract_hash, false).is_err());
+        assert!(builder.issue_asset(1, 1, 0, contract_hash, false).is_err());
+
+        let script_pubkey = builder.inputs[0].1.script_pubkey.clone();
+        builder.add_fee(1_000, policy);
+        builder.add_change(script_pubkey).unwrap();
+        assert_eq!(builder.outputs.len(), 4);
+        for (id, amount) in [(asset, 21_000_000), (token, 1)] {
+            assert!(builder.outputs.iter().any(|output| {
+                output.asset == confidential::Asset::Explicit(id)
+                    && output.value == confidential::Value::Explicit(amount)
+            }));
+        }
+
+        let state = builder.export_state();
+        let resumed = state.resume(&[builder.inputs[0].0.clone()]).unwrap();
+        assert_eq!(resumed.export_state(), state);
+
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(
+            tx.input[0].asset_issuance.amount,
+            confidential::Value::Explicit(21_000_000)
+        );
+        assert_eq!(tx.input[0].issuance_ids(), (asset, token));
+    }
+
+    #[test]
+    fn test_blinded_issuance() {
+        let policy = elements::AssetId::default();
+        let secp = Secp256k1::new();
+        let blinding_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let mut builder = keypath_builder();
+        builder.outputs.clear();
+        let address = builder.inputs[0]
+            .0
+            .address(&elements::AddressParams::ELEMENTS)
+            .to_confidential(blinding_key.public_key(&secp));
+        let (asset, token) = builder
+            .issue_asset(
+                0,
+                1_000_000,
+                1,
+                ContractHash::from_byte_array([5; 32]),
+                true,
+            )
+            .unwrap();
+        builder
+            .add_confidential_output(&address, 1_000_000, asset)
+            .unwrap();
+        builder.add_confidential_output(&address, 1, token).unwrap();
+        builder.add_fee(1_000, policy);
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+
+        let estimate = builder.estimate_weight(WitnessValues::default()).unwrap();
+        builder
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .unwrap();
+        assert!(builder.add_change(Script::new()).is_err());
+
+        let prevouts = builder.prevouts();
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        let issuance = tx.input[0].asset_issuance;
+        assert!(issuance.amount.is_confidential());
+        assert!(issuance.inflation_keys.is_confidential());
+        assert!(tx.input[0].witness.amount_rangeproof.is_some());
+        assert_eq!(tx.input[0].issuance_ids(), (asset, token));
+        assert!(tx.weight().abs_diff(estimate) < 2_000);
+        tx.verify_tx_amt_proofs(&secp, &prevouts).unwrap();
+    }
+
+    #[test]
+    fn test_add_input() {
+        let simple = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let cat = Program::from_source(CAT_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let mut builder = SpendBuilder::try_new(simple.clone(), program_utxo(&simple, 0)).unwrap();
+        builder
+            .add_program_input(cat.clone(), program_utxo(&cat, 1))
+            .unwrap();
+
+        builder
+            .add_inputs([program_utxo(&cat, 2), program_utxo(&simple, 3)])
+            .unwrap();
+        assert_eq!(builder.inputs().len(), 4);
+        assert_eq!(builder.inputs()[2].0.cmr(), cat.cmr());
+        assert_eq!(builder.inputs()[3].0.cmr(), simple.cmr());
+
+        assert!(matches!(
+            builder.add_input(test_utxo()),
+            Err(SpendError::ProgramMismatch { indices }) if indices == vec![4]
+        ));
+        assert_eq!(builder.inputs().len(), 4);
+    }
+
+    #[test]
+    fn test_output_accessors() {
+        let asset = elements::AssetId::default();
+        let mut builder = keypath_builder();
+        assert_eq!(builder.outputs().len(), 2);
+        assert_eq!(builder.fee(), 1_000_000);
+
+        let old = builder
+            .replace_output(1, TxOut::new_fee(2_000_000, asset))
+            .unwrap();
+        assert_eq!(old.value, confidential::Value::Explicit(1_000_000));
+        assert_eq!(builder.fee(), 2_000_000);
+
+        let removed = builder.remove_output(0).unwrap();
+        assert_eq!(removed.value, confidential::Value::Explicit(99_000_000));
+        assert_eq!(builder.outputs().len(), 1);
+        assert!(builder.outputs()[0].is_fee());
+
+        assert!(builder.remove_output(1).is_err());
+        assert!(builder.replace_output(1, TxOut::new_fee(1, asset)).is_err());
+        builder.remove_output(0).unwrap();
+        assert_eq!(builder.fee(), 0);
+    }
+
+    #[test]
+    fn test_clone_and_debug() {
+        let builder = keypath_builder();
+        let mut retry = builder.clone();
+        retry
+            .replace_output(1, TxOut::new_fee(2_000_000, elements::AssetId::default()))
+            .unwrap();
+        assert_ne!(
+            retry.sighash_keypath().unwrap(),
+            builder.sighash_keypath().unwrap()
+        );
+        assert_eq!(builder.fee(), 1_000_000);
+
+        let debug = format!("{builder:?}");
+        assert!(debug.starts_with("SpendBuilder {"));
+        assert!(debug.contains(&format!("{:?}", builder.inputs[0].0.cmr())));
+        assert!(debug.contains("Explicit(99000000)"));
+    }
+
+    #[test]
+    fn test_chained_configuration() {
+        let asset = elements::AssetId::default();
+        let mut chained = keypath_builder();
+        chained.outputs.clear();
+        chained
+            .set_lock_time(LockTime::from_consensus(100))
+            .signal_rbf()
+            .set_version(3)
+            .set_discount_ct(true)
+            .add_output_simple(Script::new(), 60_000_000, asset)
+            .add_fee(1_000, asset)
+            .add_change(Script::from(vec![0x51]))
+            .unwrap();
+        assert_eq!(chained.outputs.len(), 3);
+
+        #[allow(deprecated)]
+        let mut shimmed = keypath_builder()
+            .lock_time(LockTime::from_consensus(100))
+            .sequence(Sequence::ENABLE_RBF_NO_LOCKTIME);
+        shimmed.outputs.clear();
+        shimmed.set_version(3).set_discount_ct(true);
+        shimmed.add_output_simple(Script::new(), 60_000_000, asset);
+        shimmed.add_fee(1_000, asset);
+        shimmed.add_change(Script::from(vec![0x51])).unwrap();
+        assert_eq!(shimmed.export_state(), chained.export_state());
+    }
+
+    #[test]
+    fn test_version() {
+        let tx = keypath_builder()
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(tx.version, 2);
+
+        let mut builder = keypath_builder();
+        builder.set_version(3);
+        assert_ne!(
+            builder.sighash_keypath().unwrap(),
+            keypath_builder().sighash_keypath().unwrap()
+        );
+        assert_eq!(builder.export_state().version, 3);
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(tx.version, 3);
+    }
+
+    #[test]
+    fn test_add_fee_and_change() {
+        let asset = elements::AssetId::default();
+        let witness = WitnessValues::default();
+        let mut builder = keypath_builder();
+        builder.remove_output(1).unwrap();
+        builder.remove_output(0).unwrap();
+        builder.add_output_simple(Script::new(), 60_000_000, asset);
+        builder
+            .add_fee_and_change(1.0, asset, Script::from(vec![0x51]), witness.clone())
+            .unwrap();
+
+        assert_eq!(builder.outputs().len(), 3);
+        let fee = builder.fee();
+        assert!(fee >= builder.estimate_fee(1.0, witness.clone()).unwrap());
+        assert_eq!(
+            builder.outputs()[2].value,
+            confidential::Value::Explicit(40_000_000 - fee)
+        );
+        assert!(builder
+            .add_fee_and_change(1.0, asset, Script::new(), witness.clone())
+            .is_err());
+
+        // Change below the dust threshold goes to the fee
+        let mut dusty = keypath_builder();
+        dusty.remove_output(1).unwrap();
+        dusty.remove_output(0).unwrap();
+        dusty.add_output_simple(Script::new(), 100_000_000 - fee - 100, asset);
+        dusty
+            .add_fee_and_change(1.0, asset, Script::from(vec![0x51]), witness.clone())
+            .unwrap();
+        assert_eq!(dusty.outputs().len(), 2);
+        assert_eq!(dusty.fee(), fee + 100);
+
+        let mut short = keypath_builder();
+        short.remove_output(1).unwrap();
+        short.remove_output(0).unwrap();
+        short.add_output_simple(Script::new(), 100_000_000, asset);
+        assert!(matches!(
+            short.add_fee_and_change(1.0, asset, Script::new(), witness),
+            Err(SpendError::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_multiple_inputs() {
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let mut builder = SpendBuilder::from_utxos(
+            program.clone(),
+            vec![program_utxo(&program, 0), program_utxo(&program, 1)],
+        )
+        .unwrap()
+        .genesis_hash(test_genesis_hash());
+        builder.add_fee(1_000, elements::AssetId::default());
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+
+        assert_ne!(
+            builder.sighash_all_input(0).unwrap(),
+            builder.sighash_all_input(1).unwrap()
+        );
+        assert!(builder.sighash_all_input(2).is_err());
+        assert_eq!(
+            builder.sighash_all_inputs().unwrap(),
+            [
+                builder.sighash_all_input(0).unwrap(),
+                builder.sighash_all_input(1).unwrap()
+            ]
+        );
+        assert_eq!(
+            builder.outputs.last().unwrap().value,
+            confidential::Value::Explicit(199_999_000)
+        );
+
+        let tx = builder
+            .finalize_inputs(vec![WitnessValues::default(), WitnessValues::default()])
+            .unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert!(tx
+            .input
+            .iter()
+            .all(|input| input.witness.script_witness.len() == 4));
+    }
+
+    #[test]
+    fn test_different_programs_per_input() {
+        let simple = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let cat = Program::from_source(CAT_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let mut builder = SpendBuilder::from_inputs(vec![
+            (simple.clone(), program_utxo(&simple, 0)),
+            (cat.clone(), program_utxo(&cat, 1)),
+        ])
+        .unwrap()
+        .genesis_hash(test_genesis_hash());
+        builder.add_fee(1_000, elements::AssetId::default());
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+        assert!(builder.sighash_all_input(1).is_ok());
+
+        // One set of witness values cannot satisfy both programs
+        assert!(builder.estimate_weight(WitnessValues::default()).is_err());
+        assert!(builder.estimate_fee(1.0, WitnessValues::default()).is_err());
+        assert!(builder
+            .estimate_weight_inputs(vec![WitnessValues::default()])
+            .is_err());
+        let weight = builder
+            .estimate_weight_inputs(vec![WitnessValues::default(), WitnessValues::default()])
+            .unwrap();
+
+        let pset = builder.to_pset().unwrap();
+        for (input, program) in pset.inputs().iter().zip([&simple, &cat]) {
+            let (_, leaf) = input.tap_scripts.iter().next().unwrap();
+            assert_eq!(*leaf, program.script_version());
+        }
+
+        let tx = builder
+            .finalize_inputs(vec![WitnessValues::default(), WitnessValues::default()])
+            .unwrap();
+        assert_eq!(tx.weight(), weight);
+        assert_eq!(
+            tx.input[0].witness.script_witness[2],
+            simple.script_version().0.into_bytes()
+        );
+        assert_eq!(
+            tx.input[1].witness.script_witness[2],
+            cat.script_version().0.into_bytes()
+        );
+        assert!(SpendBuilder::from_inputs(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_sighash_all_inputs_match_environment() {
+        let simple = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let cat = Program::from_source(CAT_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let secp = Secp256k1::new();
+        let blinding_key = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let address = simple
+            .address(&elements::AddressParams::ELEMENTS)
+            .to_confidential(blinding_key.public_key(&secp));
+        let mut builder = SpendBuilder::from_inputs(vec![
+            (simple.clone(), program_utxo(&simple, 0)),
+            (cat.clone(), program_utxo(&cat, 1)),
+        ])
+        .unwrap()
+        .genesis_hash(test_genesis_hash());
+        builder.add_external_input(Utxo {
+            vout: 2,
+            amount: 5_000,
+            script_pubkey: Script::from(vec![0x51]),
+            ..test_utxo()
+        });
+        let (asset, token) = builder
+            .issue_asset(0, 1_000, 1, ContractHash::from_byte_array([5; 32]), true)
+            .unwrap();
+        let (unblinded, _) = builder
+            .issue_asset(1, 500, 0, ContractHash::from_byte_array([6; 32]), false)
+            .unwrap();
+        builder
+            .add_confidential_output(&address, 1_000, asset)
+            .unwrap();
+        builder.add_confidential_output(&address, 1, token).unwrap();
+        builder.add_output_simple(Script::from(vec![0x51]), 500, unblinded);
+        builder.add_fee(1_000, elements::AssetId::default());
+        builder.add_change(Script::from(vec![0x51])).unwrap();
+        builder
+            .blind(&mut secp256k1::rand::thread_rng(), &BTreeMap::new())
+            .unwrap();
+
+        let check = |builder: &SpendBuilder| {
+            let sighashes = builder.sighash_all_inputs().unwrap();
+            assert_eq!(sighashes.len(), 2);
+            for (index, sighash) in sighashes.iter().enumerate() {
+                let env = builder.elements_env(index).unwrap();
+                assert_eq!(&env.c_tx_env().sighash_all().to_byte_array(), sighash);
+                assert_eq!(&builder.sighash_all_input(index).unwrap(), sighash);
+            }
+            sighashes
+        };
+        let sighashes = check(&builder);
+        assert_ne!(sighashes[0], sighashes[1]);
+
+        // A reissuance commits to its blinding nonce and entropy instead
+        builder
+            .issuances
+            .get_mut(&1)
+            .unwrap()
+            .issuance
+            .asset_blinding_nonce = elements::secp256k1_zkp::Tweak::from_slice(&[7; 32]).unwrap();
+        assert_ne!(check(&builder), sighashes);
+    }
+
+    #[test]
+    fn test_validate_inputs() {
+        let simple = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let cat = Program::from_source(CAT_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+
+        assert!(SpendBuilder::try_new(simple.clone(), program_utxo(&simple, 0)).is_ok());
+        assert!(SpendBuilder::try_new(simple.clone(), test_utxo()).is_err());
+
+        let result = SpendBuilder::from_inputs(vec![
+            (simple.clone(), program_utxo(&simple, 0)),
+            (cat.clone(), program_utxo(&simple, 1)),
+            (simple.clone(), program_utxo(&cat, 2)),
+        ]);
+        assert!(matches!(
+            result,
+            Err(SpendError::ProgramMismatch { ref indices }) if *indices == [1, 2]
+        ));
+
+        let mut builder = SpendBuilder::try_new(simple.clone(), program_utxo(&simple, 0)).unwrap();
+        assert!(builder
+            .add_program_input(cat.clone(), program_utxo(&simple, 1))
+            .is_err());
+        builder
+            .add_program_input(cat.clone(), program_utxo(&cat, 1))
+            .unwrap();
+        assert_eq!(builder.inputs().len(), 2);
+    }
+
+    #[test]
+    fn test_select_and_keypath_sign_all_inputs() {
+        let template = keypath_builder();
+        let utxos: Vec<Utxo> = (0..3)
+            .map(|vout| Utxo {
+                vout,
+                amount: 10_000 * u64::from(vout + 1),
+                ..template.inputs[0].1.clone()
+            })
+            .collect();
+
+        let mut builder = SpendBuilder::select(
+            template.inputs[0].0.clone(),
+            &utxos,
+            45_000,
+            &crate::coin_selection::LargestFirst,
+        )
+        .unwrap()
+        .genesis_hash(test_genesis_hash());
+        assert_eq!(builder.inputs().len(), 2);
+        builder.add_fee(1_000, elements::AssetId::default());
+
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(tx.input.len(), 2);
+
+        assert!(SpendBuilder::select(
+            template.inputs[0].0.clone(),
+            &utxos,
+            100_000,
+            &crate::coin_selection::LargestFirst,
+        )
+        .is_err());
+        assert!(
+            SpendBuilder::from_utxos(keypath_builder().inputs[0].0.clone(), Vec::new()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_external_inputs() {
+        let wallet_utxo = Utxo {
+            vout: 7,
+            amount: 5_000,
+            script_pubkey: Script::from(vec![0x00, 0x14]),
+            ..test_utxo()
+        };
+        let build = |external: bool| {
+            let mut builder = keypath_builder();
+            builder.outputs.clear();
+            builder.add_fee(2_000, elements::AssetId::default());
+            if external {
+                builder.add_external_input(wallet_utxo.clone());
+            }
+            builder.add_change(Script::from(vec![0x51])).unwrap();
+            builder
+        };
+
+        let builder = build(true);
+        assert_eq!(builder.external_utxos().len(), 1);
+        assert_eq!(
+            builder.outputs.last().unwrap().value,
+            confidential::Value::Explicit(100_003_000)
+        );
+        assert_ne!(
+            builder.sighash_keypath().unwrap(),
+            build(false).sighash_keypath().unwrap()
+        );
+        assert!(builder.sighash_all_input(1).is_err());
+
+        let pset = builder.to_pset().unwrap();
+        assert!(pset.inputs()[1].witness_utxo.is_some());
+        assert!(pset.inputs()[1].tap_scripts.is_empty());
+
+        let tx = builder
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.input[1].previous_output.vout, 7);
+        assert!(tx.input[1].witness.script_witness.is_empty());
+    }
+
+    #[test]
+    fn test_to_pset() {
+        let builder = keypath_builder();
+        let pset = builder.to_pset().unwrap();
+
+        let encoded = elements::encode::serialize(&pset);
+        let decoded: Psbt = elements::encode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, pset);
+
+        let input = &pset.inputs()[0];
+        let taproot_info = builder.inputs[0].0.taproot_info();
+        assert_eq!(input.tap_internal_key, Some(taproot_info.internal_key()));
+        assert_eq!(input.tap_merkle_root, taproot_info.merkle_root());
+        assert_eq!(
+            input
+                .witness_utxo
+                .as_ref()
+                .map(|txout| &txout.script_pubkey),
+            Some(&builder.inputs[0].1.script_pubkey)
+        );
+        let (control_block, leaf) = input.tap_scripts.iter().next().unwrap();
+        assert_eq!(*leaf, builder.inputs[0].0.script_version());
+        assert!(control_block.verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            &taproot_info.output_key(),
+            &leaf.0
+        ));
+        assert_eq!(pset.outputs().len(), 2);
+    }
+
+    #[test]
+    fn test_finalize_to_pset() {
+        let mut builder = keypath_builder();
+        builder.add_external_input(Utxo {
+            vout: 1,
+            ..test_utxo()
+        });
+        let pset = builder
+            .finalize_to_pset(vec![WitnessValues::default()])
+            .unwrap();
+
+        assert_eq!(
+            pset.inputs()[0].final_script_witness.as_ref().map(Vec::len),
+            Some(4)
+        );
+        assert!(pset.inputs()[1].final_script_witness.is_none());
+        assert!(pset.inputs()[0].witness_utxo.is_some());
+
+        assert!(keypath_builder().finalize_to_pset(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_finalize_keypath_rejects_untweaked_signature() {
+        let builder = keypath_builder();
+        let sighash = builder.sighash_keypath().unwrap();
+        let signature = crate::util::sign_schnorr_with(&crate::util::keypair_from_u32(1), sighash);
+
+        assert!(builder.finalize_keypath(signature).is_err());
+    }
+
+    #[test]
+    fn test_component_hashes_match_jets() {
+        use crate::witness::WitnessBuilder;
+
+        let source = r#"
+fn main() {
+    assert!(jet::eq_256(unwrap(jet::output_hash(1)), witness::OUTPUT));
+    assert!(jet::eq_256(jet::outputs_hash(), witness::OUTPUTS));
+    assert!(jet::eq_256(unwrap(jet::input_hash(1)), witness::INPUT));
+    assert!(jet::eq_256(jet::inputs_hash(), witness::INPUTS));
+    assert!(jet::eq_256(unwrap(jet::input_utxo_hash(1)), witness::UTXO));
+    assert!(jet::eq_256(jet::input_utxos_hash(), witness::UTXOS));
+}
+"#;
+        let program = Program::from_source(source)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let mut builder = SpendBuilder::try_new(program.clone(), program_utxo(&program, 0))
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_external_input(Utxo {
+            vout: 1,
+            amount: 5_000,
+            script_pubkey: Script::from(vec![0x51]),
+            ..test_utxo()
+        });
+        builder.add_output_simple(Script::new(), 50_000_000, elements::AssetId::default());
+        builder.add_output_simple(
+            Script::from(vec![0x6a, 0x01, 0x02]),
+            49_005_000,
+            elements::AssetId::default(),
+        );
+        builder.add_fee(1_000_000, elements::AssetId::default());
+        assert!(builder.output_hash(3).is_err());
+        assert!(builder.input_hash(2).is_err());
+        assert!(builder.input_utxo_hash(2).is_err());
+
+        let witness = WitnessBuilder::new()
+            .with_u256("OUTPUT", builder.output_hash(1).unwrap())
+            .with_u256("OUTPUTS", builder.outputs_hash())
+            .with_u256("INPUT", builder.input_hash(1).unwrap())
+            .with_u256("INPUTS", builder.inputs_hash())
+            .with_u256("UTXO", builder.input_utxo_hash(1).unwrap())
+            .with_u256("UTXOS", builder.input_utxos_hash())
+            .build();
+        let tx = builder.clone().finalize(witness).unwrap();
+        builder.verify(&tx).unwrap();
+
+        // Each hash commits to a different part of the transaction
+        let mut other = builder.clone();
+        other.replace_output(1, other.outputs()[0].clone()).unwrap();
+        assert_ne!(
+            other.output_hash(1).unwrap(),
+            builder.output_hash(1).unwrap()
+        );
+        assert_eq!(other.input_hash(1).unwrap(), builder.input_hash(1).unwrap());
+        assert_eq!(other.inputs_hash(), builder.inputs_hash());
+        assert_eq!(other.input_utxos_hash(), builder.input_utxos_hash());
+    }
+
+    #[test]
+    fn test_verify() {
+        use crate::test_fixtures::P2PK_PROGRAM;
+        use crate::witness::WitnessBuilder;
+        use simplicityhl::str::WitnessName;
+        use simplicityhl::value::ValueConstructible;
+        use simplicityhl::Value;
+        use std::collections::HashMap;
+
+        let program = Program::from_source(P2PK_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::from(HashMap::from([(
+                WitnessName::from_str_unchecked("PK"),
+                Value::u256(simplicityhl::num::U256::from_byte_array(
+                    crate::util::xonly_public_key_of(&crate::util::keypair_from_u32(1)),
+                )),
+            )])))
+            .unwrap();
+        let build = || {
+            let mut builder = SpendBuilder::try_new(program.clone(), program_utxo(&program, 0))
+                .unwrap()
+                .genesis_hash(test_genesis_hash());
+            builder.add_output_simple(Script::new(), 99_000_000, elements::AssetId::default());
+            builder.add_fee(1_000_000, elements::AssetId::default());
+            builder
+        };
+
+        let builder = build();
+        let witness = WitnessBuilder::new()
+            .with_schnorr_signature(
+                "SIG",
+                &crate::util::keypair_from_u32(1),
+                builder.sighash_all().unwrap(),
+            )
+            .build();
+        let tx = build().finalize(witness).unwrap();
+        builder.verify(&tx).unwrap();
+
+        // The signature no longer covers a tampered transaction
+        let mut tampered = tx.clone();
+        tampered.output[0].value = confidential::Value::Explicit(98_000_000);
+        assert!(matches!(
+            builder.verify(&tampered),
+            Err(SpendError::VerificationError(_))
+        ));
+
+        let mut wrong_leaf = tx.clone();
+        wrong_leaf.input[0].witness.script_witness[2] = vec![0x51];
+        assert!(builder.verify(&wrong_leaf).is_err());
+
+        let mut missing_input = tx;
+        missing_input.input.clear();
+        assert!(builder.verify(&missing_input).is_err());
+
+        let keypath = keypath_builder();
+        let tx = keypath_builder()
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        keypath.verify(&tx).unwrap();
+        let mut tampered = tx;
+        tampered.lock_time = LockTime::from_consensus(1);
+        assert!(keypath.verify(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_elements_env() {
+        let builder = keypath_builder();
+        let env = builder.elements_env(0).unwrap();
+        assert_eq!(
+            env.c_tx_env().sighash_all().to_byte_array(),
+            builder.sighash_all().unwrap()
+        );
+        assert!(builder.elements_env(1).is_err());
+    }
+
+    #[test]
+    fn test_finalize_with() {
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let utxos = vec![program_utxo(&program, 0), program_utxo(&program, 1)];
+        let mut builder = SpendBuilder::from_utxos(program.clone(), utxos)
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_fee(1_000, elements::AssetId::default());
+        let sighashes = builder.sighash_all_inputs().unwrap();
+
+        let mut seen = Vec::new();
+        let tx = builder
+            .finalize_with(|index, sighash| {
+                seen.push((index, sighash));
+                Ok(WitnessValues::default())
+            })
+            .unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(seen, vec![(0, sighashes[0]), (1, sighashes[1])]);
+
+        let builder = SpendBuilder::try_new(program.clone(), program_utxo(&program, 0))
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        assert!(builder
+            .clone()
+            .finalize_with(|_, _| Err(SpendError::FinalizationError("Signer offline".into())))
+            .is_err());
+    }
+
+    #[test]
+    fn test_finalize_with_signer() {
+        use crate::test_fixtures::{block_on, P2PK_PROGRAM};
+        use simplicityhl::str::WitnessName;
+        use simplicityhl::value::ValueConstructible;
+        use simplicityhl::Value;
+        use std::collections::HashMap;
+        use std::future::Future;
+
+        struct TestSigner(u32);
+
+        impl AsyncSigner for TestSigner {
+            fn sign_sighash(
+                &self,
+                _index: usize,
+                sighash: [u8; 32],
+            ) -> impl Future<Output = Result<[u8; 64], SpendError>> + Send {
+                let key = self.0;
+                async move {
+                    if key == 0 {
+                        return Err(SpendError::SigningError("Signer offline".into()));
+                    }
+                    Ok(crate::util::sign_schnorr_with(
+                        &crate::util::keypair_from_u32(key),
+                        sighash,
+                    ))
+                }
+            }
+        }
+
+        let program = Program::from_source(P2PK_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::from(HashMap::from([(
+                WitnessName::from_str_unchecked("PK"),
+                Value::u256(simplicityhl::num::U256::from_byte_array(
+                    crate::util::xonly_public_key_of(&crate::util::keypair_from_u32(1)),
+                )),
+            )])))
+            .unwrap();
+        let utxos = vec![program_utxo(&program, 0), program_utxo(&program, 1)];
+        let mut builder = SpendBuilder::from_utxos(program, utxos)
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        builder.add_output_simple(Script::new(), 199_000_000, elements::AssetId::default());
+        builder.add_fee(1_000_000, elements::AssetId::default());
+
+        let witness = |_, signature| {
+            Ok(WitnessValues::from(HashMap::from([(
+                WitnessName::from_str_unchecked("SIG"),
+                Value::byte_array(signature),
+            )])))
+        };
+        let tx = block_on(
+            builder
+                .clone()
+                .finalize_with_signer(&TestSigner(1), witness),
+        )
+        .unwrap();
+        builder.verify(&tx).unwrap();
+
+        assert!(matches!(
+            block_on(builder.finalize_with_signer(&TestSigner(0), witness)),
+            Err(SpendError::SigningError(_))
+        ));
+    }
+
+    #[test]
+    fn test_genesis_hash_and_outputs_required() {
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let mut pending =
+            SpendBuilder::try_new(program.clone(), program_utxo(&program, 0)).unwrap();
+        pending.add_output_simple(Script::new(), 99_000_000, elements::AssetId::default());
+        let weight = pending.estimate_weight(WitnessValues::default()).unwrap();
+
+        let builder = pending.genesis_hash(test_genesis_hash());
+        assert_eq!(builder.genesis_hash, test_genesis_hash());
+        assert_eq!(builder.outputs.len(), 1);
+        assert_eq!(
+            builder.estimate_weight(WitnessValues::default()).unwrap(),
+            weight
+        );
+
+        let empty = SpendBuilder::try_new(program.clone(), program_utxo(&program, 0))
+            .unwrap()
+            .genesis_hash(test_genesis_hash());
+        assert!(matches!(
+            empty.clone().finalize(WitnessValues::default()),
+            Err(SpendError::FinalizationError(_))
+        ));
+        let satisfied = program.satisfy(WitnessValues::default()).unwrap();
+        assert!(empty.finalize_with_satisfied(&satisfied).is_err());
+    }
+
+    #[test]
+    fn test_add_asset_change() {
+        let token = elements::AssetId::from_slice(&[7u8; 32]).unwrap();
+        let policy = elements::AssetId::default();
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let treasury = Utxo {
+            asset: confidential::Asset::Explicit(token),
+            ..program_utxo(&program, 0)
+        };
+        let wallet = Utxo {
+            vout: 1,
+            amount: 10_000,
+            script_pubkey: Script::from(vec![0x00, 0x14]),
+            ..test_utxo()
+        };
+
+        // The treasury holds only the token; the wallet pays the fee
+        let mut builder = SpendBuilder::new_unchecked(program.clone(), treasury.clone());
+        builder.add_external_input(wallet.clone());
+        builder.add_output_simple(Script::new(), 40_000_000, token);
+        builder.add_fee(1_000, policy);
+        builder
+            .add_asset_change(token, treasury.script_pubkey.clone())
+            .unwrap()
+            .add_asset_change(policy, wallet.script_pubkey.clone())
+            .unwrap();
+        let outputs = &builder.outputs[2..];
+        assert_eq!(outputs[0].script_pubkey, treasury.script_pubkey);
+        assert_eq!(outputs[0].value, confidential::Value::Explicit(60_000_000));
+        assert_eq!(outputs[1].script_pubkey, wallet.script_pubkey);
+        assert_eq!(outputs[1].value, confidential::Value::Explicit(9_000));
+        assert!(builder
+            .balances()
+            .unwrap()
+            .values()
+            .all(|&amount| amount == 0));
+
+        // On chains accepting it, the fee can be paid in the token itself
+        let mut builder = SpendBuilder::new_unchecked(program, treasury);
+        builder.add_fee(5_000, token);
+        builder.add_change(Script::new()).unwrap();
+        assert_eq!(
+            builder.outputs[1].value,
+            confidential::Value::Explicit(99_995_000)
+        );
+        assert!(builder.add_asset_change(policy, Script::new()).is_ok());
+        assert_eq!(builder.outputs.len(), 2);
+    }
+
+    #[test]
+    fn test_add_change_from_client() {
+        let asset = elements::AssetId::default();
+        let unavailable = || Err(SpendError::BuildError("No wallet".into()));
+
+        // Fully spent: no address is requested
+        let mut builder = keypath_builder();
+        builder.add_change_with(unavailable).unwrap();
+        assert_eq!(builder.outputs.len(), 2);
+
+        builder.outputs.clear();
+        builder
+            .add_output_simple(Script::new(), 60_000_000, asset)
+            .add_fee(1_000, asset);
+        assert!(builder.clone().add_change_with(unavailable).is_err());
+        builder
+            .add_change_from_client(&crate::mock_client::MockClient::new())
+            .unwrap();
+        let change = &builder.outputs[2];
+        assert!(change.script_pubkey.is_v0_p2wpkh());
+        assert_eq!(change.value, confidential::Value::Explicit(39_999_000));
+
+        let mut dust = keypath_builder();
+        dust.outputs[1] = TxOut::new_fee(1_000_000 - 100, asset);
+        let mut requested = false;
+        assert!(dust
+            .add_change_with(|| {
+                requested = true;
+                Ok(Script::new())
+            })
+            .is_err());
+        assert!(!requested);
+    }
+
+    #[test]
+    fn test_check_balances() {
+        let token = elements::AssetId::from_slice(&[7u8; 32]).unwrap();
+        let policy = elements::AssetId::default();
+        let program = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let treasury = Utxo {
+            asset: confidential::Asset::Explicit(token),
+            ..program_utxo(&program, 0)
+        };
+
+        let mut builder = SpendBuilder::new_unchecked(program, treasury);
+        builder
+            .add_external_input(Utxo {
+                vout: 1,
+                amount: 10_000,
+                ..test_utxo()
+            })
+            .add_output_simple(Script::new(), 40_000_000, token)
+            .add_fee(1_000, policy);
+        let totals = builder.asset_totals().unwrap();
+        assert_eq!(
+            totals[&token],
+            AssetTotals {
+                inputs: 100_000_000,
+                outputs: 40_000_000
+            }
+        );
+        assert_eq!(totals[&policy].remaining(), Some(9_000));
+
+        // The policy asset balancing does not hide the missing token change
+        builder.add_asset_change(policy, Script::new()).unwrap();
+        assert!(matches!(
+            builder.check_balances(),
+            Err(SpendError::AssetImbalance { asset, inputs: 100_000_000, outputs: 40_000_000 })
+                if asset == token
+        ));
+        builder.add_asset_change(token, Script::new()).unwrap();
+        builder.check_balances().unwrap();
+
+        builder.add_fee(1, token);
+        assert_eq!(builder.asset_totals().unwrap()[&token].remaining(), None);
+        assert!(builder.check_balances().is_err());
+    }
+
+    #[test]
+    fn test_spend_state_roundtrip() {
+        let mut builder = keypath_builder();
+        builder
+            .set_lock_time(LockTime::from_consensus(100))
+            .signal_rbf()
+            .add_external_input(Utxo {
+                vout: 7,
+                script_pubkey: Script::from(vec![0x00, 0x14]),
+                ..test_utxo()
+            });
+        let program = builder.inputs[0].0.clone();
+
+        let state = builder.export_state();
+        let json = state.to_json().unwrap();
+        let resumed = SpendState::from_json(&json)
+            .unwrap()
+            .resume(&[program])
+            .unwrap();
+        assert_eq!(resumed.export_state(), state);
+        assert_eq!(
+            resumed.sighash_keypath().unwrap(),
+            builder.sighash_keypath().unwrap()
+        );
+        let tx = resumed
+            .finalize_keypath_with_keypair(&keypair_from_u32(1))
+            .unwrap();
+        assert_eq!(tx.input.len(), 2);
+
+        // The same program under another internal key does not match
+        let other = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        assert!(state.resume(&[other]).is_err());
+        assert!(state.resume(&[]).is_err());
+    }
+}
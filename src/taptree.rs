@@ -0,0 +1,766 @@
+//! Multi-leaf taproot trees
+//!
+//! A single taproot output can commit to several spending conditions, e.g. a
+//! cooperative spend path and a timeout path, each compiled as its own
+//! Simplicity program. The `TaprootTreeBuilder` combines instantiated
+//! programs (and optionally ordinary tapscript leaves) into one tree, and the
+//! resulting `TaprootTree` hands out programs bound to that tree so that
+//! `SpendBuilder` produces the right control block for the chosen leaf.
+//!
+//! Leaves may be given relative spend weights, in which case they are placed
+//! at Huffman depths so that the most likely path has the shortest control
+//! block and is the cheapest to spend.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::{Arguments, Program};
+//! use musk::taptree::TaprootTreeBuilder;
+//!
+//! let spend = Program::from_source("fn main() { assert!(true); }").unwrap();
+//! let timeout =
+//!     Program::from_source("fn main() { let x: u32 = 1; assert!(jet::eq_32(x, 1)); }").unwrap();
+//!
+//! let tree = TaprootTreeBuilder::new()
+//!     .add_program(&spend.instantiate(Arguments::default()).unwrap())
+//!     .add_program(&timeout.instantiate(Arguments::default()).unwrap())
+//!     .finalize()
+//!     .unwrap();
+//!
+//! assert_eq!(tree.len(), 2);
+//! let timeout_path = tree.program(1).unwrap();
+//! assert_eq!(
+//!     timeout_path.address(&musk::elements::AddressParams::ELEMENTS),
+//!     tree.address(&musk::elements::AddressParams::ELEMENTS),
+//! );
+//! ```
+
+use crate::error::ProgramError;
+use crate::program::InstantiatedProgram;
+use crate::util::default_internal_key;
+use elements::hex::{FromHex, ToHex};
+use elements::taproot::{ControlBlock, LeafVersion, TapNodeHash, TaprootBuilder, TaprootSpendInfo};
+use elements::Script;
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::str::FromStr;
+
+/// A leaf of a taproot script tree
+#[derive(Clone)]
+pub enum TapLeaf {
+    /// A Simplicity program leaf
+    Program(Box<InstantiatedProgram>),
+    /// An ordinary script leaf with its leaf version
+    Script(Script, LeafVersion),
+}
+
+impl TapLeaf {
+    /// Get the script and leaf version committed to by this leaf
+    #[must_use]
+    pub fn script_version(&self) -> (Script, LeafVersion) {
+        match self {
+            Self::Program(program) => program.script_version(),
+            Self::Script(script, version) => (script.clone(), *version),
+        }
+    }
+}
+
+/// Builder for taproot trees with several leaves
+///
+/// Leaves are placed in a balanced tree in the order they are added, unless
+/// they were given different weights, in which case heavier leaves are placed
+/// closer to the root.
+///
+/// # Examples
+///
+/// ```
+/// use musk::{Arguments, Program};
+/// use musk::taptree::TaprootTreeBuilder;
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program.instantiate(Arguments::default()).unwrap();
+/// let script = musk::elements::Script::new();
+/// let version = musk::elements::taproot::LeafVersion::default();
+///
+/// let tree = TaprootTreeBuilder::new()
+///     .add_script_weighted(script.clone(), version, 1)
+///     .add_script_weighted(script, version, 1)
+///     .add_program_weighted(&compiled, 10)
+///     .finalize()
+///     .unwrap();
+///
+/// assert_eq!(tree.depth(2), Some(1));
+/// ```
+#[derive(Clone, Default)]
+pub struct TaprootTreeBuilder {
+    leaves: Vec<TapLeaf>,
+    weights: Vec<u32>,
+    internal_key: Option<XOnlyPublicKey>,
+}
+
+impl TaprootTreeBuilder {
+    /// Create an empty tree builder
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a custom internal key instead of the default NUMS point
+    ///
+    /// The holder of `internal_key` can then also spend via the key path.
+    #[must_use]
+    pub const fn internal_key(mut self, internal_key: XOnlyPublicKey) -> Self {
+        self.internal_key = Some(internal_key);
+        self
+    }
+
+    /// Add a Simplicity program leaf
+    #[must_use]
+    pub fn add_program(self, program: &InstantiatedProgram) -> Self {
+        self.add_program_weighted(program, 1)
+    }
+
+    /// Add a Simplicity program leaf with a relative spend weight
+    #[must_use]
+    pub fn add_program_weighted(mut self, program: &InstantiatedProgram, weight: u32) -> Self {
+        self.leaves
+            .push(TapLeaf::Program(Box::new(program.clone())));
+        self.weights.push(weight);
+        self
+    }
+
+    /// Add an ordinary script leaf
+    #[must_use]
+    pub fn add_script(self, script: Script, version: LeafVersion) -> Self {
+        self.add_script_weighted(script, version, 1)
+    }
+
+    /// Add an ordinary script leaf with a relative spend weight
+    #[must_use]
+    pub fn add_script_weighted(
+        mut self,
+        script: Script,
+        version: LeafVersion,
+        weight: u32,
+    ) -> Self {
+        self.leaves.push(TapLeaf::Script(script, version));
+        self.weights.push(weight);
+        self
+    }
+
+    /// Build the combined tree
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no leaves were added or the tree cannot be finalized.
+    pub fn finalize(self) -> Result<TaprootTree, ProgramError> {
+        if self.leaves.is_empty() {
+            return Err(ProgramError::TaprootError(
+                "Taproot tree has no leaves".into(),
+            ));
+        }
+
+        let uniform = self.weights.windows(2).all(|pair| pair[0] == pair[1]);
+        let (depths, order) = if uniform {
+            let mut depths = Vec::with_capacity(self.leaves.len());
+            balanced_depths(self.leaves.len(), 0, &mut depths);
+            (depths, (0..self.leaves.len()).collect())
+        } else {
+            let depths = huffman_depths(&self.weights);
+            // Shallowest leaves first is always a valid depth-first order
+            let mut order: Vec<usize> = (0..depths.len()).collect();
+            order.sort_by_key(|&index| depths[index]);
+            (depths, order)
+        };
+
+        let mut builder = TaprootBuilder::new();
+        for &index in &order {
+            let (script, version) = self.leaves[index].script_version();
+            builder = builder
+                .add_leaf_with_ver(depths[index], script, version)
+                .map_err(|e| ProgramError::TaprootError(e.to_string()))?;
+        }
+
+        let spend_info = builder
+            .finalize(
+                &Secp256k1::new(),
+                self.internal_key.unwrap_or_else(default_internal_key),
+            )
+            .map_err(|e| ProgramError::TaprootError(e.to_string()))?;
+
+        Ok(TaprootTree {
+            spend_info,
+            leaves: self.leaves,
+            depths,
+            order,
+        })
+    }
+}
+
+/// Assign depths to `count` leaves of a balanced tree in depth-first order
+fn balanced_depths(count: usize, depth: usize, depths: &mut Vec<usize>) {
+    if count == 1 {
+        depths.push(depth);
+        return;
+    }
+    let left = count.div_ceil(2);
+    balanced_depths(left, depth + 1, depths);
+    balanced_depths(count - left, depth + 1, depths);
+}
+
+/// Assign Huffman depths to leaves with the given weights
+///
+/// Ties are broken by insertion order so the resulting tree is deterministic.
+fn huffman_depths(weights: &[u32]) -> Vec<usize> {
+    // Nodes `0..weights.len()` are leaves, later nodes are branches
+    let mut children: Vec<Option<(usize, usize)>> = vec![None; weights.len()];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = weights
+        .iter()
+        .enumerate()
+        .map(|(node, &weight)| Reverse((u64::from(weight), node)))
+        .collect();
+
+    while let (Some(Reverse((w1, n1))), Some(Reverse((w2, n2)))) = (heap.pop(), heap.pop()) {
+        heap.push(Reverse((w1 + w2, children.len())));
+        children.push(Some((n1, n2)));
+    }
+
+    let mut depths = vec![0; weights.len()];
+    let mut stack = vec![(children.len() - 1, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        match children[node] {
+            Some((left, right)) => {
+                stack.push((left, depth + 1));
+                stack.push((right, depth + 1));
+            }
+            None => depths[node] = depth,
+        }
+    }
+    depths
+}
+
+/// A finalized taproot tree with several leaves
+#[derive(Clone)]
+pub struct TaprootTree {
+    spend_info: TaprootSpendInfo,
+    leaves: Vec<TapLeaf>,
+    depths: Vec<usize>,
+    order: Vec<usize>,
+}
+
+impl TaprootTree {
+    /// Get the taproot spend info for the whole tree
+    #[must_use]
+    pub const fn spend_info(&self) -> &TaprootSpendInfo {
+        &self.spend_info
+    }
+
+    /// Get the leaves in the order they were added
+    #[must_use]
+    pub fn leaves(&self) -> &[TapLeaf] {
+        &self.leaves
+    }
+
+    /// Get the number of leaves
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Check whether the tree has no leaves
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Get the depth of the leaf at `index`, i.e. its control block path length
+    #[must_use]
+    pub fn depth(&self, index: usize) -> Option<usize> {
+        self.depths.get(index).copied()
+    }
+
+    /// Generate the taproot address of the tree
+    #[must_use]
+    pub fn address(&self, params: &'static elements::AddressParams) -> elements::Address {
+        elements::Address::p2tr(
+            &Secp256k1::new(),
+            self.spend_info.internal_key(),
+            self.spend_info.merkle_root(),
+            None,
+            params,
+        )
+    }
+
+    /// Get the program at `index`, bound to this tree
+    ///
+    /// The returned program produces the tree's address and control blocks,
+    /// so it can be passed directly to `SpendBuilder` to spend via that leaf.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of range or the leaf is not a
+    /// Simplicity program.
+    pub fn program(&self, index: usize) -> Result<InstantiatedProgram, ProgramError> {
+        match self.leaves.get(index) {
+            Some(TapLeaf::Program(program)) => {
+                Ok(program.with_taproot_info(self.spend_info.clone()))
+            }
+            Some(TapLeaf::Script(..)) => Err(ProgramError::TaprootError(format!(
+                "Leaf {index} is not a Simplicity program"
+            ))),
+            None => Err(ProgramError::TaprootError(format!(
+                "Leaf {index} out of range ({} leaves)",
+                self.leaves.len()
+            ))),
+        }
+    }
+
+    /// Get the control block for the leaf at `index`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of range.
+    pub fn control_block(&self, index: usize) -> Result<ControlBlock, ProgramError> {
+        let leaf = self.leaves.get(index).ok_or_else(|| {
+            ProgramError::TaprootError(format!(
+                "Leaf {index} out of range ({} leaves)",
+                self.leaves.len()
+            ))
+        })?;
+        self.spend_info
+            .control_block(&leaf.script_version())
+            .ok_or_else(|| ProgramError::TaprootError("Control block not found".into()))
+    }
+
+    /// Build the input witness for spending an ordinary script leaf
+    ///
+    /// `stack` holds the items consumed by the script, bottom first; the leaf
+    /// script and its control block are appended. Simplicity leaves are spent
+    /// through `SpendBuilder` instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::{Arguments, Program};
+    /// use musk::elements::{opcodes, script, taproot::LeafVersion};
+    /// use musk::taptree::TaprootTreeBuilder;
+    ///
+    /// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    /// let recovery = script::Builder::new().push_opcode(opcodes::OP_TRUE).into_script();
+    ///
+    /// let tree = TaprootTreeBuilder::new()
+    ///     .add_program(&program.instantiate(Arguments::default()).unwrap())
+    ///     .add_script(recovery.clone(), LeafVersion::default())
+    ///     .finalize()
+    ///     .unwrap();
+    ///
+    /// let witness = tree.script_witness(1, Vec::new()).unwrap();
+    /// assert_eq!(witness[0], recovery.to_bytes());
+    /// assert!(tree.script_witness(0, Vec::new()).is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of range or the leaf is a
+    /// Simplicity program.
+    pub fn script_witness(
+        &self,
+        index: usize,
+        mut stack: Vec<Vec<u8>>,
+    ) -> Result<Vec<Vec<u8>>, ProgramError> {
+        let control_block = self.control_block(index)?;
+        match &self.leaves[index] {
+            TapLeaf::Script(script, _) => {
+                stack.push(script.to_bytes());
+                stack.push(control_block.serialize());
+                Ok(stack)
+            }
+            TapLeaf::Program(_) => Err(ProgramError::TaprootError(format!(
+                "Leaf {index} is a Simplicity program"
+            ))),
+        }
+    }
+
+    /// Export the tree to a portable description
+    ///
+    /// The export contains only keys, scripts and tree shape, so it can be
+    /// shared with co-signers or auditors who do not run SimplicityHL.
+    /// Leaves keep their indices, so leaf `i` of the export is leaf `i` of
+    /// the tree.
+    #[must_use]
+    pub fn export(&self) -> TaprootTreeExport {
+        let mut positions = vec![0; self.leaves.len()];
+        for (position, &index) in self.order.iter().enumerate() {
+            positions[index] = position;
+        }
+        let leaves = self
+            .leaves
+            .iter()
+            .zip(&self.depths)
+            .zip(positions)
+            .map(|((leaf, &depth), position)| {
+                let (script, version) = leaf.script_version();
+                LeafExport {
+                    script: script.as_bytes().to_hex(),
+                    leaf_version: version.as_u8(),
+                    depth,
+                    position,
+                }
+            })
+            .collect();
+
+        TaprootTreeExport {
+            internal_key: self.spend_info.internal_key().to_string(),
+            merkle_root: self
+                .spend_info
+                .merkle_root()
+                .map(|root| root.to_string())
+                .unwrap_or_default(),
+            output_key: self.spend_info.output_key().into_inner().to_string(),
+            leaves,
+        }
+    }
+}
+
+/// Portable description of a taproot tree
+///
+/// All keys, hashes and scripts are hex encoded. Leaves are listed in the
+/// order they were added to the tree, as in [`TaprootTree::leaves`],
+/// together with their depth and position in depth-first order, which is
+/// sufficient to rebuild the tree and derive a control block for every leaf.
+///
+/// # Examples
+///
+/// ```
+/// use musk::{Arguments, Program};
+/// use musk::taptree::{TaprootTreeBuilder, TaprootTreeExport};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let tree = TaprootTreeBuilder::new()
+///     .add_program(&program.instantiate(Arguments::default()).unwrap())
+///     .finalize()
+///     .unwrap();
+///
+/// let json = tree.export().to_json().unwrap();
+/// let imported = TaprootTreeExport::from_json(&json).unwrap();
+/// assert_eq!(imported.spend_info().unwrap(), *tree.spend_info());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaprootTreeExport {
+    /// Internal key (x-only)
+    pub internal_key: String,
+    /// Merkle root of the script tree
+    pub merkle_root: String,
+    /// Tweaked output key (x-only)
+    pub output_key: String,
+    /// Leaves in the order they were added to the tree
+    pub leaves: Vec<LeafExport>,
+}
+
+/// Portable description of a single taproot leaf
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LeafExport {
+    /// Leaf script
+    pub script: String,
+    /// Leaf version byte
+    pub leaf_version: u8,
+    /// Depth of the leaf in the tree
+    pub depth: usize,
+    /// Position of the leaf in depth-first order
+    ///
+    /// Leaves with equal positions keep their order, so exports that list
+    /// their leaves depth-first may leave this out.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub position: usize,
+}
+
+impl TaprootTreeExport {
+    /// Rebuild the taproot spend info
+    ///
+    /// The rebuilt merkle root and output key are checked against the
+    /// exported values, so a tampered or inconsistent export is rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any field is malformed, the leaves do not form a
+    /// complete tree, or the rebuilt tree does not match the exported keys.
+    pub fn spend_info(&self) -> Result<TaprootSpendInfo, ProgramError> {
+        let internal_key = XOnlyPublicKey::from_str(&self.internal_key)
+            .map_err(|e| ProgramError::TaprootError(format!("Invalid internal key: {e}")))?;
+
+        let mut depth_first: Vec<&LeafExport> = self.leaves.iter().collect();
+        depth_first.sort_by_key(|leaf| leaf.position);
+
+        let mut builder = TaprootBuilder::new();
+        for leaf in depth_first {
+            let (script, version) = leaf.script_version()?;
+            builder = builder
+                .add_leaf_with_ver(leaf.depth, script, version)
+                .map_err(|e| ProgramError::TaprootError(e.to_string()))?;
+        }
+
+        let spend_info = builder
+            .finalize(&Secp256k1::new(), internal_key)
+            .map_err(|e| ProgramError::TaprootError(e.to_string()))?;
+
+        let merkle_root = TapNodeHash::from_str(&self.merkle_root)
+            .map_err(|e| ProgramError::TaprootError(format!("Invalid merkle root: {e}")))?;
+        if spend_info.merkle_root() != Some(merkle_root) {
+            return Err(ProgramError::TaprootError(
+                "Merkle root does not match leaves".into(),
+            ));
+        }
+        if spend_info.output_key().into_inner().to_string() != self.output_key {
+            return Err(ProgramError::TaprootError(
+                "Output key does not match internal key and merkle root".into(),
+            ));
+        }
+
+        Ok(spend_info)
+    }
+
+    /// Derive the control block for the leaf at `index`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index is out of range or the tree cannot be rebuilt.
+    pub fn control_block(&self, index: usize) -> Result<ControlBlock, ProgramError> {
+        let leaf = self.leaves.get(index).ok_or_else(|| {
+            ProgramError::TaprootError(format!(
+                "Leaf {index} out of range ({} leaves)",
+                self.leaves.len()
+            ))
+        })?;
+        self.spend_info()?
+            .control_block(&leaf.script_version()?)
+            .ok_or_else(|| ProgramError::TaprootError("Control block not found".into()))
+    }
+
+    /// Serialize to a JSON string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, ProgramError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| ProgramError::SerializationError(e.to_string()))
+    }
+
+    /// Parse from a JSON string
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is malformed.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, ProgramError> {
+        serde_json::from_str(json).map_err(|e| ProgramError::SerializationError(e.to_string()))
+    }
+}
+
+impl LeafExport {
+    /// Decode the leaf script and version
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script is not valid hex or the version is invalid.
+    pub fn script_version(&self) -> Result<(Script, LeafVersion), ProgramError> {
+        let script = Vec::<u8>::from_hex(&self.script)
+            .map_err(|e| ProgramError::TaprootError(format!("Invalid leaf script: {e}")))?;
+        let version = LeafVersion::from_u8(self.leaf_version)
+            .map_err(|e| ProgramError::TaprootError(format!("Invalid leaf version: {e}")))?;
+        Ok((Script::from(script), version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::test_fixtures::{CAT_PROGRAM, SIMPLE_PROGRAM};
+    use simplicityhl::Arguments;
+
+    fn instantiate(source: &str) -> InstantiatedProgram {
+        Program::from_source(source)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_balanced_depths() {
+        let mut depths = Vec::new();
+        balanced_depths(3, 0, &mut depths);
+        assert_eq!(depths, vec![2, 2, 1]);
+
+        let mut depths = Vec::new();
+        balanced_depths(1, 0, &mut depths);
+        assert_eq!(depths, vec![0]);
+    }
+
+    #[test]
+    fn test_huffman_depths() {
+        assert_eq!(huffman_depths(&[1, 1, 10]), vec![2, 2, 1]);
+        assert_eq!(huffman_depths(&[1, 2, 4, 8]), vec![3, 3, 2, 1]);
+        assert_eq!(huffman_depths(&[5]), vec![0]);
+    }
+
+    #[test]
+    fn test_weighted_tree() {
+        let script = |n: u8| Script::from(vec![n]);
+        let tree = TaprootTreeBuilder::new()
+            .add_script_weighted(script(1), LeafVersion::default(), 1)
+            .add_script_weighted(script(2), LeafVersion::default(), 2)
+            .add_program_weighted(&instantiate(CAT_PROGRAM), 1)
+            .add_program_weighted(&instantiate(SIMPLE_PROGRAM), 8)
+            .finalize()
+            .unwrap();
+
+        assert_eq!(tree.depth(3), Some(1));
+        for (index, leaf) in tree.leaves().iter().enumerate() {
+            let control_block = tree
+                .spend_info()
+                .control_block(&leaf.script_version())
+                .unwrap();
+            assert_eq!(
+                Some(control_block.merkle_branch.as_inner().len()),
+                tree.depth(index)
+            );
+        }
+
+        let program = tree.program(3).unwrap();
+        assert_eq!(
+            program.address(&elements::AddressParams::ELEMENTS),
+            tree.address(&elements::AddressParams::ELEMENTS)
+        );
+        // The export keeps the tree's leaf indices
+        let export = tree.export();
+        assert_eq!(export.spend_info().unwrap(), *tree.spend_info());
+        assert_eq!(export.leaves[3].depth, 1);
+        assert_eq!(export.leaves[3].position, 0);
+        for index in 0..tree.len() {
+            assert_eq!(
+                export.control_block(index).unwrap(),
+                tree.control_block(index).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_uniform_weights_stay_balanced() {
+        let balanced = TaprootTreeBuilder::new()
+            .add_program(&instantiate(SIMPLE_PROGRAM))
+            .add_program(&instantiate(CAT_PROGRAM))
+            .add_script(Script::new(), LeafVersion::default())
+            .finalize()
+            .unwrap();
+        let weighted = TaprootTreeBuilder::new()
+            .add_program_weighted(&instantiate(SIMPLE_PROGRAM), 3)
+            .add_program_weighted(&instantiate(CAT_PROGRAM), 3)
+            .add_script_weighted(Script::new(), LeafVersion::default(), 3)
+            .finalize()
+            .unwrap();
+        assert_eq!(balanced.spend_info(), weighted.spend_info());
+    }
+
+    #[test]
+    fn test_empty_tree_rejected() {
+        assert!(TaprootTreeBuilder::new().finalize().is_err());
+    }
+
+    #[test]
+    fn test_single_leaf_matches_program() {
+        let program = instantiate(SIMPLE_PROGRAM);
+        let tree = TaprootTreeBuilder::new()
+            .add_program(&program)
+            .finalize()
+            .unwrap();
+        assert_eq!(
+            tree.address(&elements::AddressParams::ELEMENTS),
+            program.address(&elements::AddressParams::ELEMENTS)
+        );
+    }
+
+    #[test]
+    fn test_control_block_per_leaf() {
+        let tree = TaprootTreeBuilder::new()
+            .add_program(&instantiate(SIMPLE_PROGRAM))
+            .add_program(&instantiate(CAT_PROGRAM))
+            .add_script(Script::new(), LeafVersion::default())
+            .finalize()
+            .unwrap();
+
+        for leaf in tree.leaves() {
+            assert!(tree
+                .spend_info()
+                .control_block(&leaf.script_version())
+                .is_some());
+        }
+        assert!(tree.program(0).is_ok());
+        assert!(tree.program(2).is_err());
+        assert!(tree.program(3).is_err());
+    }
+
+    #[test]
+    fn test_hybrid_tree_control_blocks() {
+        let recovery = Script::from(vec![0x51]);
+        let tree = TaprootTreeBuilder::new()
+            .add_program(&instantiate(SIMPLE_PROGRAM))
+            .add_script(recovery.clone(), LeafVersion::default())
+            .add_program(&instantiate(CAT_PROGRAM))
+            .finalize()
+            .unwrap();
+        let output_key = tree.spend_info().output_key();
+
+        for (index, leaf) in tree.leaves().iter().enumerate() {
+            let (script, _) = leaf.script_version();
+            let control_block = tree.control_block(index).unwrap();
+            assert!(control_block.verify_taproot_commitment(
+                &Secp256k1::verification_only(),
+                &output_key,
+                &script
+            ));
+        }
+
+        let witness = tree.script_witness(1, vec![vec![1]]).unwrap();
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness[1], recovery.to_bytes());
+        assert!(tree.control_block(3).is_err());
+    }
+
+    #[test]
+    fn test_export_control_blocks() {
+        let tree = TaprootTreeBuilder::new()
+            .add_program(&instantiate(SIMPLE_PROGRAM))
+            .add_program(&instantiate(CAT_PROGRAM))
+            .add_script(Script::new(), LeafVersion::default())
+            .finalize()
+            .unwrap();
+        let export = tree.export();
+
+        assert_eq!(export.spend_info().unwrap(), *tree.spend_info());
+        for (index, leaf) in tree.leaves().iter().enumerate() {
+            let expected = tree.spend_info().control_block(&leaf.script_version());
+            assert_eq!(export.control_block(index).ok(), expected);
+        }
+    }
+
+    #[test]
+    fn test_export_rejects_tampering() {
+        let tree = TaprootTreeBuilder::new()
+            .add_program(&instantiate(SIMPLE_PROGRAM))
+            .add_program(&instantiate(CAT_PROGRAM))
+            .finalize()
+            .unwrap();
+
+        let mut export = tree.export();
+        export.leaves.swap(0, 1);
+        assert!(export.spend_info().is_ok());
+
+        export.leaves[0].script = "00".repeat(32);
+        assert!(export.spend_info().is_err());
+    }
+}
@@ -1,6 +1,6 @@
 //! Test fixtures and constants for musk tests
-
-#![cfg(test)]
+//!
+//! Available to other crates with the `test-utils` feature.
 
 /// Simple program that always succeeds
 pub const SIMPLE_PROGRAM: &str = "fn main() { assert!(true); }";
@@ -60,6 +60,9 @@ pub fn test_utxo() -> crate::client::Utxo {
         asset: elements::confidential::Asset::Explicit(
             AssetId::from_slice(&[0u8; 32]).expect("valid asset"),
         ),
+        value_commitment: None,
+        confirmations: 0,
+        block_height: None,
     }
 }
 
@@ -67,7 +70,7 @@ pub fn test_utxo() -> crate::client::Utxo {
 #[must_use]
 pub fn test_address() -> elements::Address {
     // Create a simple P2WPKH address for testing
-    use elements::bitcoin::{PublicKey, XOnlyPublicKey};
+    use elements::bitcoin::PublicKey;
     use elements::AddressParams;
     use secp256k1::Secp256k1;
 
@@ -78,3 +81,30 @@ pub fn test_address() -> elements::Address {
 
     elements::Address::p2wpkh(&bitcoin_pubkey, None, &AddressParams::ELEMENTS)
 }
+
+/// Run a future to completion on the current thread
+///
+/// Only suitable for futures that never wait on outside events, like the
+/// test signers, since nothing ever wakes the task.
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    // SAFETY: the vtable functions ignore the null data pointer
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
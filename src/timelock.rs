@@ -0,0 +1,226 @@
+//! Timelock construction and analysis
+//!
+//! Simplicity programs enforce timelocks with the `check_lock_height`,
+//! `check_lock_time`, `check_lock_distance` and `check_lock_duration` jets,
+//! which only pass if the spending transaction's lock time or input sequence
+//! commits to a late enough height or time. [`Timelocks`] records which of
+//! these jets a program uses, so `SpendBuilder::check_timelocks` can reject
+//! a spend whose lock time or sequence cannot satisfy them before it is
+//! broadcast.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::timelock::{lock_time_from_height, sequence_from_blocks};
+//!
+//! let lock_time = lock_time_from_height(1_000).unwrap();
+//! assert!(lock_time.is_block_height());
+//! assert!(sequence_from_blocks(144).is_height_locked());
+//! ```
+
+use crate::error::SpendError;
+use elements::{LockTime, Sequence};
+use simplicityhl::simplicity::jet::Elements;
+use std::time::Duration;
+
+/// Create an absolute lock time at block `height`
+///
+/// # Errors
+///
+/// Returns an error if `height` is not below 500,000,000, above which lock
+/// times are timestamps.
+pub fn lock_time_from_height(height: u32) -> Result<LockTime, SpendError> {
+    LockTime::from_height(height)
+        .map_err(|e| SpendError::BuildError(format!("Invalid lock height {height}: {e}")))
+}
+
+/// Create an absolute lock time at UNIX time `timestamp`
+///
+/// # Errors
+///
+/// Returns an error if `timestamp` is below 500,000,000, below which lock
+/// times are block heights.
+pub fn lock_time_from_timestamp(timestamp: u32) -> Result<LockTime, SpendError> {
+    LockTime::from_time(timestamp)
+        .map_err(|e| SpendError::BuildError(format!("Invalid lock timestamp {timestamp}: {e}")))
+}
+
+/// Create a sequence number relatively locked for `blocks` blocks
+#[must_use]
+pub const fn sequence_from_blocks(blocks: u16) -> Sequence {
+    Sequence::from_height(blocks)
+}
+
+/// Create a sequence number relatively locked for at least `duration`
+///
+/// Relative time locks count in units of 512 seconds, so `duration` is
+/// rounded up to the next unit.
+///
+/// # Errors
+///
+/// Returns an error if `duration` exceeds the largest relative time lock of
+/// about 388 days.
+pub fn sequence_from_duration(duration: Duration) -> Result<Sequence, SpendError> {
+    u16::try_from(duration.as_secs().div_ceil(512))
+        .map(Sequence::from_512_second_intervals)
+        .map_err(|_| SpendError::BuildError(format!("Relative lock of {duration:?} is too long")))
+}
+
+/// The timelock jets used by a program
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timelocks {
+    /// `check_lock_height`: absolute lock time as a block height
+    pub height: bool,
+    /// `check_lock_time`: absolute lock time as a timestamp
+    pub time: bool,
+    /// `check_lock_distance`: relative lock time in blocks
+    pub distance: bool,
+    /// `check_lock_duration`: relative lock time in 512-second units
+    pub duration: bool,
+}
+
+impl Timelocks {
+    /// Collect the timelock jets among `jets`
+    pub(crate) fn from_jets(jets: impl IntoIterator<Item = Elements>) -> Self {
+        let mut timelocks = Self::default();
+        for jet in jets {
+            match jet {
+                Elements::CheckLockHeight => timelocks.height = true,
+                Elements::CheckLockTime => timelocks.time = true,
+                Elements::CheckLockDistance => timelocks.distance = true,
+                Elements::CheckLockDuration => timelocks.duration = true,
+                _ => {}
+            }
+        }
+        timelocks
+    }
+
+    /// Whether the program uses any timelock jet
+    #[must_use]
+    pub const fn any(&self) -> bool {
+        self.height || self.time || self.distance || self.duration
+    }
+
+    /// Check that a transaction with `lock_time` and `sequence` can satisfy
+    /// these timelocks
+    ///
+    /// Only the kind of lock is checked, not the height or time the program
+    /// requires: absolute locks need a lock time of the right unit and a
+    /// sequence number that enables it, and relative locks need a sequence
+    /// number of the right unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first timelock that cannot be satisfied.
+    pub fn check(&self, lock_time: LockTime, sequence: Sequence) -> Result<(), SpendError> {
+        let fail = |message: &str| Err(SpendError::BuildError(message.into()));
+        if (self.height || self.time) && !sequence.enables_absolute_lock_time() {
+            return fail("Program requires a lock time, but the sequence number disables it");
+        }
+        if self.height && !(lock_time.is_block_height() && lock_time.to_consensus_u32() > 0) {
+            return fail("Program requires a lock height, but the lock time is not a block height");
+        }
+        if self.time && !lock_time.is_block_time() {
+            return fail("Program requires a lock timestamp, but the lock time is not a timestamp");
+        }
+        if self.distance && !sequence.is_height_locked() {
+            return fail("Program requires a relative lock in blocks, but the sequence has none");
+        }
+        if self.duration && !sequence.is_time_locked() {
+            return fail("Program requires a relative lock in time, but the sequence has none");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Program;
+    use crate::spend::SpendBuilder;
+    use crate::test_fixtures::{test_utxo, SIMPLE_PROGRAM};
+    use simplicityhl::{Arguments, WitnessValues};
+
+    const LOCK_HEIGHT_PROGRAM: &str = r"
+fn main() {
+    let height: Height = 100;
+    jet::check_lock_height(height);
+}
+";
+
+    #[test]
+    fn test_lock_time_and_sequence_helpers() {
+        assert!(lock_time_from_height(100).unwrap().is_block_height());
+        assert!(lock_time_from_height(500_000_000).is_err());
+        assert!(lock_time_from_timestamp(1_700_000_000)
+            .unwrap()
+            .is_block_time());
+        assert!(lock_time_from_timestamp(100).is_err());
+
+        assert!(sequence_from_blocks(10).is_height_locked());
+        let sequence = sequence_from_duration(Duration::from_secs(513)).unwrap();
+        assert!(sequence.is_time_locked());
+        assert_eq!(sequence, Sequence::from_512_second_intervals(2));
+        assert!(sequence_from_duration(Duration::from_secs(512 * 70_000)).is_err());
+    }
+
+    #[test]
+    fn test_program_timelocks() {
+        let program = Program::from_source(LOCK_HEIGHT_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let timelocks = program.timelocks();
+        assert!(timelocks.height && !timelocks.time);
+        assert_eq!(
+            program
+                .satisfy(WitnessValues::default())
+                .unwrap()
+                .timelocks(),
+            timelocks
+        );
+
+        let simple = Program::from_source(SIMPLE_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        assert!(!simple.timelocks().any());
+    }
+
+    #[test]
+    fn test_check_timelocks() {
+        let timelocks = Timelocks {
+            height: true,
+            ..Timelocks::default()
+        };
+        assert!(timelocks
+            .check(LockTime::ZERO, Sequence::ENABLE_RBF_NO_LOCKTIME)
+            .is_err());
+        assert!(timelocks
+            .check(lock_time_from_height(100).unwrap(), Sequence::MAX)
+            .is_err());
+        assert!(timelocks
+            .check(
+                lock_time_from_height(100).unwrap(),
+                Sequence::ENABLE_RBF_NO_LOCKTIME
+            )
+            .is_ok());
+
+        let program = Program::from_source(LOCK_HEIGHT_PROGRAM)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let builder = SpendBuilder::new_unchecked(program.clone(), test_utxo());
+        assert!(builder
+            .check_timelocks(vec![WitnessValues::default()])
+            .is_err());
+
+        let mut builder = SpendBuilder::new_unchecked(program, test_utxo());
+        builder
+            .set_lock_time(lock_time_from_height(100).unwrap())
+            .signal_rbf();
+        assert!(builder
+            .check_timelocks(vec![WitnessValues::default()])
+            .is_ok());
+    }
+}
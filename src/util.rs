@@ -1,9 +1,16 @@
 //! Cryptographic utilities for signing and key management
 
-use secp256k1::{Keypair, Message, Secp256k1, XOnlyPublicKey};
+use elements::schnorr::TapTweak;
+use elements::taproot::TapNodeHash;
+use secp256k1::{
+    Keypair, Message, Parity, PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey,
+};
 
 /// Create a keypair from a u32 secret key (for testing)
 ///
+/// A `u32` is not a secure secret key, so this is only available in tests
+/// and with the `test-utils` feature.
+///
 /// # Examples
 ///
 /// ```
@@ -17,6 +24,7 @@ use secp256k1::{Keypair, Message, Secp256k1, XOnlyPublicKey};
 ///
 /// Panics if the secret key bytes produce an invalid secp256k1 secret key
 /// (this should never happen for reasonable u32 inputs).
+#[cfg(any(test, feature = "test-utils"))]
 #[must_use]
 pub fn keypair_from_u32(secret_key: u32) -> Keypair {
     let mut secret_key_bytes = [0u8; 32];
@@ -25,42 +33,476 @@ pub fn keypair_from_u32(secret_key: u32) -> Keypair {
         .expect("secret key should be valid")
 }
 
-/// Sign a message using Schnorr signature
+/// A secret key that can make Schnorr signatures
+///
+/// Implemented for [`Keypair`] and [`SecretKey`], so signing helpers accept
+/// either form of a real key.
+pub trait SigningKey {
+    /// Get the keypair of this secret key
+    fn keypair(&self) -> Keypair;
+}
+
+impl SigningKey for Keypair {
+    fn keypair(&self) -> Keypair {
+        *self
+    }
+}
+
+impl SigningKey for SecretKey {
+    fn keypair(&self) -> Keypair {
+        Keypair::from_secret_key(&Secp256k1::new(), self)
+    }
+}
+
+/// Sign a message with a Schnorr signature
 ///
 /// # Examples
 ///
 /// ```
-/// use musk::util::sign_schnorr;
+/// use musk::util::sign_schnorr_with;
+/// use secp256k1::{schnorr, Message, Secp256k1, SecretKey};
 ///
+/// let secret_key = SecretKey::from_slice(&[7u8; 32]).unwrap();
 /// let message = [0u8; 32];
-/// let signature = sign_schnorr(1, message);
-/// assert_eq!(signature.len(), 64);
+/// let signature = sign_schnorr_with(&secret_key, message);
+///
+/// let public_key = secret_key.x_only_public_key(&Secp256k1::new()).0;
+/// let signature = schnorr::Signature::from_slice(&signature).unwrap();
+/// assert!(Secp256k1::new()
+///     .verify_schnorr(&signature, &Message::from_digest(message), &public_key)
+///     .is_ok());
 /// ```
 #[must_use]
-pub fn sign_schnorr(secret_key: u32, message: [u8; 32]) -> [u8; 64] {
-    let keypair = keypair_from_u32(secret_key);
-    let message = Message::from_digest(message);
-    keypair.sign_schnorr(message).serialize()
+pub fn sign_schnorr_with<K: SigningKey + ?Sized>(key: &K, message: [u8; 32]) -> [u8; 64] {
+    key.keypair()
+        .sign_schnorr(Message::from_digest(message))
+        .serialize()
+}
+
+/// Sign a message with a Schnorr signature using explicit auxiliary randomness
+///
+/// BIP340 mixes `aux_rand` into the nonce, so signatures stay secure even if
+/// it is weak, and the same key, message and `aux_rand` always give the same
+/// signature. [`sign_schnorr_with`] draws it from the thread RNG instead.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::sign_schnorr_with_aux_rand;
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let first = sign_schnorr_with_aux_rand(&keypair, [0u8; 32], &[9u8; 32]);
+/// let second = sign_schnorr_with_aux_rand(&keypair, [0u8; 32], &[9u8; 32]);
+/// assert_eq!(first, second);
+/// ```
+#[must_use]
+pub fn sign_schnorr_with_aux_rand<K: SigningKey + ?Sized>(
+    key: &K,
+    message: [u8; 32],
+    aux_rand: &[u8; 32],
+) -> [u8; 64] {
+    Secp256k1::signing_only()
+        .sign_schnorr_with_aux_rand(&Message::from_digest(message), &key.keypair(), aux_rand)
+        .serialize()
 }
 
-/// Get the x-only public key for a secret key
+/// Sign a message with a deterministic Schnorr signature
+///
+/// No auxiliary randomness is used, so signatures are reproducible and do not
+/// depend on an RNG at all, at the cost of the side-channel hardening BIP340
+/// gets from fresh randomness.
 ///
 /// # Examples
 ///
 /// ```
-/// use musk::util::xonly_public_key;
+/// use musk::util::sign_schnorr_deterministic;
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// assert_eq!(
+///     sign_schnorr_deterministic(&keypair, [0u8; 32]),
+///     sign_schnorr_deterministic(&keypair, [0u8; 32])
+/// );
+/// ```
+#[must_use]
+pub fn sign_schnorr_deterministic<K: SigningKey + ?Sized>(key: &K, message: [u8; 32]) -> [u8; 64] {
+    Secp256k1::signing_only()
+        .sign_schnorr_no_aux_rand(&Message::from_digest(message), &key.keypair())
+        .serialize()
+}
+
+/// Verify a BIP340 signature the way `jet::bip_0340_verify` does
+///
+/// The public key is taken as the raw `u256` the jet gets, so a key that is
+/// not a valid x coordinate fails like an invalid signature. Checking a
+/// counterparty's signature with this before satisfaction catches what would
+/// otherwise only fail when the program runs, or when a node rejects it.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::{sign_schnorr_with, verify_schnorr, xonly_public_key_of};
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let signature = sign_schnorr_with(&keypair, [1u8; 32]);
+/// assert!(verify_schnorr(xonly_public_key_of(&keypair), [1u8; 32], &signature));
+/// assert!(!verify_schnorr(xonly_public_key_of(&keypair), [2u8; 32], &signature));
+/// ```
+#[must_use]
+pub fn verify_schnorr(pubkey: [u8; 32], message: [u8; 32], signature: &[u8; 64]) -> bool {
+    XOnlyPublicKey::from_slice(&pubkey)
+        .is_ok_and(|pubkey| verify_schnorr_batch(&[(pubkey, message, *signature)]).is_ok())
+}
+
+/// Verify many Schnorr signatures, reporting every invalid one
+///
+/// Each item is a public key, a message and a signature over it. libsecp256k1
+/// has no batch verification yet, so the signatures are checked one by one,
+/// sharing a single verification context; the result does not depend on
+/// how they are checked.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::{sign_schnorr_with, verify_schnorr_batch};
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let pubkey = keypair.x_only_public_key().0;
+/// let items = [
+///     (pubkey, [1u8; 32], sign_schnorr_with(&keypair, [1u8; 32])),
+///     (pubkey, [2u8; 32], sign_schnorr_with(&keypair, [1u8; 32])),
+/// ];
+/// assert_eq!(verify_schnorr_batch(&items[..1]), Ok(()));
+/// assert_eq!(verify_schnorr_batch(&items), Err(vec![1]));
+/// ```
+///
+/// # Errors
+///
+/// Returns the indices of the items whose signature is invalid.
+pub fn verify_schnorr_batch(
+    items: &[(XOnlyPublicKey, [u8; 32], [u8; 64])],
+) -> Result<(), Vec<usize>> {
+    let secp = Secp256k1::verification_only();
+    let invalid: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, (pubkey, message, signature))| {
+            secp256k1::schnorr::Signature::from_slice(signature)
+                .and_then(|signature| {
+                    secp.verify_schnorr(&signature, &Message::from_digest(*message), pubkey)
+                })
+                .is_err()
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(invalid)
+    }
+}
+
+/// Sign the tagged hash of an application-defined message
 ///
-/// let pubkey = xonly_public_key(1);
-/// assert_eq!(pubkey.len(), 32);
+/// The signature is over [`tagged_hash`]`(tag, &[message])`, for contracts
+/// that verify signatures on messages other than the spending transaction.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::{sign_tagged, verify_tagged};
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let pubkey = keypair.x_only_public_key().0;
+/// let signature = sign_tagged(&keypair, "oracle/price", &42u64.to_be_bytes());
+/// assert!(verify_tagged(&pubkey, "oracle/price", &42u64.to_be_bytes(), &signature));
+/// assert!(!verify_tagged(&pubkey, "oracle/other", &42u64.to_be_bytes(), &signature));
+/// ```
+#[must_use]
+pub fn sign_tagged<K: SigningKey + ?Sized>(key: &K, tag: &str, message: &[u8]) -> [u8; 64] {
+    sign_schnorr_with(key, tagged_hash(tag, &[message]))
+}
+
+/// Verify a signature made by [`sign_tagged`]
+#[must_use]
+pub fn verify_tagged(
+    pubkey: &XOnlyPublicKey,
+    tag: &str,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> bool {
+    verify_schnorr(pubkey.serialize(), tagged_hash(tag, &[message]), signature)
+}
+
+/// BIP340 tag of the signature challenge
+const BIP340_CHALLENGE_TAG: &str = "BIP0340/challenge";
+
+/// A Schnorr adaptor signature
+///
+/// An adaptor signature is a BIP340 signature "encrypted" to an adaptor point
+/// `T = t·G`: anyone can check it against the key and message, but only the
+/// holder of `t` can complete it into a valid signature, and publishing that
+/// signature reveals `t` to whoever holds the adaptor signature. Atomic swaps
+/// adapt the signatures of both legs to the same point, so claiming one leg
+/// on-chain reveals the secret that claims the other.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::{verify_schnorr_batch, AdaptorSignature};
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let adaptor_secret = secp256k1::SecretKey::from_slice(&[2; 32]).unwrap();
+/// let adaptor_point = adaptor_secret.public_key(&secp256k1::Secp256k1::new());
+/// let sighash = [7u8; 32];
+///
+/// // Alice adapts her signature to Bob's point
+/// let adaptor = AdaptorSignature::sign(&keypair, sighash, &adaptor_point);
+/// let pubkey = keypair.x_only_public_key().0;
+/// assert!(adaptor.verify(&pubkey, sighash));
+///
+/// // Bob completes it with his secret, which Alice learns from the signature
+/// let signature = adaptor.complete(&adaptor_secret).unwrap();
+/// assert!(verify_schnorr_batch(&[(pubkey, sighash, signature)]).is_ok());
+/// assert_eq!(adaptor.extract_secret(&signature), Some(adaptor_secret));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptorSignature {
+    /// Nonce of the completed signature, `R = k·G + T`, with its parity
+    nonce: PublicKey,
+    adaptor_point: PublicKey,
+    /// `k + e·x`, or `-k + e·x` if `R` has an odd y coordinate
+    s: [u8; 32],
+}
+
+impl AdaptorSignature {
+    /// Length of a serialized adaptor signature
+    pub const SERIALIZED_LEN: usize = 98;
+
+    /// Sign a message with a signature adapted to `adaptor_point`
+    #[must_use]
+    pub fn sign<K: SigningKey + ?Sized>(
+        key: &K,
+        message: [u8; 32],
+        adaptor_point: &PublicKey,
+    ) -> Self {
+        let secp = Secp256k1::new();
+        let keypair = key.keypair();
+        let (pubkey, parity) = keypair.x_only_public_key();
+        let mut secret = SecretKey::from_keypair(&keypair);
+        if parity == Parity::Odd {
+            secret = secret.negate();
+        }
+
+        // Retry the (negligibly rare) nonces giving an invalid point or scalar
+        loop {
+            let nonce_secret = SecretKey::new(&mut secp256k1::rand::thread_rng());
+            let Ok(nonce) = PublicKey::from_secret_key(&secp, &nonce_secret).combine(adaptor_point)
+            else {
+                continue;
+            };
+            let Some(challenge) = bip340_challenge(&nonce, &pubkey, message) else {
+                continue;
+            };
+            let nonce_secret = if nonce.x_only_public_key().1 == Parity::Odd {
+                nonce_secret.negate()
+            } else {
+                nonce_secret
+            };
+            let Ok(s) = secret
+                .mul_tweak(&challenge)
+                .and_then(|s| s.add_tweak(&Scalar::from(nonce_secret)))
+            else {
+                continue;
+            };
+            return Self {
+                nonce,
+                adaptor_point: *adaptor_point,
+                s: s.secret_bytes(),
+            };
+        }
+    }
+
+    /// Get the adaptor point the signature is adapted to
+    #[must_use]
+    pub const fn adaptor_point(&self) -> PublicKey {
+        self.adaptor_point
+    }
+
+    /// Check that the signature completes to a signature of `message` by
+    /// `pubkey` once the adaptor secret is known
+    #[must_use]
+    pub fn verify(&self, pubkey: &XOnlyPublicKey, message: [u8; 32]) -> bool {
+        let secp = Secp256k1::new();
+        let Some(challenge) = bip340_challenge(&self.nonce, pubkey, message) else {
+            return false;
+        };
+        let Ok(s) = SecretKey::from_slice(&self.s) else {
+            return false;
+        };
+        // s·G = ±(R - T) + e·P
+        let Ok(nonce_point) = self.nonce.combine(&self.adaptor_point.negate(&secp)) else {
+            return false;
+        };
+        let nonce_point = if self.is_odd() {
+            nonce_point.negate(&secp)
+        } else {
+            nonce_point
+        };
+        PublicKey::from_x_only_public_key(*pubkey, Parity::Even)
+            .mul_tweak(&secp, &challenge)
+            .and_then(|point| point.combine(&nonce_point))
+            .is_ok_and(|expected| expected == PublicKey::from_secret_key(&secp, &s))
+    }
+
+    /// Complete the signature with the adaptor secret
+    ///
+    /// Returns `None` if `secret` is not the secret of the adaptor point.
+    #[must_use]
+    pub fn complete(&self, secret: &SecretKey) -> Option<[u8; 64]> {
+        if PublicKey::from_secret_key(&Secp256k1::new(), secret) != self.adaptor_point {
+            return None;
+        }
+        let tweak = if self.is_odd() {
+            secret.negate()
+        } else {
+            *secret
+        };
+        let s = SecretKey::from_slice(&self.s)
+            .ok()?
+            .add_tweak(&Scalar::from(tweak))
+            .ok()?;
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&self.nonce.x_only_public_key().0.serialize());
+        signature[32..].copy_from_slice(&s.secret_bytes());
+        Some(signature)
+    }
+
+    /// Learn the adaptor secret from the completed signature
+    ///
+    /// Returns `None` if `signature` is not the completion of this adaptor
+    /// signature.
+    #[must_use]
+    pub fn extract_secret(&self, signature: &[u8; 64]) -> Option<SecretKey> {
+        if signature[..32] != self.nonce.x_only_public_key().0.serialize() {
+            return None;
+        }
+        let s = SecretKey::from_slice(&signature[32..]).ok()?;
+        let adaptor_s = SecretKey::from_slice(&self.s).ok()?;
+        // t = s - s', or s' - s if R has an odd y coordinate
+        let (from, to) = if self.is_odd() {
+            (adaptor_s, s)
+        } else {
+            (s, adaptor_s)
+        };
+        let secret = from.add_tweak(&Scalar::from(to.negate())).ok()?;
+        (PublicKey::from_secret_key(&Secp256k1::new(), &secret) == self.adaptor_point)
+            .then_some(secret)
+    }
+
+    /// Serialize as the nonce and adaptor point (33 bytes each) and `s`
+    #[must_use]
+    pub fn serialize(&self) -> [u8; Self::SERIALIZED_LEN] {
+        let mut bytes = [0u8; Self::SERIALIZED_LEN];
+        bytes[..33].copy_from_slice(&self.nonce.serialize());
+        bytes[33..66].copy_from_slice(&self.adaptor_point.serialize());
+        bytes[66..].copy_from_slice(&self.s);
+        bytes
+    }
+
+    /// Parse a serialized adaptor signature
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the length or a point is invalid.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, secp256k1::Error> {
+        if bytes.len() != Self::SERIALIZED_LEN {
+            return Err(secp256k1::Error::InvalidSignature);
+        }
+        let mut s = [0u8; 32];
+        s.copy_from_slice(&bytes[66..]);
+        Ok(Self {
+            nonce: PublicKey::from_slice(&bytes[..33])?,
+            adaptor_point: PublicKey::from_slice(&bytes[33..66])?,
+            s,
+        })
+    }
+
+    fn is_odd(&self) -> bool {
+        self.nonce.x_only_public_key().1 == Parity::Odd
+    }
+}
+
+/// Compute the BIP340 challenge `e`, or `None` in the negligible case that
+/// the hash is not below the curve order
+fn bip340_challenge(
+    nonce: &PublicKey,
+    pubkey: &XOnlyPublicKey,
+    message: [u8; 32],
+) -> Option<Scalar> {
+    let hash = tagged_hash(
+        BIP340_CHALLENGE_TAG,
+        &[
+            &nonce.x_only_public_key().0.serialize(),
+            &pubkey.serialize(),
+            &message,
+        ],
+    );
+    Scalar::from_be_bytes(hash).ok()
+}
+
+/// Get the serialized x-only public key of a secret key
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::xonly_public_key_of;
+/// use secp256k1::{Keypair, Secp256k1};
 ///
-/// // Same key should produce same pubkey
-/// let pubkey2 = xonly_public_key(1);
-/// assert_eq!(pubkey, pubkey2);
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// assert_eq!(
+///     xonly_public_key_of(&keypair),
+///     keypair.x_only_public_key().0.serialize()
+/// );
+/// assert_eq!(xonly_public_key_of(&keypair.secret_key()), xonly_public_key_of(&keypair));
 /// ```
 #[must_use]
+pub fn xonly_public_key_of<K: SigningKey + ?Sized>(key: &K) -> [u8; 32] {
+    key.keypair().x_only_public_key().0.serialize()
+}
+
+/// Sign a message using Schnorr signature with a u32 secret key (for testing)
+///
+/// Only available in tests and with the `test-utils` feature; use
+/// [`sign_schnorr_with`] with a real key.
+///
+/// # Panics
+///
+/// Panics if `secret_key` is 0.
+#[cfg(any(test, feature = "test-utils"))]
+#[must_use]
+pub fn sign_schnorr(secret_key: u32, message: [u8; 32]) -> [u8; 64] {
+    sign_schnorr_with(&keypair_from_u32(secret_key), message)
+}
+
+/// Get the x-only public key for a u32 secret key (for testing)
+///
+/// Only available in tests and with the `test-utils` feature; use
+/// [`xonly_public_key_of`] with a real key.
+///
+/// # Panics
+///
+/// Panics if `secret_key` is 0.
+#[cfg(any(test, feature = "test-utils"))]
+#[must_use]
 pub fn xonly_public_key(secret_key: u32) -> [u8; 32] {
-    let keypair = keypair_from_u32(secret_key);
-    keypair.x_only_public_key().0.serialize()
+    xonly_public_key_of(&keypair_from_u32(secret_key))
 }
 
 /// Parse an x-only public key from bytes
@@ -68,9 +510,11 @@ pub fn xonly_public_key(secret_key: u32) -> [u8; 32] {
 /// # Examples
 ///
 /// ```
-/// use musk::util::{xonly_public_key, parse_xonly_public_key};
+/// use musk::util::{parse_xonly_public_key, xonly_public_key_of};
+/// use secp256k1::{Keypair, Secp256k1};
 ///
-/// let pubkey_bytes = xonly_public_key(1);
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let pubkey_bytes = xonly_public_key_of(&keypair);
 /// let pubkey = parse_xonly_public_key(&pubkey_bytes).unwrap();
 /// assert_eq!(pubkey.serialize(), pubkey_bytes);
 /// ```
@@ -82,6 +526,92 @@ pub fn parse_xonly_public_key(bytes: &[u8]) -> Result<XOnlyPublicKey, secp256k1:
     XOnlyPublicKey::from_slice(bytes)
 }
 
+/// Tweak an internal keypair into the taproot output keypair
+///
+/// The resulting keypair signs for the output key committed to by
+/// `merkle_root`, as required for key-path spends.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::tweak_keypair;
+/// use musk::{Arguments, Program};
+/// use secp256k1::{Keypair, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let compiled = program
+///     .instantiate_with_internal_key(Arguments::default(), keypair.x_only_public_key().0)
+///     .unwrap();
+///
+/// let tweaked = tweak_keypair(&keypair, compiled.taproot_info().merkle_root());
+/// assert_eq!(
+///     tweaked.x_only_public_key().0,
+///     compiled.taproot_info().output_key().into_inner()
+/// );
+/// ```
+#[must_use]
+pub fn tweak_keypair(keypair: &Keypair, merkle_root: Option<TapNodeHash>) -> Keypair {
+    keypair.tap_tweak(&Secp256k1::new(), merkle_root).to_inner()
+}
+
+/// Sign a taproot key-path sighash with an untweaked internal keypair
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::{sign_keypath, tweak_keypair};
+/// use secp256k1::{schnorr, Keypair, Message, Secp256k1};
+///
+/// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+/// let sighash = [7u8; 32];
+/// let signature = sign_keypath(&keypair, None, sighash);
+///
+/// let output_key = tweak_keypair(&keypair, None).x_only_public_key().0;
+/// let signature = schnorr::Signature::from_slice(&signature).unwrap();
+/// assert!(Secp256k1::new()
+///     .verify_schnorr(&signature, &Message::from_digest(sighash), &output_key)
+///     .is_ok());
+/// ```
+#[must_use]
+pub fn sign_keypath(
+    keypair: &Keypair,
+    merkle_root: Option<TapNodeHash>,
+    sighash: [u8; 32],
+) -> [u8; 64] {
+    tweak_keypair(keypair, merkle_root)
+        .sign_schnorr(Message::from_digest(sighash))
+        .serialize()
+}
+
+/// Compute a BIP340-style tagged hash: `SHA256(SHA256(tag) || SHA256(tag) || data)`
+///
+/// `data` is hashed as the concatenation of its chunks. Contracts use this
+/// to separate application messages such as oracle attestations or state
+/// updates from transaction sighashes.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::tagged_hash;
+///
+/// assert_eq!(tagged_hash("state", &[b"4", b"2"]), tagged_hash("state", &[b"42"]));
+/// assert_ne!(tagged_hash("oracle", &[b"42"]), tagged_hash("state", &[b"42"]));
+/// ```
+#[must_use]
+pub fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    use elements::hashes::{sha256, Hash, HashEngine};
+
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
 /// Default internal key for taproot (NUMS point)
 ///
 /// Returns a "Nothing Up My Sleeve" (NUMS) point used as the internal key
@@ -113,6 +643,57 @@ pub fn default_internal_key() -> XOnlyPublicKey {
     .expect("valid xonly pubkey")
 }
 
+/// Tag used by [`nums_tweak`] to derive per-contract NUMS tweaks
+const NUMS_TWEAK_TAG: &str = "Musk/NUMS";
+
+/// Derive a provably unspendable internal key from a tweak `r`
+///
+/// Following BIP341, the key is `H + r*G`, where `H` is the standard NUMS
+/// point returned by [`default_internal_key`]. Publishing `r` lets anyone
+/// check with [`verify_nums_internal_key`] that no one knows the discrete
+/// log of the key, while distinct tweaks keep contracts unlinkable.
+///
+/// # Examples
+///
+/// ```
+/// use musk::util::{nums_internal_key, nums_tweak, verify_nums_internal_key};
+/// use musk::{Arguments, Program};
+///
+/// let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+/// let r = nums_tweak(b"contract #1");
+/// let internal_key = nums_internal_key(r).unwrap();
+/// let compiled = program
+///     .instantiate_with_internal_key(Arguments::default(), internal_key)
+///     .unwrap();
+///
+/// assert!(verify_nums_internal_key(&compiled.internal_key(), r));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if `r` is not a valid secp256k1 scalar.
+pub fn nums_internal_key(r: [u8; 32]) -> Result<XOnlyPublicKey, secp256k1::Error> {
+    let nums = secp256k1::PublicKey::from_x_only_public_key(
+        default_internal_key(),
+        secp256k1::Parity::Even,
+    );
+    let tweak = secp256k1::Scalar::from_be_bytes(r).map_err(|_| secp256k1::Error::InvalidTweak)?;
+    let key = nums.add_exp_tweak(&Secp256k1::verification_only(), &tweak)?;
+    Ok(key.x_only_public_key().0)
+}
+
+/// Check that an internal key is the NUMS key derived from tweak `r`
+#[must_use]
+pub fn verify_nums_internal_key(internal_key: &XOnlyPublicKey, r: [u8; 32]) -> bool {
+    nums_internal_key(r).is_ok_and(|expected| expected == *internal_key)
+}
+
+/// Derive a deterministic NUMS tweak from per-contract data (e.g. a contract hash)
+#[must_use]
+pub fn nums_tweak(data: &[u8]) -> [u8; 32] {
+    tagged_hash(NUMS_TWEAK_TAG, &[data])
+}
+
 // Add hex dependency for default_internal_key
 #[doc(hidden)]
 mod hex {
@@ -147,6 +728,151 @@ mod tests {
         assert_eq!(sig2.len(), 64);
     }
 
+    #[test]
+    fn test_signing_keys() {
+        let keypair = keypair_from_u32(3);
+        let secret_key = keypair.secret_key();
+        assert_eq!(xonly_public_key_of(&secret_key), xonly_public_key(3));
+
+        let message = [5u8; 32];
+        let signature = sign_schnorr_with(&secret_key, message);
+        let signature = secp256k1::schnorr::Signature::from_slice(&signature).unwrap();
+        assert!(Secp256k1::new()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(message),
+                &keypair.x_only_public_key().0
+            )
+            .is_ok());
+        assert_eq!(sign_schnorr_with(&keypair, message).len(), 64);
+    }
+
+    #[test]
+    fn test_aux_rand_signing() {
+        let keypair = keypair_from_u32(4);
+        let message = [6u8; 32];
+        let pubkey = keypair.x_only_public_key().0;
+        let verify = |signature: [u8; 64]| {
+            let signature = secp256k1::schnorr::Signature::from_slice(&signature).unwrap();
+            Secp256k1::new()
+                .verify_schnorr(&signature, &Message::from_digest(message), &pubkey)
+                .is_ok()
+        };
+
+        let with_aux = sign_schnorr_with_aux_rand(&keypair, message, &[1u8; 32]);
+        assert!(verify(with_aux));
+        assert_eq!(
+            sign_schnorr_with_aux_rand(&keypair.secret_key(), message, &[1u8; 32]),
+            with_aux
+        );
+        assert_ne!(
+            sign_schnorr_with_aux_rand(&keypair, message, &[2u8; 32]),
+            with_aux
+        );
+
+        let deterministic = sign_schnorr_deterministic(&keypair, message);
+        assert!(verify(deterministic));
+        assert_eq!(sign_schnorr_deterministic(&keypair, message), deterministic);
+    }
+
+    #[test]
+    fn test_verify_schnorr() {
+        let keypair = keypair_from_u32(7);
+        let pubkey = xonly_public_key_of(&keypair);
+        let message = [8u8; 32];
+        let signature = sign_schnorr_with(&keypair, message);
+        assert!(verify_schnorr(pubkey, message, &signature));
+        let other = xonly_public_key_of(&keypair_from_u32(8));
+        assert!(!verify_schnorr(other, message, &signature));
+
+        let mut tampered = signature;
+        tampered[63] ^= 1;
+        assert!(!verify_schnorr(pubkey, message, &tampered));
+        // Not the x coordinate of a point on the curve
+        assert!(!verify_schnorr([0xff; 32], message, &signature));
+    }
+
+    #[test]
+    fn test_verify_schnorr_batch() {
+        let items: Vec<_> = (1..=4)
+            .map(|key| {
+                let keypair = keypair_from_u32(key);
+                let message = [key as u8; 32];
+                (
+                    keypair.x_only_public_key().0,
+                    message,
+                    sign_schnorr_with(&keypair, message),
+                )
+            })
+            .collect();
+        assert_eq!(verify_schnorr_batch(&items), Ok(()));
+        assert_eq!(verify_schnorr_batch(&[]), Ok(()));
+
+        let mut tampered = items.clone();
+        tampered[1].1 = [0u8; 32];
+        tampered[3].0 = items[0].0;
+        assert_eq!(verify_schnorr_batch(&tampered), Err(vec![1, 3]));
+    }
+
+    #[test]
+    fn test_tagged_signing() {
+        use elements::hashes::Hash;
+        use elements::taproot::{LeafVersion, TapLeafHash};
+
+        // Taproot leaf hashes are tagged hashes of the leaf version and script
+        let leaf_hash = TapLeafHash::from_script(&elements::Script::new(), LeafVersion::default());
+        assert_eq!(
+            tagged_hash("TapLeaf/elements", &[&[0xc4, 0x00]]),
+            leaf_hash.to_byte_array()
+        );
+
+        let keypair = keypair_from_u32(5);
+        let pubkey = keypair.x_only_public_key().0;
+        let signature = sign_tagged(&keypair.secret_key(), "state", b"update 1");
+        assert!(verify_tagged(&pubkey, "state", b"update 1", &signature));
+        assert!(!verify_tagged(&pubkey, "state", b"update 2", &signature));
+        assert!(!verify_tagged(
+            &keypair_from_u32(6).x_only_public_key().0,
+            "state",
+            b"update 1",
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_adaptor_signature() {
+        let secp = Secp256k1::new();
+        let message = [3u8; 32];
+        // Random nonces give both nonce parities over a few rounds
+        for key in 1..=8 {
+            let keypair = keypair_from_u32(key);
+            let pubkey = keypair.x_only_public_key().0;
+            let secret = keypair_from_u32(100 + key).secret_key();
+            let adaptor_point = secret.public_key(&secp);
+
+            let adaptor = AdaptorSignature::sign(&keypair, message, &adaptor_point);
+            assert!(adaptor.verify(&pubkey, message));
+            assert!(!adaptor.verify(&pubkey, [4u8; 32]));
+            assert_eq!(
+                AdaptorSignature::from_slice(&adaptor.serialize()).unwrap(),
+                adaptor
+            );
+
+            let wrong_secret = keypair_from_u32(200).secret_key();
+            assert_eq!(adaptor.complete(&wrong_secret), None);
+            let signature = adaptor.complete(&secret).unwrap();
+            assert_eq!(
+                verify_schnorr_batch(&[(pubkey, message, signature)]),
+                Ok(())
+            );
+            assert_eq!(adaptor.extract_secret(&signature), Some(secret));
+
+            let other = sign_schnorr_with(&keypair, message);
+            assert_eq!(adaptor.extract_secret(&other), None);
+        }
+        assert!(AdaptorSignature::from_slice(&[0u8; 97]).is_err());
+    }
+
     #[test]
     fn test_xonly_public_key() {
         let pk1 = xonly_public_key(1);
@@ -161,7 +887,7 @@ mod tests {
 
     #[test]
     fn test_parse_xonly_public_key() {
-        let pk_bytes = xonly_public_key(1);
+        let pk_bytes = xonly_public_key_of(&keypair_from_u32(1));
         let pk = parse_xonly_public_key(&pk_bytes).unwrap();
         assert_eq!(pk.serialize(), pk_bytes);
     }
@@ -179,4 +905,16 @@ mod tests {
         assert_eq!(key1, key2);
         assert_eq!(key1.serialize().len(), 32);
     }
+
+    #[test]
+    fn test_nums_internal_key() {
+        let r = nums_tweak(b"contract");
+        let key = nums_internal_key(r).unwrap();
+
+        assert_ne!(key, default_internal_key());
+        assert_eq!(key, nums_internal_key(r).unwrap());
+        assert!(verify_nums_internal_key(&key, r));
+        assert!(!verify_nums_internal_key(&key, nums_tweak(b"other")));
+        assert!(nums_internal_key([0xff; 32]).is_err());
+    }
 }
@@ -1,31 +1,45 @@
 //! Witness utilities and signing helpers
 //!
 //! This module provides the `WitnessBuilder` for constructing witness values
-//! for Simplicity programs.
+//! for Simplicity programs, and [`from_json`] and [`to_json`] for reading
+//! and writing them as JSON.
 //!
 //! # Examples
 //!
 //! ```
 //! use musk::witness::WitnessBuilder;
-//! use simplicityhl::value::ValueConstructible;
-//! use simplicityhl::Value;
+//! use secp256k1::SecretKey;
 //!
 //! // Build witness with signature
+//! let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
 //! let sighash = [0u8; 32];
 //! let witness = WitnessBuilder::new()
-//!     .with_signature("sig", 1, sighash)
+//!     .with_schnorr_signature("sig", &secret_key, sighash)
+//!     .with_public_key("pk", &secret_key)
 //!     .build();
 //! ```
 
-use crate::util;
+use crate::error::ProgramError;
+use crate::program::InstantiatedProgram;
+use crate::util::{self, SigningKey};
+use simplicityhl::num::NonZeroPow2Usize;
 use simplicityhl::str::WitnessName;
+use simplicityhl::types::ResolvedType;
 use simplicityhl::value::ValueConstructible;
-use simplicityhl::{Value, WitnessValues};
+use simplicityhl::{Arguments, Parameters, Value, WitnessValues};
 use std::collections::HashMap;
 
 /// Builder for constructing witness values
 pub struct WitnessBuilder {
     values: HashMap<WitnessName, Value>,
+    preset: Option<Preset>,
+}
+
+/// The preset a builder is checked against
+struct Preset {
+    name: String,
+    needs: Vec<String>,
+    witness_types: HashMap<WitnessName, ResolvedType>,
 }
 
 impl WitnessBuilder {
@@ -43,11 +57,66 @@ impl WitnessBuilder {
     pub fn new() -> Self {
         Self {
             values: HashMap::new(),
+            preset: None,
         }
     }
 
+    /// Create a witness builder for a preset of `program`
+    ///
+    /// [`Self::try_build`] then checks that every witness the preset needs
+    /// is given with its type, and that no value is for a witness the
+    /// program does not have.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Arguments, Program};
+    ///
+    /// let source = r#"
+    /// // @preset refund: TIMEOUT
+    /// fn main() {
+    ///     let timeout: u32 = witness::TIMEOUT;
+    /// }
+    /// "#;
+    /// let program = Program::from_source(source)?.instantiate(Arguments::default())?;
+    ///
+    /// let witness = WitnessBuilder::for_preset(&program, "refund")?
+    ///     .with_u32("TIMEOUT", 144)
+    ///     .try_build()?;
+    /// assert!(WitnessBuilder::for_preset(&program, "refund")?.try_build().is_err());
+    /// assert!(WitnessBuilder::for_preset(&program, "claim").is_err());
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidPreset`] if the program has no preset
+    /// `preset`.
+    pub fn for_preset(program: &InstantiatedProgram, preset: &str) -> Result<Self, ProgramError> {
+        let needs = program.presets().get(preset).ok_or_else(|| {
+            ProgramError::InvalidPreset(format!("The program has no preset {preset}"))
+        })?;
+        Ok(Self {
+            values: HashMap::new(),
+            preset: Some(Preset {
+                name: preset.to_string(),
+                needs: needs.clone(),
+                witness_types: program
+                    .witness_types()
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.clone()))
+                    .collect(),
+            }),
+        })
+    }
+
     /// Add a witness value
     ///
+    /// The name is not validated, like in all `with_*` helpers; an invalid
+    /// one only fails when the program is satisfied. Use [`Self::try_with`]
+    /// or [`Self::try_build`] to catch it early.
+    ///
     /// # Examples
     ///
     /// ```
@@ -66,47 +135,472 @@ impl WitnessBuilder {
         self
     }
 
-    /// Add a signature witness (signs the given message with the secret key)
+    /// Add a witness value, checking that `name` is a valid witness name
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Value, ValueConstructible};
+    ///
+    /// assert!(WitnessBuilder::new().try_with("AMOUNT", Value::u64(1)).is_ok());
+    /// assert!(WitnessBuilder::new().try_with("AM OUNT", Value::u64(1)).is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidWitnessName`] if `name` is not a valid
+    /// SimplicityHL identifier.
+    pub fn try_with(mut self, name: &str, value: Value) -> Result<Self, ProgramError> {
+        self.values.insert(witness_name(name)?, value);
+        Ok(self)
+    }
+
+    /// Add a `u8` witness
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// let witness = WitnessBuilder::new()
+    ///     .with_u8("flags", 0b101)
+    ///     .with_u64("amount", 100_000)
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn with_u8(self, name: &str, value: u8) -> Self {
+        self.with(name, Value::u8(value))
+    }
+
+    /// Add a `u16` witness
+    #[must_use]
+    pub fn with_u16(self, name: &str, value: u16) -> Self {
+        self.with(name, Value::u16(value))
+    }
+
+    /// Add a `u32` witness
+    #[must_use]
+    pub fn with_u32(self, name: &str, value: u32) -> Self {
+        self.with(name, Value::u32(value))
+    }
+
+    /// Add a `u64` witness
+    #[must_use]
+    pub fn with_u64(self, name: &str, value: u64) -> Self {
+        self.with(name, Value::u64(value))
+    }
+
+    /// Add a `u128` witness
+    #[must_use]
+    pub fn with_u128(self, name: &str, value: u128) -> Self {
+        self.with(name, Value::u128(value))
+    }
+
+    /// Add a `u256` witness from its big-endian bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// let witness = WitnessBuilder::new().with_u256("x", [0xff; 32]).build();
+    /// ```
+    #[must_use]
+    pub fn with_u256(self, name: &str, value: [u8; 32]) -> Self {
+        self.with(
+            name,
+            Value::u256(simplicityhl::num::U256::from_byte_array(value)),
+        )
+    }
+
+    /// Add a `bool` witness
+    #[must_use]
+    pub fn with_bool(self, name: &str, value: bool) -> Self {
+        self.with(name, Value::boolean(value))
+    }
+
+    /// Add a byte array witness of type `[u8; N]`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// let witness = WitnessBuilder::new().with_bytes("tag", [1, 2, 3, 4]).build();
+    /// ```
+    #[must_use]
+    pub fn with_bytes<const N: usize>(self, name: &str, bytes: [u8; N]) -> Self {
+        self.with(name, Value::byte_array(bytes))
+    }
+
+    /// Add a tuple witness of the given elements
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Value, ValueConstructible};
+    ///
+    /// let witness = WitnessBuilder::new()
+    ///     .with_tuple("pair", [Value::u32(1), Value::boolean(true)])
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn with_tuple<I: IntoIterator<Item = Value>>(self, name: &str, elements: I) -> Self {
+        self.with(name, Value::tuple(elements))
+    }
+
+    /// Add a `Left` witness of an `Either` type
+    ///
+    /// Sum-typed witnesses usually select the spend path, so the type of the
+    /// branch not taken must be given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::simplicityhl::parse::ParseFromStr;
+    /// use musk::simplicityhl::types::ResolvedType;
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Value, ValueConstructible};
+    ///
+    /// // witness::PATH: Either<Signature, (u256, Signature)>
+    /// let witness = WitnessBuilder::new()
+    ///     .with_left(
+    ///         "PATH",
+    ///         Value::byte_array([0; 64]),
+    ///         ResolvedType::parse_from_str("(u256, [u8; 64])").unwrap(),
+    ///     )
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn with_left(self, name: &str, value: Value, right_type: ResolvedType) -> Self {
+        self.with(name, Value::left(value, right_type))
+    }
+
+    /// Add a `Right` witness of an `Either` type
+    ///
+    /// See [`Self::with_left`].
+    #[must_use]
+    pub fn with_right(self, name: &str, left_type: ResolvedType, value: Value) -> Self {
+        self.with(name, Value::right(left_type, value))
+    }
+
+    /// Add a `Some` witness of an `Option` type
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Value, ValueConstructible};
+    ///
+    /// let witness = WitnessBuilder::new().with_some("REFUND", Value::u32(7)).build();
+    /// ```
+    #[must_use]
+    pub fn with_some(self, name: &str, value: Value) -> Self {
+        self.with(name, Value::some(value))
+    }
+
+    /// Add a `None` witness of type `Option<inner_type>`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::simplicityhl::parse::ParseFromStr;
+    /// use musk::simplicityhl::types::ResolvedType;
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// let witness = WitnessBuilder::new()
+    ///     .with_none("REFUND", ResolvedType::parse_from_str("u32").unwrap())
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn with_none(self, name: &str, inner_type: ResolvedType) -> Self {
+        self.with(name, Value::none(inner_type))
+    }
+
+    /// Add an array witness of type `[element_type; N]`, where `N` is the
+    /// number of elements
     ///
     /// # Examples
     ///
     /// ```
+    /// use musk::simplicityhl::parse::ParseFromStr;
+    /// use musk::simplicityhl::types::ResolvedType;
     /// use musk::witness::WitnessBuilder;
+    /// use musk::{Value, ValueConstructible};
+    ///
+    /// let signatures = vec![[1u8; 64], [2u8; 64], [3u8; 64]];
+    /// let witness = WitnessBuilder::new()
+    ///     .with_array(
+    ///         "SIGS",
+    ///         ResolvedType::parse_from_str("[u8; 64]").unwrap(),
+    ///         signatures.into_iter().map(Value::byte_array),
+    ///     )?
+    ///     .build();
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
     ///
+    /// Returns [`ProgramError::InvalidWitness`] if an element is not of
+    /// `element_type`.
+    pub fn with_array<I>(
+        self,
+        name: &str,
+        element_type: ResolvedType,
+        elements: I,
+    ) -> Result<Self, ProgramError>
+    where
+        I: IntoIterator<Item = Value>,
+    {
+        let elements = typed_elements(name, &element_type, elements)?;
+        Ok(self.with(name, Value::array(elements, element_type)))
+    }
+
+    /// Add a list witness of type `List<element_type, bound>`
+    ///
+    /// A list holds fewer than `bound` elements, which must be a power of two
+    /// of at least 2.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::simplicityhl::num::U256;
+    /// use musk::simplicityhl::parse::ParseFromStr;
+    /// use musk::simplicityhl::types::ResolvedType;
+    /// use musk::witness::WitnessBuilder;
+    /// use musk::{Value, ValueConstructible};
+    ///
+    /// let u256 = ResolvedType::parse_from_str("u256").unwrap();
+    /// let key = Value::u256(U256::from_byte_array([1; 32]));
+    /// let witness = WitnessBuilder::new()
+    ///     .with_list("KEYS", u256.clone(), 4, [key.clone()])?
+    ///     .build();
+    ///
+    /// let too_many = vec![key; 4];
+    /// assert!(WitnessBuilder::new().with_list("KEYS", u256, 4, too_many).is_err());
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidWitness`] if `bound` is invalid, there
+    /// are too many elements, or an element is not of `element_type`.
+    pub fn with_list<I>(
+        self,
+        name: &str,
+        element_type: ResolvedType,
+        bound: usize,
+        elements: I,
+    ) -> Result<Self, ProgramError>
+    where
+        I: IntoIterator<Item = Value>,
+    {
+        let invalid = |message: String| ProgramError::InvalidWitness(format!("{name}: {message}"));
+        let bound = NonZeroPow2Usize::new(bound)
+            .ok_or_else(|| invalid(format!("list bound {bound} is not a power of two >= 2")))?;
+        let elements = typed_elements(name, &element_type, elements)?;
+        if elements.len() >= bound.get() {
+            return Err(invalid(format!(
+                "{} elements do not fit a list bounded by {bound}",
+                elements.len()
+            )));
+        }
+        Ok(self.with(name, Value::list(elements, element_type, bound)))
+    }
+
+    /// Add a hash as a `u256` witness
+    ///
+    /// The hash is taken in its byte order, which is how `jet::sha_256_*`
+    /// returns digests, not in the reversed order txids are displayed in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::elements::hashes::{sha256, Hash};
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// let witness = WitnessBuilder::new()
+    ///     .with_hash("image", sha256::Hash::hash(b"preimage"))
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn with_hash<H: elements::hashes::Hash<Bytes = [u8; 32]>>(
+        self,
+        name: &str,
+        hash: H,
+    ) -> Self {
+        self.with_u256(name, hash.to_byte_array())
+    }
+
+    /// Add a signature witness (signs the given message with the key)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    /// use secp256k1::{Keypair, Secp256k1};
+    ///
+    /// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1u8; 32]).unwrap();
     /// let message = [0u8; 32];
     /// let witness = WitnessBuilder::new()
-    ///     .with_signature("sig", 1, message)
+    ///     .with_schnorr_signature("sig", &keypair, message)
     ///     .build();
     /// ```
     #[must_use]
-    pub fn with_signature(mut self, name: &str, secret_key: u32, message: [u8; 32]) -> Self {
-        let signature = util::sign_schnorr(secret_key, message);
-        self.values.insert(
-            WitnessName::from_str_unchecked(name),
-            Value::byte_array(signature),
-        );
-        self
+    pub fn with_schnorr_signature<K: SigningKey + ?Sized>(
+        self,
+        name: &str,
+        key: &K,
+        message: [u8; 32],
+    ) -> Self {
+        let signature = util::sign_schnorr_with(key, message);
+        self.with(name, Value::byte_array(signature))
+    }
+
+    /// Add a signature made elsewhere, checking it first
+    ///
+    /// The signature must verify against `pubkey` and `message` as
+    /// `jet::bip_0340_verify` would, so a remote signer that signed the wrong
+    /// sighash is caught here rather than when the program runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::util::sign_schnorr_with;
+    /// use musk::witness::WitnessBuilder;
+    /// use secp256k1::{Keypair, Secp256k1};
+    ///
+    /// let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
+    /// let pubkey = keypair.x_only_public_key().0;
+    /// let signature = sign_schnorr_with(&keypair, [1u8; 32]);
+    ///
+    /// let builder = WitnessBuilder::new();
+    /// let builder = builder.with_external_signature("SIG", signature, &pubkey, [1u8; 32])?;
+    /// assert!(WitnessBuilder::new()
+    ///     .with_external_signature("SIG", signature, &pubkey, [2u8; 32])
+    ///     .is_err());
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidWitness`] if the signature does not
+    /// verify.
+    pub fn with_external_signature(
+        self,
+        name: &str,
+        signature: [u8; 64],
+        pubkey: &secp256k1::XOnlyPublicKey,
+        message: [u8; 32],
+    ) -> Result<Self, ProgramError> {
+        if !util::verify_schnorr(pubkey.serialize(), message, &signature) {
+            return Err(ProgramError::InvalidWitness(format!(
+                "{name}: signature does not verify against {pubkey} and the message"
+            )));
+        }
+        Ok(self.with(name, Value::byte_array(signature)))
     }
 
-    /// Add a public key witness
+    /// Add a signature witness completed from an adaptor signature
+    ///
+    /// See [`util::AdaptorSignature`] for how adaptor signatures swap
+    /// secrets.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidWitness`] if `secret` is not the secret
+    /// of the adaptor point.
+    pub fn with_adaptor_signature(
+        self,
+        name: &str,
+        adaptor: &util::AdaptorSignature,
+        secret: &secp256k1::SecretKey,
+    ) -> Result<Self, ProgramError> {
+        let signature = adaptor.complete(secret).ok_or_else(|| {
+            ProgramError::InvalidWitness(format!("{name}: secret does not match the adaptor point"))
+        })?;
+        Ok(self.with(name, Value::byte_array(signature)))
+    }
+
+    /// Add the x-only public key of a key as a witness
     ///
     /// # Examples
     ///
     /// ```
     /// use musk::witness::WitnessBuilder;
+    /// use secp256k1::SecretKey;
     ///
+    /// let secret_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
     /// let witness = WitnessBuilder::new()
-    ///     .with_pubkey("pk", 1)
+    ///     .with_public_key("pk", &secret_key)
     ///     .build();
     /// ```
     #[must_use]
-    pub fn with_pubkey(mut self, name: &str, secret_key: u32) -> Self {
-        let pubkey = util::xonly_public_key(secret_key);
-        self.values.insert(
-            WitnessName::from_str_unchecked(name),
+    pub fn with_public_key<K: SigningKey + ?Sized>(self, name: &str, key: &K) -> Self {
+        let pubkey = util::xonly_public_key_of(key);
+        self.with(
+            name,
             Value::u256(simplicityhl::num::U256::from_byte_array(pubkey)),
-        );
-        self
+        )
+    }
+
+    /// Add a signature witness made with a u32 secret key (for testing)
+    ///
+    /// Only available in tests and with the `test-utils` feature; use
+    /// [`Self::with_schnorr_signature`] with a real key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `secret_key` is 0.
+    #[cfg(any(test, feature = "test-utils"))]
+    #[must_use]
+    pub fn with_signature(self, name: &str, secret_key: u32, message: [u8; 32]) -> Self {
+        self.with_schnorr_signature(name, &util::keypair_from_u32(secret_key), message)
+    }
+
+    /// Add the public key of a u32 secret key as a witness (for testing)
+    ///
+    /// Only available in tests and with the `test-utils` feature; use
+    /// [`Self::with_public_key`] with a real key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `secret_key` is 0.
+    #[cfg(any(test, feature = "test-utils"))]
+    #[must_use]
+    pub fn with_pubkey(self, name: &str, secret_key: u32) -> Self {
+        self.with_public_key(name, &util::keypair_from_u32(secret_key))
+    }
+
+    /// Add the values of a co-signer's witness
+    ///
+    /// Each party fills in only its own witnesses; the coordinator merges
+    /// them before satisfaction. A witness given by both with the same value
+    /// is fine.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// let alice = WitnessBuilder::new().with_u32("A", 1).build();
+    /// let bob = WitnessBuilder::new().with_u32("B", 2).build();
+    /// let witness = WitnessBuilder::new().merge(&alice)?.merge(&bob)?.build();
+    ///
+    /// let mallory = WitnessBuilder::new().with_u32("A", 3).build();
+    /// assert!(WitnessBuilder::new().merge(&alice)?.merge(&mallory).is_err());
+    /// # Ok::<(), musk::ProgramError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::WitnessConflict`] if a witness already has a
+    /// different value.
+    pub fn merge(mut self, other: &WitnessValues) -> Result<Self, ProgramError> {
+        merge_into(&mut self.values, other)?;
+        Ok(self)
     }
 
     /// Build the witness values
@@ -126,6 +620,64 @@ impl WitnessBuilder {
     pub fn build(self) -> WitnessValues {
         WitnessValues::from(self.values)
     }
+
+    /// Build the witness values, checking that every name is valid
+    ///
+    /// A builder made by [`Self::for_preset`] is also checked against its
+    /// preset.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musk::witness::WitnessBuilder;
+    ///
+    /// assert!(WitnessBuilder::new().with_u32("N", 1).try_build().is_ok());
+    /// assert!(WitnessBuilder::new().with_u32("1N", 1).try_build().is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ProgramError::InvalidWitnessName`] for the first invalid
+    /// name in sorted order, or [`ProgramError::InvalidPreset`] if the values
+    /// do not fit the preset.
+    pub fn try_build(self) -> Result<WitnessValues, ProgramError> {
+        let mut names: Vec<String> = self.values.keys().map(ToString::to_string).collect();
+        names.sort();
+        for name in &names {
+            witness_name(name)?;
+        }
+        if let Some(preset) = &self.preset {
+            self.check_preset(preset, &names)?;
+        }
+        Ok(self.build())
+    }
+
+    fn check_preset(&self, preset: &Preset, names: &[String]) -> Result<(), ProgramError> {
+        let invalid =
+            |message: String| ProgramError::InvalidPreset(format!("{}: {message}", preset.name));
+        if let Some(name) = preset.needs.iter().find(|name| {
+            !self
+                .values
+                .contains_key(&WitnessName::from_str_unchecked(name))
+        }) {
+            return Err(invalid(format!("missing witness {name}")));
+        }
+        for name in names {
+            let witness = WitnessName::from_str_unchecked(name);
+            let ty = preset
+                .witness_types
+                .get(&witness)
+                .ok_or_else(|| invalid(format!("{name} is not a witness of the program")))?;
+            let value = &self.values[&witness];
+            if &value.ty() != ty {
+                return Err(invalid(format!(
+                    "{name} is of type {}, expected {ty}",
+                    value.ty()
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for WitnessBuilder {
@@ -134,9 +686,344 @@ impl Default for WitnessBuilder {
     }
 }
 
+/// Parse a witness name, checking that it is a valid SimplicityHL identifier
+///
+/// Identifiers start with an ASCII letter or `_`, followed by ASCII letters,
+/// digits or `_`.
+///
+/// # Examples
+///
+/// ```
+/// use musk::witness::witness_name;
+///
+/// assert_eq!(witness_name("SIG").unwrap().to_string(), "SIG");
+/// assert!(witness_name("").is_err());
+/// assert!(witness_name("witness::SIG").is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns [`ProgramError::InvalidWitnessName`] naming the invalid string.
+pub fn witness_name(name: &str) -> Result<WitnessName, ProgramError> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(WitnessName::from_str_unchecked(name))
+    } else {
+        Err(ProgramError::InvalidWitnessName(name.to_string()))
+    }
+}
+
+/// Collect the elements of the array or list witness `name`, checking
+/// their type
+fn typed_elements<I>(
+    name: &str,
+    element_type: &ResolvedType,
+    elements: I,
+) -> Result<Vec<Value>, ProgramError>
+where
+    I: IntoIterator<Item = Value>,
+{
+    elements
+        .into_iter()
+        .enumerate()
+        .map(|(index, element)| {
+            if &element.ty() == element_type {
+                Ok(element)
+            } else {
+                Err(ProgramError::InvalidWitness(format!(
+                    "{name}: element {index} is of type {}, expected {element_type}",
+                    element.ty()
+                )))
+            }
+        })
+        .collect()
+}
+
+/// Merge partial witness values from several parties
+///
+/// # Examples
+///
+/// ```
+/// use musk::witness::{self, WitnessBuilder};
+///
+/// let alice = WitnessBuilder::new().with_u32("A", 1).with_u32("N", 0).build();
+/// let bob = WitnessBuilder::new().with_u32("B", 2).with_u32("N", 0).build();
+/// let witness = witness::merge([&alice, &bob]).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// Returns [`ProgramError::WitnessConflict`] naming every witness given
+/// different values.
+pub fn merge<'a, I>(witnesses: I) -> Result<WitnessValues, ProgramError>
+where
+    I: IntoIterator<Item = &'a WitnessValues>,
+{
+    let mut values = HashMap::new();
+    let mut conflicts = Vec::new();
+    for witness in witnesses {
+        if let Err(ProgramError::WitnessConflict { names }) = merge_into(&mut values, witness) {
+            conflicts.extend(names);
+        }
+    }
+    if conflicts.is_empty() {
+        Ok(WitnessValues::from(values))
+    } else {
+        conflicts.sort();
+        conflicts.dedup();
+        Err(ProgramError::WitnessConflict { names: conflicts })
+    }
+}
+
+/// Add `other` to `values`, leaving them unchanged on conflict
+fn merge_into(
+    values: &mut HashMap<WitnessName, Value>,
+    other: &WitnessValues,
+) -> Result<(), ProgramError> {
+    let mut names: Vec<String> = other
+        .iter()
+        .filter(|(name, value)| values.get(*name).is_some_and(|existing| existing != *value))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    if !names.is_empty() {
+        names.sort();
+        return Err(ProgramError::WitnessConflict { names });
+    }
+    values.extend(
+        other
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
+    Ok(())
+}
+
+/// Parse witness values from a JSON object, checked against `schema`
+///
+/// `schema` maps every witness name of the program to its type. Each JSON
+/// member is a value in SimplicityHL syntax, given as a string, number or
+/// boolean, or an object `{"value": ..., "type": ...}` whose optional type
+/// must match the schema. Every witness in `schema` must be given, and no
+/// other.
+///
+/// # Examples
+///
+/// ```
+/// use musk::simplicityhl::parse::ParseFromStr;
+/// use musk::simplicityhl::types::ResolvedType;
+/// use musk::{Parameters, WitnessName};
+/// use std::collections::HashMap;
+///
+/// let schema = Parameters::from(HashMap::from([
+///     (WitnessName::from_str_unchecked("AMOUNT"), ResolvedType::parse_from_str("u64").unwrap()),
+///     (WitnessName::from_str_unchecked("OK"), ResolvedType::parse_from_str("bool").unwrap()),
+/// ]));
+/// let json = r#"{"AMOUNT": {"value": "100000", "type": "u64"}, "OK": true}"#;
+/// let witness = musk::witness::from_json(json, &schema).unwrap();
+///
+/// assert!(musk::witness::from_json(r#"{"AMOUNT": "x", "OK": true}"#, &schema).is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed, a witness is missing or not in
+/// `schema`, or a value does not have its witness's type.
+#[cfg(feature = "serde")]
+pub fn from_json(json: &str, schema: &Parameters) -> Result<WitnessValues, ProgramError> {
+    parse_json_values(json, schema, true).map(WitnessValues::from)
+}
+
+/// Parse witness values from JSON written by [`to_json`], using the types
+/// it declares
+///
+/// Unlike [`from_json`], no schema is needed, but every value must be an
+/// object `{"value": ..., "type": ...}`.
+///
+/// # Examples
+///
+/// ```
+/// use musk::witness::{self, WitnessBuilder};
+///
+/// let witness = WitnessBuilder::new().with_u32("N", 42).with_bool("OK", true).build();
+/// let json = witness::to_json(&witness).unwrap();
+/// let parsed = witness::from_json_typed(&json).unwrap();
+/// assert_eq!(witness::to_json(&parsed).unwrap(), json);
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed, a type is missing or invalid,
+/// or a value does not have its declared type.
+#[cfg(feature = "serde")]
+pub fn from_json_typed(json: &str) -> Result<WitnessValues, ProgramError> {
+    use serde_json::Value as Json;
+    use simplicityhl::parse::ParseFromStr;
+
+    let invalid = |message: String| ProgramError::SerializationError(message);
+    let members: serde_json::Map<String, Json> =
+        serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+    let schema = members
+        .iter()
+        .map(|(name, member)| {
+            member
+                .get("type")
+                .and_then(Json::as_str)
+                .and_then(|ty| ResolvedType::parse_from_str(ty).ok())
+                .map(|ty| (WitnessName::from_str_unchecked(name), ty))
+                .ok_or_else(|| invalid(format!("Missing or invalid type of {name}")))
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    from_json(json, &Parameters::from(schema))
+}
+
+/// Parse some of the witness values of a program from a JSON object
+///
+/// Like [`from_json`], except that witnesses in `schema` may be left out,
+/// e.g. while co-signers are still filling in their own.
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed, a witness is not in `schema`,
+/// or a value does not have its witness's type.
+#[cfg(feature = "serde")]
+pub fn from_json_partial(json: &str, schema: &Parameters) -> Result<WitnessValues, ProgramError> {
+    parse_json_values(json, schema, false).map(WitnessValues::from)
+}
+
+/// Parse program arguments from a JSON object, checked against `parameters`
+///
+/// The JSON format is the same as for [`from_json`].
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed, an argument is missing or not
+/// a parameter, or a value does not have its parameter's type.
+#[cfg(feature = "serde")]
+pub fn arguments_from_json(json: &str, parameters: &Parameters) -> Result<Arguments, ProgramError> {
+    parse_json_values(json, parameters, true).map(Arguments::from)
+}
+
+/// Serialize witness values to JSON
+///
+/// Values are written in SimplicityHL syntax with their types, sorted by
+/// name, in the format [`from_json`] reads, so the output is stable for
+/// logs and audit trails and can be handed to co-signers.
+///
+/// # Examples
+///
+/// ```
+/// use musk::witness::WitnessBuilder;
+///
+/// let witness = WitnessBuilder::new().with_u32("N", 42).build();
+/// let json = musk::witness::to_json(&witness).unwrap();
+/// assert!(json.contains(r#""type": "u32""#));
+/// ```
+///
+/// # Errors
+///
+/// Returns an error if serialization fails.
+#[cfg(feature = "serde")]
+pub fn to_json(witness_values: &WitnessValues) -> Result<String, ProgramError> {
+    json_values(witness_values.iter())
+}
+
+/// Serialize program arguments to JSON
+///
+/// The format is the same as for [`to_json`].
+///
+/// # Errors
+///
+/// Returns an error if serialization fails.
+#[cfg(feature = "serde")]
+pub fn arguments_to_json(arguments: &Arguments) -> Result<String, ProgramError> {
+    json_values(arguments.iter())
+}
+
+#[cfg(feature = "serde")]
+fn json_values<'a, I>(values: I) -> Result<String, ProgramError>
+where
+    I: Iterator<Item = (&'a WitnessName, &'a Value)>,
+{
+    let members: std::collections::BTreeMap<_, _> = values
+        .map(|(name, value)| {
+            let member = serde_json::json!({
+                "value": value.to_string(),
+                "type": value.ty().to_string(),
+            });
+            (name.to_string(), member)
+        })
+        .collect();
+    serde_json::to_string_pretty(&members)
+        .map_err(|e| ProgramError::SerializationError(e.to_string()))
+}
+
+#[cfg(feature = "serde")]
+fn parse_json_values(
+    json: &str,
+    schema: &Parameters,
+    complete: bool,
+) -> Result<HashMap<WitnessName, Value>, ProgramError> {
+    use serde_json::Value as Json;
+    use simplicityhl::parse::ParseFromStr;
+
+    let invalid = |message: String| ProgramError::SerializationError(message);
+    let members = match serde_json::from_str(json).map_err(|e| invalid(e.to_string()))? {
+        Json::Object(members) => members,
+        _ => return Err(invalid("Expected a JSON object of values".into())),
+    };
+
+    let mut values = HashMap::new();
+    for (name, member) in members {
+        let witness = WitnessName::from_str_unchecked(&name);
+        let ty = schema
+            .get(&witness)
+            .ok_or_else(|| invalid(format!("Unexpected value {name}")))?;
+        let value = match member {
+            Json::Object(mut object) => {
+                if let Some(declared) = object.remove("type") {
+                    let declared = declared
+                        .as_str()
+                        .and_then(|declared| ResolvedType::parse_from_str(declared).ok())
+                        .ok_or_else(|| invalid(format!("Invalid type of {name}")))?;
+                    if &declared != ty {
+                        return Err(invalid(format!(
+                            "{name} is declared as {declared}, expected {ty}"
+                        )));
+                    }
+                }
+                object
+                    .remove("value")
+                    .ok_or_else(|| invalid(format!("Missing value of {name}")))?
+            }
+            member => member,
+        };
+        let value = match value {
+            Json::String(value) => value,
+            Json::Number(value) => value.to_string(),
+            Json::Bool(value) => value.to_string(),
+            _ => return Err(invalid(format!("Invalid value of {name}"))),
+        };
+        let value = Value::parse_from_str(&value, ty)
+            .map_err(|e| invalid(format!("Invalid value of {name}: {e}")))?;
+        values.insert(witness, value);
+    }
+
+    if let Some((name, _)) = schema
+        .iter()
+        .find(|(name, _)| complete && !values.contains_key(*name))
+    {
+        return Err(invalid(format!("Missing value {name}")));
+    }
+    Ok(values)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use simplicityhl::num::U256;
     use simplicityhl::value::ValueConstructible;
 
     #[test]
@@ -186,6 +1073,347 @@ mod tests {
         assert!(std::mem::size_of_val(&witness) > 0);
     }
 
+    #[test]
+    fn test_witness_builder_typed_helpers() {
+        use elements::hashes::{sha256, Hash};
+
+        let hash = sha256::Hash::hash(b"preimage");
+        let builder = WitnessBuilder::new()
+            .with_u8("a", 1)
+            .with_u16("b", 2)
+            .with_u32("c", 3)
+            .with_u64("d", 4)
+            .with_u128("e", 5)
+            .with_u256("f", [6; 32])
+            .with_bool("g", true)
+            .with_bytes("h", [7, 8])
+            .with_tuple("i", [Value::u8(9), Value::boolean(false)])
+            .with_hash("j", hash);
+
+        let expected = [
+            ("a", Value::u8(1)),
+            ("b", Value::u16(2)),
+            ("c", Value::u32(3)),
+            ("d", Value::u64(4)),
+            ("e", Value::u128(5)),
+            ("f", Value::u256(U256::from_byte_array([6; 32]))),
+            ("g", Value::boolean(true)),
+            ("h", Value::byte_array([7, 8])),
+            ("i", Value::tuple([Value::u8(9), Value::boolean(false)])),
+            (
+                "j",
+                Value::u256(U256::from_byte_array(hash.to_byte_array())),
+            ),
+        ];
+        assert_eq!(builder.values.len(), expected.len());
+        for (name, value) in expected {
+            assert_eq!(
+                builder.values[&WitnessName::from_str_unchecked(name)],
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_witness_builder_with_real_keys() {
+        let message = [2u8; 32];
+        let keypair = util::keypair_from_u32(1);
+        let with_keypair = WitnessBuilder::new()
+            .with_schnorr_signature("sig", &keypair, message)
+            .with_public_key("pk", &keypair.secret_key());
+        let with_test_key = WitnessBuilder::new()
+            .with_signature("sig", 1, message)
+            .with_pubkey("pk", 1);
+        assert_eq!(with_keypair.values, with_test_key.values);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_json() {
+        use crate::program::Program;
+        use crate::test_fixtures::PARAMETERIZED_PROGRAM;
+        use simplicityhl::parse::ParseFromStr;
+        use simplicityhl::types::ResolvedType;
+
+        let ty = |ty| ResolvedType::parse_from_str(ty).unwrap();
+        let schema = Parameters::from(HashMap::from([
+            (WitnessName::from_str_unchecked("SIG"), ty("[u8; 64]")),
+            (WitnessName::from_str_unchecked("PAIR"), ty("(u8, bool)")),
+            (WitnessName::from_str_unchecked("N"), ty("u32")),
+        ]));
+        let signature = format!("0x{}", "ab".repeat(64));
+        let json = format!(
+            r#"{{"SIG": "{signature}", "N": 42,
+                "PAIR": {{"value": "(7, false)", "type": "(u8, bool)"}}}}"#
+        );
+        let witness = from_json(&json, &schema).unwrap();
+        assert_eq!(
+            witness.get(&WitnessName::from_str_unchecked("N")),
+            Some(&Value::u32(42))
+        );
+        assert_eq!(
+            witness.get(&WitnessName::from_str_unchecked("SIG")),
+            Some(&Value::byte_array([0xab; 64]))
+        );
+
+        for json in [
+            r#"["N"]"#,
+            r#"{"N": 1}"#,
+            r#"{"SIG": "0x00", "PAIR": "(7, false)", "N": 1}"#,
+            &json.replace(r#""type": "(u8, bool)""#, r#""type": "(u8, u8)""#),
+            &json.replace("42", r#"42, "EXTRA": 1"#),
+            &json.replace("42", "4294967296"),
+        ] {
+            assert!(from_json(json, &schema).is_err(), "{json}");
+        }
+
+        let program = Program::from_source(PARAMETERIZED_PROGRAM).unwrap();
+        let arguments = arguments_from_json(r#"{"VALUE": 42}"#, program.parameters()).unwrap();
+        assert!(program.instantiate(arguments).is_ok());
+        assert!(arguments_from_json(r#"{"VALUE": true}"#, program.parameters()).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_json_round_trip() {
+        let witness = WitnessBuilder::new()
+            .with_u64("AMOUNT", 100_000)
+            .with_bytes("SIG", [0xab; 64])
+            .with_tuple("PAIR", [Value::u8(7), Value::boolean(false)])
+            .with_u256("PK", [1; 32])
+            .build();
+        let json = to_json(&witness).unwrap();
+        assert!(json.find("AMOUNT") < json.find("PAIR"));
+        assert_eq!(to_json(&witness).unwrap(), json);
+
+        let schema = Parameters::from(
+            witness
+                .iter()
+                .map(|(name, value)| (name.clone(), value.ty().clone()))
+                .collect::<HashMap<_, _>>(),
+        );
+        let parsed = from_json(&json, &schema).unwrap();
+        for (name, value) in witness.iter() {
+            assert_eq!(parsed.get(name), Some(value));
+        }
+
+        let arguments = Arguments::from(HashMap::from([(
+            WitnessName::from_str_unchecked("VALUE"),
+            Value::u32(42),
+        )]));
+        let json = arguments_to_json(&arguments).unwrap();
+        assert!(json.contains(r#""value": "42""#));
+    }
+
+    #[test]
+    fn test_witness_builder_sum_helpers() {
+        use simplicityhl::parse::ParseFromStr;
+
+        let u8_type = || ResolvedType::parse_from_str("u8").unwrap();
+        let builder = WitnessBuilder::new()
+            .with_left("a", Value::u32(1), u8_type())
+            .with_right("b", u8_type(), Value::u32(2))
+            .with_some("c", Value::u8(3))
+            .with_none("d", u8_type());
+
+        let expected = [
+            ("a", "Either<u32, u8>", "Left(1)"),
+            ("b", "Either<u8, u32>", "Right(2)"),
+            ("c", "Option<u8>", "Some(3)"),
+            ("d", "Option<u8>", "None"),
+        ];
+        for (name, ty, value) in expected {
+            let ty = ResolvedType::parse_from_str(ty).unwrap();
+            let value = Value::parse_from_str(value, &ty).unwrap();
+            assert_eq!(
+                builder.values[&WitnessName::from_str_unchecked(name)],
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_witness_builder_collections() {
+        use simplicityhl::parse::ParseFromStr;
+
+        let u8_type = || ResolvedType::parse_from_str("u8").unwrap();
+        let elements = || vec![Value::u8(1), Value::u8(2), Value::u8(3)];
+        let builder = WitnessBuilder::new()
+            .with_array("a", u8_type(), elements())
+            .unwrap()
+            .with_list("l", u8_type(), 4, elements())
+            .unwrap()
+            .with_list("e", u8_type(), 2, [])
+            .unwrap();
+
+        let expected = [
+            ("a", "[u8; 3]", "[1, 2, 3]"),
+            ("l", "List<u8, 4>", "list![1, 2, 3]"),
+            ("e", "List<u8, 2>", "list![]"),
+        ];
+        for (name, ty, value) in expected {
+            let ty = ResolvedType::parse_from_str(ty).unwrap();
+            let value = Value::parse_from_str(value, &ty).unwrap();
+            assert_eq!(
+                builder.values[&WitnessName::from_str_unchecked(name)],
+                value
+            );
+        }
+
+        let mixed = vec![Value::u8(1), Value::u16(2)];
+        assert!(matches!(
+            WitnessBuilder::new().with_array("a", u8_type(), mixed),
+            Err(ProgramError::InvalidWitness(_))
+        ));
+        assert!(WitnessBuilder::new()
+            .with_list("l", u8_type(), 3, elements())
+            .is_err());
+        assert!(WitnessBuilder::new()
+            .with_list("l", u8_type(), 2, elements())
+            .is_err());
+    }
+
+    #[test]
+    fn test_witness_name_validation() {
+        for name in ["SIG", "sig_1", "_X"] {
+            assert!(witness_name(name).is_ok(), "{name}");
+        }
+        for name in ["", "1SIG", "SIG-1", "S IG", "witness::SIG"] {
+            match witness_name(name) {
+                Err(ProgramError::InvalidWitnessName(invalid)) => assert_eq!(invalid, name),
+                other => panic!("expected {name:?} to be invalid, got {other:?}"),
+            }
+        }
+
+        let builder = WitnessBuilder::new().try_with("A", Value::u8(1)).unwrap();
+        assert!(builder.try_build().is_ok());
+        assert!(WitnessBuilder::new().try_with("A-1", Value::u8(1)).is_err());
+        match WitnessBuilder::new()
+            .with_u8("A", 1)
+            .with_u8("B C", 2)
+            .try_build()
+        {
+            Err(ProgramError::InvalidWitnessName(name)) => assert_eq!(name, "B C"),
+            other => panic!("expected an invalid name, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_witness_builder_with_external_signature() {
+        let keypair = util::keypair_from_u32(1);
+        let pubkey = keypair.x_only_public_key().0;
+        let sighash = [1u8; 32];
+        let signature = util::sign_schnorr_with(&keypair, sighash);
+
+        let builder = WitnessBuilder::new()
+            .with_external_signature("SIG", signature, &pubkey, sighash)
+            .unwrap();
+        assert_eq!(
+            builder.values[&WitnessName::from_str_unchecked("SIG")],
+            Value::byte_array(signature)
+        );
+
+        let other_key = util::keypair_from_u32(2).x_only_public_key().0;
+        for (pubkey, sighash) in [(other_key, sighash), (pubkey, [2u8; 32])] {
+            assert!(matches!(
+                WitnessBuilder::new().with_external_signature("SIG", signature, &pubkey, sighash),
+                Err(ProgramError::InvalidWitness(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_witness_builder_with_adaptor_signature() {
+        let keypair = util::keypair_from_u32(1);
+        let secret = util::keypair_from_u32(2).secret_key();
+        let adaptor_point = secret.public_key(&secp256k1::Secp256k1::new());
+        let adaptor = util::AdaptorSignature::sign(&keypair, [1u8; 32], &adaptor_point);
+
+        let builder = WitnessBuilder::new()
+            .with_adaptor_signature("SIG", &adaptor, &secret)
+            .unwrap();
+        assert_eq!(
+            builder.values[&WitnessName::from_str_unchecked("SIG")],
+            Value::byte_array(adaptor.complete(&secret).unwrap())
+        );
+        assert!(WitnessBuilder::new()
+            .with_adaptor_signature("SIG", &adaptor, &keypair.secret_key())
+            .is_err());
+    }
+
+    #[test]
+    fn test_witness_builder_for_preset() {
+        use crate::program::Program;
+
+        let source = r#"
+// @preset claim: A, B
+// @preset refund: B
+fn main() {
+    let a: u8 = witness::A;
+    let b: u16 = witness::B;
+}
+"#;
+        let program = Program::from_source(source)
+            .unwrap()
+            .instantiate(Arguments::default())
+            .unwrap();
+        let preset = |name| WitnessBuilder::for_preset(&program, name).unwrap();
+
+        assert!(preset("refund").with_u16("B", 1).try_build().is_ok());
+        assert!(preset("claim")
+            .with_u8("A", 1)
+            .with_u16("B", 2)
+            .try_build()
+            .is_ok());
+
+        for builder in [
+            preset("claim").with_u16("B", 2),
+            preset("refund").with_u8("B", 1),
+            preset("refund").with_u16("B", 1).with_u8("C", 1),
+        ] {
+            assert!(matches!(
+                builder.try_build(),
+                Err(ProgramError::InvalidPreset(_))
+            ));
+        }
+        assert!(WitnessBuilder::for_preset(&program, "other").is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let alice = WitnessBuilder::new()
+            .with_u32("A", 1)
+            .with_u32("N", 0)
+            .build();
+        let bob = WitnessBuilder::new()
+            .with_u32("B", 2)
+            .with_u32("N", 0)
+            .build();
+        let merged = merge([&alice, &bob]).unwrap();
+        assert_eq!(merged.iter().count(), 3);
+
+        let coordinator = WitnessBuilder::new()
+            .merge(&alice)
+            .unwrap()
+            .merge(&bob)
+            .unwrap();
+        assert_eq!(coordinator.values.len(), 3);
+
+        let mallory = WitnessBuilder::new()
+            .with_u32("A", 3)
+            .with_u32("B", 3)
+            .build();
+        match merge([&alice, &bob, &mallory]) {
+            Err(ProgramError::WitnessConflict { names }) => assert_eq!(names, ["A", "B"]),
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert!(WitnessBuilder::new()
+            .merge(&alice)
+            .unwrap()
+            .merge(&mallory)
+            .is_err());
+    }
+
     #[test]
     fn test_witness_builder_default() {
         let builder = WitnessBuilder::default();
@@ -0,0 +1,297 @@
+//! Encrypted storage of witness values
+//!
+//! Multi-party spends can take days to collect every witness. The helpers
+//! here seal the witness values gathered so far into an authenticated,
+//! encrypted blob that can be written to disk and reopened in a later
+//! session, with either a 32-byte key or a passphrase.
+//!
+//! The blob is laid out as follows, with all integers big-endian:
+//!
+//! | Bytes | Content |
+//! |-------|---------|
+//! | 6 | Magic `muskw` and format version `2` |
+//! | 1 | Key derivation: `0` for a raw key, `1` for PBKDF2-HMAC-SHA256 |
+//! | 20 | PBKDF2 iterations (4) and salt (16), only with a passphrase |
+//! | 12 | Random nonce |
+//! | ... | Witness values as JSON from `witness::to_json`, encrypted |
+//! | 16 | Poly1305 tag |
+//!
+//! The JSON is encrypted with ChaCha20-Poly1305 (RFC 8439), as implemented
+//! by `ring`, and the bytes before the nonce are authenticated as associated
+//! data.
+//!
+//! # Examples
+//!
+//! ```
+//! use musk::witness::WitnessBuilder;
+//! use musk::witness_store;
+//!
+//! let witness = WitnessBuilder::new().with_u32("AMOUNT", 1000).build();
+//! let key = [7; 32];
+//!
+//! let blob = witness_store::encrypt(&witness, &key).unwrap();
+//! assert!(witness_store::decrypt(&blob, &key).is_ok());
+//! assert!(witness_store::decrypt(&blob, &[8; 32]).is_err());
+//! ```
+
+use crate::error::ProgramError;
+use crate::witness;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use secp256k1::rand::RngCore;
+use simplicityhl::WitnessValues;
+use std::num::NonZeroU32;
+
+/// Magic bytes and format version at the start of every blob
+const MAGIC: &[u8] = b"muskw\x02";
+
+/// Key derivation byte of a blob sealed with a raw key
+const KDF_NONE: u8 = 0x00;
+
+/// Key derivation byte of a blob sealed with a passphrase
+const KDF_PBKDF2: u8 = 0x01;
+
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+
+/// PBKDF2 iterations used by [`encrypt_with_passphrase`]
+pub const DEFAULT_PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Most PBKDF2 iterations [`decrypt_with_passphrase`] accepts
+///
+/// The iteration count is read from the blob, so without a bound a crafted
+/// blob could keep the caller busy deriving a key for hours.
+pub const MAX_PBKDF2_ITERATIONS: u32 = 10 * DEFAULT_PBKDF2_ITERATIONS;
+
+/// Encrypt witness values with a 32-byte key
+///
+/// # Errors
+///
+/// Returns an error if the witness values cannot be serialized.
+pub fn encrypt(witness_values: &WitnessValues, key: &[u8; 32]) -> Result<Vec<u8>, ProgramError> {
+    let mut blob = MAGIC.to_vec();
+    blob.push(KDF_NONE);
+    seal(blob, key, witness_values)
+}
+
+/// Encrypt witness values with a passphrase
+///
+/// The key is derived with PBKDF2-HMAC-SHA256 over a random salt, using
+/// [`DEFAULT_PBKDF2_ITERATIONS`] iterations.
+///
+/// # Errors
+///
+/// Returns an error if the witness values cannot be serialized.
+pub fn encrypt_with_passphrase(
+    witness_values: &WitnessValues,
+    passphrase: &str,
+) -> Result<Vec<u8>, ProgramError> {
+    let iterations = NonZeroU32::new(DEFAULT_PBKDF2_ITERATIONS).expect("nonzero iterations");
+    encrypt_with_iterations(witness_values, passphrase, iterations)
+}
+
+/// Decrypt witness values encrypted by [`encrypt`]
+///
+/// # Errors
+///
+/// Returns an error if the blob is malformed, was sealed with a passphrase,
+/// or the key is wrong or the blob was tampered with.
+pub fn decrypt(blob: &[u8], key: &[u8; 32]) -> Result<WitnessValues, ProgramError> {
+    let rest = blob
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| invalid("not a musk witness blob"))?;
+    match rest.first() {
+        Some(&KDF_NONE) => open(blob, MAGIC.len() + 1, key),
+        Some(&KDF_PBKDF2) => Err(invalid("blob is sealed with a passphrase")),
+        _ => Err(invalid("unknown key derivation")),
+    }
+}
+
+/// Decrypt witness values encrypted by [`encrypt_with_passphrase`]
+///
+/// # Errors
+///
+/// Returns an error if the blob is malformed, was sealed with a raw key,
+/// asks for zero or more than [`MAX_PBKDF2_ITERATIONS`] iterations, or the
+/// passphrase is wrong or the blob was tampered with.
+pub fn decrypt_with_passphrase(
+    blob: &[u8],
+    passphrase: &str,
+) -> Result<WitnessValues, ProgramError> {
+    let rest = blob
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| invalid("not a musk witness blob"))?;
+    match rest.first() {
+        Some(&KDF_PBKDF2) => {}
+        Some(&KDF_NONE) => return Err(invalid("blob is sealed with a raw key")),
+        _ => return Err(invalid("unknown key derivation")),
+    }
+    let params = rest
+        .get(1..1 + 4 + SALT_LEN)
+        .ok_or_else(|| invalid("blob is truncated"))?;
+    let iterations = u32::from_be_bytes(params[..4].try_into().expect("4 bytes"));
+    if iterations > MAX_PBKDF2_ITERATIONS {
+        return Err(invalid("too many PBKDF2 iterations"));
+    }
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| invalid("PBKDF2 iterations are zero"))?;
+    let key = pbkdf2(passphrase.as_bytes(), &params[4..], iterations);
+    open(blob, MAGIC.len() + 1 + params.len(), &key)
+}
+
+fn encrypt_with_iterations(
+    witness_values: &WitnessValues,
+    passphrase: &str,
+    iterations: NonZeroU32,
+) -> Result<Vec<u8>, ProgramError> {
+    let mut salt = [0u8; SALT_LEN];
+    secp256k1::rand::thread_rng().fill_bytes(&mut salt);
+    let key = pbkdf2(passphrase.as_bytes(), &salt, iterations);
+
+    let mut blob = MAGIC.to_vec();
+    blob.push(KDF_PBKDF2);
+    blob.extend_from_slice(&iterations.get().to_be_bytes());
+    blob.extend_from_slice(&salt);
+    seal(blob, &key, witness_values)
+}
+
+/// Append the nonce, ciphertext and tag of `witness_values` to `header`
+fn seal(
+    mut header: Vec<u8>,
+    key: &[u8; 32],
+    witness_values: &WitnessValues,
+) -> Result<Vec<u8>, ProgramError> {
+    let mut sealed = witness::to_json(witness_values)?.into_bytes();
+    let mut nonce = [0u8; NONCE_LEN];
+    secp256k1::rand::thread_rng().fill_bytes(&mut nonce);
+
+    aead_key(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&header),
+            &mut sealed,
+        )
+        .map_err(|_| invalid("cannot encrypt witness"))?;
+    header.extend_from_slice(&nonce);
+    header.extend_from_slice(&sealed);
+    Ok(header)
+}
+
+/// Authenticate and decrypt the blob whose nonce starts at `offset`
+fn open(blob: &[u8], offset: usize, key: &[u8; 32]) -> Result<WitnessValues, ProgramError> {
+    if blob.len() < offset + NONCE_LEN + TAG_LEN {
+        return Err(invalid("blob is truncated"));
+    }
+    let (header, rest) = blob.split_at(offset);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("nonce has the right length");
+    let mut sealed = sealed.to_vec();
+    let plaintext = aead_key(key)
+        .open_in_place(nonce, Aad::from(header), &mut sealed)
+        .map_err(|_| invalid("wrong key or corrupted blob"))?;
+    let json =
+        String::from_utf8(plaintext.to_vec()).map_err(|_| invalid("witness is not UTF-8"))?;
+    witness::from_json_typed(&json)
+}
+
+fn aead_key(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(
+        UnboundKey::new(&CHACHA20_POLY1305, key).expect("ChaCha20-Poly1305 takes 32-byte keys"),
+    )
+}
+
+/// PBKDF2-HMAC-SHA256 with a single 32-byte output block
+fn pbkdf2(passphrase: &[u8], salt: &[u8], iterations: NonZeroU32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase,
+        &mut key,
+    );
+    key
+}
+
+fn invalid(message: &str) -> ProgramError {
+    ProgramError::SerializationError(format!("Invalid witness blob: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::witness::WitnessBuilder;
+    use elements::hex::ToHex;
+
+    fn test_witness() -> WitnessValues {
+        WitnessBuilder::new()
+            .with_u32("AMOUNT", 1000)
+            .with_bool("OK", true)
+            .with_u256("DATA", [0xab; 32])
+            .build()
+    }
+
+    #[test]
+    fn test_round_trip_with_key() {
+        let witness = test_witness();
+        let blob = encrypt(&witness, &[1; 32]).unwrap();
+        let restored = decrypt(&blob, &[1; 32]).unwrap();
+        assert_eq!(
+            witness::to_json(&restored).unwrap(),
+            witness::to_json(&witness).unwrap()
+        );
+
+        // Fresh nonce every time
+        assert_ne!(encrypt(&witness, &[1; 32]).unwrap(), blob);
+        assert!(decrypt(&blob, &[2; 32]).is_err());
+        assert!(decrypt_with_passphrase(&blob, "").is_err());
+    }
+
+    #[test]
+    fn test_round_trip_with_passphrase() {
+        let witness = test_witness();
+        let iterations = NonZeroU32::new(10).unwrap();
+        let blob = encrypt_with_iterations(&witness, "correct horse", iterations).unwrap();
+        let restored = decrypt_with_passphrase(&blob, "correct horse").unwrap();
+        assert_eq!(
+            witness::to_json(&restored).unwrap(),
+            witness::to_json(&witness).unwrap()
+        );
+
+        assert!(decrypt_with_passphrase(&blob, "battery staple").is_err());
+        assert!(decrypt(&blob, &[0; 32]).is_err());
+
+        // The iteration count is bounded before any key is derived
+        let iterations = MAGIC.len() + 1;
+        for count in [0, MAX_PBKDF2_ITERATIONS + 1, u32::MAX] {
+            let mut crafted = blob.clone();
+            crafted[iterations..iterations + 4].copy_from_slice(&count.to_be_bytes());
+            assert!(decrypt_with_passphrase(&crafted, "correct horse").is_err());
+        }
+    }
+
+    #[test]
+    fn test_tampered_blob_is_rejected() {
+        let blob = encrypt(&test_witness(), &[1; 32]).unwrap();
+        for i in [MAGIC.len() + 1, MAGIC.len() + 1 + NONCE_LEN, blob.len() - 1] {
+            let mut tampered = blob.clone();
+            tampered[i] ^= 1;
+            assert!(decrypt(&tampered, &[1; 32]).is_err());
+        }
+        assert!(decrypt(&blob[..blob.len() - 1], &[1; 32]).is_err());
+        assert!(decrypt(&blob[..MAGIC.len() + 1], &[1; 32]).is_err());
+        assert!(decrypt(b"not a blob", &[1; 32]).is_err());
+    }
+
+    #[test]
+    fn test_pbkdf2_vectors() {
+        // Published PBKDF2-HMAC-SHA256 vectors, truncated to one block
+        assert_eq!(
+            pbkdf2(b"password", b"salt", NonZeroU32::new(1).unwrap()).to_hex(),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            pbkdf2(b"password", b"salt", NonZeroU32::new(2).unwrap()).to_hex(),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+    }
+}
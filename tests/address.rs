@@ -1,6 +1,18 @@
 //! Unit tests for address generation and taproot utilities
 
-use musk::{Arguments, Program};
+use musk::address::{
+    blinding_pubkey, parse_address, parse_uri, receiver_nonce, same_destination, to_uri,
+    verify_control_block,
+};
+use musk::{elements, Arguments, Program};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+/// Public key of a fixed test secret key
+fn test_pubkey(byte: u8) -> PublicKey {
+    SecretKey::from_slice(&[byte; 32])
+        .unwrap()
+        .public_key(&Secp256k1::new())
+}
 
 #[test]
 fn test_create_taproot_info() {
@@ -120,3 +132,163 @@ fn test_different_programs_different_addresses() {
         "Different programs should have different addresses"
     );
 }
+
+#[test]
+fn test_spend_from_multi_leaf_tree() {
+    use musk::client::Utxo;
+    use musk::elements::hashes::Hash;
+    use musk::{SpendBuilder, TaprootTreeBuilder, WitnessValues};
+
+    let spend = Program::from_source("fn main() { assert!(true); }").unwrap();
+    let timeout =
+        Program::from_source("fn main() { let x: u32 = 1; assert!(jet::eq_32(x, 1)); }").unwrap();
+
+    let tree = TaprootTreeBuilder::new()
+        .add_program(&spend.instantiate(Arguments::default()).unwrap())
+        .add_program(&timeout.instantiate(Arguments::default()).unwrap())
+        .finalize()
+        .unwrap();
+    let address = tree.address(&musk::elements::AddressParams::ELEMENTS);
+
+    let asset = musk::elements::AssetId::default();
+    let utxo = Utxo {
+        txid: musk::Txid::from_byte_array([2u8; 32]),
+        vout: 0,
+        amount: 100_000,
+        script_pubkey: address.script_pubkey(),
+        asset: musk::elements::confidential::Asset::Explicit(asset),
+        value_commitment: None,
+        confirmations: 0,
+        block_height: None,
+    };
+
+    let mut builder = SpendBuilder::from_tree(&tree, 1, utxo.clone())
+        .unwrap()
+        .genesis_hash(musk::elements::BlockHash::from_byte_array([1u8; 32]));
+    builder.add_output_simple(address.script_pubkey(), 99_000, asset);
+    builder.add_fee(1_000, asset);
+    let tx = builder.finalize(WitnessValues::default()).unwrap();
+
+    // The control block proves one sibling (33 + 32 bytes)
+    let witness = &tx.input[0].witness.script_witness;
+    assert_eq!(witness.len(), 4);
+    assert_eq!(witness[3].len(), 65);
+
+    // A UTXO not locked by the tree is rejected before signing
+    let other = Utxo {
+        script_pubkey: musk::elements::Script::new(),
+        ..utxo
+    };
+    assert!(SpendBuilder::from_tree(&tree, 1, other).is_err());
+}
+
+#[test]
+fn test_confidential_address_conversion() {
+    let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    let compiled = program.instantiate(Arguments::default()).unwrap();
+    let params = &elements::AddressParams::ELEMENTS;
+    let pubkey = test_pubkey(7);
+
+    let confidential = compiled.confidential_address(params, pubkey);
+    let parsed = parse_address(&confidential.to_string(), params).unwrap();
+    assert_eq!(blinding_pubkey(&parsed), Some(pubkey));
+    assert!(!receiver_nonce(&parsed).is_null());
+
+    let unconfidential = parsed.to_unconfidential();
+    assert_eq!(unconfidential, compiled.address(params));
+    assert_eq!(blinding_pubkey(&unconfidential), None);
+    assert_eq!(unconfidential.to_confidential(pubkey), confidential);
+    assert!(same_destination(&confidential, &unconfidential));
+    assert!(same_destination(
+        &confidential,
+        &compiled.address(&elements::AddressParams::LIQUID)
+    ));
+
+    assert!(parse_address("not an address", params).is_err());
+}
+
+#[test]
+fn test_verify_control_block() {
+    let simple = Program::from_source("fn main() { assert!(true); }")
+        .unwrap()
+        .instantiate(Arguments::default())
+        .unwrap();
+    let other = Program::from_source("fn main() { let x: u32 = 1; assert!(jet::eq_32(x, 1)); }")
+        .unwrap()
+        .instantiate(Arguments::default())
+        .unwrap();
+
+    let (script, version) = simple.script_version();
+    let control_block = simple
+        .taproot_info()
+        .control_block(&(script.clone(), version))
+        .unwrap();
+    let output_key = simple.taproot_info().output_key().into_inner();
+    let other_key = other.taproot_info().output_key().into_inner();
+
+    assert!(verify_control_block(
+        &script,
+        version,
+        &control_block,
+        &output_key
+    ));
+    assert!(!verify_control_block(
+        &script,
+        version,
+        &control_block,
+        &other_key
+    ));
+    assert!(!verify_control_block(
+        &script,
+        elements::taproot::LeafVersion::default(),
+        &control_block,
+        &output_key
+    ));
+}
+
+#[test]
+fn test_payment_uri_roundtrip() {
+    let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    let compiled = program.instantiate(Arguments::default()).unwrap();
+    let address =
+        compiled.confidential_address(&elements::AddressParams::LIQUID_TESTNET, test_pubkey(7));
+    let asset = elements::AssetId::from_slice(&[7u8; 32]).unwrap();
+
+    let uri = to_uri(&address, Some(12_345), Some(asset), Some("Café & co"));
+    assert!(uri.starts_with("liquidtestnet:"));
+
+    let parsed = parse_uri(&uri).unwrap();
+    assert_eq!(parsed.address, address);
+    assert_eq!(parsed.amount, Some(12_345));
+    assert_eq!(parsed.asset, Some(asset));
+    assert_eq!(parsed.label.as_deref(), Some("Café & co"));
+    assert_eq!(parsed.message, None);
+
+    let bare = to_uri(&address, None, None, None);
+    assert_eq!(bare, format!("liquidtestnet:{address}"));
+    assert_eq!(parse_uri(&bare).unwrap().amount, None);
+    assert!(parse_uri(&format!("liquidnetwork:{address}")).is_err());
+}
+
+#[test]
+fn test_payment_uri_rejects_invalid() {
+    let program = Program::from_source("fn main() { assert!(true); }").unwrap();
+    let address = program
+        .instantiate(Arguments::default())
+        .unwrap()
+        .address(&elements::AddressParams::LIQUID);
+
+    assert!(parse_uri(&format!("bitcoin:{address}")).is_err());
+    assert!(parse_uri(&format!("liquidtestnet:{address}")).is_err());
+    assert!(parse_uri(&format!("LiquidNetwork:{address}")).is_ok());
+    assert!(parse_uri(&format!("liquidnetwork:{address}?amount=1.123456789")).is_err());
+    assert!(parse_uri(&format!("liquidnetwork:{address}?amount=-1")).is_err());
+    assert!(parse_uri(&format!("liquidnetwork:{address}?req-foo=1")).is_err());
+    assert!(parse_uri(&format!("liquidnetwork:{address}?foo=1")).is_ok());
+    assert_eq!(
+        parse_uri(&format!("liquidnetwork:{address}?amount=.5"))
+            .unwrap()
+            .amount,
+        Some(50_000_000)
+    );
+}
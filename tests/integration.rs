@@ -64,12 +64,14 @@ fn test_witness_building() {
 #[test]
 fn test_signature_witness() {
     use musk::witness::WitnessBuilder;
+    use secp256k1::{Keypair, Secp256k1};
 
     // Build witness with signature
+    let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap();
     let sighash = [1u8; 32];
     let witness = WitnessBuilder::new()
-        .with_signature("sig", 1, sighash)
-        .with_pubkey("pk", 1)
+        .with_schnorr_signature("sig", &keypair, sighash)
+        .with_public_key("pk", &keypair)
         .build();
 
     assert!(std::mem::size_of_val(&witness) > 0);
@@ -95,18 +97,19 @@ fn test_network_config() {
 
 #[test]
 fn test_cryptographic_utilities() {
-    use musk::util::{keypair_from_u32, sign_schnorr, xonly_public_key};
+    use musk::util::{sign_schnorr_with, xonly_public_key_of};
+    use secp256k1::{Keypair, Secp256k1};
 
     // Test key generation
-    let keypair = keypair_from_u32(42);
+    let keypair = Keypair::from_seckey_slice(&Secp256k1::new(), &[42; 32]).unwrap();
     assert_eq!(keypair.x_only_public_key().0.serialize().len(), 32);
 
     // Test public key extraction
-    let pubkey = xonly_public_key(42);
+    let pubkey = xonly_public_key_of(&keypair);
     assert_eq!(pubkey.len(), 32);
 
     // Test signing
     let message = [0u8; 32];
-    let signature = sign_schnorr(42, message);
+    let signature = sign_schnorr_with(&keypair.secret_key(), message);
     assert_eq!(signature.len(), 64);
 }
@@ -0,0 +1,82 @@
+//! Regtest scenario tests
+//!
+//! These tests run the `musk::scenarios` flows against a live Elements
+//! regtest node with a funded wallet. They are ignored by default; run with:
+//!
+//! ```text
+//! MUSK_RPC_URL=http://127.0.0.1:18884 MUSK_RPC_USER=user MUSK_RPC_PASSWORD=pass \
+//!     cargo test --test regtest -- --ignored
+//! ```
+
+#![cfg(feature = "rpc")]
+
+use musk::scenarios::{fund_spend_confirm, htlc_swap, ScenarioConfig};
+use musk::{Arguments, NodeClient, Program, RpcClient, WitnessValues};
+use secp256k1::{Keypair, Secp256k1};
+
+fn regtest_client() -> (RpcClient, ScenarioConfig) {
+    let var = |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.into());
+    let mut client = RpcClient::from_url(
+        &var("MUSK_RPC_URL", "http://127.0.0.1:18884"),
+        &var("MUSK_RPC_USER", "user"),
+        &var("MUSK_RPC_PASSWORD", "password"),
+    )
+    .unwrap();
+    client.test_connection().expect("regtest node reachable");
+
+    let config = ScenarioConfig::regtest(client.genesis_hash().unwrap());
+    (client, config)
+}
+
+#[test]
+#[ignore = "requires a running Elements regtest node"]
+fn test_regtest_fund_spend_confirm() {
+    let (client, config) = regtest_client();
+    let program = Program::from_source("fn main() { assert!(true); }")
+        .unwrap()
+        .instantiate(Arguments::default())
+        .unwrap();
+
+    let report =
+        fund_spend_confirm(&client, &program, &config, |_| WitnessValues::default()).unwrap();
+    assert_eq!(
+        report.spend_tx.input[0].previous_output.txid,
+        report.funding_txid
+    );
+}
+
+#[test]
+#[ignore = "requires a running Elements regtest node"]
+fn test_regtest_htlc_swap() {
+    let (client, config) = regtest_client();
+    let secp = Secp256k1::new();
+    let recipient = Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+    let sender_pk = Keypair::from_seckey_slice(&secp, &[2; 32])
+        .unwrap()
+        .x_only_public_key()
+        .0;
+
+    let report = htlc_swap(&client, &config, &recipient, sender_pk, [7u8; 32], 1000).unwrap();
+    assert_eq!(report.spend_tx.input.len(), 1);
+}
+
+#[test]
+#[ignore = "requires a running Elements regtest node"]
+fn test_regtest_scan_utxos() {
+    let (client, _) = regtest_client();
+    let program = Program::from_source("fn main() { assert!(true); }")
+        .unwrap()
+        .instantiate(Arguments::default())
+        .unwrap();
+    let address = program.address(client.address_params());
+
+    // Neither imported nor watched by the wallet
+    let txid = client.send_to_address(&address, 100_000).unwrap();
+    client.generate_blocks(1).unwrap();
+
+    let utxos = client.scan_utxos(&address).unwrap();
+    let utxo = utxos.iter().find(|utxo| utxo.txid == txid).unwrap();
+    assert_eq!(utxo.amount, 100_000);
+    assert_eq!(utxo.script_pubkey, address.script_pubkey());
+    assert_eq!(utxo.confirmations, 1);
+}